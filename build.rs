@@ -0,0 +1,22 @@
+use std::process::Command;
+
+// Exposes the commit the binary was built from as GIT_SHA, CI can override it when .git is not available
+fn main() {
+    println!("cargo:rerun-if-env-changed=GIT_SHA");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+    // Migrations are embedded at compile time
+    println!("cargo:rerun-if-changed=migrations");
+
+    let git_sha = std::env::var("GIT_SHA").ok().unwrap_or_else(|| {
+        Command::new("git")
+            .args(["rev-parse", "--short", "HEAD"])
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+            .unwrap_or_else(|| "unknown".to_string())
+    });
+
+    println!("cargo:rustc-env=GIT_SHA={}", git_sha);
+}
@@ -0,0 +1 @@
+ALTER TABLE swift_user DROP COLUMN locale;
@@ -0,0 +1,2 @@
+-- Preferred locale for translated messages, NULL falls back to Accept-Language
+ALTER TABLE swift_user ADD COLUMN locale TEXT;
@@ -0,0 +1 @@
+ALTER TABLE organisation DROP COLUMN plan;
@@ -0,0 +1,3 @@
+ALTER TABLE organisation
+    ADD COLUMN plan TEXT NOT NULL DEFAULT 'free'
+        CONSTRAINT organisation_plan_check CHECK (plan IN ('free', 'pro', 'enterprise'));
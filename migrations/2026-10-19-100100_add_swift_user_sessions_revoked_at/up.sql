@@ -0,0 +1,2 @@
+-- Tokens issued at or before this instant are rejected
+ALTER TABLE swift_user ADD COLUMN sessions_revoked_at TIMESTAMP;
@@ -0,0 +1,13 @@
+DROP POLICY tenant_isolation ON swift_user;
+ALTER TABLE swift_user NO FORCE ROW LEVEL SECURITY;
+ALTER TABLE swift_user DISABLE ROW LEVEL SECURITY;
+
+DROP POLICY tenant_isolation ON swift_user_accessible_organisation;
+ALTER TABLE swift_user_accessible_organisation NO FORCE ROW LEVEL SECURITY;
+ALTER TABLE swift_user_accessible_organisation DISABLE ROW LEVEL SECURITY;
+
+DROP POLICY tenant_isolation ON application;
+ALTER TABLE application NO FORCE ROW LEVEL SECURITY;
+ALTER TABLE application DISABLE ROW LEVEL SECURITY;
+
+DROP FUNCTION app_current_organisation_id();
@@ -0,0 +1,34 @@
+-- Organisation the connection is scoped to, NULL when the request has none (login, signup, admin tasks)
+-- and then the policies match no rows. Paths that work across organisations connect as a BYPASSRLS
+-- role (DATABASE_ADMIN_URL) instead of leaving the organisation unset
+CREATE FUNCTION app_current_organisation_id() RETURNS UUID AS $$
+    SELECT NULLIF(current_setting('app.organisation_id', true), '')::UUID
+$$ LANGUAGE SQL STABLE;
+
+-- FORCE so the policies also apply when the application connects as the table owner.
+-- Superusers and BYPASSRLS roles always skip them
+ALTER TABLE application ENABLE ROW LEVEL SECURITY;
+ALTER TABLE application FORCE ROW LEVEL SECURITY;
+CREATE POLICY tenant_isolation ON application
+    USING (organisation_id = app_current_organisation_id())
+    WITH CHECK (organisation_id = app_current_organisation_id());
+
+ALTER TABLE swift_user_accessible_organisation ENABLE ROW LEVEL SECURITY;
+ALTER TABLE swift_user_accessible_organisation FORCE ROW LEVEL SECURITY;
+CREATE POLICY tenant_isolation ON swift_user_accessible_organisation
+    USING (organisation_id = app_current_organisation_id())
+    WITH CHECK (organisation_id = app_current_organisation_id());
+
+-- Users belong to the organisation through their membership. A new user has no membership yet,
+-- users are created as the admin role
+ALTER TABLE swift_user ENABLE ROW LEVEL SECURITY;
+ALTER TABLE swift_user FORCE ROW LEVEL SECURITY;
+CREATE POLICY tenant_isolation ON swift_user
+    USING (EXISTS (
+        SELECT 1 FROM swift_user_accessible_organisation
+        WHERE swift_user_accessible_organisation.swift_user_id = swift_user.id
+          AND swift_user_accessible_organisation.organisation_id = app_current_organisation_id()))
+    WITH CHECK (EXISTS (
+        SELECT 1 FROM swift_user_accessible_organisation
+        WHERE swift_user_accessible_organisation.swift_user_id = swift_user.id
+          AND swift_user_accessible_organisation.organisation_id = app_current_organisation_id()));
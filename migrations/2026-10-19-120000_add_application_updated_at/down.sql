@@ -0,0 +1 @@
+ALTER TABLE application DROP COLUMN updated_at;
@@ -0,0 +1,5 @@
+ALTER TABLE application ADD COLUMN updated_at TIMESTAMP NOT NULL DEFAULT NOW();
+-- The tenant policy hides every row from a migration, which has no organisation. NO FORCE lets the table owner through
+ALTER TABLE application NO FORCE ROW LEVEL SECURITY;
+UPDATE application SET updated_at = created_at;
+ALTER TABLE application FORCE ROW LEVEL SECURITY;
@@ -0,0 +1,20 @@
+-- Responses to POST requests sent with an Idempotency-Key, replayed when the client retries.
+-- response_status stays NULL while the first request is still being handled. claim_token identifies
+-- the request holding the key, a request whose lock timed out and was taken over can no longer
+-- store its response or release the key
+CREATE TABLE idempotency_key
+(
+    swift_user_id       UUID      NOT NULL,
+    key                 TEXT      NOT NULL,
+    request_fingerprint TEXT      NOT NULL,
+    response_status     SMALLINT,
+    response_headers    JSONB,
+    response_body       BYTEA,
+    claim_token         UUID      NOT NULL DEFAULT gen_random_uuid(),
+    locked_at           TIMESTAMP NOT NULL DEFAULT NOW(),
+    created_at          TIMESTAMP NOT NULL DEFAULT NOW(),
+    expires_at          TIMESTAMP NOT NULL,
+    PRIMARY KEY (swift_user_id, key),
+    CONSTRAINT fk_idempotency_key_swift_user_id FOREIGN KEY (swift_user_id) REFERENCES swift_user (id) ON DELETE CASCADE
+);
+CREATE INDEX idx_idempotency_key_expires_at ON idempotency_key(expires_at);
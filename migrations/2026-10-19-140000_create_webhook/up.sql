@@ -0,0 +1,50 @@
+-- Endpoints an organisation receives events on, payloads are signed with the secret
+CREATE TABLE webhook_endpoint
+(
+    id              UUID PRIMARY KEY,
+    organisation_id UUID      NOT NULL,
+    url             TEXT      NOT NULL,
+    secret          TEXT      NOT NULL,
+    event_types     TEXT[]    NOT NULL,
+    is_enabled      BOOLEAN   NOT NULL DEFAULT TRUE,
+    created_at      TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated_at      TIMESTAMP NOT NULL DEFAULT NOW(),
+    CONSTRAINT fk_webhook_endpoint_organisation_id FOREIGN KEY (organisation_id) REFERENCES organisation (id) ON DELETE CASCADE
+);
+CREATE INDEX idx_webhook_endpoint_organisation_id ON webhook_endpoint(organisation_id);
+
+-- One event for one endpoint. Pending deliveries are picked up once next_attempt_at has passed,
+-- a delivery that keeps failing ends up dead
+CREATE TABLE webhook_delivery
+(
+    id                  UUID PRIMARY KEY,
+    webhook_endpoint_id UUID      NOT NULL,
+    organisation_id     UUID      NOT NULL,
+    event_id            UUID      NOT NULL,
+    event_type          TEXT      NOT NULL,
+    payload             JSONB     NOT NULL,
+    status              TEXT      NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'delivered', 'dead')),
+    attempts            INTEGER   NOT NULL DEFAULT 0,
+    next_attempt_at     TIMESTAMP NOT NULL DEFAULT NOW(),
+    last_attempt_at     TIMESTAMP,
+    response_status     SMALLINT,
+    last_error          TEXT,
+    created_at          TIMESTAMP NOT NULL DEFAULT NOW(),
+    CONSTRAINT fk_webhook_delivery_webhook_endpoint_id FOREIGN KEY (webhook_endpoint_id) REFERENCES webhook_endpoint (id) ON DELETE CASCADE
+);
+CREATE INDEX idx_webhook_delivery_webhook_endpoint_id ON webhook_delivery(webhook_endpoint_id, created_at);
+CREATE INDEX idx_webhook_delivery_next_attempt_at ON webhook_delivery(next_attempt_at) WHERE status = 'pending';
+
+-- Without app.organisation_id the policies match no rows, the delivery worker and the outbox
+-- subscriber work across organisations and connect as the BYPASSRLS role
+ALTER TABLE webhook_endpoint ENABLE ROW LEVEL SECURITY;
+ALTER TABLE webhook_endpoint FORCE ROW LEVEL SECURITY;
+CREATE POLICY tenant_isolation ON webhook_endpoint
+    USING (organisation_id = app_current_organisation_id())
+    WITH CHECK (organisation_id = app_current_organisation_id());
+
+ALTER TABLE webhook_delivery ENABLE ROW LEVEL SECURITY;
+ALTER TABLE webhook_delivery FORCE ROW LEVEL SECURITY;
+CREATE POLICY tenant_isolation ON webhook_delivery
+    USING (organisation_id = app_current_organisation_id())
+    WITH CHECK (organisation_id = app_current_organisation_id());
@@ -0,0 +1,24 @@
+-- Domain events written in the same transaction as the change they describe, the dispatcher hands
+-- them to the in-process subscribers. The sequence orders the events of an aggregate, it is taken
+-- while the change holds the aggregate's row lock. Events still failing after OUTBOX_MAX_ATTEMPTS
+-- are given up on, they no longer hold back the later events of their aggregate and are kept for
+-- inspection. Internal, only read by the dispatcher so no row level security
+CREATE TABLE outbox_event
+(
+    sequence        BIGSERIAL PRIMARY KEY,
+    id              UUID      NOT NULL UNIQUE,
+    aggregate_type  TEXT      NOT NULL,
+    aggregate_id    UUID      NOT NULL,
+    event_type      TEXT      NOT NULL,
+    payload         JSONB     NOT NULL,
+    organisation_id UUID,
+    occurred_at     TIMESTAMP NOT NULL DEFAULT NOW(),
+    attempts        INTEGER   NOT NULL DEFAULT 0,
+    next_attempt_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    last_error      TEXT,
+    dispatched_at   TIMESTAMP,
+    dead_at         TIMESTAMP
+);
+CREATE INDEX idx_outbox_event_pending ON outbox_event(aggregate_type, aggregate_id, sequence) WHERE dispatched_at IS NULL AND dead_at IS NULL;
+CREATE INDEX idx_outbox_event_dispatched_at ON outbox_event(dispatched_at) WHERE dispatched_at IS NOT NULL;
+CREATE INDEX idx_outbox_event_dead_at ON outbox_event(dead_at) WHERE dead_at IS NOT NULL;
@@ -0,0 +1,21 @@
+-- Background jobs, taken by the workers of every instance. A running job whose lock ran out is
+-- taken again, its worker died. unique_key keeps the instances from enqueueing a scheduled run twice.
+-- Internal, workers see every organisation so no row level security
+CREATE TABLE job
+(
+    id           UUID      NOT NULL PRIMARY KEY,
+    kind         TEXT      NOT NULL,
+    payload      JSONB     NOT NULL,
+    status       TEXT      NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'running', 'succeeded', 'failed')),
+    attempts     INTEGER   NOT NULL DEFAULT 0,
+    max_attempts INTEGER   NOT NULL,
+    run_at       TIMESTAMP NOT NULL DEFAULT NOW(),
+    locked_until TIMESTAMP,
+    last_error   TEXT,
+    unique_key   TEXT UNIQUE,
+    created_at   TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated_at   TIMESTAMP NOT NULL DEFAULT NOW(),
+    finished_at  TIMESTAMP
+);
+CREATE INDEX idx_job_due ON job(run_at) WHERE status IN ('pending', 'running');
+CREATE INDEX idx_job_finished_at ON job(status, finished_at) WHERE status IN ('succeeded', 'failed');
@@ -0,0 +1,8 @@
+-- Addresses no email is sent to anymore, they hard bounced or were suppressed by an admin.
+-- Stored lowercase. Internal, addresses aren't owned by an organisation so no row level security
+CREATE TABLE email_suppression
+(
+    email      TEXT      NOT NULL PRIMARY KEY,
+    reason     TEXT      NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,14 @@
+-- Who is in which channel, one row per WebSocket connection and channel, shared by the instances.
+-- seen_at is refreshed while the connection is alive, rows of connections that went away with their
+-- instance are purged once stale. Internal, instances see every organisation so no row level security
+CREATE TABLE presence
+(
+    connection_id   UUID      NOT NULL,
+    channel         TEXT      NOT NULL,
+    organisation_id UUID      NOT NULL,
+    user_id         UUID      NOT NULL,
+    seen_at         TIMESTAMP NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (connection_id, channel)
+);
+CREATE INDEX idx_presence_channel ON presence(organisation_id, channel);
+CREATE INDEX idx_presence_seen_at ON presence(seen_at);
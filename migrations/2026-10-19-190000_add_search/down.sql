@@ -0,0 +1,8 @@
+DROP INDEX idx_application_search_trgm;
+DROP INDEX idx_application_search_vector;
+ALTER TABLE application DROP COLUMN search_vector;
+DROP INDEX idx_swift_user_search_trgm;
+DROP INDEX idx_swift_user_search_vector;
+ALTER TABLE swift_user DROP COLUMN search_vector;
+DROP FUNCTION prefix_tsquery(TEXT);
+DROP EXTENSION IF EXISTS pg_trgm;
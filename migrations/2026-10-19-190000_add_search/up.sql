@@ -0,0 +1,25 @@
+-- Full text search over users and applications, with trigram matching for typos and partial words.
+-- The 'simple' configuration doesn't stem, names and emails aren't in any one language
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+-- Every word of the search as a prefix, so `acm corp` finds "Acme Corporation" while typing
+CREATE FUNCTION prefix_tsquery(search TEXT) RETURNS tsquery
+    LANGUAGE SQL IMMUTABLE PARALLEL SAFE AS
+$$
+SELECT to_tsquery('simple', coalesce(string_agg(quote_literal(lexeme) || ':*', ' & '), ''))
+FROM unnest(tsvector_to_array(to_tsvector('simple', search))) AS lexeme
+$$;
+
+ALTER TABLE swift_user ADD COLUMN search_vector tsvector NOT NULL GENERATED ALWAYS AS (
+    setweight(to_tsvector('simple', first_name || ' ' || coalesce(last_name, '')), 'A') ||
+    setweight(to_tsvector('simple', email), 'B')
+) STORED;
+CREATE INDEX idx_swift_user_search_vector ON swift_user USING GIN (search_vector);
+CREATE INDEX idx_swift_user_search_trgm ON swift_user USING GIN ((first_name || ' ' || coalesce(last_name, '') || ' ' || email) gin_trgm_ops);
+
+ALTER TABLE application ADD COLUMN search_vector tsvector NOT NULL GENERATED ALWAYS AS (
+    setweight(to_tsvector('simple', name), 'A') ||
+    setweight(to_tsvector('simple', coalesce(description, '')), 'B')
+) STORED;
+CREATE INDEX idx_application_search_vector ON application USING GIN (search_vector);
+CREATE INDEX idx_application_search_trgm ON application USING GIN ((name || ' ' || coalesce(description, '')) gin_trgm_ops);
@@ -0,0 +1,45 @@
+use clap::Subcommand;
+use serde::Serialize;
+use crate::cli::CliResult;
+use crate::cli::output::{self, OutputFormat, Render};
+use crate::common::security::password;
+use crate::config::app_env::JWT_EXP;
+
+#[derive(Subcommand)]
+pub enum KeysCommand {
+    /// Print a new JWT secret and the steps to roll it out without logging everyone out.
+    /// Nothing is changed, the secrets live in the environment of every replica
+    Generate,
+}
+
+#[derive(Serialize)]
+pub struct KeyRotation {
+    pub jwt_secret: String,
+    pub steps: Vec<String>,
+}
+
+impl Render for KeyRotation {
+    fn human(&self) -> String {
+        let steps: Vec<String> = self.steps.iter()
+            .enumerate()
+            .map(|(index, step)| format!("{}. {}", index + 1, step))
+            .collect();
+        format!("New JWT secret: {}\n\n{}", self.jwt_secret, steps.join("\n"))
+    }
+}
+
+pub fn execute(command: KeysCommand, output: OutputFormat) -> CliResult {
+    match command {
+        KeysCommand::Generate => {
+            output::print(output, &KeyRotation {
+                jwt_secret: password::generate_secret(48),
+                steps: vec![
+                    "Set JWT_PREVIOUS_SECRET to the current JWT_SECRET".to_string(),
+                    "Set JWT_SECRET to the new secret and restart every replica".to_string(),
+                    format!("Remove JWT_PREVIOUS_SECRET once tokens signed with the old secret expired ({} hours)", JWT_EXP.num_hours()),
+                ],
+            });
+        },
+    }
+    Ok(())
+}
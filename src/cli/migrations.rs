@@ -0,0 +1,39 @@
+use clap::Subcommand;
+use crate::cli::CliResult;
+use crate::cli::output::{self, Message, OutputFormat, Render};
+use crate::config::migration_config::{self, MigrationStatus};
+
+#[derive(Subcommand)]
+pub enum MigrationsCommand {
+    /// List embedded migrations and whether they are applied
+    Status,
+    /// Apply pending migrations
+    Run,
+}
+
+impl Render for Vec<MigrationStatus> {
+    fn human(&self) -> String {
+        self.iter()
+            .map(|migration| {
+                let state = if migration.applied { "applied" } else { "pending" };
+                format!("{:<8} {:<15} {}", state, migration.version, migration.name)
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+pub async fn execute(command: MigrationsCommand, output: OutputFormat) -> CliResult {
+    match command {
+        MigrationsCommand::Status => output::print(output, &migration_config::status().await?),
+        MigrationsCommand::Run => {
+            let applied = migration_config::run_pending().await?;
+            let message = match applied.is_empty() {
+                true => "No pending migrations".to_string(),
+                false => format!("Applied {}", applied.join(", ")),
+            };
+            output::print(output, &Message { message });
+        },
+    }
+    Ok(())
+}
@@ -0,0 +1,136 @@
+use std::error::Error;
+use clap::{Parser, Subcommand};
+use crate::cli::output::OutputFormat;
+use crate::config::diesel_config::{self, DbPools};
+use crate::domains::applications::repository::ApplicationRepository;
+use crate::domains::applications::services::ApplicationService;
+use crate::domains::organisations::repository::OrganisationRepository;
+use crate::domains::organisations::services::OrganisationService;
+use crate::domains::jobs::repository::JobRepository;
+use crate::domains::outbox::repository::OutboxRepository;
+use crate::domains::users::repository::UserRepository;
+use crate::domains::users::services::UserService;
+
+pub mod keys;
+pub mod migrations;
+pub mod organisations;
+pub mod output;
+pub mod seed;
+pub mod users;
+
+pub type CliResult = Result<(), Box<dyn Error + Send + Sync>>;
+
+#[derive(Parser)]
+#[command(version, about = "Swift API server and operational commands")]
+pub struct Cli {
+    /// Output format of operational commands
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Human)]
+    pub output: OutputFormat,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Run the API server (default)
+    Serve,
+    /// Inspect or apply the database migrations embedded in this build
+    Migrations {
+        #[command(subcommand)]
+        command: migrations::MigrationsCommand,
+    },
+    /// Manage users
+    Users {
+        #[command(subcommand)]
+        command: users::UsersCommand,
+    },
+    /// Manage organisations
+    Organisations {
+        #[command(subcommand)]
+        command: organisations::OrganisationsCommand,
+    },
+    /// Manage JWT signing keys
+    Keys {
+        #[command(subcommand)]
+        command: keys::KeysCommand,
+    },
+    /// Create a demo user, organisation and applications
+    Seed(seed::SeedArgs),
+}
+
+/*
+    Services wired against DATABASE_URL, the same way the server builds them
+*/
+pub struct Services {
+    pub user_service: UserService,
+    pub organisation_service: OrganisationService,
+    pub application_service: ApplicationService,
+}
+
+impl Services {
+    pub fn new(db_pools: &DbPools) -> Self {
+        let user_repository = UserRepository::new(db_pools.clone());
+        // Events and jobs are only written here, the server dispatches and runs them
+        let outbox_repository = OutboxRepository::new(db_pools.clone());
+        let job_repository = JobRepository::new(db_pools.clone());
+        Services {
+            user_service: UserService::new(user_repository.clone(), outbox_repository.clone(), job_repository.clone()),
+            organisation_service: OrganisationService::new(OrganisationRepository::new(db_pools.clone()), user_repository, outbox_repository.clone(), job_repository),
+            application_service: ApplicationService::new(ApplicationRepository::new(db_pools.clone()), outbox_repository),
+        }
+    }
+}
+
+pub async fn execute(command: Command, output: OutputFormat) -> CliResult {
+    match command {
+        Command::Serve => unreachable!("serve is handled by main"),
+        Command::Migrations { command } => migrations::execute(command, output).await,
+        Command::Keys { command } => keys::execute(command, output),
+        command => {
+            let db_pools = diesel_config::establish_connection().await?;
+            let services = Services::new(&db_pools);
+
+            let result = match command {
+                Command::Users { command } => users::execute(command, &services, output).await,
+                Command::Organisations { command } => organisations::execute(command, &services, output).await,
+                Command::Seed(args) => seed::execute(args, &services, output).await,
+                _ => unreachable!(),
+            };
+
+            drop(services);
+            diesel_config::release(db_pools);
+            result
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Cli, clap::Error> {
+        Cli::try_parse_from(std::iter::once("rust-axum-template").chain(args.iter().copied()))
+    }
+
+    #[test]
+    fn passwords_are_not_accepted_as_arguments() {
+        assert!(parse(&["users", "create-admin", "--email", "admin@example.com"]).is_ok());
+        assert!(parse(&["users", "create-admin", "--email", "admin@example.com", "--password", "secret"]).is_err());
+        assert!(parse(&["users", "reset-password", "--email", "admin@example.com", "--password", "secret"]).is_err());
+        assert!(parse(&["seed", "--password", "secret"]).is_err());
+    }
+
+    #[test]
+    fn keys_generate_only_prints_a_secret() {
+        assert!(matches!(parse(&["keys", "generate"]).unwrap().command, Some(Command::Keys { command: keys::KeysCommand::Generate })));
+        assert!(parse(&["keys", "rotate"]).is_err());
+    }
+
+    #[test]
+    fn serve_is_the_default() {
+        let cli = parse(&["--output", "json"]).unwrap();
+        assert!(cli.command.is_none());
+        assert!(matches!(cli.output, OutputFormat::Json));
+    }
+}
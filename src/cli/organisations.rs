@@ -0,0 +1,83 @@
+use chrono::NaiveDateTime;
+use clap::Subcommand;
+use serde::Serialize;
+use uuid::Uuid;
+use crate::cli::{CliResult, Services};
+use crate::cli::output::{self, Message, OutputFormat, Render};
+use crate::domains::organisations::db_models::{Organisation, PLANS};
+
+#[derive(Subcommand)]
+pub enum OrganisationsCommand {
+    /// List organisations
+    List {
+        #[arg(long)]
+        include_archived: bool,
+    },
+    /// Archive an organisation
+    Archive {
+        id: Uuid,
+    },
+    /// Assign a plan to an organisation
+    SetPlan {
+        id: Uuid,
+        #[arg(value_parser = PLANS)]
+        plan: String,
+    },
+}
+
+#[derive(Serialize)]
+pub struct OrganisationRow {
+    pub id: Uuid,
+    pub name: String,
+    pub owner: Uuid,
+    pub plan: String,
+    pub is_archived: bool,
+    pub created_at: NaiveDateTime,
+}
+
+impl From<Organisation> for OrganisationRow {
+    fn from(organisation: Organisation) -> Self {
+        OrganisationRow {
+            id: organisation.id,
+            name: organisation.name,
+            owner: organisation.owner,
+            plan: organisation.plan,
+            is_archived: organisation.is_archived,
+            created_at: organisation.created_at,
+        }
+    }
+}
+
+impl Render for Vec<OrganisationRow> {
+    fn human(&self) -> String {
+        let mut lines = vec![format!("{:<36}  {:<10}  {:<8}  {}", "ID", "PLAN", "ARCHIVED", "NAME")];
+        lines.extend(self.iter().map(|organisation| {
+            format!("{:<36}  {:<10}  {:<8}  {}", organisation.id, organisation.plan, organisation.is_archived, organisation.name)
+        }));
+        lines.join("\n")
+    }
+}
+
+pub async fn execute(command: OrganisationsCommand, services: &Services, output: OutputFormat) -> CliResult {
+    match command {
+        OrganisationsCommand::List { include_archived } => {
+            let organisations: Vec<OrganisationRow> = services.organisation_service
+                .find_all_unscoped(include_archived)
+                .await?
+                .into_iter()
+                .map(Organisation::into)
+                .collect();
+
+            output::print(output, &organisations);
+        },
+        OrganisationsCommand::Archive { id } => {
+            services.organisation_service.archive_by_id(&id).await?;
+            output::print(output, &Message { message: format!("Organisation {} archived", id) });
+        },
+        OrganisationsCommand::SetPlan { id, plan } => {
+            services.organisation_service.update_plan_by_id(&id, &plan).await?;
+            output::print(output, &Message { message: format!("Organisation {} moved to the {} plan", id, plan) });
+        },
+    }
+    Ok(())
+}
@@ -0,0 +1,55 @@
+use std::env;
+use std::io::{self, BufRead, IsTerminal};
+use clap::ValueEnum;
+use serde::Serialize;
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum OutputFormat {
+    Human,
+    Json,
+}
+
+/*
+    Command results are printed as a human readable line (or table) or as JSON for scripts
+*/
+pub trait Render: Serialize {
+    fn human(&self) -> String;
+}
+
+pub fn print<T: Render>(output: OutputFormat, value: &T) {
+    match output {
+        OutputFormat::Human => println!("{}", value.human()),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(value).expect("Unable to serialize output")),
+    }
+}
+
+#[derive(Serialize)]
+pub struct Message {
+    pub message: String,
+}
+
+impl Render for Message {
+    fn human(&self) -> String {
+        self.message.clone()
+    }
+}
+
+// Environment variable scripts pass a password in, argv would leave it in shell history and `ps`
+pub const PASSWORD_ENV: &str = "CLI_PASSWORD";
+
+/*
+    Reads a password from CLI_PASSWORD, otherwise prompts for it without echo on a terminal
+    or reads the first line of piped stdin
+*/
+pub fn read_password() -> io::Result<String> {
+    if let Ok(password) = env::var(PASSWORD_ENV) {
+        return Ok(password);
+    }
+
+    if io::stdin().is_terminal() {
+        return rpassword::prompt_password("Password: ");
+    }
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line)?;
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
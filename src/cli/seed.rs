@@ -0,0 +1,139 @@
+use std::env;
+use std::error::Error;
+use clap::Args;
+use serde::Serialize;
+use uuid::Uuid;
+use crate::cli::{CliResult, Services};
+use crate::cli::output::{self, OutputFormat, Render};
+use crate::common::errors::application_error::ApplicationError;
+use crate::common::errors::global_api_error::ApiError;
+use crate::common::models::models::Identity;
+use crate::common::repository;
+use crate::common::security::password;
+use crate::domains::applications::api_models::ApplicationCreateRequest;
+use crate::domains::organisations::api_models::OrganisationCreateRequest;
+use crate::domains::users::api_models::UserCreateRequest;
+
+#[derive(Args)]
+pub struct SeedArgs {
+    #[arg(long, default_value = "demo@swiftapi.com")]
+    email: String,
+}
+
+#[derive(Serialize)]
+pub struct SeedResult {
+    pub user_id: Uuid,
+    pub email: String,
+    // Only set when it was generated, a password from CLI_PASSWORD is already known
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub generated_password: Option<String>,
+    pub organisation_id: Uuid,
+    pub application_ids: Vec<Uuid>,
+}
+
+impl Render for SeedResult {
+    fn human(&self) -> String {
+        let seeded = format!("Seeded user {} ({}), organisation {} and {} applications",
+                             self.email, self.user_id, self.organisation_id, self.application_ids.len());
+        match &self.generated_password {
+            Some(password) => format!("{}\nPassword: {}", seeded, password),
+            None => seeded,
+        }
+    }
+}
+
+pub async fn execute(args: SeedArgs, services: &Services, output: OutputFormat) -> CliResult {
+    let password = match env::var(output::PASSWORD_ENV) {
+        Ok(_) => Some(output::read_password()?),
+        Err(_) => None,
+    };
+    let result = seed(args.email, password, services).await?;
+
+    output::print(output, &result);
+    Ok(())
+}
+
+/*
+    Seeds the demo data for a new user, with a generated password when none is given.
+    Never a well known default, demo data ends up on shared environments
+*/
+async fn seed(email: String, password: Option<String>, services: &Services) -> Result<SeedResult, Box<dyn Error + Send + Sync>> {
+    match services.user_service.find_by_email(&email).await {
+        Ok(_) => return Err(format!("{} already exists, demo data is already seeded", email).into()),
+        Err(ApiError::ApplicationError(ApplicationError::NotFound)) => {},
+        Err(e) => return Err(e.into()),
+    }
+
+    let generated_password = password.is_none().then(|| password::generate_secret(12));
+    let password = password.or_else(|| generated_password.clone()).unwrap_or_default();
+
+    let user = services.user_service.create(UserCreateRequest {
+        email,
+        password: Some(password::hash_password(&password)?),
+        first_name: "Demo".to_string(),
+        last_name: Some("User".to_string()),
+        locale: None,
+    }).await?;
+
+    let identity = Identity { user_id: user.id, organisation_ids: vec![], locale: None };
+    let organisation = services.organisation_service.create_by_user(
+        OrganisationCreateRequest { name: "Demo Organisation".to_string() }.into_with_owner(user.id),
+        identity,
+    ).await?;
+
+    let mut application_ids = vec![];
+    for (name, description) in [("Demo Web", "Browser client"), ("Demo Mobile", "iOS and Android client")] {
+        let application = repository::tenant_scope(organisation.id, services.application_service.create(ApplicationCreateRequest {
+            name: name.to_string(),
+            description: Some(description.to_string()),
+        }.into_with_organisation(organisation.id))).await?;
+        application_ids.push(application.id);
+    }
+
+    Ok(SeedResult {
+        user_id: user.id,
+        email: user.email,
+        generated_password,
+        organisation_id: organisation.id,
+        application_ids,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use argon2::{Argon2, PasswordHash, PasswordVerifier};
+    use crate::common::test_database::TestDatabase;
+    use super::*;
+
+    async fn verifies(services: &Services, email: &str, password: &str) -> bool {
+        let user = services.user_service.find_by_email(&email.to_string()).await.unwrap();
+        let hash = user.password.unwrap();
+        Argon2::default().verify_password(password.as_bytes(), &PasswordHash::new(&hash).unwrap()).is_ok()
+    }
+
+    #[tokio::test]
+    async fn generates_a_password_unless_one_is_given() {
+        let Some(database) = TestDatabase::create().await else { return };
+        let services = Services::new(&database.pools);
+
+        let generated = seed("generated@example.com".to_string(), None, &services).await.unwrap();
+        let password = generated.generated_password.expect("a generated password");
+        assert_eq!(password.len(), 24);
+        assert!(verifies(&services, "generated@example.com", &password).await);
+        assert_eq!(generated.application_ids.len(), 2);
+
+        let given = seed("given@example.com".to_string(), Some("correct horse".to_string()), &services).await.unwrap();
+        assert!(given.generated_password.is_none());
+        assert!(verifies(&services, "given@example.com", "correct horse").await);
+    }
+
+    #[tokio::test]
+    async fn refuses_to_seed_twice() {
+        let Some(database) = TestDatabase::create().await else { return };
+        let services = Services::new(&database.pools);
+
+        seed("twice@example.com".to_string(), None, &services).await.unwrap();
+        let result = seed("twice@example.com".to_string(), None, &services).await;
+        assert!(result.is_err_and(|error| error.to_string().contains("already seeded")));
+    }
+}
@@ -0,0 +1,62 @@
+use clap::Subcommand;
+use crate::cli::{CliResult, Services};
+use crate::cli::output::{self, Message, OutputFormat, Render};
+use crate::common::security::password;
+use crate::domains::users::api_models::{UserCreateRequest, UserResponse};
+use crate::domains::users::db_models::SwiftUser;
+
+#[derive(Subcommand)]
+pub enum UsersCommand {
+    /// Create a super admin user, the password is taken from CLI_PASSWORD or prompted for
+    CreateAdmin {
+        #[arg(long)]
+        email: String,
+        #[arg(long, default_value = "Admin")]
+        first_name: String,
+        #[arg(long)]
+        last_name: Option<String>,
+    },
+    /// Set a new password (from CLI_PASSWORD or prompted for), which also revokes the user's sessions
+    ResetPassword {
+        #[arg(long)]
+        email: String,
+    },
+    /// Invalidate every access token issued to the user so far
+    RevokeSessions {
+        #[arg(long)]
+        email: String,
+    },
+}
+
+impl Render for UserResponse {
+    fn human(&self) -> String {
+        format!("{}  {}  {}", self.id, self.email, self.first_name)
+    }
+}
+
+pub async fn execute(command: UsersCommand, services: &Services, output: OutputFormat) -> CliResult {
+    match command {
+        UsersCommand::CreateAdmin { email, first_name, last_name } => {
+            let password_hash = password::hash_password(&output::read_password()?)?;
+            let user: SwiftUser = services.user_service.create_super_admin(UserCreateRequest {
+                email,
+                password: Some(password_hash),
+                first_name,
+                last_name,
+                locale: None,
+            }).await?;
+
+            output::print::<UserResponse>(output, &user.into());
+        },
+        UsersCommand::ResetPassword { email } => {
+            let password_hash = password::hash_password(&output::read_password()?)?;
+            services.user_service.reset_password(&email, &password_hash).await?;
+            output::print(output, &Message { message: format!("Password reset and sessions revoked for {}", email) });
+        },
+        UsersCommand::RevokeSessions { email } => {
+            services.user_service.revoke_sessions(&email).await?;
+            output::print(output, &Message { message: format!("Sessions revoked for {}", email) });
+        },
+    }
+    Ok(())
+}
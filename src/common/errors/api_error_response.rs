@@ -2,23 +2,56 @@ use axum::body::Body;
 use axum::response::{IntoResponse, Response};
 use http::{StatusCode};
 use serde::Serialize;
+use utoipa::{ToResponse, ToSchema};
+use crate::common::i18n::catalogue;
+use crate::common::utils::tracing_utils;
+use crate::config::app_env::SUPPORT_CONTACT;
 
-#[derive(Debug, Serialize)]
+/*
+    RFC 7807 problem details, extended with a stable `code`, field `errors` and `support_contact`.
+    `detail` is translated from the message catalogue into the request locale unless set explicitly
+*/
+#[derive(Debug, Serialize, ToSchema, ToResponse)]
+#[response(description = "Problem details, `code` identifies the error", content_type = "application/problem+json")]
 pub struct ErrorResponse {
     #[serde(skip_serializing)]
     pub status_code: StatusCode,
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    pub title: String,
+    pub status: u16,
+    pub code: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instance: Option<String>,
     pub support_contact: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub errors: Option<Vec<ErrorMessage>>
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct ErrorMessage {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub field: Option<String>,
+    pub code: String,
     pub message: String
 }
 
+impl ErrorMessage {
+    pub fn new(field: Option<&str>, code: &str) -> Self {
+        Self::with_args(field, code, &[])
+    }
+
+    pub fn with_args(field: Option<&str>, code: &str, args: &[(&str, &str)]) -> Self {
+        Self {
+            field: field.map(str::to_string),
+            code: code.to_string(),
+            message: catalogue::translate(code, args),
+        }
+    }
+}
+
 impl IntoResponse for ErrorResponse {
     fn into_response(self) -> Response<Body> {
         let json_body = serde_json::to_string(&self)
@@ -26,7 +59,7 @@ impl IntoResponse for ErrorResponse {
 
         Response::builder()
             .status(self.status_code)
-            .header(http::header::CONTENT_TYPE, "application/json")
+            .header(http::header::CONTENT_TYPE, "application/problem+json")
             .body(Body::from(json_body))
             .expect("Unable to build response from ErrorResponse")
     }
@@ -35,16 +68,26 @@ impl IntoResponse for ErrorResponse {
 // The builder for `ErrorResponse`
 pub struct ErrorResponseBuilder {
     status_code: StatusCode,
-    support_contact: String,
+    code: String,
+    args: Vec<(String, String)>,
+    detail: Option<String>,
     errors: Option<Vec<ErrorMessage>>,
 }
 
 impl ErrorResponse {
 
-    pub fn build(status_code: StatusCode, message: String) -> Self {
-        return ErrorResponseBuilder::default()
+    pub fn build(status_code: StatusCode, code: &str) -> Self {
+        ErrorResponseBuilder::default()
             .status_code(status_code)
-            .error(ErrorMessage { field: None, message })
+            .code(code)
+            .build()
+    }
+
+    pub fn build_with_args(status_code: StatusCode, code: &str, args: &[(&str, &str)]) -> Self {
+        ErrorResponseBuilder::default()
+            .status_code(status_code)
+            .code(code)
+            .args(args)
             .build()
     }
 
@@ -58,7 +101,9 @@ impl ErrorResponseBuilder {
     pub fn default() -> Self {
         Self {
             status_code: StatusCode::INTERNAL_SERVER_ERROR,
-            support_contact: "support@swiftapi.com".to_string(),
+            code: crate::common::errors::error_code::INTERNAL_ERROR.to_string(),
+            args: vec![],
+            detail: None,
             errors: None,
         }
     }
@@ -68,8 +113,18 @@ impl ErrorResponseBuilder {
         self
     }
 
-    pub fn error(mut self, error: ErrorMessage) -> Self {
-        self.errors = Some(vec![error]);
+    pub fn code(mut self, code: &str) -> Self {
+        self.code = code.to_string();
+        self
+    }
+
+    pub fn args(mut self, args: &[(&str, &str)]) -> Self {
+        self.args = args.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect();
+        self
+    }
+
+    pub fn detail(mut self, detail: String) -> Self {
+        self.detail = Some(detail);
         self
     }
 
@@ -79,10 +134,21 @@ impl ErrorResponseBuilder {
     }
 
     pub fn build(self) -> ErrorResponse {
+        let detail = self.detail.unwrap_or_else(|| {
+            let args: Vec<(&str, &str)> = self.args.iter().map(|(name, value)| (name.as_str(), value.as_str())).collect();
+            catalogue::translate(&self.code, &args)
+        });
+
         ErrorResponse {
             status_code: self.status_code,
-            support_contact: self.support_contact,
+            problem_type: "about:blank".to_string(),
+            title: self.status_code.canonical_reason().unwrap_or("Unknown Error").to_string(),
+            status: self.status_code.as_u16(),
+            code: self.code,
+            detail: Some(detail),
+            instance: tracing_utils::current_tracing_id(),
+            support_contact: SUPPORT_CONTACT.clone(),
             errors: self.errors,
         }
     }
-}
\ No newline at end of file
+}
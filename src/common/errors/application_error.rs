@@ -1,65 +1,148 @@
+use std::fmt;
 use axum::response::{IntoResponse, Response};
-use diesel::result::DatabaseErrorKind;
-use http::StatusCode;
+use bb8::RunError;
+use http::header::RETRY_AFTER;
+use http::{HeaderValue, StatusCode};
 use serde::Serialize;
-use tracing::error;
+use tracing::{error, warn};
 use crate::common::errors::db_error::DbError;
-use crate::common::errors::api_error_response::{ErrorResponse};
-use diesel::result::{Error::DatabaseError};
+use crate::common::errors::api_error_response::{ErrorMessage, ErrorResponse};
+use crate::common::errors::error_code;
+use crate::common::i18n::catalogue;
 
 #[derive(Debug, Serialize)]
 pub enum ApplicationError {
-    ConflictError,
+    // Carries the error code of the conflicting constraint
+    ConflictError(&'static str),
+    // A referenced resource does not exist, carries the error code
+    InvalidReference(&'static str),
+    // Column left empty that the database requires
+    MissingField(Option<String>),
+    ConstraintViolation,
+    ConcurrentModification,
+    // If-Match didn't match the current version of the resource
+    PreconditionFailed,
     NotFound,
     InternalServerError,
     LoginError,
     Unauthorized,
     Forbidden,
-    LimitReached(String),
+    // Carries the message code explaining which limit was hit
+    LimitReached(&'static str),
+    // No database connection could be checked out in time, the client should retry
+    ServiceUnavailable,
+    // The user has as many long lived connections open as allowed
+    TooManyConnections,
 }
 
+const SERVICE_UNAVAILABLE_RETRY_AFTER_SECS: u64 = 2;
+
 impl IntoResponse for ApplicationError {
     fn into_response(self) -> Response {
         match self {
-            Self::ConflictError => StatusCode::CONFLICT.into_response(),
-            Self::NotFound => StatusCode::NOT_FOUND.into_response(),
-            Self::InternalServerError => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
-            Self::LoginError => StatusCode::BAD_REQUEST.into_response(),
-            Self::Unauthorized => StatusCode::UNAUTHORIZED.into_response(),
-            Self::Forbidden => StatusCode::FORBIDDEN.into_response(),
-            Self::LimitReached(message) => ErrorResponse::build(StatusCode::FORBIDDEN, message).into_response(),
+            Self::ConflictError(code) => ErrorResponse::build(StatusCode::CONFLICT, code).into_response(),
+            Self::InvalidReference(code) => ErrorResponse::build(StatusCode::UNPROCESSABLE_ENTITY, code).into_response(),
+            Self::MissingField(column) => ErrorResponse::builder()
+                .status_code(StatusCode::BAD_REQUEST)
+                .code(error_code::REQUEST_VALIDATION_FAILED)
+                .errors(vec![ErrorMessage::new(column.as_deref(), error_code::VALIDATION_FIELD_REQUIRED)])
+                .build()
+                .into_response(),
+            Self::ConstraintViolation => ErrorResponse::build(StatusCode::UNPROCESSABLE_ENTITY, error_code::REQUEST_CONSTRAINT_VIOLATED).into_response(),
+            Self::ConcurrentModification => ErrorResponse::build(StatusCode::CONFLICT, error_code::RESOURCE_CONCURRENT_MODIFICATION).into_response(),
+            Self::PreconditionFailed => ErrorResponse::build(StatusCode::PRECONDITION_FAILED, error_code::RESOURCE_PRECONDITION_FAILED).into_response(),
+            Self::NotFound => ErrorResponse::build(StatusCode::NOT_FOUND, error_code::RESOURCE_NOT_FOUND).into_response(),
+            Self::InternalServerError => ErrorResponse::build(StatusCode::INTERNAL_SERVER_ERROR, error_code::INTERNAL_ERROR).into_response(),
+            Self::LoginError => ErrorResponse::build(StatusCode::BAD_REQUEST, error_code::AUTH_INVALID_CREDENTIALS).into_response(),
+            Self::Unauthorized => ErrorResponse::build(StatusCode::UNAUTHORIZED, error_code::AUTH_UNAUTHORIZED).into_response(),
+            Self::Forbidden => ErrorResponse::build(StatusCode::FORBIDDEN, error_code::AUTH_FORBIDDEN).into_response(),
+            Self::LimitReached(message_code) => ErrorResponse::builder()
+                .status_code(StatusCode::FORBIDDEN)
+                .code(error_code::PLAN_LIMIT_REACHED)
+                .detail(catalogue::translate(message_code, &[]))
+                .build()
+                .into_response(),
+            Self::ServiceUnavailable => {
+                let mut response = ErrorResponse::build(StatusCode::SERVICE_UNAVAILABLE, error_code::SERVICE_UNAVAILABLE).into_response();
+                response.headers_mut().insert(RETRY_AFTER, HeaderValue::from(SERVICE_UNAVAILABLE_RETRY_AFTER_SECS));
+                response
+            },
+            Self::TooManyConnections => ErrorResponse::build(StatusCode::TOO_MANY_REQUESTS, error_code::CONNECTION_LIMIT_REACHED).into_response(),
         }
     }
 }
 
+impl fmt::Display for ApplicationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::ConflictError(code) => write!(f, "Conflict: {}", code),
+            Self::InvalidReference(code) => write!(f, "Invalid reference: {}", code),
+            Self::MissingField(column) => write!(f, "Missing field: {:?}", column),
+            Self::ConstraintViolation => write!(f, "Constraint violated"),
+            Self::ConcurrentModification => write!(f, "Concurrent modification"),
+            Self::PreconditionFailed => write!(f, "Precondition failed"),
+            Self::NotFound => write!(f, "Not found"),
+            Self::InternalServerError => write!(f, "Internal server error"),
+            Self::LoginError => write!(f, "Invalid credentials"),
+            Self::Unauthorized => write!(f, "Unauthorized"),
+            Self::Forbidden => write!(f, "Forbidden"),
+            Self::LimitReached(code) => write!(f, "Limit reached: {}", code),
+            Self::ServiceUnavailable => write!(f, "Service unavailable"),
+            Self::TooManyConnections => write!(f, "Too many connections"),
+        }
+    }
+}
+
+impl std::error::Error for ApplicationError {}
+
 impl From<DbError> for ApplicationError {
     fn from(error: DbError) -> Self {
+        let code = constraint_code(error.constraint());
         match error {
+            DbError::PoolError(RunError::TimedOut) => {
+                warn!("Timed out waiting for a database connection");
+                metrics::counter!("db_pool_timeouts_total").increment(1);
+                Self::ServiceUnavailable
+            },
             DbError::PoolError(e) => {
                 error!("Error with connection pool {:?}", e);
                 Self::InternalServerError
             },
+            DbError::UniqueViolation(_) => Self::ConflictError(code.unwrap_or(error_code::RESOURCE_CONFLICT)),
+            DbError::ForeignKeyViolation(_) => Self::InvalidReference(code.unwrap_or(error_code::RESOURCE_REFERENCE_INVALID)),
+            DbError::NotNullViolation(column) => Self::MissingField(column),
+            DbError::CheckViolation(constraint) => {
+                warn!("Check constraint violated: {:?}", constraint);
+                Self::ConstraintViolation
+            },
+            DbError::NotFound => Self::NotFound,
+            DbError::SerializationFailure | DbError::Deadlock => Self::ConcurrentModification,
             DbError::DieselError(e) => {
-                match e {
-                    DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
-                        Self::ConflictError
-                    },
-                    // Handle other kinds of database errors here
-                    _ => {
-                        error!("Error with query {:?}", e);
-                        Self::InternalServerError
-                    },
-                }
+                error!("Error with query {:?}", e);
+                Self::InternalServerError
             },
         }
     }
 }
 
-// For DB Transactions errors
+/*
+    Error codes for constraints with a business meaning, other violations fall back to the generic code of their kind
+*/
+fn constraint_code(constraint: Option<&str>) -> Option<&'static str> {
+    match constraint? {
+        "swift_user_email_key" => Some(error_code::USER_EMAIL_TAKEN),
+        "swift_user_accessible_organisation_pkey" => Some(error_code::USER_ALREADY_MEMBER),
+        "fk_organisation_owner" | "fk_swift_user_cr_organisation_swift_user_id" => Some(error_code::USER_NOT_FOUND),
+        "fk_application_organisation" | "fk_swift_user_cr_organisation_organisation_id" => Some(error_code::ORGANISATION_NOT_FOUND),
+        "fk_swift_user_cr_organisation_role_id" => Some(error_code::ROLE_NOT_FOUND),
+        _ => None,
+    }
+}
+
+// For DB Transactions errors, classified like every other database error
 impl From<diesel::result::Error> for ApplicationError {
     fn from(e: diesel::result::Error) -> Self {
-        error!("Error with query {:?}", e);
-        Self::InternalServerError
+        Self::from(DbError::from(e))
     }
 }
 
@@ -68,4 +151,88 @@ impl From<headers::Error> for ApplicationError {
         error!("Error with query {:?}", e);
         Self::InternalServerError
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use diesel::sql_query;
+    use uuid::Uuid;
+    use crate::common::query::RunQueryDsl;
+    use diesel_async::scoped_futures::ScopedFutureExt;
+    use crate::common::repository::{self, BaseRepository, TransactionOptions};
+    use crate::common::test_database::TestDatabase;
+    use crate::domains::applications::db_models::Application;
+    use crate::domains::applications::repository::ApplicationRepository;
+    use crate::domains::users::db_models::SwiftUser;
+    use crate::domains::users::repository::UserRepository;
+    use super::*;
+
+    fn user(email: &str) -> SwiftUser {
+        SwiftUser {
+            id: Uuid::now_v7(),
+            email: email.to_string(),
+            password: None,
+            is_super_admin: false,
+            first_name: "Test".to_string(),
+            last_name: None,
+            created_at: Utc::now().naive_utc(),
+            updated_at: Utc::now().naive_utc(),
+            locale: None,
+            sessions_revoked_at: None,
+        }
+    }
+
+    #[test]
+    fn missing_rows_are_not_found() {
+        assert!(matches!(ApplicationError::from(diesel::result::Error::NotFound), ApplicationError::NotFound));
+        assert!(matches!(ApplicationError::from(diesel::result::Error::RollbackTransaction), ApplicationError::InternalServerError));
+    }
+
+    #[tokio::test]
+    async fn constraint_violations_map_to_their_error_codes() {
+        let Some(database) = TestDatabase::create().await else { return };
+        let users = UserRepository::new(database.pools.clone());
+        let applications = ApplicationRepository::new(database.pools.clone());
+
+        let mut conn = users.admin_conn().await.unwrap();
+        users.insert_with_conn(&mut conn, &user("taken@example.com")).await.unwrap();
+        let duplicate = users.insert_with_conn(&mut conn, &user("taken@example.com")).await.unwrap_err();
+        assert!(matches!(ApplicationError::from(duplicate), ApplicationError::ConflictError(error_code::USER_EMAIL_TAKEN)));
+        drop(conn);
+
+        let missing_organisation = Uuid::now_v7();
+        let applications = &applications;
+        let orphan = &Application {
+            id: Uuid::now_v7(),
+            organisation_id: missing_organisation,
+            name: "Orphan".to_string(),
+            description: None,
+            created_at: Utc::now().naive_utc(),
+            updated_at: Utc::now().naive_utc(),
+        };
+        let orphan = repository::tenant_scope(missing_organisation, applications.in_transaction(TransactionOptions::default(), |mut uow| async move {
+            applications.insert_with_conn(uow.conn(), orphan).await
+        }.scope_boxed())).await.unwrap_err();
+        assert!(matches!(ApplicationError::from(orphan), ApplicationError::InvalidReference(error_code::ORGANISATION_NOT_FOUND)));
+    }
+
+    #[tokio::test]
+    async fn serialization_failures_are_concurrent_modifications() {
+        let Some(database) = TestDatabase::create().await else { return };
+        let (mut first, mut second) = (database.admin().await, database.admin().await);
+        sql_query("CREATE TABLE counter (value INT)").execute(&mut first).await.unwrap();
+
+        // Both read what the other one writes, Postgres can only commit one of them
+        for conn in [&mut first, &mut second] {
+            sql_query("BEGIN ISOLATION LEVEL SERIALIZABLE").execute(&mut *conn).await.unwrap();
+            sql_query("SELECT count(*) FROM counter").execute(&mut *conn).await.unwrap();
+        }
+        sql_query("INSERT INTO counter VALUES (1)").execute(&mut first).await.unwrap();
+        sql_query("INSERT INTO counter VALUES (2)").execute(&mut second).await.unwrap();
+        sql_query("COMMIT").execute(&mut first).await.unwrap();
+        let error = sql_query("COMMIT").execute(&mut second).await.unwrap_err();
+
+        assert!(matches!(DbError::from(error), DbError::SerializationFailure));
+    }
+}
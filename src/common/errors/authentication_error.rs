@@ -1,20 +1,46 @@
+use std::fmt;
 use axum::response::{IntoResponse, Response};
 use http::StatusCode;
 use serde::Serialize;
+use crate::common::errors::api_error_response::ErrorResponse;
+use crate::common::errors::error_code;
 
 #[derive(Debug, Serialize)]
 pub enum AuthenticationError {
     InvalidToken,
     SerializationError,
     ExpiredToken,
+    RevokedToken,
+}
+
+impl AuthenticationError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::InvalidToken => error_code::AUTH_TOKEN_INVALID,
+            Self::SerializationError => error_code::AUTH_TOKEN_SERIALIZATION,
+            Self::ExpiredToken => error_code::AUTH_TOKEN_EXPIRED,
+            Self::RevokedToken => error_code::AUTH_TOKEN_REVOKED,
+        }
+    }
 }
 
 impl IntoResponse for AuthenticationError {
     fn into_response(self) -> Response {
+        let status_code = match self {
+            Self::SerializationError => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::InvalidToken | Self::ExpiredToken | Self::RevokedToken => StatusCode::UNAUTHORIZED,
+        };
+        ErrorResponse::build(status_code, self.code()).into_response()
+    }
+}
+
+impl fmt::Display for AuthenticationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Self::InvalidToken => StatusCode::UNAUTHORIZED.into_response(),
-            Self::SerializationError => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
-            Self::ExpiredToken => StatusCode::UNAUTHORIZED.into_response()
+            Self::InvalidToken => write!(f, "Invalid token"),
+            Self::SerializationError => write!(f, "Token serialization error"),
+            Self::ExpiredToken => write!(f, "Expired token"),
+            Self::RevokedToken => write!(f, "Revoked token"),
         }
     }
-}
\ No newline at end of file
+}
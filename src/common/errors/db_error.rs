@@ -1,14 +1,62 @@
+use std::fmt;
 use bb8::RunError;
+use diesel::result::{DatabaseErrorKind, Error::DatabaseError};
 use diesel_async::pooled_connection::PoolError;
+use tracing::debug;
 
 use diesel::result::Error as DieselError;
 
+/*
+    Database errors classified by kind. Constraint violations keep the name of the failed constraint
+    (or the column for not-null) so callers can map them to specific error codes
+*/
 #[derive(Debug)]
 pub enum DbError {
     PoolError(RunError<PoolError>),
+    UniqueViolation(Option<String>),
+    ForeignKeyViolation(Option<String>),
+    NotNullViolation(Option<String>),
+    CheckViolation(Option<String>),
+    // A query expecting exactly one row got none
+    NotFound,
+    SerializationFailure,
+    Deadlock,
     DieselError(DieselError),
 }
 
+impl DbError {
+    pub fn constraint(&self) -> Option<&str> {
+        match self {
+            DbError::UniqueViolation(constraint)
+            | DbError::ForeignKeyViolation(constraint)
+            | DbError::CheckViolation(constraint) => constraint.as_deref(),
+            _ => None,
+        }
+    }
+
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, DbError::SerializationFailure | DbError::Deadlock)
+    }
+}
+
+impl fmt::Display for DbError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DbError::PoolError(e) => write!(f, "Connection pool error: {}", e),
+            DbError::UniqueViolation(constraint) => write!(f, "Unique violation: {:?}", constraint),
+            DbError::ForeignKeyViolation(constraint) => write!(f, "Foreign key violation: {:?}", constraint),
+            DbError::NotNullViolation(column) => write!(f, "Not null violation: {:?}", column),
+            DbError::CheckViolation(constraint) => write!(f, "Check violation: {:?}", constraint),
+            DbError::NotFound => write!(f, "Record not found"),
+            DbError::SerializationFailure => write!(f, "Serialization failure"),
+            DbError::Deadlock => write!(f, "Deadlock detected"),
+            DbError::DieselError(e) => write!(f, "Query error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for DbError {}
+
 impl From<RunError<PoolError>> for DbError {
     fn from(error: RunError<PoolError>) -> Self {
         DbError::PoolError(error)
@@ -18,6 +66,65 @@ impl From<RunError<PoolError>> for DbError {
 // Implement the conversion from DieselError to AppError
 impl From<DieselError> for DbError {
     fn from(error: DieselError) -> Self {
-        DbError::DieselError(error)
+        let DatabaseError(kind, info) = &error else {
+            return match error {
+                DieselError::NotFound => DbError::NotFound,
+                error => DbError::DieselError(error),
+            };
+        };
+        debug!("Database error {:?}: {}", kind, info.message());
+
+        let constraint = info.constraint_name().map(str::to_string);
+        match kind {
+            DatabaseErrorKind::UniqueViolation => DbError::UniqueViolation(constraint),
+            DatabaseErrorKind::ForeignKeyViolation => DbError::ForeignKeyViolation(constraint),
+            DatabaseErrorKind::NotNullViolation => DbError::NotNullViolation(info.column_name().map(str::to_string)),
+            DatabaseErrorKind::CheckViolation => DbError::CheckViolation(constraint),
+            DatabaseErrorKind::SerializationFailure => DbError::SerializationFailure,
+            // diesel-async maps SQLSTATEs Diesel has no kind for (40P01 among them) to Unknown and drops the code,
+            // the message is all that is left. It is only English while the server's lc_messages is
+            DatabaseErrorKind::Unknown if info.message().starts_with("deadlock detected") => DbError::Deadlock,
+            _ => DbError::DieselError(error),
+        }
     }
-}
\ No newline at end of file
+}
+
+
+#[cfg(test)]
+mod tests {
+    use diesel::sql_query;
+    use crate::common::query::RunQueryDsl;
+    use crate::common::test_database::TestDatabase;
+    use super::*;
+
+    #[tokio::test]
+    async fn deadlocks_are_retryable() {
+        let Some(database) = TestDatabase::create().await else { return };
+        let (mut first, mut second) = (database.admin().await, database.admin().await);
+        sql_query("CREATE TABLE account (id INT PRIMARY KEY, balance INT)").execute(&mut first).await.unwrap();
+        sql_query("INSERT INTO account VALUES (1, 0), (2, 0)").execute(&mut first).await.unwrap();
+
+        // Each locks one row, then waits for the other's
+        for (conn, id) in [(&mut first, 1), (&mut second, 2)] {
+            sql_query("BEGIN").execute(&mut *conn).await.unwrap();
+            sql_query("SET LOCAL deadlock_timeout = '50ms'").execute(&mut *conn).await.unwrap();
+            sql_query(format!("UPDATE account SET balance = 1 WHERE id = {}", id)).execute(&mut *conn).await.unwrap();
+        }
+        let (first_result, second_result) = tokio::join!(
+            sql_query("UPDATE account SET balance = 2 WHERE id = 2").execute(&mut first),
+            sql_query("UPDATE account SET balance = 2 WHERE id = 1").execute(&mut second),
+        );
+
+        let error = first_result.err().or(second_result.err()).expect("Postgres aborts one of them");
+        let error = DbError::from(error);
+        assert!(matches!(error, DbError::Deadlock), "{:?}", error);
+        assert!(error.is_retryable());
+    }
+
+    #[test]
+    fn only_concurrency_failures_are_retryable() {
+        assert!(DbError::SerializationFailure.is_retryable());
+        assert!(!DbError::UniqueViolation(None).is_retryable());
+        assert!(!DbError::NotFound.is_retryable());
+    }
+}
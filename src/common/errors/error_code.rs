@@ -0,0 +1,58 @@
+/*
+    Stable machine readable error codes, clients rely on these so never rename one
+*/
+pub const INTERNAL_ERROR: &str = "internal_error";
+pub const SERVICE_UNAVAILABLE: &str = "service.unavailable";
+pub const RESOURCE_NOT_FOUND: &str = "resource.not_found";
+pub const RESOURCE_CONFLICT: &str = "resource.conflict";
+pub const PLAN_LIMIT_REACHED: &str = "plan.limit_reached";
+pub const CONNECTION_LIMIT_REACHED: &str = "connection.limit_reached";
+pub const RESOURCE_REFERENCE_INVALID: &str = "resource.reference_invalid";
+pub const RESOURCE_CONCURRENT_MODIFICATION: &str = "resource.concurrent_modification";
+pub const RESOURCE_PRECONDITION_FAILED: &str = "resource.precondition_failed";
+
+pub const USER_EMAIL_TAKEN: &str = "user.email_taken";
+pub const USER_ALREADY_MEMBER: &str = "user.already_member";
+pub const USER_NOT_FOUND: &str = "user.not_found";
+pub const ORGANISATION_NOT_FOUND: &str = "organisation.not_found";
+pub const ROLE_NOT_FOUND: &str = "role.not_found";
+
+pub const AUTH_INVALID_CREDENTIALS: &str = "auth.invalid_credentials";
+pub const AUTH_UNAUTHORIZED: &str = "auth.unauthorized";
+pub const AUTH_FORBIDDEN: &str = "auth.forbidden";
+pub const AUTH_TOKEN_INVALID: &str = "auth.token_invalid";
+pub const AUTH_TOKEN_EXPIRED: &str = "auth.token_expired";
+pub const AUTH_TOKEN_REVOKED: &str = "auth.token_revoked";
+pub const AUTH_TOKEN_SERIALIZATION: &str = "auth.token_serialization";
+
+pub const REQUEST_VALIDATION_FAILED: &str = "request.validation_failed";
+pub const REQUEST_INVALID_JSON: &str = "request.invalid_json";
+pub const REQUEST_QUERY_INVALID: &str = "request.query_invalid";
+pub const REQUEST_HEADER_MISSING: &str = "request.header_missing";
+pub const REQUEST_HEADER_INVALID: &str = "request.header_invalid";
+pub const REQUEST_CONSTRAINT_VIOLATED: &str = "request.constraint_violated";
+pub const REQUEST_PRECONDITION_REQUIRED: &str = "request.precondition_required";
+pub const REQUEST_BODY_TOO_LARGE: &str = "request.body_too_large";
+pub const REQUEST_VERSION_UNSUPPORTED: &str = "request.version_unsupported";
+
+pub const REALTIME_CHANNEL_INVALID: &str = "realtime.channel_invalid";
+pub const REALTIME_CHANNEL_LIMIT_REACHED: &str = "realtime.channel_limit_reached";
+pub const REALTIME_AUTHENTICATION_TIMEOUT: &str = "realtime.authentication_timeout";
+
+pub const IDEMPOTENCY_KEY_INVALID: &str = "idempotency.key_invalid";
+pub const IDEMPOTENCY_KEY_REUSED: &str = "idempotency.key_reused";
+pub const IDEMPOTENCY_REQUEST_IN_PROGRESS: &str = "idempotency.request_in_progress";
+
+/*
+    Message codes for field level validation errors and error details
+*/
+pub const VALIDATION_EMAIL_INVALID: &str = "validation.email_invalid";
+pub const VALIDATION_FIELD_REQUIRED: &str = "validation.field_required";
+pub const VALIDATION_LOG_FILTER_INVALID: &str = "validation.log_filter_invalid";
+pub const VALIDATION_LOCALE_UNSUPPORTED: &str = "validation.locale_unsupported";
+pub const VALIDATION_SEARCH_TOO_LONG: &str = "validation.search_too_long";
+pub const VALIDATION_URL_INVALID: &str = "validation.url_invalid";
+pub const VALIDATION_WEBHOOK_EVENT_TYPE_UNSUPPORTED: &str = "validation.webhook_event_type_unsupported";
+pub const VALIDATION_WEBHOOK_SECRET_TOO_SHORT: &str = "validation.webhook_secret_too_short";
+pub const VALIDATION_WEBHOOK_URL_NOT_ALLOWED: &str = "validation.webhook_url_not_allowed";
+pub const ORGANISATION_OWNED_LIMIT_REACHED: &str = "organisation.owned_limit_reached";
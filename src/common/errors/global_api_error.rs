@@ -1,9 +1,12 @@
+use std::fmt;
 use axum::response::{IntoResponse, Response};
 use crate::common::errors::application_error::ApplicationError;
 use crate::common::errors::authentication_error::AuthenticationError;
 use crate::common::errors::request_error::RequestError;
 
+// Named after the error types they wrap
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum ApiError {
     ApplicationError(ApplicationError),
     RequestError(RequestError),
@@ -20,6 +23,18 @@ impl IntoResponse for ApiError {
     }
 }
 
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ApiError::ApplicationError(e) => e.fmt(f),
+            ApiError::RequestError(e) => e.fmt(f),
+            ApiError::AuthenticationError(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {}
+
 impl From<RequestError> for ApiError {
     fn from(error: RequestError) -> Self {
         ApiError::RequestError(error)
@@ -4,3 +4,4 @@ pub mod global_api_error;
 pub mod api_error_response;
 pub mod authentication_error;
 pub mod db_error;
+pub mod error_code;
@@ -1,15 +1,27 @@
 use std::fmt;
-use axum::extract::{rejection::JsonRejection};
+use axum::extract::rejection::{JsonRejection, QueryRejection};
 use axum::response::{IntoResponse, Response};
 use http::StatusCode;
+use tracing::debug;
 use crate::common::errors::api_error_response::{ErrorMessage, ErrorResponse};
+use crate::common::errors::error_code;
+use crate::common::i18n::catalogue;
+use crate::common::models::api_version::SUPPORTED_VERSIONS;
 
 #[derive(Debug)]
 pub enum RequestError {
     ValidationError(Vec<ErrorMessage>),
     JsonRejection(JsonRejection),
+    QueryRejection(QueryRejection),
     HeaderNotFound(String),
     InvalidUUIDHeaderFormat(String),
+    // Changing a versioned resource without If-Match
+    PreconditionRequired,
+    // Idempotency-Key header empty, too long or not visible ASCII
+    InvalidIdempotencyKey,
+    PayloadTooLarge,
+    // Accept asked for an API version that isn't served on this path
+    UnsupportedVersion,
 }
 
 
@@ -19,29 +31,92 @@ impl From<JsonRejection> for RequestError {
     }
 }
 
+impl From<QueryRejection> for RequestError {
+    fn from(rejection: QueryRejection) -> Self {
+        Self::QueryRejection(rejection)
+    }
+}
+
 impl IntoResponse for RequestError {
     fn into_response(self) -> Response {
         match self {
             RequestError::ValidationError(messages) =>
                 ErrorResponse::builder()
                     .status_code(StatusCode::BAD_REQUEST)
+                    .code(error_code::REQUEST_VALIDATION_FAILED)
                     .errors(messages)
                     .build()
                     .into_response(),
-            RequestError::JsonRejection(e) => ErrorResponse::build(e.status(), e.body_text()).into_response(),
-            RequestError::HeaderNotFound(header_name) => ErrorResponse::build(StatusCode::BAD_REQUEST, format!("Required header {header_name} is missing")).into_response(),
-            RequestError::InvalidUUIDHeaderFormat(header_name) => ErrorResponse::build(StatusCode::BAD_REQUEST, format!("Header {header_name} must be in UUID format")).into_response(),
+            RequestError::JsonRejection(e) => {
+                debug!("Rejected request body: {}", e.body_text());
+                rejection_response(e.status(), error_code::REQUEST_INVALID_JSON, e.body_text())
+            },
+            RequestError::QueryRejection(e) => {
+                debug!("Rejected query string: {}", e.body_text());
+                rejection_response(e.status(), error_code::REQUEST_QUERY_INVALID, e.body_text())
+            },
+            RequestError::HeaderNotFound(header_name) => ErrorResponse::build_with_args(StatusCode::BAD_REQUEST, error_code::REQUEST_HEADER_MISSING, &[("header", &header_name)]).into_response(),
+            RequestError::InvalidUUIDHeaderFormat(header_name) => ErrorResponse::build_with_args(StatusCode::BAD_REQUEST, error_code::REQUEST_HEADER_INVALID, &[("header", &header_name)]).into_response(),
+            RequestError::PreconditionRequired => ErrorResponse::build(StatusCode::PRECONDITION_REQUIRED, error_code::REQUEST_PRECONDITION_REQUIRED).into_response(),
+            RequestError::InvalidIdempotencyKey => ErrorResponse::build(StatusCode::BAD_REQUEST, error_code::IDEMPOTENCY_KEY_INVALID).into_response(),
+            RequestError::PayloadTooLarge => ErrorResponse::build(StatusCode::PAYLOAD_TOO_LARGE, error_code::REQUEST_BODY_TOO_LARGE).into_response(),
+            RequestError::UnsupportedVersion => {
+                let supported = SUPPORTED_VERSIONS.map(|version| version.as_str()).join(", ");
+                ErrorResponse::build_with_args(StatusCode::NOT_ACCEPTABLE, error_code::REQUEST_VERSION_UNSUPPORTED, &[("versions", &supported)]).into_response()
+            },
         }
     }
 }
 
+// Translated message followed by axum's reason, which tells the client what to fix (it is English only)
+fn rejection_response(status_code: StatusCode, code: &str, reason: String) -> Response {
+    ErrorResponse::builder()
+        .status_code(status_code)
+        .code(code)
+        .detail(format!("{} {}", catalogue::translate(code, &[]), reason))
+        .build()
+        .into_response()
+}
+
 impl fmt::Display for RequestError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             RequestError::ValidationError(messages) => write!(f, "Validation Error: {:?}", messages),
             RequestError::JsonRejection(_) => write!(f, "JSON Parsing Error"),
+            RequestError::QueryRejection(_) => write!(f, "Query String Parsing Error"),
             RequestError::HeaderNotFound(message) => write!(f, "Required header not found: {:?}", message),
             RequestError::InvalidUUIDHeaderFormat(header_name) => write!(f, "Invalid UUID header format: {}", header_name),
+            RequestError::PreconditionRequired => write!(f, "If-Match header required"),
+            RequestError::InvalidIdempotencyKey => write!(f, "Invalid Idempotency-Key header"),
+            RequestError::PayloadTooLarge => write!(f, "Request body too large"),
+            RequestError::UnsupportedVersion => write!(f, "Unsupported API version"),
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use axum::extract::FromRequest;
+    use axum::Json;
+    use http::{header, Request};
+    use super::*;
+
+    #[tokio::test]
+    async fn json_rejection_keeps_the_reason_in_the_detail() {
+        let request = Request::post("/")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(r#"{"name": "#))
+            .unwrap();
+        let rejection = Json::<serde_json::Value>::from_request(request, &()).await.unwrap_err();
+        let reason = rejection.body_text();
+
+        let response = RequestError::from(rejection).into_response();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let problem: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        let detail = problem["detail"].as_str().unwrap();
+        assert!(detail.starts_with("The request body is not valid JSON"), "{}", detail);
+        assert!(detail.ends_with(&reason), "{}", detail);
+    }
+}
@@ -0,0 +1,33 @@
+use std::collections::HashMap;
+use lazy_static::lazy_static;
+use crate::common::i18n::locale::{self, Locale};
+
+lazy_static! {
+    static ref CATALOGUE: HashMap<Locale, HashMap<String, String>> = HashMap::from([
+        (Locale::En, parse(include_str!("locales/en.json"))),
+        (Locale::De, parse(include_str!("locales/de.json"))),
+        (Locale::Fr, parse(include_str!("locales/fr.json"))),
+    ]);
+}
+
+fn parse(messages: &str) -> HashMap<String, String> {
+    serde_json::from_str(messages).expect("Invalid message catalogue")
+}
+
+/*
+    Message for the code in the current request locale, falling back to English then to the code itself.
+    Placeholders such as {header} are replaced from args.
+*/
+pub fn translate(code: &str, args: &[(&str, &str)]) -> String {
+    translate_for(locale::current(), code, args)
+}
+
+pub fn translate_for(locale: Locale, code: &str, args: &[(&str, &str)]) -> String {
+    let message = CATALOGUE.get(&locale)
+        .and_then(|messages| messages.get(code))
+        .or_else(|| CATALOGUE.get(&Locale::En).and_then(|messages| messages.get(code)))
+        .cloned()
+        .unwrap_or_else(|| code.to_string());
+
+    args.iter().fold(message, |message, (name, value)| message.replace(&format!("{{{}}}", name), value))
+}
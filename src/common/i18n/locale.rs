@@ -0,0 +1,82 @@
+use std::fmt;
+use std::str::FromStr;
+use serde::{Deserialize, Serialize};
+
+tokio::task_local! {
+    static CURRENT_LOCALE: Locale;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Locale {
+    En,
+    De,
+    Fr,
+}
+
+pub const SUPPORTED_LOCALES: [Locale; 3] = [Locale::En, Locale::De, Locale::Fr];
+
+impl Locale {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Locale::En => "en",
+            Locale::De => "de",
+            Locale::Fr => "fr",
+        }
+    }
+
+    /*
+        Picks the supported locale with the highest quality from an Accept-Language value,
+        e.g. "fr-CH, fr;q=0.9, en;q=0.8, *;q=0.5"
+    */
+    pub fn negotiate(accept_language: &str) -> Option<Locale> {
+        let mut candidates: Vec<(Locale, f32)> = accept_language
+            .split(',')
+            .filter_map(|entry| {
+                let mut parts = entry.trim().split(';');
+                let tag = parts.next()?.trim();
+                let quality = parts
+                    .find_map(|param| param.trim().strip_prefix("q="))
+                    .and_then(|q| q.parse::<f32>().ok())
+                    .unwrap_or(1.0);
+                let primary = tag.split('-').next()?;
+                Locale::from_str(primary).ok().map(|locale| (locale, quality))
+            })
+            .filter(|(_, quality)| *quality > 0.0)
+            .collect();
+
+        // Stable sort keeps header order between equal qualities
+        candidates.sort_by(|a, b| b.1.total_cmp(&a.1));
+        candidates.first().map(|(locale, _)| *locale)
+    }
+}
+
+impl FromStr for Locale {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "en" => Ok(Locale::En),
+            "de" => Ok(Locale::De),
+            "fr" => Ok(Locale::Fr),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for Locale {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/*
+    Locale of the request being handled, English outside of a request
+*/
+pub fn current() -> Locale {
+    CURRENT_LOCALE.try_with(|locale| *locale).unwrap_or(Locale::En)
+}
+
+pub async fn scope<F: std::future::Future>(locale: Locale, future: F) -> F::Output {
+    CURRENT_LOCALE.scope(locale, future).await
+}
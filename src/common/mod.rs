@@ -1,7 +1,11 @@
+pub mod query;
 pub mod repository;
 pub mod utils;
 pub mod errors;
 pub mod extract;
 pub mod models;
 pub mod security;
-pub mod schema;
\ No newline at end of file
+pub mod schema;
+pub mod i18n;
+#[cfg(test)]
+pub mod test_database;
\ No newline at end of file
@@ -0,0 +1,77 @@
+use chrono::NaiveDate;
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiVersion {
+    V1,
+}
+
+pub const SUPPORTED_VERSIONS: [ApiVersion; 1] = [ApiVersion::V1];
+
+impl ApiVersion {
+    // Served on the unversioned paths
+    pub const DEFAULT: ApiVersion = ApiVersion::V1;
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiVersion::V1 => "v1",
+        }
+    }
+
+    pub fn prefix(&self) -> &'static str {
+        match self {
+            ApiVersion::V1 => "/v1",
+        }
+    }
+}
+
+/*
+    Routes announced as deprecated through API_DEPRECATIONS, e.g.
+    [{"route": "/v1/users", "deprecated_at": "2026-11-01", "sunset_at": "2027-05-01", "link": "https://..."}]
+    `route` is a prefix of the versioned route pattern such as "/v1/users/:id", "/v1" deprecates the whole version
+*/
+#[derive(Debug, Deserialize)]
+pub struct RouteDeprecation {
+    pub route: String,
+    pub deprecated_at: NaiveDate,
+    pub sunset_at: Option<NaiveDate>,
+    pub link: Option<String>,
+}
+
+impl RouteDeprecation {
+    pub fn covers(&self, route: &str) -> bool {
+        route == self.route || route.strip_prefix(self.route.as_str()).is_some_and(|rest| rest.starts_with('/'))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn deprecation(route: &str) -> RouteDeprecation {
+        RouteDeprecation {
+            route: route.to_string(),
+            deprecated_at: NaiveDate::from_ymd_opt(2026, 11, 1).unwrap(),
+            sunset_at: None,
+            link: None,
+        }
+    }
+
+    #[test]
+    fn covers_the_route_and_the_routes_below_it() {
+        let users = deprecation("/v1/users");
+        assert!(users.covers("/v1/users"));
+        assert!(users.covers("/v1/users/:id"));
+        assert!(!users.covers("/v1/users_export"));
+        assert!(!users.covers("/v1/applications"));
+        assert!(!users.covers("/v1"));
+    }
+
+    #[test]
+    fn a_version_prefix_covers_the_whole_version() {
+        let v1 = deprecation("/v1");
+        assert!(v1.covers("/v1"));
+        assert!(v1.covers("/v1/organisations/:id/users"));
+        assert!(!v1.covers("/v10/users"));
+    }
+}
@@ -1 +1,3 @@
+pub mod api_version;
+#[allow(clippy::module_inception)]
 pub mod models;
\ No newline at end of file
@@ -1,20 +1,83 @@
+use std::future::Future;
 use axum::async_trait;
 use axum::extract::FromRequestParts;
+use chrono::NaiveDateTime;
+use http::header::IF_MATCH;
 use http::request::Parts;
+use serde::Serialize;
 use uuid::Uuid;
+use crate::common::i18n::locale::Locale;
 use crate::common::errors::application_error::ApplicationError;
 use crate::common::errors::request_error::RequestError;
 use crate::common::utils::constants::ORGANISATION_ID_HEADER;
+use crate::common::utils::etag;
 
 #[derive(Debug, Clone)]
 pub struct Identity {
     pub user_id: Uuid,
     pub organisation_ids: Vec<Uuid>,
+    pub locale: Option<Locale>,
 }
 
 #[derive(Debug, Clone)]
 pub struct OrganisationId(pub Uuid);
 
+/*
+    Response extension for responses that must not be stored as they are, e.g. because they carry a secret.
+    Retries with the same Idempotency-Key get this body replayed instead
+*/
+#[derive(Debug, Clone)]
+pub struct ReplayBody(pub Vec<u8>);
+
+impl ReplayBody {
+    pub fn json<T: Serialize>(value: &T) -> Self {
+        ReplayBody(serde_json::to_vec(value).unwrap_or_default())
+    }
+}
+
+/*
+    Versions the client read before changing a resource, from the required If-Match header.
+    Any is `If-Match: *`, it only requires the resource to exist
+*/
+#[derive(Debug, Clone)]
+pub enum ExpectedVersion {
+    Any,
+    OneOf(Vec<NaiveDateTime>),
+}
+
+impl ExpectedVersion {
+    /*
+        Versions a change may apply to, Any resolves to the current version (only awaited then)
+    */
+    async fn resolve(self, current: impl Future<Output = Result<NaiveDateTime, ApplicationError>>) -> Result<Vec<NaiveDateTime>, ApplicationError> {
+        match self {
+            ExpectedVersion::Any => Ok(vec![current.await?]),
+            ExpectedVersion::OneOf(versions) => Ok(versions),
+        }
+    }
+
+    /*
+        Applies a change conditional on this version. The change gets the versions it may apply to
+        and returns None when the resource matched none of them, `current_version` reads it from the primary.
+        Returns what the change returned, the new version for updates. Fails with PreconditionFailed when
+        the resource changed since the expected version, with NotFound when it doesn't exist
+    */
+    pub async fn apply<T, C, CF, F, FF>(self, current_version: C, change: F) -> Result<T, ApplicationError>
+        where
+            C: Fn() -> CF,
+            CF: Future<Output = Result<NaiveDateTime, ApplicationError>>,
+            F: FnOnce(Vec<NaiveDateTime>) -> FF,
+            FF: Future<Output = Result<Option<T>, ApplicationError>>,
+    {
+        let versions = self.resolve(current_version()).await?;
+        match change(versions).await? {
+            Some(changed) => Ok(changed),
+            // A stale version is told apart from a missing resource
+            None => Err(current_version().await.err().unwrap_or(ApplicationError::PreconditionFailed)),
+        }
+    }
+}
+
 #[async_trait]
 impl<S> FromRequestParts<S> for Identity
     where
@@ -43,4 +106,89 @@ impl<S> FromRequestParts<S> for OrganisationId
             None => Err(RequestError::HeaderNotFound(ORGANISATION_ID_HEADER.to_string()))
         }
     }
-}
\ No newline at end of file
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ExpectedVersion
+    where
+        S: Send + Sync,
+{
+    type Rejection = RequestError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let values: Vec<&str> = parts.headers.get_all(IF_MATCH)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .collect();
+        if values.is_empty() {
+            return Err(RequestError::PreconditionRequired);
+        }
+
+        let tags: Vec<&str> = values.iter().flat_map(|value| value.split(',')).map(str::trim).collect();
+        if tags.contains(&"*") {
+            return Ok(ExpectedVersion::Any);
+        }
+        Ok(ExpectedVersion::OneOf(tags.into_iter().filter_map(etag::parse_version).collect()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::DateTime;
+    use super::*;
+
+    fn version(micros: i64) -> NaiveDateTime {
+        DateTime::from_timestamp_micros(micros).unwrap().naive_utc()
+    }
+
+    async fn extract(if_match: &[&str]) -> Result<ExpectedVersion, RequestError> {
+        let mut request = http::Request::builder();
+        for value in if_match {
+            request = request.header(IF_MATCH, *value);
+        }
+        let (mut parts, _) = request.body(()).unwrap().into_parts();
+        ExpectedVersion::from_request_parts(&mut parts, &()).await
+    }
+
+    #[tokio::test]
+    async fn if_match_is_required() {
+        assert!(matches!(extract(&[]).await, Err(RequestError::PreconditionRequired)));
+    }
+
+    #[tokio::test]
+    async fn if_match_accepts_any_version_or_the_strong_tags_listed() {
+        assert!(matches!(extract(&["*"]).await, Ok(ExpectedVersion::Any)));
+        let versions = match extract(&["\"a\", W/\"b\"", "\"c\", bad"]).await {
+            Ok(ExpectedVersion::OneOf(versions)) => versions,
+            other => panic!("unexpected {:?}", other),
+        };
+        assert_eq!(versions, vec![version(0xa), version(0xc)]);
+    }
+
+    // The change applies when the resource is at one of the versions, like the conditional updates do
+    async fn apply(expected: ExpectedVersion, current: Option<NaiveDateTime>) -> Result<Vec<NaiveDateTime>, ApplicationError> {
+        expected.apply(
+            || async move { current.ok_or(ApplicationError::NotFound) },
+            |versions| async move { Ok(current.filter(|current| versions.contains(current)).map(|_| versions)) },
+        ).await
+    }
+
+    #[tokio::test]
+    async fn applies_to_the_expected_version() {
+        let expected = ExpectedVersion::OneOf(vec![version(1), version(2)]);
+        assert_eq!(apply(expected, Some(version(2))).await.unwrap(), vec![version(1), version(2)]);
+    }
+
+    #[tokio::test]
+    async fn any_version_resolves_to_the_current_one() {
+        assert_eq!(apply(ExpectedVersion::Any, Some(version(3))).await.unwrap(), vec![version(3)]);
+        assert!(matches!(apply(ExpectedVersion::Any, None).await, Err(ApplicationError::NotFound)));
+    }
+
+    #[tokio::test]
+    async fn a_stale_version_is_told_apart_from_a_missing_resource() {
+        let stale = || ExpectedVersion::OneOf(vec![version(1)]);
+        assert!(matches!(apply(stale(), Some(version(2))).await, Err(ApplicationError::PreconditionFailed)));
+        assert!(matches!(apply(stale(), None).await, Err(ApplicationError::NotFound)));
+    }
+}
@@ -0,0 +1,67 @@
+use diesel::pg::{Pg, PgQueryBuilder};
+use diesel::query_builder::{QueryBuilder, QueryFragment};
+use diesel_async::methods::{ExecuteDsl, LoadQuery};
+use diesel_async::return_futures::{GetResult, LoadFuture};
+use diesel_async::AsyncConnection;
+use tracing::field::Empty;
+use tracing::instrument::Instrumented;
+use tracing::{Instrument, Span};
+
+/*
+    diesel_async::RunQueryDsl with a `db_query` span around every query, so slow requests break down
+    into their statements. Repositories import this one in its place. Only the SQL is recorded, never the binds
+*/
+pub trait RunQueryDsl<Conn>: diesel_async::RunQueryDsl<Conn> {
+    fn execute<'conn, 'query>(self, conn: &'conn mut Conn) -> Instrumented<Conn::ExecuteFuture<'conn, 'query>>
+        where
+            Conn: AsyncConnection<Backend = Pg> + Send,
+            Self: ExecuteDsl<Conn> + QueryFragment<Pg> + 'query,
+    {
+        let span = query_span(&self);
+        diesel_async::RunQueryDsl::execute(self, conn).instrument(span)
+    }
+
+    fn load<'query, 'conn, U>(self, conn: &'conn mut Conn) -> Instrumented<LoadFuture<'conn, 'query, Self, Conn, U>>
+        where
+            U: Send,
+            Conn: AsyncConnection<Backend = Pg>,
+            Self: LoadQuery<'query, Conn, U> + QueryFragment<Pg> + 'query,
+    {
+        let span = query_span(&self);
+        diesel_async::RunQueryDsl::load(self, conn).instrument(span)
+    }
+
+    fn get_result<'query, 'conn, U>(self, conn: &'conn mut Conn) -> Instrumented<GetResult<'conn, 'query, Self, Conn, U>>
+        where
+            U: Send + 'conn,
+            Conn: AsyncConnection<Backend = Pg>,
+            Self: LoadQuery<'query, Conn, U> + QueryFragment<Pg> + 'query,
+    {
+        let span = query_span(&self);
+        diesel_async::RunQueryDsl::get_result(self, conn).instrument(span)
+    }
+
+    fn get_results<'query, 'conn, U>(self, conn: &'conn mut Conn) -> Instrumented<LoadFuture<'conn, 'query, Self, Conn, U>>
+        where
+            U: Send,
+            Conn: AsyncConnection<Backend = Pg>,
+            Self: LoadQuery<'query, Conn, U> + QueryFragment<Pg> + 'query,
+    {
+        let span = query_span(&self);
+        diesel_async::RunQueryDsl::get_results(self, conn).instrument(span)
+    }
+}
+
+impl<T, Conn> RunQueryDsl<Conn> for T {}
+
+pub fn query_span<T: QueryFragment<Pg>>(query: &T) -> Span {
+    let span = tracing::info_span!("db_query", otel.kind = "client", db.system = "postgresql", db.statement = Empty);
+    // Built only when something records the span
+    if !span.is_disabled() {
+        let mut builder = PgQueryBuilder::default();
+        if query.to_sql(&mut builder, &Pg).is_ok() {
+            span.record("db.statement", builder.finish());
+        }
+    }
+    span
+}
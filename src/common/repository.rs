@@ -1,16 +1,623 @@
 
+use std::cell::Cell;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
 use axum::async_trait;
+use diesel_async::scoped_futures::{ScopedBoxFuture, ScopedFutureExt};
 use bb8::{Pool, PooledConnection};
-use diesel_async::{AsyncPgConnection};
+use diesel::sql_query;
+use diesel::sql_types::Text;
+use diesel_async::{AsyncConnection, AsyncPgConnection};
+use tracing::{warn, Instrument};
+use uuid::Uuid;
 use crate::common::errors::db_error::DbError;
 use diesel_async::pooled_connection::AsyncDieselConnectionManager;
+use crate::config::diesel_config::{self, DbPools};
 
 
+const MAX_RETRY_ATTEMPTS: u32 = 3;
+const RETRY_BACKOFF: Duration = Duration::from_millis(20);
+
+// bb8 only exposes historical statistics, so requests currently waiting on a checkout are counted here
+static POOL_WAITERS: AtomicU64 = AtomicU64::new(0);
+
+tokio::task_local! {
+    // Set once the request checked out a primary connection, later reads then stay on the primary
+    static WROTE_TO_PRIMARY: Cell<bool>;
+}
+
+/*
+    Tracks primary checkouts for the duration of the future so reads after a write see that write,
+    outside of a scope every read may go to the replica
+*/
+pub async fn read_your_writes_scope<F: Future>(future: F) -> F::Output {
+    WROTE_TO_PRIMARY.scope(Cell::new(false), future).await
+}
+
+fn mark_wrote_to_primary() {
+    let _ = WROTE_TO_PRIMARY.try_with(|wrote| wrote.set(true));
+}
+
+fn wrote_to_primary() -> bool {
+    WROTE_TO_PRIMARY.try_with(Cell::get).unwrap_or(false)
+}
+
+tokio::task_local! {
+    // Organisation the request operates in, enforced by the row level security policies
+    static CURRENT_ORGANISATION: Uuid;
+}
+
+/*
+    Transactions started within the future are scoped to the organisation
+*/
+pub async fn tenant_scope<F: Future>(organisation_id: Uuid, future: F) -> F::Output {
+    CURRENT_ORGANISATION.scope(organisation_id, future).await
+}
+
+// Resets the statement timeout on every checkout, a request that changed it can't leak it to the next one
+async fn apply_session(conn: &mut AsyncPgConnection) -> Result<(), DbError> {
+    use crate::common::query::RunQueryDsl;
+
+    sql_query("SELECT set_config('statement_timeout', $1, false)")
+        .bind::<Text, _>(diesel_config::statement_timeout_setting())
+        .execute(conn)
+        .await
+        .map(|_| ())
+        .map_err(DbError::from)
+}
+
+/*
+    Sets app.organisation_id for the row level security policies until the end of the transaction,
+    so it never outlives it on a pooled connection. Without a tenant scope nothing is set and the
+    policies show no rows
+*/
+async fn apply_tenant(conn: &mut AsyncPgConnection) -> Result<(), DbError> {
+    use crate::common::query::RunQueryDsl;
+
+    let Ok(organisation_id) = CURRENT_ORGANISATION.try_with(Uuid::to_string) else {
+        return Ok(());
+    };
+    sql_query("SELECT set_config('app.organisation_id', $1, true)")
+        .bind::<Text, _>(organisation_id)
+        .execute(conn)
+        .await
+        .map(|_| ())
+        .map_err(DbError::from)
+}
+
+/*
+    Runs the queries in a transaction on the connection, scoped to the organisation of the request.
+    Queries on the tenant tables (application, swift_user, swift_user_accessible_organisation,
+    webhook_endpoint, webhook_delivery) that don't run in `in_transaction` go through this
+*/
+pub async fn scoped<'a, T, F>(conn: &mut AsyncPgConnection, operation: F) -> Result<T, DbError>
+    where
+        F: for<'r> FnOnce(&'r mut AsyncPgConnection) -> ScopedBoxFuture<'a, 'r, Result<T, DbError>> + Send + 'a,
+        T: Send + 'a,
+{
+    conn.transaction(|conn| async move {
+        apply_tenant(conn).await?;
+        operation(conn).await
+    }.scope_boxed()).await
+}
+
+pub fn pool_waiters() -> u64 {
+    POOL_WAITERS.load(Ordering::Relaxed)
+}
+
+struct WaiterGuard;
+
+impl WaiterGuard {
+    fn new() -> Self {
+        POOL_WAITERS.fetch_add(1, Ordering::Relaxed);
+        WaiterGuard
+    }
+}
+
+impl Drop for WaiterGuard {
+    fn drop(&mut self) {
+        POOL_WAITERS.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+async fn checkout(pool: &Pool<AsyncDieselConnectionManager<AsyncPgConnection>>) -> Result<PooledConnection<'_, AsyncDieselConnectionManager<AsyncPgConnection>>, DbError> {
+    let _waiter = WaiterGuard::new();
+    let mut conn = pool.get()
+        .instrument(tracing::info_span!("db_pool_checkout"))
+        .await
+        .map_err(DbError::from)?;
+
+    apply_session(&mut conn).await?;
+    Ok(conn)
+}
+
+fn record_replica_fallback(reason: &'static str) {
+    metrics::counter!("db_replica_fallbacks_total", "reason" => reason).increment(1);
+}
+
 #[async_trait]
 pub trait BaseRepository {
-    fn pool(&self) -> &Pool<AsyncDieselConnectionManager<AsyncPgConnection>>;
+    fn pools(&self) -> &DbPools;
+
+    fn pool(&self) -> &Pool<AsyncDieselConnectionManager<AsyncPgConnection>> {
+        &self.pools().primary
+    }
 
+    /*
+        Connection to the primary, anything that writes (or must not read stale data) uses this
+    */
     async fn conn(&self) -> Result<PooledConnection<AsyncDieselConnectionManager<AsyncPgConnection>>, DbError> {
-        self.pool().get().await.map_err(DbError::from)
+        mark_wrote_to_primary();
+        checkout(self.pool()).await
+    }
+
+    /*
+        Primary connection for reads that must never be stale, unlike `conn` it keeps later reads
+        of the request on the replica
+    */
+    async fn fresh_read_conn(&self) -> Result<PooledConnection<AsyncDieselConnectionManager<AsyncPgConnection>>, DbError> {
+        checkout(self.pool()).await
+    }
+
+    /*
+        Connection for reads, served by the replica when one is configured. Falls back to the primary
+        after a write in the same request, or while the replica is unreachable or lagging
+    */
+    async fn read_conn(&self) -> Result<PooledConnection<AsyncDieselConnectionManager<AsyncPgConnection>>, DbError> {
+        let Some(replica) = self.pools().replica.as_ref() else {
+            return checkout(self.pool()).await;
+        };
+
+        if wrote_to_primary() {
+            record_replica_fallback("read_your_writes");
+            return checkout(self.pool()).await;
+        }
+
+        if !diesel_config::is_replica_available() {
+            record_replica_fallback("unavailable");
+            return checkout(self.pool()).await;
+        }
+
+        match checkout(replica).await {
+            Ok(conn) => Ok(conn),
+            Err(e) => {
+                warn!("Replica checkout failed, reading from the primary: {:?}", e);
+                record_replica_fallback("checkout_failed");
+                checkout(self.pool()).await
+            },
+        }
+    }
+
+    /*
+        Primary connection as the BYPASSRLS role of DATABASE_ADMIN_URL, only for the paths that work
+        across organisations: logins, sessions, memberships of a user and background work
+    */
+    async fn admin_conn(&self) -> Result<PooledConnection<AsyncDieselConnectionManager<AsyncPgConnection>>, DbError> {
+        mark_wrote_to_primary();
+        checkout(&self.pools().admin).await
+    }
+
+    /*
+        Runs the operation in one transaction on a single connection, retrying the whole transaction
+        on serialization failures and deadlocks. The operation may therefore run more than once.
+        The transaction is scoped to the organisation of the request
+    */
+    async fn in_transaction<'a, T, F>(&self, options: TransactionOptions, operation: F) -> Result<T, DbError>
+        where
+            F: for<'r> Fn(UnitOfWork<'r>) -> ScopedBoxFuture<'a, 'r, Result<T, DbError>> + Send + Sync + 'a,
+            T: Send + 'a,
+    {
+        run_transaction(self.pool(), true, options, operation).await
+    }
+
+    /*
+        `in_transaction` as the admin role, for the changes that span organisations
+        (signing up, inviting a user, resetting a password)
+    */
+    async fn in_admin_transaction<'a, T, F>(&self, options: TransactionOptions, operation: F) -> Result<T, DbError>
+        where
+            F: for<'r> Fn(UnitOfWork<'r>) -> ScopedBoxFuture<'a, 'r, Result<T, DbError>> + Send + Sync + 'a,
+            T: Send + 'a,
+    {
+        run_transaction(&self.pools().admin, false, options, operation).await
+    }
+}
+
+async fn run_transaction<'a, T, F>(pool: &Pool<AsyncDieselConnectionManager<AsyncPgConnection>>, tenant: bool, options: TransactionOptions, operation: F) -> Result<T, DbError>
+    where
+        F: for<'r> Fn(UnitOfWork<'r>) -> ScopedBoxFuture<'a, 'r, Result<T, DbError>> + Send + Sync + 'a,
+        T: Send + 'a,
+{
+    let operation = &operation;
+    retry_transaction(|| async move {
+        mark_wrote_to_primary();
+        let mut conn = checkout(pool).await?;
+        let mut transaction = conn.build_transaction();
+        transaction = match options.isolation_level {
+            IsolationLevel::ReadCommitted => transaction.read_committed(),
+            IsolationLevel::Serializable => transaction.serializable(),
+        };
+        if options.read_only {
+            transaction = transaction.read_only();
+        }
+
+        transaction.run(|conn| async move {
+            if tenant {
+                apply_tenant(conn).await?;
+            }
+            operation(UnitOfWork { conn }).await
+        }.scope_boxed())
+            .instrument(tracing::info_span!("db_transaction"))
+            .await
+    }).await
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub enum IsolationLevel {
+    #[default]
+    ReadCommitted,
+    Serializable,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TransactionOptions {
+    pub isolation_level: IsolationLevel,
+    pub read_only: bool,
+}
+
+impl TransactionOptions {
+    pub fn serializable() -> Self {
+        TransactionOptions { isolation_level: IsolationLevel::Serializable, read_only: false }
+    }
+}
+
+/*
+    The connection of an open transaction, handed to the `*_with_conn` methods of any repository
+    so they all take part in the same transaction
+*/
+pub struct UnitOfWork<'r> {
+    conn: &'r mut AsyncPgConnection,
+}
+
+impl UnitOfWork<'_> {
+    pub fn conn(&mut self) -> &mut AsyncPgConnection {
+        self.conn
+    }
+}
+
+/*
+    Runs the operation again when Postgres aborts it with a serialization failure or deadlock,
+    the operation must be safe to repeat from the start (e.g. a whole transaction)
+*/
+async fn retry_transaction<T, F, Fut>(mut operation: F) -> Result<T, DbError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, DbError>>,
+{
+    let mut attempt = 1;
+    loop {
+        match operation().await {
+            Err(e) if e.is_retryable() && attempt < MAX_RETRY_ATTEMPTS => {
+                warn!("Retrying after {:?}, attempt {} of {}", e, attempt, MAX_RETRY_ATTEMPTS);
+                metrics::counter!("db_transaction_retries_total").increment(1);
+                tokio::time::sleep(RETRY_BACKOFF * attempt).await;
+                attempt += 1;
+            },
+            result => return result,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicU32;
+    use chrono::{NaiveDateTime, Utc};
+    use diesel::QueryableByName;
+    use crate::common::query::RunQueryDsl;
+    use crate::common::test_database::TestDatabase;
+    use crate::domains::applications::db_models::{Application, PutApplication};
+    use crate::domains::applications::repository::ApplicationRepository;
+    use crate::domains::organisations::repository::OrganisationRepository;
+    use crate::domains::search::repository::SearchRepository;
+    use crate::domains::users::db_models::{PutSwiftUser, SwiftUserOrganisation};
+    use crate::domains::users::repository::UserRepository;
+    use crate::domains::webhooks::db_models::NewWebhookDelivery;
+    use crate::domains::webhooks::repository::WebhookRepository;
+    use super::*;
+
+    #[derive(QueryableByName)]
+    struct Database {
+        #[diesel(sql_type = Text)]
+        name: String,
+    }
+
+    struct Probe {
+        pools: DbPools,
+    }
+
+    impl BaseRepository for Probe {
+        fn pools(&self) -> &DbPools {
+            &self.pools
+        }
+    }
+
+    async fn database_of(conn: &mut AsyncPgConnection) -> String {
+        sql_query("SELECT current_database() AS name").get_result::<Database>(conn).await.unwrap().name
+    }
+
+    // The only test with a replica, the availability flag is global
+    #[tokio::test]
+    async fn routes_reads_between_primary_and_replica() {
+        let Some(primary) = TestDatabase::create().await else { return };
+        let Some(replica) = TestDatabase::create().await else { return };
+        let pools = DbPools { replica: Some(replica.pools.primary.clone()), ..primary.pools.clone() };
+        let probe = Probe { pools: pools.clone() };
+        let (primary_name, replica_name) = (database_of(&mut primary.admin().await).await, database_of(&mut replica.admin().await).await);
+
+        diesel_config::set_replica_available(true);
+        read_your_writes_scope(async {
+            assert_eq!(database_of(&mut probe.read_conn().await.unwrap()).await, replica_name);
+            // Fresh reads don't count as writes, later reads stay on the replica
+            assert_eq!(database_of(&mut probe.fresh_read_conn().await.unwrap()).await, primary_name);
+            assert_eq!(database_of(&mut probe.read_conn().await.unwrap()).await, replica_name);
+            assert_eq!(database_of(&mut probe.conn().await.unwrap()).await, primary_name);
+            assert_eq!(database_of(&mut probe.read_conn().await.unwrap()).await, primary_name);
+        }).await;
+
+        // Authorization reads ignore the replica, which doesn't have the user yet
+        let user_id = Uuid::now_v7();
+        let organisation_id = Uuid::now_v7();
+        let mut admin = primary.admin().await;
+        sql_query(format!("INSERT INTO swift_user (id, email, first_name) VALUES ('{}', 'routed@example.com', 'Routed')", user_id)).execute(&mut admin).await.unwrap();
+        sql_query(format!("INSERT INTO organisation (id, owner, name) VALUES ('{}', '{}', 'Routed')", organisation_id, user_id)).execute(&mut admin).await.unwrap();
+        sql_query(format!("INSERT INTO swift_user_accessible_organisation (organisation_id, swift_user_id, role_id) VALUES ('{}', '{}', 1)", organisation_id, user_id)).execute(&mut admin).await.unwrap();
+
+        let version = tenant_scope(organisation_id, UserRepository::new(pools.clone()).find_version(&user_id)).await.unwrap();
+        assert!(version.is_some());
+        let is_super_admin = UserRepository::new(pools.clone()).find_is_super_admin(&user_id).await.unwrap();
+        assert_eq!(is_super_admin, Some(false));
+        let role = tenant_scope(organisation_id, OrganisationRepository::new(pools.clone()).find_role(&organisation_id, &user_id)).await.unwrap();
+        assert_eq!(role, Some(1));
+
+        diesel_config::set_replica_available(false);
+        assert_eq!(database_of(&mut probe.read_conn().await.unwrap()).await, primary_name);
+    }
+
+    // One organisation with its owner and an application
+    struct Tenant {
+        organisation_id: Uuid,
+        user_id: Uuid,
+        email: String,
+        application_id: Uuid,
+        version: NaiveDateTime,
+    }
+
+    async fn seed_tenant(database: &TestDatabase, name: &str) -> Tenant {
+        let tenant = Tenant {
+            organisation_id: Uuid::now_v7(),
+            user_id: Uuid::now_v7(),
+            email: format!("{}@tenant.test", name),
+            application_id: Uuid::now_v7(),
+            version: Utc::now().naive_utc(),
+        };
+        let mut admin = database.admin().await;
+        for statement in [
+            format!("INSERT INTO swift_user (id, email, first_name, updated_at) VALUES ('{}', '{}', 'Tenant', '{}')", tenant.user_id, tenant.email, tenant.version),
+            format!("INSERT INTO organisation (id, owner, name) VALUES ('{}', '{}', 'Tenant')", tenant.organisation_id, tenant.user_id),
+            format!("INSERT INTO swift_user_accessible_organisation (organisation_id, swift_user_id, role_id) VALUES ('{}', '{}', 1)", tenant.organisation_id, tenant.user_id),
+            format!("INSERT INTO application (id, organisation_id, name, updated_at) VALUES ('{}', '{}', 'Tenant', '{}')", tenant.application_id, tenant.organisation_id, tenant.version),
+        ] {
+            sql_query(statement).execute(&mut admin).await.unwrap();
+        }
+        tenant
+    }
+
+    fn application(organisation_id: Uuid) -> Application {
+        Application {
+            id: Uuid::now_v7(),
+            organisation_id,
+            name: "Planted".to_string(),
+            description: None,
+            created_at: Utc::now().naive_utc(),
+            updated_at: Utc::now().naive_utc(),
+        }
+    }
+
+    #[tokio::test]
+    async fn repositories_only_see_the_current_organisation() {
+        let Some(database) = TestDatabase::create().await else { return };
+        let (a, b) = (seed_tenant(&database, "a").await, seed_tenant(&database, "b").await);
+        let users = UserRepository::new(database.pools.clone());
+        let applications = ApplicationRepository::new(database.pools.clone());
+        let search = SearchRepository::new(database.pools.clone());
+
+        tenant_scope(a.organisation_id, async {
+            assert!(users.find_version(&a.user_id).await.unwrap().is_some());
+            assert!(applications.find_by_id_and_organisation_id(&a.application_id, &a.organisation_id).await.unwrap().is_some());
+
+            // Asking for the other organisation by id doesn't get past the policies
+            assert!(users.find_version(&b.user_id).await.unwrap().is_none());
+            assert!(users.find_by_id_and_organisation_id(&b.user_id, &b.organisation_id).await.unwrap().is_none());
+            assert!(users.find_all_by_organisation_id(&b.organisation_id, None).await.unwrap().is_empty());
+            assert!(applications.find_by_id_and_organisation_id(&b.application_id, &b.organisation_id).await.unwrap().is_none());
+            assert!(applications.find_all_by_organisation_id(&b.organisation_id, None).await.unwrap().is_empty());
+            assert!(search.find_applications("Tenant", &b.organisation_id, 10).await.unwrap().is_empty());
+            assert!(search.find_users("Tenant", &b.organisation_id, 10).await.unwrap().is_empty());
+        }).await;
+    }
+
+    #[tokio::test]
+    async fn writes_to_another_organisation_affect_no_rows() {
+        let Some(database) = TestDatabase::create().await else { return };
+        let (a, b) = (seed_tenant(&database, "a").await, seed_tenant(&database, "b").await);
+        let users = &UserRepository::new(database.pools.clone());
+        let applications = &ApplicationRepository::new(database.pools.clone());
+        let versions = &[b.version];
+        let b = &b;
+
+        let (application, user, deleted) = tenant_scope(a.organisation_id, users.in_transaction(TransactionOptions::default(), |mut uow| async move {
+            let put_application = PutApplication { name: "Taken over".to_string(), description: None, updated_at: Utc::now().naive_utc() };
+            let application = applications.update_by_id_and_organisation_id_with_conn(uow.conn(), &b.application_id, &b.organisation_id, versions, &put_application).await?;
+            let put_user = PutSwiftUser { password: None, first_name: "Taken over".to_string(), last_name: None, updated_at: Utc::now().naive_utc(), locale: None };
+            let user = users.update_by_id_with_conn(uow.conn(), &b.user_id, versions, &put_user).await?;
+            let deleted = applications.delete_by_id_and_organisation_id_with_conn(uow.conn(), &b.application_id, &b.organisation_id, versions).await?
+                + users.delete_by_id_with_conn(uow.conn(), &b.user_id, versions).await?;
+            Ok((application, user, deleted))
+        }.scope_boxed())).await.unwrap();
+        assert_eq!((application, user, deleted), (None, None, 0));
+
+        let unchanged = tenant_scope(b.organisation_id, async {
+            let application = applications.find_by_id_and_organisation_id(&b.application_id, &b.organisation_id).await.unwrap().unwrap();
+            let user = users.find_by_id_and_organisation_id(&b.user_id, &b.organisation_id).await.unwrap().unwrap();
+            application.name == "Tenant" && user.first_name == "Tenant"
+        }).await;
+        assert!(unchanged);
+    }
+
+    #[tokio::test]
+    async fn inserts_into_another_organisation_are_rejected() {
+        let Some(database) = TestDatabase::create().await else { return };
+        let (a, b) = (seed_tenant(&database, "a").await, seed_tenant(&database, "b").await);
+        let users = &UserRepository::new(database.pools.clone());
+        let applications = &ApplicationRepository::new(database.pools.clone());
+        let (a, b) = (&a, &b);
+
+        let insert = |organisation_id: Uuid| tenant_scope(a.organisation_id, applications.in_transaction(TransactionOptions::default(), move |mut uow| async move {
+            applications.insert_with_conn(uow.conn(), &application(organisation_id)).await
+        }.scope_boxed()));
+        assert!(insert(b.organisation_id).await.unwrap_err().to_string().contains("row-level security"));
+        assert!(insert(a.organisation_id).await.is_ok());
+
+        let membership = tenant_scope(a.organisation_id, users.in_transaction(TransactionOptions::default(), |mut uow| async move {
+            users.insert_user_accessible_organisation_with_conn(uow.conn(), SwiftUserOrganisation {
+                swift_user_id: a.user_id,
+                organisation_id: b.organisation_id,
+                role_id: 1,
+            }).await
+        }.scope_boxed())).await;
+        assert!(membership.unwrap_err().to_string().contains("row-level security"));
+
+        // Users are only created as the admin role, they have no membership yet
+        let user = tenant_scope(a.organisation_id, users.in_transaction(TransactionOptions::default(), |mut uow| async move {
+            let mut user = users.find_by_id_and_organisation_id(&a.user_id, &a.organisation_id).await?.unwrap();
+            user.id = Uuid::now_v7();
+            user.email = "planted@tenant.test".to_string();
+            users.insert_with_conn(uow.conn(), &user).await
+        }.scope_boxed())).await;
+        assert!(user.unwrap_err().to_string().contains("row-level security"));
+    }
+
+    #[tokio::test]
+    async fn without_an_organisation_nothing_is_visible() {
+        let Some(database) = TestDatabase::create().await else { return };
+        let a = seed_tenant(&database, "a").await;
+        // A single connection, so the unscoped reads get the one the scoped read used
+        let primary = Pool::builder()
+            .max_size(1)
+            .build(diesel_config::manager(database.app_url.clone()))
+            .await
+            .unwrap();
+        let pools = DbPools { primary, ..database.pools.clone() };
+        let users = &UserRepository::new(pools.clone());
+        let applications = &ApplicationRepository::new(pools.clone());
+
+        assert!(tenant_scope(a.organisation_id, users.find_version(&a.user_id)).await.unwrap().is_some());
+
+        assert!(users.find_version(&a.user_id).await.unwrap().is_none());
+        assert!(users.find_all_by_organisation_id(&a.organisation_id, None).await.unwrap().is_empty());
+        assert!(applications.find_all_by_organisation_id(&a.organisation_id, None).await.unwrap().is_empty());
+        assert!(applications.find_by_id_and_organisation_id(&a.application_id, &a.organisation_id).await.unwrap().is_none());
+
+        let organisation_id = a.organisation_id;
+        let insert = applications.in_transaction(TransactionOptions::default(), |mut uow| async move {
+            applications.insert_with_conn(uow.conn(), &application(organisation_id)).await
+        }.scope_boxed()).await;
+        assert!(insert.unwrap_err().to_string().contains("row-level security"));
+    }
+
+    #[tokio::test]
+    async fn cross_organisation_paths_run_as_the_admin_role() {
+        let Some(database) = TestDatabase::create().await else { return };
+        let (a, b) = (seed_tenant(&database, "a").await, seed_tenant(&database, "b").await);
+        let users = UserRepository::new(database.pools.clone());
+        let organisations = OrganisationRepository::new(database.pools.clone());
+
+        // Logins and permission checks happen before or outside of the organisation of the request
+        tenant_scope(a.organisation_id, async {
+            assert_eq!(users.find_by_email(&b.email).await.unwrap().map(|user| user.id), Some(b.user_id));
+            assert!(users.find_session_state(&b.user_id).await.unwrap().is_some());
+            assert_eq!(users.find_is_super_admin(&b.user_id).await.unwrap(), Some(false));
+            assert_eq!(organisations.find_role(&b.organisation_id, &b.user_id).await.unwrap(), Some(1));
+            let accessible = organisations.find_all_accessible(&b.user_id).await.unwrap();
+            assert_eq!(accessible.into_iter().map(|organisation| organisation.id).collect::<Vec<_>>(), vec![b.organisation_id]);
+        }).await;
+    }
+
+    #[tokio::test]
+    async fn webhooks_are_isolated_and_delivered_as_the_admin_role() {
+        let Some(database) = TestDatabase::create().await else { return };
+        let (a, b) = (seed_tenant(&database, "a").await, seed_tenant(&database, "b").await);
+        let endpoint_id = Uuid::now_v7();
+        sql_query(format!("INSERT INTO webhook_endpoint (id, organisation_id, url, secret, event_types) \
+                           VALUES ('{}', '{}', 'https://hooks.test', 'whsec_test_secret', '{{application.created}}')", endpoint_id, a.organisation_id))
+            .execute(&mut database.admin().await)
+            .await
+            .unwrap();
+        let webhooks = WebhookRepository::new(database.pools.clone());
+
+        assert_eq!(tenant_scope(a.organisation_id, webhooks.find_all_by_organisation_id(&a.organisation_id)).await.unwrap().len(), 1);
+        assert!(tenant_scope(b.organisation_id, webhooks.find_all_by_organisation_id(&a.organisation_id)).await.unwrap().is_empty());
+        assert!(webhooks.find_by_id_and_organisation_id(&endpoint_id, &a.organisation_id).await.unwrap().is_none());
+
+        // Events of any organisation are queued and delivered outside of requests
+        let event_id = Uuid::now_v7();
+        assert_eq!(webhooks.find_subscribed_ids(&a.organisation_id, "application.created", &event_id).await.unwrap(), vec![endpoint_id]);
+        webhooks.insert_deliveries(&[NewWebhookDelivery {
+            id: Uuid::now_v7(),
+            webhook_endpoint_id: endpoint_id,
+            organisation_id: a.organisation_id,
+            event_id,
+            event_type: "application.created".to_string(),
+            payload: serde_json::json!({}),
+        }]).await.unwrap();
+        let claimed = webhooks.claim_due_deliveries(10, Duration::from_secs(60)).await.unwrap();
+        assert_eq!(claimed.iter().map(|delivery| delivery.event_id).collect::<Vec<_>>(), vec![event_id]);
+
+        assert!(tenant_scope(b.organisation_id, webhooks.find_deliveries_by_endpoint_id(&endpoint_id, &a.organisation_id, 10)).await.unwrap().is_empty());
+        assert_eq!(tenant_scope(a.organisation_id, webhooks.find_deliveries_by_endpoint_id(&endpoint_id, &a.organisation_id, 10)).await.unwrap().len(), 1);
+    }
+
+    async fn attempts_until(results: Vec<Result<u32, DbError>>) -> (Result<u32, DbError>, u32) {
+        let attempts = AtomicU32::new(0);
+        let results = std::sync::Mutex::new(results.into_iter());
+        let result = retry_transaction(|| async {
+            attempts.fetch_add(1, Ordering::Relaxed);
+            results.lock().unwrap().next().unwrap()
+        }).await;
+        (result, attempts.into_inner())
+    }
+
+    #[tokio::test]
+    async fn retries_serialization_failures_and_deadlocks() {
+        let (result, attempts) = attempts_until(vec![Err(DbError::SerializationFailure), Err(DbError::Deadlock), Ok(7)]).await;
+        assert_eq!(result.unwrap(), 7);
+        assert_eq!(attempts, 3);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_the_last_attempt() {
+        let failures = (0..MAX_RETRY_ATTEMPTS + 1).map(|_| Err(DbError::SerializationFailure)).collect();
+        let (result, attempts) = attempts_until(failures).await;
+        assert!(matches!(result, Err(DbError::SerializationFailure)));
+        assert_eq!(attempts, MAX_RETRY_ATTEMPTS);
+    }
+
+    #[tokio::test]
+    async fn other_errors_are_returned_right_away() {
+        let (result, attempts) = attempts_until(vec![Err(DbError::UniqueViolation(None)), Ok(7)]).await;
+        assert!(matches!(result, Err(DbError::UniqueViolation(None))));
+        assert_eq!(attempts, 1);
     }
-}
\ No newline at end of file
+}
@@ -1,31 +1,68 @@
 // @generated automatically by Diesel CLI.
 
+pub mod sql_types {
+    #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "tsvector", schema = "pg_catalog"))]
+    pub struct Tsvector;
+}
+
 diesel::table! {
     use diesel::sql_types::*;
+    use super::sql_types::Tsvector;
 
-    swift_user (id) {
+    application (id) {
         id -> Uuid,
-        email -> Text,
-        password -> Nullable<Text>,
-        first_name -> Text,
-        last_name -> Nullable<Text>,
+        organisation_id -> Uuid,
+        name -> Text,
+        description -> Nullable<Text>,
         created_at -> Timestamp,
         updated_at -> Timestamp,
+        search_vector -> Tsvector,
     }
 }
 
 diesel::table! {
-    use diesel::sql_types::*;
+    email_suppression (email) {
+        email -> Text,
+        reason -> Text,
+        created_at -> Timestamp,
+    }
+}
 
-    organisation_user_role (id) {
-        id -> BigSerial,
-        name -> Text,
+diesel::table! {
+    idempotency_key (swift_user_id, key) {
+        swift_user_id -> Uuid,
+        key -> Text,
+        request_fingerprint -> Text,
+        response_status -> Nullable<Int2>,
+        response_headers -> Nullable<Jsonb>,
+        response_body -> Nullable<Bytea>,
+        claim_token -> Uuid,
+        locked_at -> Timestamp,
+        created_at -> Timestamp,
+        expires_at -> Timestamp,
     }
 }
 
 diesel::table! {
-    use diesel::sql_types::*;
+    job (id) {
+        id -> Uuid,
+        kind -> Text,
+        payload -> Jsonb,
+        status -> Text,
+        attempts -> Int4,
+        max_attempts -> Int4,
+        run_at -> Timestamp,
+        locked_until -> Nullable<Timestamp>,
+        last_error -> Nullable<Text>,
+        unique_key -> Nullable<Text>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        finished_at -> Nullable<Timestamp>,
+    }
+}
 
+diesel::table! {
     organisation (id) {
         id -> Uuid,
         owner -> Uuid,
@@ -33,33 +70,123 @@ diesel::table! {
         is_archived -> Bool,
         created_at -> Timestamp,
         updated_at -> Timestamp,
+        plan -> Text,
+    }
+}
+
+diesel::table! {
+    organisation_user_role (id) {
+        id -> Int8,
+        name -> Text,
+    }
+}
+
+diesel::table! {
+    outbox_event (sequence) {
+        sequence -> Int8,
+        id -> Uuid,
+        aggregate_type -> Text,
+        aggregate_id -> Uuid,
+        event_type -> Text,
+        payload -> Jsonb,
+        organisation_id -> Nullable<Uuid>,
+        occurred_at -> Timestamp,
+        attempts -> Int4,
+        next_attempt_at -> Timestamp,
+        last_error -> Nullable<Text>,
+        dispatched_at -> Nullable<Timestamp>,
+        dead_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    presence (connection_id, channel) {
+        connection_id -> Uuid,
+        channel -> Text,
+        organisation_id -> Uuid,
+        user_id -> Uuid,
+        seen_at -> Timestamp,
     }
 }
 
 diesel::table! {
     use diesel::sql_types::*;
+    use super::sql_types::Tsvector;
+
+    swift_user (id) {
+        id -> Uuid,
+        email -> Text,
+        password -> Nullable<Text>,
+        is_super_admin -> Bool,
+        first_name -> Text,
+        last_name -> Nullable<Text>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        locale -> Nullable<Text>,
+        sessions_revoked_at -> Nullable<Timestamp>,
+        search_vector -> Tsvector,
+    }
+}
 
+diesel::table! {
     swift_user_accessible_organisation (organisation_id, swift_user_id) {
         organisation_id -> Uuid,
         swift_user_id -> Uuid,
-        role_id -> BigInt
+        role_id -> Int8,
     }
 }
 
 diesel::table! {
-    use diesel::sql_types::*;
+    webhook_delivery (id) {
+        id -> Uuid,
+        webhook_endpoint_id -> Uuid,
+        organisation_id -> Uuid,
+        event_id -> Uuid,
+        event_type -> Text,
+        payload -> Jsonb,
+        status -> Text,
+        attempts -> Int4,
+        next_attempt_at -> Timestamp,
+        last_attempt_at -> Nullable<Timestamp>,
+        response_status -> Nullable<Int2>,
+        last_error -> Nullable<Text>,
+        created_at -> Timestamp,
+    }
+}
 
-    application (id) {
+diesel::table! {
+    webhook_endpoint (id) {
         id -> Uuid,
         organisation_id -> Uuid,
-        name -> Text,
-        description -> Nullable<Text>,
+        url -> Text,
+        secret -> Text,
+        event_types -> Array<Text>,
+        is_enabled -> Bool,
         created_at -> Timestamp,
+        updated_at -> Timestamp,
     }
 }
 
+diesel::joinable!(application -> organisation (organisation_id));
+diesel::joinable!(idempotency_key -> swift_user (swift_user_id));
+diesel::joinable!(organisation -> swift_user (owner));
+diesel::joinable!(swift_user_accessible_organisation -> organisation (organisation_id));
+diesel::joinable!(swift_user_accessible_organisation -> organisation_user_role (role_id));
+diesel::joinable!(swift_user_accessible_organisation -> swift_user (swift_user_id));
+diesel::joinable!(webhook_delivery -> webhook_endpoint (webhook_endpoint_id));
+diesel::joinable!(webhook_endpoint -> organisation (organisation_id));
+
 diesel::allow_tables_to_appear_in_same_query!(
+    application,
+    email_suppression,
+    idempotency_key,
+    job,
     organisation,
+    organisation_user_role,
+    outbox_event,
+    presence,
+    swift_user,
     swift_user_accessible_organisation,
-    swift_user
-);
\ No newline at end of file
+    webhook_delivery,
+    webhook_endpoint,
+);
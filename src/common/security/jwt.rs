@@ -3,22 +3,26 @@ use std::str::FromStr;
 use axum::body::Body;
 use axum::extract::{State};
 use axum::middleware::Next;
-use axum::response::IntoResponse;
+use axum::response::Response;
 use axum_extra::extract::CookieJar;
 use chrono::{DateTime, Utc};
-use http::{Request, header};
+use http::{HeaderMap, Request, header};
 use jsonwebtoken::{Algorithm, decode, encode, Header, TokenData, Validation};
 use jsonwebtoken::errors::ErrorKind;
 use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
-use tracing::{debug, error, trace};
+use tracing::{debug, error, trace, Span};
 use uuid::Uuid;
 use crate::common::errors::authentication_error::AuthenticationError;
+use crate::common::errors::global_api_error::ApiError;
+use crate::common::i18n::locale::Locale;
 use crate::common::models::models::Identity;
 use crate::config::app_env::{JWT_AUD, JWT_EXP, JWT_ISS};
 use crate::config::AppState;
 use crate::config::jwt_config::{KEYS};
 use crate::domains::users::db_models::SwiftUser;
+use crate::domains::users::services::UserService;
+use crate::middleware::locale::run_with_locale;
 
 
 lazy_static! {
@@ -49,49 +53,67 @@ pub struct Claims {
 
 pub async fn authenticate(
     cookie_jar: CookieJar,
-    State(_app_data): State<AppState>,
+    State(app_state): State<AppState>,
     mut req: Request<Body>,
     next: Next,
-) -> Result<impl IntoResponse, AuthenticationError> {
+) -> Result<Response, ApiError> {
     debug!("Authenticating...");
-    let access_token = get_token(cookie_jar, &req)?;
+    let access_token = get_token(&cookie_jar, req.headers())?;
+    let (identity, _) = verify(&app_state.user_service, access_token).await?;
+
+    Span::current().record("user_id", identity.user_id.to_string());
+    let locale = identity.locale;
+    req.extensions_mut().insert(identity);
+
+    // The user's stored preference wins over Accept-Language
+    match locale {
+        Some(locale) => Ok(run_with_locale(locale, next, req).await),
+        None => Ok(next.run(req).await),
+    }
+}
+
+/*
+    Identity of a valid access token whose session is still active, with the token's expiry in seconds since epoch
+*/
+pub async fn verify(user_service: &UserService, access_token: String) -> Result<(Identity, usize), ApiError> {
     let access_token_details = handle_decode(access_token)?;
 
     trace!("Claims: {:?}", access_token_details.claims);
 
-    // Can use app_state or redis for further process
+    let user_id = Uuid::from_str(access_token_details.claims.sub.as_str())
+        .map_err(|e| {
+            error!("Error mapping user sub to uuid: {:?}", e);
+            AuthenticationError::SerializationError
+        })?;
 
-    // convert to Identity for future-proof
+    let Some(session) = user_service.find_active_session(&user_id, access_token_details.claims.iat as i64).await? else {
+        debug!("Session revoked for user {:?}", user_id);
+        metrics::counter!("auth_token_validation_failures_total", "reason" => "revoked").increment(1);
+        return Err(AuthenticationError::RevokedToken.into());
+    };
+
+    // convert to Identity for future-proof, the locale comes from the user so a changed preference applies right away
     let identity = Identity {
-        user_id: Uuid::from_str(access_token_details.claims.sub.as_str())
-            .map_err(|e| {
-                error!("Error mapping user sub to uuid: {:?}", e);
-                AuthenticationError::SerializationError
-            })?,
+        user_id,
         organisation_ids: access_token_details.claims.org_access,
+        locale: session.locale.as_deref().and_then(|locale| Locale::from_str(locale).ok()),
     };
 
-    req.extensions_mut().insert(identity);
-    Ok(next.run(req).await)
+    Ok((identity, access_token_details.claims.exp))
 }
 
-fn get_token(cookie_jar: CookieJar, req: &Request<Body>) -> Result<String, AuthenticationError> {
+pub fn get_token(cookie_jar: &CookieJar, headers: &HeaderMap) -> Result<String, AuthenticationError> {
     trace!("Attempting token from cookie...");
     cookie_jar
         .get("access_token")
         .map(|cookie| cookie.value().to_string())
         .or_else(|| {
             trace!("Unable to get token from cookie, attempting from Header Authorization...");
-            req.headers()
+            headers
                 .get(header::AUTHORIZATION)
                 .and_then(|auth_header| auth_header.to_str().ok())
-                .and_then(|auth_value| {
-                    if auth_value.starts_with("Bearer ") {
-                        Some(auth_value[7..].to_owned())
-                    } else {
-                        None
-                    }
-                })
+                .and_then(|auth_value| auth_value.strip_prefix("Bearer "))
+                .map(|token| token.to_owned())
         })
         .ok_or(AuthenticationError::InvalidToken)
 }
@@ -124,10 +146,33 @@ pub fn handle_encode_for_user(user: &SwiftUser, organisations: Vec<Uuid>) -> Res
     Ok((claims, access_token))
 }
 
+/*
+    Round trips a probe token through the configured keys,
+    used by readiness to make sure the keys are loaded and usable.
+*/
+pub fn check_keys() -> bool {
+    let mut validation = Validation::new(Algorithm::HS256);
+    validation.required_spec_claims.clear();
+    validation.validate_exp = false;
+    validation.validate_aud = false;
+
+    encode(&Header::default(), &serde_json::json!({ "probe": true }), &KEYS.encoding)
+        .and_then(|token| decode::<serde_json::Value>(&token, &KEYS.decoding, &validation))
+        .map_err(|e| error!("JWT keys check failed: {:?}", e))
+        .is_ok()
+}
+
 fn handle_decode(access_token: String) -> Result<TokenData<Claims>, AuthenticationError> {
     trace!("Decoding access token");
 
-    let decoding_result = decode::<Claims>(access_token.as_str(), &(*KEYS).decoding, &*VALIDATION);
+    let decoding_result = decode::<Claims>(access_token.as_str(), &KEYS.decoding, &VALIDATION)
+        .or_else(|e| match (e.kind(), &KEYS.previous_decoding) {
+            (ErrorKind::InvalidSignature, Some(previous_decoding)) => {
+                trace!("Retrying access token with the previous key");
+                decode::<Claims>(access_token.as_str(), previous_decoding, &VALIDATION)
+            },
+            _ => Err(e),
+        });
 
     match &decoding_result {
         Ok(_) => debug!("Successfully finished decoding access token."),
@@ -138,9 +183,13 @@ fn handle_decode(access_token: String) -> Result<TokenData<Claims>, Authenticati
         },
     }
 
-    decoding_result.map_err(|e| match *e.kind() {
-        ErrorKind::InvalidToken => AuthenticationError::InvalidToken,
-        ErrorKind::ExpiredSignature => AuthenticationError::ExpiredToken,
-        _ => AuthenticationError::InvalidToken,
+    decoding_result.map_err(|e| {
+        let (reason, error) = match *e.kind() {
+            ErrorKind::InvalidToken => ("invalid", AuthenticationError::InvalidToken),
+            ErrorKind::ExpiredSignature => ("expired", AuthenticationError::ExpiredToken),
+            _ => ("invalid", AuthenticationError::InvalidToken),
+        };
+        metrics::counter!("auth_token_validation_failures_total", "reason" => reason).increment(1);
+        error
     })
 }
\ No newline at end of file
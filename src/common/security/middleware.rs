@@ -2,10 +2,12 @@ use axum::body::Body;
 use axum::middleware::Next;
 use axum::response::IntoResponse;
 use http::Request;
+use tracing::Span;
 use crate::common::errors::application_error::ApplicationError;
 use crate::common::errors::global_api_error::ApiError;
 
 use crate::common::models::models::{Identity, OrganisationId};
+use crate::common::repository;
 use crate::common::utils::constants::ORGANISATION_ID_HEADER;
 use crate::common::utils::header_utils;
 
@@ -16,8 +18,9 @@ pub async fn inject_organisation_id(
     if let Some(identity) = req.extensions().get::<Identity>() {
         let organisation_id = header_utils::extract_uuid_from_required_header(req.headers(), &ORGANISATION_ID_HEADER)?;
         if identity.organisation_ids.contains(&organisation_id) {
+            Span::current().record("organisation_id", organisation_id.to_string());
             req.extensions_mut().insert(OrganisationId(organisation_id));
-            Ok(next.run(req).await)
+            Ok(repository::tenant_scope(organisation_id, next.run(req)).await)
         } else {
             Err(ApplicationError::Unauthorized.into())
         }
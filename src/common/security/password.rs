@@ -0,0 +1,26 @@
+use argon2::{Argon2, PasswordHasher};
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use argon2::password_hash::SaltString;
+use tracing::error;
+use crate::common::errors::application_error::ApplicationError;
+
+/*
+    Random secret of `bytes` bytes, hex encoded
+*/
+pub fn generate_secret(bytes: usize) -> String {
+    let mut secret = vec![0u8; bytes];
+    OsRng.fill_bytes(&mut secret);
+    secret.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub fn hash_password(password: &str) -> Result<String, ApplicationError> {
+    let salt = SaltString::generate(&mut OsRng);
+
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|password_hash| password_hash.to_string())
+        .map_err(|e| {
+            error!("Failed to hash password: {:?}", e);
+            ApplicationError::InternalServerError
+        })
+}
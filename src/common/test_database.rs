@@ -0,0 +1,100 @@
+use std::env;
+use std::sync::atomic::{AtomicU32, Ordering};
+use bb8::Pool;
+use diesel::{Connection, PgConnection, RunQueryDsl, sql_query};
+use diesel_async::{AsyncConnection, AsyncPgConnection};
+use diesel_migrations::MigrationHarness;
+use crate::config::diesel_config::{self, DbPools, MIGRATIONS};
+
+static DATABASES: AtomicU32 = AtomicU32::new(0);
+
+/*
+    Migrated scratch database for tests that need Postgres, created next to the one in DATABASE_URL
+    and dropped again with the value. The primary pool runs as a role without superuser or BYPASSRLS so
+    the row level security policies apply like in production, the admin pool as one with BYPASSRLS.
+    `admin` connections are superuser ones to seed data. Tests return early when DATABASE_URL isn't set,
+    unless CI is set:
+
+    DATABASE_URL=postgres://postgres@localhost/swift cargo test
+*/
+pub struct TestDatabase {
+    pub pools: DbPools,
+    // Connects as the unprivileged role, for tests that need a pool configured differently
+    pub app_url: String,
+    server_url: String,
+    admin_url: String,
+    name: String,
+}
+
+impl TestDatabase {
+    pub async fn create() -> Option<TestDatabase> {
+        let Ok(database_url) = env::var("DATABASE_URL") else {
+            assert!(env::var_os("CI").is_none(), "DATABASE_URL must be set in CI, the database tests would be skipped");
+            eprintln!("DATABASE_URL not set, skipping database test");
+            return None;
+        };
+        let (server_url, _) = database_url.rsplit_once('/').expect("DATABASE_URL must contain a database name");
+        let name = format!("swift_test_{}_{}", std::process::id(), DATABASES.fetch_add(1, Ordering::Relaxed));
+        let admin_url = format!("{}/{}", server_url, name);
+
+        let setup = (database_url.clone(), admin_url.clone(), name.clone());
+        tokio::task::spawn_blocking(move || {
+            let (database_url, admin_url, name) = setup;
+            let mut server = PgConnection::establish(&database_url).expect("Unable to connect to DATABASE_URL");
+            execute(&mut server, &format!("CREATE DATABASE {}", name));
+            execute(&mut server, &format!("CREATE ROLE {} NOSUPERUSER NOBYPASSRLS", name));
+            execute(&mut server, &format!("CREATE ROLE {}_admin NOSUPERUSER BYPASSRLS", name));
+
+            let mut admin = PgConnection::establish(&admin_url).unwrap();
+            admin.run_pending_migrations(MIGRATIONS).unwrap();
+            for role in [name.clone(), format!("{}_admin", name)] {
+                execute(&mut admin, &format!("GRANT SELECT, INSERT, UPDATE, DELETE ON ALL TABLES IN SCHEMA public TO {}", role));
+                execute(&mut admin, &format!("GRANT USAGE ON ALL SEQUENCES IN SCHEMA public TO {}", role));
+            }
+        }).await.unwrap();
+
+        // Authenticates like DATABASE_URL, then switches to the role for the whole session
+        let app_url = format!("{}?options=-c%20role%3D{}", admin_url, name);
+        let primary = Pool::builder()
+            .max_size(4)
+            .build(diesel_config::manager(app_url.clone()))
+            .await
+            .unwrap();
+        let admin = Pool::builder()
+            .max_size(2)
+            .build(diesel_config::manager(format!("{}?options=-c%20role%3D{}_admin", admin_url, name)))
+            .await
+            .unwrap();
+
+        Some(TestDatabase {
+            pools: DbPools { primary, replica: None, admin },
+            app_url,
+            server_url: server_url.to_string(),
+            admin_url,
+            name,
+        })
+    }
+
+    // Superuser connection to the scratch database, not subject to row level security
+    pub async fn admin(&self) -> AsyncPgConnection {
+        AsyncPgConnection::establish(&self.admin_url).await.unwrap()
+    }
+}
+
+impl Drop for TestDatabase {
+    fn drop(&mut self) {
+        let database_url = env::var("DATABASE_URL").unwrap_or_else(|_| format!("{}/postgres", self.server_url));
+        let name = self.name.clone();
+        // Blocking work off the runtime thread, FORCE closes the connections the pools still hold
+        let _ = std::thread::spawn(move || {
+            let mut server = PgConnection::establish(&database_url).unwrap();
+            execute(&mut server, &format!("DROP DATABASE IF EXISTS {} WITH (FORCE)", name));
+            execute(&mut server, &format!("DROP ROLE IF EXISTS {}", name));
+            execute(&mut server, &format!("DROP ROLE IF EXISTS {}_admin", name));
+        }).join();
+    }
+}
+
+fn execute(conn: &mut PgConnection, query: &str) {
+    sql_query(query).execute(conn).unwrap_or_else(|e| panic!("{}: {}", query, e));
+}
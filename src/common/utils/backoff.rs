@@ -0,0 +1,28 @@
+use std::time::Duration;
+
+/*
+    Exponential backoff after the given number of failed attempts, `base` after the first one
+    and doubling with every further one up to `max`
+*/
+pub fn retry_delay(attempts: i32, base: Duration, max: Duration) -> Duration {
+    let exponent = attempts.saturating_sub(1).clamp(0, 16) as u32;
+    base.saturating_mul(2u32.pow(exponent)).min(max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: Duration = Duration::from_secs(60);
+    const MAX: Duration = Duration::from_secs(6 * 60 * 60);
+
+    #[test]
+    fn retry_delay_doubles_up_to_the_cap() {
+        assert_eq!(retry_delay(0, BASE, MAX), BASE);
+        assert_eq!(retry_delay(1, BASE, MAX), Duration::from_secs(60));
+        assert_eq!(retry_delay(2, BASE, MAX), Duration::from_secs(120));
+        assert_eq!(retry_delay(5, BASE, MAX), Duration::from_secs(960));
+        assert_eq!(retry_delay(10, BASE, MAX), MAX);
+        assert_eq!(retry_delay(i32::MAX, BASE, MAX), MAX);
+    }
+}
@@ -1,4 +1,15 @@
 use http::HeaderName;
 
 pub static TRACING_ID_HEADER: HeaderName = HeaderName::from_static("x-tracing-id");
-pub static ORGANISATION_ID_HEADER: HeaderName = HeaderName::from_static("x-organisation-id");
\ No newline at end of file
+pub static ORGANISATION_ID_HEADER: HeaderName = HeaderName::from_static("x-organisation-id");
+pub static IDEMPOTENCY_KEY_HEADER: HeaderName = HeaderName::from_static("idempotency-key");
+pub static IDEMPOTENT_REPLAYED_HEADER: HeaderName = HeaderName::from_static("idempotent-replayed");
+pub static DEPRECATION_HEADER: HeaderName = HeaderName::from_static("deprecation");
+pub static SUNSET_HEADER: HeaderName = HeaderName::from_static("sunset");
+pub static WEBHOOK_ID_HEADER: HeaderName = HeaderName::from_static("webhook-id");
+pub static WEBHOOK_SIGNATURE_HEADER: HeaderName = HeaderName::from_static("webhook-signature");
+pub static LAST_EVENT_ID_HEADER: HeaderName = HeaderName::from_static("last-event-id");
+pub static ACCEL_BUFFERING_HEADER: HeaderName = HeaderName::from_static("x-accel-buffering");
+
+// Replaces secrets (passwords, tokens, cookies) in logs
+pub const REDACTED: &str = "[REDACTED]";
\ No newline at end of file
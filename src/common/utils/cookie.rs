@@ -1,4 +1,4 @@
-use axum_extra::extract::cookie::Cookie;
+use axum_extra::extract::cookie::{Cookie, SameSite};
 use time::OffsetDateTime;
 use tracing::error;
 use crate::common::security::jwt::Claims;
@@ -20,6 +20,8 @@ pub fn build_cookie(claims: &Claims, cookie_name: String, value: String, domain:
         .path("/")
         .secure(is_secure)
         .http_only(true)
+        // Not sent with requests other sites make, top level navigations to the app keep it
+        .same_site(SameSite::Lax)
         .expires(expiration_time)
         .build()
 }
\ No newline at end of file
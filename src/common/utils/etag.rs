@@ -0,0 +1,98 @@
+use axum::response::{IntoResponse, Response};
+use chrono::{DateTime, NaiveDateTime};
+use headers::{ETag, HeaderMapExt, IfNoneMatch};
+use http::{HeaderMap, StatusCode};
+
+/*
+    Resources are versioned by their updated_at timestamp, the ETag is its microseconds in hex.
+    Postgres stores microseconds, so only timestamps read back from the database round trip exactly
+*/
+pub fn from_version(updated_at: &NaiveDateTime) -> ETag {
+    format!("\"{:x}\"", updated_at.and_utc().timestamp_micros())
+        .parse()
+        .expect("Hex timestamp is a valid entity tag")
+}
+
+// None for tags this server didn't issue (or weak ones), they can never match a version
+pub fn parse_version(tag: &str) -> Option<NaiveDateTime> {
+    let micros = tag.strip_prefix('"')?.strip_suffix('"')?;
+    i64::from_str_radix(micros, 16).ok()
+        .and_then(DateTime::from_timestamp_micros)
+        .map(|timestamp| timestamp.naive_utc())
+}
+
+/*
+    Responds with the body and its ETag, or 304 Not Modified when If-None-Match already has this version
+*/
+pub fn respond_with_version(headers: &HeaderMap, updated_at: &NaiveDateTime, body: impl IntoResponse) -> Response {
+    let etag = from_version(updated_at);
+    let not_modified = headers.typed_get::<IfNoneMatch>()
+        .is_some_and(|if_none_match| !if_none_match.precondition_passes(&etag));
+
+    let mut response = match not_modified {
+        true => StatusCode::NOT_MODIFIED.into_response(),
+        false => body.into_response(),
+    };
+    response.headers_mut().typed_insert(etag);
+    response
+}
+
+// 204 with the ETag of the version an update produced
+pub fn updated(updated_at: &NaiveDateTime) -> Response {
+    let mut response = StatusCode::NO_CONTENT.into_response();
+    response.headers_mut().typed_insert(from_version(updated_at));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use http::header::{ETAG, IF_NONE_MATCH};
+    use super::*;
+
+    fn version() -> NaiveDateTime {
+        DateTime::from_timestamp_micros(1_760_000_000_123_456).unwrap().naive_utc()
+    }
+
+    fn tag() -> String {
+        format!("\"{:x}\"", 1_760_000_000_123_456_i64)
+    }
+
+    fn if_none_match(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(IF_NONE_MATCH, value.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn issued_tags_parse_back_to_their_version() {
+        assert_eq!(tag(), "\"640b5eecfe240\"");
+        assert_eq!(from_version(&version()), tag().parse().unwrap());
+        assert_eq!(parse_version(&tag()), Some(version()));
+    }
+
+    #[test]
+    fn weak_wildcard_and_malformed_tags_match_no_version() {
+        for tag in [format!("W/{}", tag()), "*".to_string(), "640b5eecfe240".to_string(), "\"\"".to_string(),
+                    "\"not hex\"".to_string(), "\"640b5eecfe240".to_string(), "\"ffffffffffffffffffff\"".to_string()] {
+            assert_eq!(parse_version(&tag), None, "{}", tag);
+        }
+    }
+
+    #[test]
+    fn responds_with_the_body_and_its_etag() {
+        for headers in [HeaderMap::new(), if_none_match("\"1\""), if_none_match("not a tag")] {
+            let response = respond_with_version(&headers, &version(), "body");
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(response.headers()[ETAG], tag().as_str());
+        }
+    }
+
+    #[test]
+    fn responds_not_modified_when_the_client_has_the_version() {
+        for value in [tag(), format!("W/{}", tag()), format!("\"1\", {}", tag()), "*".to_string()] {
+            let response = respond_with_version(&if_none_match(&value), &version(), "body");
+            assert_eq!(response.status(), StatusCode::NOT_MODIFIED, "{}", value);
+            assert_eq!(response.headers()[ETAG], tag().as_str());
+        }
+    }
+}
@@ -1,16 +1,6 @@
-use axum::extract::Request;
 use http::{HeaderMap, HeaderName};
 use uuid::Uuid;
 use crate::common::errors::request_error::RequestError;
-use crate::common::utils::constants::TRACING_ID_HEADER;
-
-pub fn extract_from_request(req: &Request, header_name: &HeaderName) -> Option<String> {
-    extract_from_header(req.headers(), header_name)
-}
-
-pub fn extract_tracing_id_from_request(req: &Request) -> String {
-    extract_from_request(&req, &TRACING_ID_HEADER).expect("Could not find tracing id in request")
-}
 
 pub fn extract_from_header(headers: &HeaderMap, header_name: &HeaderName) -> Option<String> {
     headers.get(header_name.clone())
@@ -1,3 +1,6 @@
+pub mod backoff;
 pub mod constants;
 pub mod header_utils;
 pub mod cookie;
+pub mod etag;
+pub mod tracing_utils;
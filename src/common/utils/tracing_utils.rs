@@ -0,0 +1,27 @@
+use opentelemetry::trace::{TraceContextExt, TraceId};
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+tokio::task_local! {
+    static CURRENT_TRACING_ID: String;
+}
+
+/*
+    Tracing id of the request being handled, this is the value sent back as x-tracing-id.
+    Falls back to the trace id of the current span outside of a request scope
+*/
+pub fn current_tracing_id() -> Option<String> {
+    CURRENT_TRACING_ID.try_with(|tracing_id| tracing_id.clone()).ok()
+        .or_else(span_trace_id)
+}
+
+pub fn span_trace_id() -> Option<String> {
+    match Span::current().context().span().span_context().trace_id() {
+        TraceId::INVALID => None,
+        trace_id => Some(trace_id.to_string()),
+    }
+}
+
+pub async fn scope<F: std::future::Future>(tracing_id: String, future: F) -> F::Output {
+    CURRENT_TRACING_ID.scope(tracing_id, future).await
+}
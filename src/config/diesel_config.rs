@@ -1,10 +1,308 @@
 use std::env;
-use bb8::Pool;
-use diesel_async::AsyncPgConnection;
-use diesel_async::pooled_connection::{AsyncDieselConnectionManager};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use bb8::{Builder, Pool};
+use diesel::{ConnectionError, QueryableByName, QueryResult, sql_query};
+use diesel::sql_types::{Bool, Double, Text};
+use tracing::{info, warn};
+use crate::common::errors::db_error::DbError;
+use crate::config::app_env::{
+    DATABASE_ADMIN_URL, DATABASE_CONNECTION_TIMEOUT, DATABASE_CONNECT_ATTEMPTS, DATABASE_IDLE_TIMEOUT, DATABASE_MAX_LIFETIME,
+    DATABASE_POOL_MAX_SIZE, DATABASE_POOL_MIN_IDLE, DATABASE_READ_MAX_LAG, DATABASE_READ_URL,
+    DATABASE_STATEMENT_TIMEOUT, DATABASE_TEST_ON_CHECKOUT, SEARCH_SIMILARITY_THRESHOLD,
+};
+use crate::server::shutdown::ShutdownSignal;
+use diesel_async::{AsyncConnection, AsyncPgConnection};
+use diesel_migrations::{embed_migrations, EmbeddedMigrations};
+use diesel_async::pooled_connection::{AsyncDieselConnectionManager, ManagerConfig, RecyclingMethod};
 
-pub async fn establish_connection() -> Pool<AsyncDieselConnectionManager<AsyncPgConnection>> {
+// Compiled into the binary from the migrations directory
+pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations");
+
+// A replica that can't hand out a connection quickly is skipped in favour of the primary
+const REPLICA_CONNECTION_TIMEOUT: Duration = Duration::from_secs(1);
+const REPLICA_LAG_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+const CONNECT_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_CONNECT_BACKOFF: Duration = Duration::from_secs(30);
+
+static REPLICA_AVAILABLE: AtomicBool = AtomicBool::new(false);
+
+#[derive(Clone)]
+pub struct DbPools {
+    pub primary: Pool<AsyncDieselConnectionManager<AsyncPgConnection>>,
+    // Optional read replica, see BaseRepository::read_conn
+    pub replica: Option<Pool<AsyncDieselConnectionManager<AsyncPgConnection>>>,
+    // Not subject to row level security, see BaseRepository::admin_conn
+    pub admin: Pool<AsyncDieselConnectionManager<AsyncPgConnection>>,
+}
+
+#[derive(QueryableByName)]
+struct RowSecurityBypass {
+    #[diesel(sql_type = Bool)]
+    bypasses: bool,
+}
+
+#[derive(QueryableByName)]
+struct ReplicaLag {
+    #[diesel(sql_type = Double)]
+    lag_seconds: f64,
+}
+
+/*
+    Builds the pools and waits for the primary to accept connections, retrying with backoff
+    so the server can start alongside its database
+*/
+pub async fn establish_connection() -> Result<DbPools, DbError> {
     let database_url: String = env::var("DATABASE_URL").unwrap();
-    let manager: AsyncDieselConnectionManager<AsyncPgConnection> = AsyncDieselConnectionManager::<AsyncPgConnection>::new(database_url);
-    Pool::builder().build(manager).await.expect("Failed to create pool.")
-}
\ No newline at end of file
+    let primary = pool_builder()
+        .connection_timeout(*DATABASE_CONNECTION_TIMEOUT)
+        .build_unchecked(manager(database_url));
+    wait_for_database(&primary, *DATABASE_CONNECT_ATTEMPTS).await?;
+
+    // Not waited for, an unreachable replica doesn't prevent startup and reads fall back to the primary
+    let replica = DATABASE_READ_URL.as_ref().map(|database_read_url| {
+        pool_builder()
+            .connection_timeout(REPLICA_CONNECTION_TIMEOUT)
+            .build_unchecked(manager(database_read_url))
+    });
+
+    // Shares the primary pool when DATABASE_URL already connects as a role that bypasses the policies
+    let admin = match DATABASE_ADMIN_URL.as_ref() {
+        Some(database_admin_url) => pool_builder()
+            .connection_timeout(*DATABASE_CONNECTION_TIMEOUT)
+            .build_unchecked(manager(database_admin_url)),
+        None => primary.clone(),
+    };
+
+    Ok(DbPools { primary, replica, admin })
+}
+
+/*
+    Logins and background work read across organisations through the admin pool, with a role the policies
+    apply to they would find no rows at all
+*/
+pub async fn check_admin_role(db_pools: &DbPools) -> Result<(), String> {
+    match bypasses_row_security(&db_pools.admin).await {
+        Ok(true) => Ok(()),
+        Ok(false) => Err("DATABASE_ADMIN_URL (or DATABASE_URL) connects as a role without BYPASSRLS".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+async fn bypasses_row_security(pool: &Pool<AsyncDieselConnectionManager<AsyncPgConnection>>) -> Result<bool, DbError> {
+    use crate::common::query::RunQueryDsl;
+
+    let mut conn = pool.get().await?;
+    sql_query("SELECT rolsuper OR rolbypassrls AS bypasses FROM pg_roles WHERE rolname = current_user")
+        .get_result::<RowSecurityBypass>(&mut conn)
+        .await
+        .map(|role| role.bypasses)
+        .map_err(DbError::from)
+}
+
+fn pool_builder() -> Builder<AsyncDieselConnectionManager<AsyncPgConnection>> {
+    Pool::builder()
+        .max_size(*DATABASE_POOL_MAX_SIZE)
+        .min_idle(*DATABASE_POOL_MIN_IDLE)
+        .idle_timeout(Some(*DATABASE_IDLE_TIMEOUT))
+        .max_lifetime(Some(*DATABASE_MAX_LIFETIME))
+        .test_on_check_out(*DATABASE_TEST_ON_CHECKOUT)
+}
+
+/*
+    Session settings are applied once when a connection is opened, the statement timeout again on every
+    checkout (see BaseRepository) so a request that changed it can't leak a different timeout to the next one.
+    DATABASE_TEST_ON_CHECKOUT only adds a liveness check
+*/
+pub fn manager(database_url: impl Into<String>) -> AsyncDieselConnectionManager<AsyncPgConnection> {
+    let mut config = ManagerConfig::default();
+    config.custom_setup = Box::new(|url| Box::pin(async move {
+        let mut conn = AsyncPgConnection::establish(url).await?;
+        configure_session(&mut conn).await.map_err(ConnectionError::CouldntSetupConfiguration)?;
+        Ok(conn)
+    }));
+    config.recycling_method = RecyclingMethod::Verified;
+
+    AsyncDieselConnectionManager::new_with_config(database_url, config)
+}
+
+// The statement timeout in the form set_config takes it
+pub fn statement_timeout_setting() -> String {
+    DATABASE_STATEMENT_TIMEOUT.as_millis().to_string()
+}
+
+// Once per connection as the pool opens it. The trigram threshold is a placeholder until pg_trgm is loaded
+async fn configure_session(conn: &mut AsyncPgConnection) -> QueryResult<()> {
+    use crate::common::query::RunQueryDsl;
+
+    sql_query("SELECT set_config('statement_timeout', $1, false), set_config('pg_trgm.word_similarity_threshold', $2, false)")
+        .bind::<Text, _>(statement_timeout_setting())
+        .bind::<Text, _>(SEARCH_SIMILARITY_THRESHOLD.to_string())
+        .execute(conn)
+        .await
+        .map(|_| ())
+}
+
+async fn wait_for_database(pool: &Pool<AsyncDieselConnectionManager<AsyncPgConnection>>, attempts: u32) -> Result<(), DbError> {
+    let mut attempt = 1;
+    loop {
+        match pool.get().await {
+            Ok(_) => return Ok(()),
+            Err(e) if attempt < attempts => {
+                let backoff = connect_backoff(attempt);
+                warn!("Database not reachable ({}), attempt {} of {}, retrying in {:?}", e, attempt, attempts, backoff);
+                tokio::time::sleep(backoff).await;
+                attempt += 1;
+            },
+            Err(e) => return Err(DbError::from(e)),
+        }
+    }
+}
+
+// Doubles from CONNECT_BACKOFF up to MAX_CONNECT_BACKOFF, however many attempts are configured
+fn connect_backoff(attempt: u32) -> Duration {
+    CONNECT_BACKOFF.saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1))).min(MAX_CONNECT_BACKOFF)
+}
+
+pub fn is_replica_available() -> bool {
+    REPLICA_AVAILABLE.load(Ordering::Relaxed)
+}
+
+#[cfg(test)]
+pub fn set_replica_available(available: bool) {
+    REPLICA_AVAILABLE.store(available, Ordering::Relaxed);
+}
+
+/*
+    Marks the replica unavailable while it can't be reached or lags behind the primary by more than
+    DATABASE_READ_MAX_LAG, reads then go to the primary until it catches up
+*/
+pub async fn monitor_replica(pools: DbPools, shutdown: ShutdownSignal) {
+    let Some(replica) = pools.replica else {
+        return;
+    };
+
+    let mut interval = tokio::time::interval(REPLICA_LAG_CHECK_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {},
+            _ = shutdown.wait() => return,
+        }
+
+        let available = match replica_lag(&replica).await {
+            Ok(lag_seconds) => {
+                metrics::gauge!("db_replica_lag_seconds").set(lag_seconds);
+                lag_seconds <= DATABASE_READ_MAX_LAG.as_secs_f64()
+            },
+            Err(e) => {
+                warn!("Unable to check replica lag: {:?}", e);
+                false
+            },
+        };
+
+        if available != REPLICA_AVAILABLE.swap(available, Ordering::Relaxed) {
+            match available {
+                true => info!("Read replica available, routing reads to it"),
+                false => warn!("Read replica unavailable or lagging, routing reads to the primary"),
+            }
+        }
+    }
+}
+
+async fn replica_lag(replica: &Pool<AsyncDieselConnectionManager<AsyncPgConnection>>) -> Result<f64, DbError> {
+    use crate::common::query::RunQueryDsl;
+
+    let mut conn = replica.get().await?;
+    // Replay timestamp is stale when the primary is idle, so a fully replayed replica counts as no lag
+    sql_query("SELECT CASE \
+                   WHEN NOT pg_is_in_recovery() OR pg_last_wal_receive_lsn() = pg_last_wal_replay_lsn() THEN 0 \
+                   ELSE COALESCE(EXTRACT(EPOCH FROM now() - pg_last_xact_replay_timestamp()), 0) \
+               END::float8 AS lag_seconds")
+        .get_result::<ReplicaLag>(&mut conn)
+        .await
+        .map(|lag| lag.lag_seconds)
+        .map_err(DbError::from)
+}
+
+/*
+    Drops this handle without waiting on anything, connections are only closed once the last handle is gone
+    and a checked out one when it is returned. Called after the server (and every repository clone it holds) is gone.
+*/
+pub fn release(pools: DbPools) {
+    let state = pools.primary.state();
+    info!("Releasing connection pool, connections: {}, idle: {}", state.connections, state.idle_connections);
+    drop(pools);
+}
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+    use diesel::sql_query;
+    use crate::common::query::RunQueryDsl;
+    use crate::common::repository::BaseRepository;
+    use crate::common::test_database::TestDatabase;
+    use super::*;
+
+    #[derive(QueryableByName)]
+    struct Setting {
+        #[diesel(sql_type = Text)]
+        statement_timeout: String,
+    }
+
+    struct Probe {
+        pools: DbPools,
+    }
+
+    impl BaseRepository for Probe {
+        fn pools(&self) -> &DbPools {
+            &self.pools
+        }
+    }
+
+    #[test]
+    fn connect_backoff_doubles_up_to_the_cap() {
+        assert_eq!(connect_backoff(1), CONNECT_BACKOFF);
+        assert_eq!(connect_backoff(2), CONNECT_BACKOFF * 2);
+        assert_eq!(connect_backoff(5), CONNECT_BACKOFF * 16);
+        for attempt in [6, 32, 33, 64, u32::MAX] {
+            assert_eq!(connect_backoff(attempt), MAX_CONNECT_BACKOFF);
+        }
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_the_last_attempt() {
+        // Nothing listens on port 1
+        let pool = Pool::builder()
+            .connection_timeout(Duration::from_millis(200))
+            .build_unchecked(manager("postgres://postgres@127.0.0.1:1/swift"));
+
+        let started = Instant::now();
+        assert!(matches!(wait_for_database(&pool, 1).await, Err(DbError::PoolError(_))));
+        assert!(started.elapsed() < CONNECT_BACKOFF);
+    }
+
+    #[tokio::test]
+    async fn statement_timeout_is_reset_on_every_checkout() {
+        let Some(database) = TestDatabase::create().await else { return };
+        // One connection without checkout tests, so the second checkout gets the same one back
+        let primary = Pool::builder()
+            .max_size(1)
+            .test_on_check_out(false)
+            .build(manager(database.app_url.clone()))
+            .await
+            .unwrap();
+        let probe = Probe { pools: DbPools { admin: primary.clone(), primary, replica: None } };
+
+        sql_query("SET statement_timeout = 1").execute(&mut probe.conn().await.unwrap()).await.unwrap();
+
+        let setting = sql_query("SELECT current_setting('statement_timeout') AS statement_timeout")
+            .get_result::<Setting>(&mut probe.conn().await.unwrap())
+            .await
+            .unwrap();
+        let expected = match DATABASE_STATEMENT_TIMEOUT.as_millis() {
+            0 => "0".to_string(),
+            millis if millis % 1000 == 0 => format!("{}s", millis / 1000),
+            millis => format!("{}ms", millis),
+        };
+        assert_eq!(setting.statement_timeout, expected);
+    }
+}
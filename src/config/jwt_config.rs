@@ -3,19 +3,25 @@ use lazy_static::lazy_static;
 use std::env;
 
 lazy_static! {
-    pub static ref KEYS: Keys = Keys::new(env::var("JWT_SECRET").expect("JWT_SECRET must be set").as_bytes());
+    pub static ref KEYS: Keys = Keys::new(
+        env::var("JWT_SECRET").expect("JWT_SECRET must be set").as_bytes(),
+        env::var("JWT_PREVIOUS_SECRET").ok().as_deref().map(str::as_bytes),
+    );
 }
 
 pub struct Keys {
     pub encoding: EncodingKey,
     pub decoding: DecodingKey,
+    // Still accepted while tokens signed before a key rotation expire
+    pub previous_decoding: Option<DecodingKey>,
 }
 
 impl Keys {
-    fn new(secret: &[u8]) -> Self {
+    fn new(secret: &[u8], previous_secret: Option<&[u8]>) -> Self {
         Self {
             encoding: EncodingKey::from_secret(secret),
             decoding: DecodingKey::from_secret(secret),
+            previous_decoding: previous_secret.map(DecodingKey::from_secret),
         }
     }
 }
\ No newline at end of file
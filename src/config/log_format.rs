@@ -0,0 +1,132 @@
+use std::fmt;
+use serde_json::Value;
+use tracing::{Event, Subscriber};
+use tracing_subscriber::field::MakeExt;
+use tracing_subscriber::fmt::format::{debug_fn, Writer};
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+use crate::common::utils::constants::REDACTED;
+
+const SENSITIVE_FIELDS: &[&str] = &["password", "token", "secret", "cookie", "authorization"];
+
+fn is_sensitive(field_name: &str) -> bool {
+    let field_name = field_name.to_ascii_lowercase();
+    SENSITIVE_FIELDS.iter().any(|sensitive| field_name.contains(sensitive))
+}
+
+/*
+    Field formatter for the pretty/compact formats, same output as the default one apart from redaction
+*/
+pub fn redacting_fields() -> impl for<'writer> FormatFields<'writer> + 'static {
+    debug_fn(|writer, field, value| {
+        match field.name() {
+            name if is_sensitive(name) => write!(writer, "{}={}", name, REDACTED),
+            "message" => write!(writer, "{:?}", value),
+            name => write!(writer, "{}={:?}", name, value),
+        }
+    }).delimited(" ")
+}
+
+/*
+    tracing-subscriber's json format, one object per line with the event fields at the top level
+    and every span from root to leaf (tracing_id, user_id, organisation_id, path...) under "spans"
+*/
+pub fn json_layer<S>(writer: BoxMakeWriter) -> impl Layer<S> + Send + Sync + 'static
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    tracing_subscriber::fmt::layer()
+        .with_writer(writer)
+        .json()
+        .flatten_event(true)
+        .with_current_span(false)
+        .with_span_list(true)
+        .map_event_format(Redacting)
+}
+
+/*
+    Wraps an event format whose lines are json objects and redacts the sensitive fields of the event and its spans
+*/
+struct Redacting<F>(F);
+
+impl<S, N, F> FormatEvent<S, N> for Redacting<F>
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+        N: for<'a> FormatFields<'a> + 'static,
+        F: FormatEvent<S, N>,
+{
+    fn format_event(&self, ctx: &FmtContext<'_, S, N>, mut writer: Writer<'_>, event: &Event<'_>) -> fmt::Result {
+        let mut line = String::new();
+        self.0.format_event(ctx, Writer::new(&mut line), event)?;
+        match serde_json::from_str::<Value>(&line) {
+            Ok(mut line) => {
+                redact(&mut line);
+                writeln!(writer, "{}", line)
+            }
+            Err(_) => writer.write_str(&line),
+        }
+    }
+}
+
+fn redact(value: &mut Value) {
+    match value {
+        Value::Object(fields) => {
+            for (name, value) in fields.iter_mut() {
+                match is_sensitive(name) {
+                    true => *value = Value::String(REDACTED.to_string()),
+                    false => redact(value),
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(redact),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::sync::{Arc, Mutex};
+    use tracing_subscriber::layer::SubscriberExt;
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Buffer {
+        fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(bytes);
+            Ok(bytes.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn json_lines_redact_the_sensitive_fields_of_events_and_spans() {
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let subscriber = tracing_subscriber::registry().with(json_layer(BoxMakeWriter::new(move || writer.clone())));
+
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("request", path = "/v1/auth/login", authorization = "Bearer abc");
+            let _entered = span.enter();
+            tracing::info!(email = "ada@example.com", password = "hunter2", "signing in");
+        });
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let line: Value = serde_json::from_str(output.trim()).unwrap();
+        assert_eq!(line["message"], "signing in");
+        assert_eq!(line["level"], "INFO");
+        assert_eq!(line["email"], "ada@example.com");
+        assert_eq!(line["password"], REDACTED);
+        assert_eq!(line["spans"][0]["name"], "request");
+        assert_eq!(line["spans"][0]["path"], "/v1/auth/login");
+        assert_eq!(line["spans"][0]["authorization"], REDACTED);
+        assert!(!output.contains("hunter2") && !output.contains("Bearer abc"));
+    }
+}
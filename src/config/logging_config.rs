@@ -0,0 +1,62 @@
+use std::sync::OnceLock;
+use tracing_subscriber::{EnvFilter, Layer, Registry, reload};
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use crate::config::app_env::LOG_FORMAT;
+use crate::config::log_format;
+use crate::config::otel_config;
+
+static FILTER_HANDLE: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+
+pub fn init_logging() {
+    init_with_writer(BoxMakeWriter::new(std::io::stdout));
+}
+
+// Operational commands keep stdout for their own (possibly JSON) output
+pub fn init_cli_logging() {
+    init_with_writer(BoxMakeWriter::new(std::io::stderr));
+}
+
+fn init_with_writer(writer: BoxMakeWriter) {
+    let (filter, filter_handle) = reload::Layer::new(EnvFilter::from_default_env());
+    let _ = FILTER_HANDLE.set(filter_handle);
+
+    let fmt_layer = match LOG_FORMAT.as_str() {
+        "json" => log_format::json_layer(writer).boxed(),
+        "compact" => tracing_subscriber::fmt::layer()
+            .with_writer(writer)
+            .compact()
+            .fmt_fields(log_format::redacting_fields())
+            .boxed(),
+        // LOG_FORMAT is one of the three
+        _ => tracing_subscriber::fmt::layer()
+            .with_writer(writer)
+            .pretty()
+            .fmt_fields(log_format::redacting_fields())
+            .boxed(),
+    };
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt_layer)
+        .with(tracing_opentelemetry::layer().with_tracer(otel_config::init_tracer()))
+        .init();
+}
+
+pub fn current_filter() -> Option<String> {
+    FILTER_HANDLE.get()
+        .and_then(|handle| handle.with_current(|filter| filter.to_string()).ok())
+}
+
+/*
+    Swaps the filter at runtime, e.g. "info,rust_axum_template=debug"
+*/
+pub fn set_filter(directives: &str) -> Result<(), String> {
+    let filter = EnvFilter::try_new(directives).map_err(|e| e.to_string())?;
+
+    FILTER_HANDLE.get()
+        .ok_or_else(|| "Logging is not initialised".to_string())?
+        .reload(filter)
+        .map_err(|e| e.to_string())
+}
@@ -0,0 +1,15 @@
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+
+const LATENCY_BUCKETS_IN_SECS: &[f64] = &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/*
+    Installs the global recorder used by the metrics! macros,
+    the returned handle renders everything recorded so far in Prometheus text format.
+*/
+pub fn install_recorder() -> PrometheusHandle {
+    PrometheusBuilder::new()
+        .set_buckets_for_metric(Matcher::Full("http_request_duration_seconds".to_string()), LATENCY_BUCKETS_IN_SECS)
+        .expect("Invalid latency buckets")
+        .install_recorder()
+        .expect("Failed to install metrics recorder")
+}
@@ -0,0 +1,135 @@
+use std::collections::HashSet;
+use std::env;
+use std::error::Error;
+use diesel::{Connection, PgConnection, RunQueryDsl, sql_query};
+use diesel::migration::MigrationSource;
+use diesel::pg::Pg;
+use diesel::sql_types::BigInt;
+use diesel_migrations::MigrationHarness;
+use serde::Serialize;
+use tracing::{info, warn};
+use crate::config::app_env::RUN_MIGRATIONS;
+use crate::config::diesel_config::MIGRATIONS;
+
+type MigrationResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+// Arbitrary key, every replica must use the same one
+const MIGRATION_LOCK_ID: i64 = 7_305_911_274_102;
+
+#[derive(Debug, Serialize)]
+pub struct MigrationStatus {
+    pub version: String,
+    pub name: String,
+    pub applied: bool,
+}
+
+/*
+    Startup check: refuses to continue when the database has migrations this binary doesn't know about
+    (i.e. a newer release already migrated it), then applies pending ones when RUN_MIGRATIONS is enabled.
+    Runs under an advisory lock so replicas starting together don't race.
+*/
+pub async fn prepare_database() -> MigrationResult<()> {
+    let run_pending = *RUN_MIGRATIONS;
+    with_migration_lock(move |conn| {
+        let unknown = unknown_migrations(conn)?;
+        if !unknown.is_empty() {
+            return Err(format!("Database has migrations unknown to this build: {}", unknown.join(", ")).into());
+        }
+
+        if run_pending {
+            let applied = conn.run_pending_migrations(MIGRATIONS)?;
+            applied.iter().for_each(|version| info!("Applied migration {}", version));
+        } else {
+            let pending = conn.pending_migrations(MIGRATIONS)?;
+            if !pending.is_empty() {
+                warn!("{} pending migrations, set RUN_MIGRATIONS=true or run `migrations run`", pending.len());
+            }
+        }
+        Ok(())
+    }).await
+}
+
+pub async fn run_pending() -> MigrationResult<Vec<String>> {
+    with_migration_lock(|conn| {
+        let unknown = unknown_migrations(conn)?;
+        if !unknown.is_empty() {
+            return Err(format!("Database has migrations unknown to this build: {}", unknown.join(", ")).into());
+        }
+
+        conn.run_pending_migrations(MIGRATIONS)
+            .map(|versions| versions.iter().map(|version| version.to_string()).collect())
+    }).await
+}
+
+/*
+    Embedded migrations with whether they are applied, followed by applied ones missing from this build
+*/
+pub async fn status() -> MigrationResult<Vec<MigrationStatus>> {
+    with_connection(|conn| {
+        let applied: HashSet<String> = conn.applied_migrations()?
+            .iter()
+            .map(|version| version.to_string())
+            .collect();
+
+        let mut statuses: Vec<MigrationStatus> = MigrationSource::<Pg>::migrations(&MIGRATIONS)?
+            .iter()
+            .map(|migration| {
+                let version = migration.name().version().to_string();
+                MigrationStatus {
+                    applied: applied.contains(&version),
+                    name: migration.name().to_string(),
+                    version,
+                }
+            })
+            .collect();
+
+        statuses.extend(unknown_migrations(conn)?.into_iter().map(|version| MigrationStatus {
+            name: format!("{} (unknown to this build)", version),
+            version,
+            applied: true,
+        }));
+        Ok(statuses)
+    }).await
+}
+
+fn unknown_migrations(conn: &mut PgConnection) -> MigrationResult<Vec<String>> {
+    let embedded: HashSet<String> = MigrationSource::<Pg>::migrations(&MIGRATIONS)?
+        .iter()
+        .map(|migration| migration.name().version().to_string())
+        .collect();
+
+    let mut unknown: Vec<String> = conn.applied_migrations()?
+        .iter()
+        .map(|version| version.to_string())
+        .filter(|version| !embedded.contains(version))
+        .collect();
+    unknown.sort();
+    Ok(unknown)
+}
+
+async fn with_migration_lock<T, F>(operation: F) -> MigrationResult<T>
+    where
+        F: FnOnce(&mut PgConnection) -> MigrationResult<T> + Send + 'static,
+        T: Send + 'static,
+{
+    with_connection(|conn| {
+        info!("Waiting for migration lock...");
+        sql_query("SELECT pg_advisory_lock($1)").bind::<BigInt, _>(MIGRATION_LOCK_ID).execute(conn)?;
+        let result = operation(conn);
+        sql_query("SELECT pg_advisory_unlock($1)").bind::<BigInt, _>(MIGRATION_LOCK_ID).execute(conn)?;
+        result
+    }).await
+}
+
+// Diesel migrations need a synchronous connection, so they run on the blocking pool
+async fn with_connection<T, F>(operation: F) -> MigrationResult<T>
+    where
+        F: FnOnce(&mut PgConnection) -> MigrationResult<T> + Send + 'static,
+        T: Send + 'static,
+{
+    tokio::task::spawn_blocking(move || {
+        let database_url = env::var("DATABASE_URL")?;
+        let mut conn = PgConnection::establish(&database_url)?;
+        operation(&mut conn)
+    }).await?
+}
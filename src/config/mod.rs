@@ -1,29 +1,69 @@
+use std::sync::Arc;
 use axum::extract::FromRef;
-use tracing_subscriber::EnvFilter;
-use tracing_subscriber::layer::SubscriberExt;
-use tracing_subscriber::util::SubscriberInitExt;
+use metrics_exporter_prometheus::PrometheusHandle;
 
+use crate::config::diesel_config::DbPools;
 use crate::config::jwt_config::KEYS;
+use crate::domains::activity::repository::ActivityRepository;
+use crate::domains::activity::services::ActivityService;
 use crate::domains::applications::repository::ApplicationRepository;
 use crate::domains::applications::services::ApplicationService;
+use crate::domains::health::repository::HealthRepository;
+use crate::domains::health::services::HealthService;
+use crate::domains::idempotency::repository::IdempotencyRepository;
+use crate::domains::idempotency::services::IdempotencyService;
+use crate::domains::jobs::maintenance::PurgeFinishedJobs;
+use crate::domains::jobs::registry::JobRegistry;
+use crate::domains::jobs::repository::JobRepository;
+use crate::domains::jobs::services::JobService;
+use crate::domains::mail::jobs::SendEmail;
+use crate::domains::mail::mailer::Mailer;
+use crate::domains::mail::repository::EmailSuppressionRepository;
+use crate::domains::mail::services::MailService;
+use crate::domains::metrics::services::MetricsService;
 use crate::domains::organisations::repository::OrganisationRepository;
+use crate::domains::organisations::jobs::PurgeArchivedOrganisation;
 use crate::domains::organisations::services::OrganisationService;
+use crate::domains::outbox::repository::OutboxRepository;
+use crate::domains::outbox::services::OutboxService;
+use crate::domains::realtime::repository::PresenceRepository;
+use crate::domains::realtime::services::RealtimeService;
+use crate::domains::search::repository::SearchRepository;
+use crate::domains::search::services::SearchService;
 use crate::domains::users::repository::UserRepository;
 use crate::domains::users::services::UserService;
+use crate::domains::webhooks::repository::WebhookRepository;
+use crate::domains::webhooks::services::WebhookService;
+use crate::server::shutdown::ShutdownSignal;
 
 pub mod diesel_config;
 pub mod jwt_config;
 pub mod app_env;
+pub mod metrics_config;
+pub mod otel_config;
+pub mod logging_config;
+pub mod log_format;
+pub mod migration_config;
 
 #[derive(FromRef, Clone)]
 pub struct AppState {
     pub user_service: UserService,
     pub organisation_service: OrganisationService,
     pub application_service: ApplicationService,
+    pub health_service: HealthService,
+    pub metrics_service: MetricsService,
+    pub idempotency_service: IdempotencyService,
+    pub webhook_service: WebhookService,
+    pub outbox_service: OutboxService,
+    pub job_service: JobService,
+    pub mail_service: MailService,
+    pub activity_service: ActivityService,
+    pub realtime_service: RealtimeService,
+    pub search_service: SearchService,
 }
 
 pub fn init() {
-    init_logging();
+    logging_config::init_logging();
     app_env::init();
     init_keys();
 }
@@ -33,19 +73,43 @@ fn init_keys() {
     let _d = &KEYS.decoding;
 }
 
-fn init_logging() -> () {
-    tracing_subscriber::registry()
-        .with(EnvFilter::from_default_env())
-        .with(tracing_subscriber::fmt::layer())
-        .init();
-}
-
-pub(crate) async fn init_app_state() -> AppState {
-    let db_pool = diesel_config::establish_connection().await;
-    let user_repository = UserRepository::new(db_pool.clone());
+pub(crate) fn init_app_state(db_pools: DbPools, mailer: Arc<dyn Mailer>, shutdown: ShutdownSignal, prometheus_handle: PrometheusHandle) -> AppState {
+    let user_repository = UserRepository::new(db_pools.clone());
+    let outbox_repository = OutboxRepository::new(db_pools.clone());
+    let job_repository = JobRepository::new(db_pools.clone());
+    let webhook_service = WebhookService::new(WebhookRepository::new(db_pools.clone()));
+    let organisation_service = OrganisationService::new(OrganisationRepository::new(db_pools.clone()), user_repository.clone(), outbox_repository.clone(), job_repository.clone());
+    let activity_service = ActivityService::new(ActivityRepository::new(db_pools.clone()), shutdown.clone());
+    let user_service = UserService::new(user_repository, outbox_repository.clone(), job_repository.clone());
+    let application_service = ApplicationService::new(ApplicationRepository::new(db_pools.clone()), outbox_repository.clone());
+    let realtime_service = RealtimeService::new(
+        PresenceRepository::new(db_pools.clone()),
+        ActivityRepository::new(db_pools.clone()),
+        user_service.clone(),
+        organisation_service.clone(),
+        application_service.clone(),
+        activity_service.clone(),
+        shutdown.clone(),
+    );
+    let mail_service = MailService::new(mailer, EmailSuppressionRepository::new(db_pools.clone()));
+    let job_registry = JobRegistry::default()
+        .register::<PurgeArchivedOrganisation, _>(organisation_service.clone())
+        .register::<SendEmail, _>(mail_service.clone())
+        .register::<PurgeFinishedJobs, _>(job_repository.clone())
+        .schedule("0 0 * * * *", PurgeFinishedJobs);
     AppState {
-        user_service: UserService::new(user_repository.clone()),
-        organisation_service: OrganisationService::new(OrganisationRepository::new(db_pool.clone()), user_repository),
-        application_service: ApplicationService::new(ApplicationRepository::new(db_pool.clone())),
+        user_service,
+        organisation_service,
+        application_service,
+        health_service: HealthService::new(HealthRepository::new(db_pools.clone()), shutdown),
+        metrics_service: MetricsService::new(prometheus_handle, HealthRepository::new(db_pools.clone())),
+        idempotency_service: IdempotencyService::new(IdempotencyRepository::new(db_pools.clone())),
+        outbox_service: OutboxService::new(outbox_repository, vec![Arc::new(webhook_service.clone()), Arc::new(activity_service.clone())]),
+        webhook_service,
+        job_service: JobService::new(job_repository, job_registry),
+        mail_service,
+        activity_service,
+        realtime_service,
+        search_service: SearchService::new(SearchRepository::new(db_pools.clone())),
     }
 }
\ No newline at end of file
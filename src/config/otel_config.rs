@@ -0,0 +1,132 @@
+use opentelemetry::{global, KeyValue};
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_otlp::HttpExporterBuilder;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{Config, Tracer, TracerProvider};
+use opentelemetry_sdk::{runtime, Resource};
+use std::sync::OnceLock;
+use tracing::{error, info};
+
+static TRACER_PROVIDER: OnceLock<TracerProvider> = OnceLock::new();
+
+/*
+    Spans are always given W3C trace ids so x-tracing-id matches traceparent,
+    they are only exported when OTEL_EXPORTER_OTLP_ENDPOINT (or the traces specific variant) is set.
+*/
+pub fn init_tracer() -> Tracer {
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let exporter = otlp_endpoint_configured().then(|| opentelemetry_otlp::new_exporter().http());
+    let provider = build_provider(exporter);
+
+    let tracer = provider.tracer(env!("CARGO_PKG_NAME"));
+    global::set_tracer_provider(provider.clone());
+    let _ = TRACER_PROVIDER.set(provider);
+    tracer
+}
+
+// Flushes spans still buffered in the batch processor, the tracing layer keeps its own provider reference
+pub async fn shutdown() {
+    if let Some(provider) = TRACER_PROVIDER.get() {
+        info!("Flushing remaining spans");
+        let result = tokio::task::spawn_blocking(|| provider.shutdown()).await;
+        if let Ok(Err(e)) = result {
+            error!("Unable to flush spans: {:?}", e);
+        }
+    }
+}
+
+fn build_provider(exporter: Option<HttpExporterBuilder>) -> TracerProvider {
+    let service_name = std::env::var("OTEL_SERVICE_NAME").unwrap_or_else(|_| env!("CARGO_PKG_NAME").to_string());
+    let config = Config::default().with_resource(Resource::new(vec![
+        KeyValue::new("service.name", service_name),
+        KeyValue::new("service.version", env!("CARGO_PKG_VERSION")),
+    ]));
+
+    match exporter {
+        Some(exporter) => opentelemetry_otlp::new_pipeline()
+            .tracing()
+            .with_exporter(exporter)
+            .with_trace_config(config)
+            .install_batch(runtime::Tokio)
+            .expect("Failed to install OTLP trace exporter"),
+        None => TracerProvider::builder()
+            .with_config(config)
+            .build(),
+    }
+}
+
+fn otlp_endpoint_configured() -> bool {
+    std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT").is_ok() || std::env::var("OTEL_EXPORTER_OTLP_TRACES_ENDPOINT").is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use diesel::sql_query;
+    use opentelemetry_otlp::WithExportConfig;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc;
+    use tracing_subscriber::layer::SubscriberExt;
+    use super::*;
+    use crate::common::query::query_span;
+
+    // Answers every request with 200 and hands over the request line and body
+    async fn collector_stub() -> (String, mpsc::UnboundedReceiver<(String, Vec<u8>)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}/v1/traces", listener.local_addr().unwrap());
+        let (sender, receiver) = mpsc::unbounded_channel();
+
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = Vec::new();
+                let mut buffer = [0u8; 8192];
+                let (head, body_length) = loop {
+                    let read = stream.read(&mut buffer).await.unwrap();
+                    request.extend_from_slice(&buffer[..read]);
+                    if let Some(end) = request.windows(4).position(|window| window == b"\r\n\r\n") {
+                        let head = String::from_utf8_lossy(&request[..end]).to_string();
+                        let length = head.lines()
+                            .find_map(|line| line.to_ascii_lowercase().strip_prefix("content-length:").map(|value| value.trim().parse::<usize>().unwrap()))
+                            .unwrap_or(0);
+                        request.drain(..end + 4);
+                        break (head, length);
+                    }
+                };
+                while request.len() < body_length {
+                    let read = stream.read(&mut buffer).await.unwrap();
+                    request.extend_from_slice(&buffer[..read]);
+                }
+                stream.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n").await.unwrap();
+                let request_line = head.lines().next().unwrap_or_default().to_string();
+                let _ = sender.send((request_line, request));
+            }
+        });
+
+        (endpoint, receiver)
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn exports_request_and_query_spans_over_otlp() {
+        let (endpoint, mut requests) = collector_stub().await;
+        let provider = build_provider(Some(opentelemetry_otlp::new_exporter().http().with_endpoint(endpoint)));
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+
+        tracing::subscriber::with_default(subscriber, || {
+            let request = tracing::info_span!("request");
+            let _request = request.enter();
+            let _query = query_span(&sql_query("SELECT 1")).entered();
+        });
+        let flushing = provider.clone();
+        tokio::task::spawn_blocking(move || flushing.force_flush()).await.unwrap();
+
+        let (request_line, body) = tokio::time::timeout(Duration::from_secs(10), requests.recv()).await.unwrap().unwrap();
+        assert!(request_line.starts_with("POST /v1/traces"), "{}", request_line);
+        for expected in [&b"request"[..], b"db_query", b"SELECT 1"] {
+            assert!(body.windows(expected.len()).any(|window| window == expected), "{} missing from the export", String::from_utf8_lossy(expected));
+        }
+        let _ = tokio::task::spawn_blocking(move || provider.shutdown()).await;
+    }
+}
@@ -0,0 +1,50 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use uuid::Uuid;
+use crate::domains::outbox::events::{DomainEvent, OutboxEvent};
+
+pub const APPLICATION_CREATED: &str = "application.created";
+pub const APPLICATION_UPDATED: &str = "application.updated";
+pub const APPLICATION_DELETED: &str = "application.deleted";
+pub const MEMBER_JOINED: &str = "member.joined";
+pub const MEMBER_LEFT: &str = "member.left";
+
+/*
+    A change in an organisation pushed to its open event streams. `data` identifies what changed,
+    clients fetch the resource when they need its current state
+*/
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Activity {
+    pub id: Uuid,
+    #[serde(rename = "type")]
+    pub event_type: String,
+    pub organisation_id: Uuid,
+    pub data: serde_json::Value,
+    pub created_at: NaiveDateTime,
+}
+
+impl Activity {
+    /*
+        The activity for a domain event, None when streams don't show it.
+        It keeps the id of the domain event, a dispatch repeated by the outbox is pushed once
+    */
+    pub fn from_outbox(outbox_event: &OutboxEvent) -> Option<Self> {
+        let (event_type, organisation_id, data) = match &outbox_event.event {
+            DomainEvent::ApplicationCreated { organisation_id, application_id, name } => (APPLICATION_CREATED, organisation_id, json!({ "id": application_id, "name": name })),
+            DomainEvent::ApplicationUpdated { organisation_id, application_id } => (APPLICATION_UPDATED, organisation_id, json!({ "id": application_id })),
+            DomainEvent::ApplicationDeleted { organisation_id, application_id } => (APPLICATION_DELETED, organisation_id, json!({ "id": application_id })),
+            DomainEvent::MemberAdded { organisation_id, user_id, .. } => (MEMBER_JOINED, organisation_id, json!({ "id": user_id })),
+            DomainEvent::MemberRemoved { organisation_id, user_id } => (MEMBER_LEFT, organisation_id, json!({ "id": user_id })),
+            _ => return None,
+        };
+
+        Some(Activity {
+            id: outbox_event.id,
+            event_type: event_type.to_string(),
+            organisation_id: *organisation_id,
+            data,
+            created_at: outbox_event.occurred_at,
+        })
+    }
+}
@@ -0,0 +1,68 @@
+use axum::extract::State;
+use axum::Router;
+use axum::response::{IntoResponse, Response};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::routing::get;
+use futures::StreamExt;
+use http::{HeaderMap, HeaderValue};
+use uuid::Uuid;
+use crate::config::AppState;
+use crate::config::app_env::EVENT_STREAM_HEARTBEAT;
+use crate::common::errors::api_error_response::ErrorResponse;
+use crate::common::errors::application_error::ApplicationError;
+use crate::common::models::models::{Identity, OrganisationId};
+use crate::common::security;
+use crate::common::utils::constants::{ACCEL_BUFFERING_HEADER, LAST_EVENT_ID_HEADER};
+use crate::domains::activity::services::{ActivityService, StreamEvent};
+use crate::domains::docs::params::OrganisationHeader;
+
+const RESYNC: &str = "resync";
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/events/stream", get(stream_events))
+        .route_layer(axum::middleware::from_fn(security::middleware::inject_organisation_id))
+}
+
+/*
+    Server-Sent Events of the changes in the organisation. Each event has the activity id as its `id`,
+    its type (application.created, member.left, ...) as `event` and the activity as JSON `data`.
+    A `resync` event means activities were missed and what the client shows should be fetched again
+*/
+#[utoipa::path(
+    get,
+    path = "/events/stream",
+    tag = "events",
+    params(OrganisationHeader, ("Last-Event-ID" = Option<String>, Header, description = "Id of the last event received, the events after it are sent first")),
+    responses(
+        (status = 200, description = "Stream of the organisation's activity, with a heartbeat comment while it is quiet", content_type = "text/event-stream", body = String),
+        (status = 401, response = ErrorResponse),
+        (status = 429, response = ErrorResponse),
+    ),
+)]
+async fn stream_events(
+    State(activity_service): State<ActivityService>,
+    identity: Identity,
+    organisation_id: OrganisationId,
+    headers: HeaderMap,
+) -> Result<Response, ApplicationError> {
+    // An id that isn't ours can't be in the replay buffer either, the stream starts with a resync
+    let last_event_id = headers.get(&LAST_EVENT_ID_HEADER)
+        .map(|value| value.to_str().ok().and_then(|value| Uuid::parse_str(value).ok()).unwrap_or_default());
+
+    let events = activity_service.stream(identity.user_id, organisation_id.0, last_event_id)?
+        .map(|event| match event {
+            StreamEvent::Activity(activity) => Event::default()
+                .id(activity.id.to_string())
+                .event(&activity.event_type)
+                .json_data(&*activity),
+            StreamEvent::Resync => Ok(Event::default().event(RESYNC).data("{}")),
+        });
+
+    let mut response = Sse::new(events)
+        .keep_alive(KeepAlive::new().interval(*EVENT_STREAM_HEARTBEAT))
+        .into_response();
+    // Proxies would hold the events back otherwise
+    response.headers_mut().insert(ACCEL_BUFFERING_HEADER.clone(), HeaderValue::from_static("no"));
+    Ok(response)
+}
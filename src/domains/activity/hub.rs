@@ -0,0 +1,199 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+use uuid::Uuid;
+use crate::domains::activity::events::Activity;
+
+// Recent activities kept per organisation for streams resuming with Last-Event-ID
+const REPLAY_SIZE: usize = 100;
+// Activities a slow stream may fall behind before it is told to refetch
+const CHANNEL_CAPACITY: usize = 256;
+// How long the replay of an organisation without streams is kept, for streams reconnecting
+const IDLE_RETENTION: Duration = Duration::from_secs(5 * 60);
+
+/*
+    Fans the activities of each organisation out to the streams open on this instance.
+    Organisations without a stream for IDLE_RETENTION are dropped
+*/
+#[derive(Default)]
+pub struct ActivityHub {
+    organisations: Mutex<HashMap<Uuid, OrganisationActivity>>,
+    connections: Mutex<HashMap<Uuid, usize>>,
+}
+
+struct OrganisationActivity {
+    recent: VecDeque<Arc<Activity>>,
+    sender: broadcast::Sender<Arc<Activity>>,
+    // Last time a stream was open, or it was created
+    watched_at: Instant,
+}
+
+/*
+    What a new stream gets: the activities it missed and the ones still to come.
+    `missed` is None when the last event it saw is no longer in the replay buffer
+*/
+pub struct Subscription {
+    pub missed: Option<Vec<Arc<Activity>>>,
+    pub receiver: broadcast::Receiver<Arc<Activity>>,
+}
+
+// Counts as one of the user's connections until dropped
+pub struct ConnectionGuard {
+    hub: Arc<ActivityHub>,
+    user_id: Uuid,
+}
+
+impl ActivityHub {
+    // An activity already published (the outbox dispatched it again) is dropped
+    pub fn publish(&self, activity: Activity) {
+        let mut organisations = self.organisations.lock().unwrap();
+        evict_idle(&mut organisations, Instant::now());
+        let organisation = organisations.entry(activity.organisation_id).or_insert_with(OrganisationActivity::new);
+        if organisation.recent.iter().any(|recent| recent.id == activity.id) {
+            return;
+        }
+
+        let activity = Arc::new(activity);
+        if organisation.recent.len() == REPLAY_SIZE {
+            organisation.recent.pop_front();
+        }
+        organisation.recent.push_back(activity.clone());
+        // No receivers only means no stream is open for the organisation
+        let _ = organisation.sender.send(activity);
+    }
+
+    // Subscribed under the same lock as the replay is read, no activity falls between the two
+    pub fn subscribe(&self, organisation_id: Uuid, last_event_id: Option<Uuid>) -> Subscription {
+        let mut organisations = self.organisations.lock().unwrap();
+        let organisation = organisations.entry(organisation_id).or_insert_with(OrganisationActivity::new);
+        organisation.watched_at = Instant::now();
+
+        let missed = match last_event_id {
+            None => Some(Vec::new()),
+            Some(last_event_id) => organisation.recent.iter()
+                .position(|recent| recent.id == last_event_id)
+                .map(|position| organisation.recent.iter().skip(position + 1).cloned().collect()),
+        };
+        Subscription { missed, receiver: organisation.sender.subscribe() }
+    }
+
+    // None when the user already has `limit` connections open on this instance
+    pub fn connect(self: &Arc<Self>, user_id: Uuid, limit: usize) -> Option<ConnectionGuard> {
+        let mut connections = self.connections.lock().unwrap();
+        let count = connections.entry(user_id).or_default();
+        if *count >= limit {
+            return None;
+        }
+
+        *count += 1;
+        metrics::gauge!("event_stream_connections").increment(1.0);
+        Some(ConnectionGuard { hub: self.clone(), user_id })
+    }
+}
+
+impl OrganisationActivity {
+    fn new() -> Self {
+        OrganisationActivity { recent: VecDeque::new(), sender: broadcast::channel(CHANNEL_CAPACITY).0, watched_at: Instant::now() }
+    }
+}
+
+fn evict_idle(organisations: &mut HashMap<Uuid, OrganisationActivity>, now: Instant) {
+    organisations.retain(|_, organisation| {
+        if organisation.sender.receiver_count() > 0 {
+            organisation.watched_at = now;
+            return true;
+        }
+        now.saturating_duration_since(organisation.watched_at) < IDLE_RETENTION
+    });
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        let mut connections = self.hub.connections.lock().unwrap();
+        if let Some(count) = connections.get_mut(&self.user_id) {
+            *count -= 1;
+            if *count == 0 {
+                connections.remove(&self.user_id);
+            }
+        }
+        metrics::gauge!("event_stream_connections").decrement(1.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use super::*;
+
+    fn activity(organisation_id: Uuid) -> Activity {
+        Activity {
+            id: Uuid::now_v7(),
+            event_type: "application.created".to_string(),
+            organisation_id,
+            data: serde_json::json!({}),
+            created_at: Utc::now().naive_utc(),
+        }
+    }
+
+    #[tokio::test]
+    async fn streams_resume_after_the_last_event_they_saw() {
+        let hub = ActivityHub::default();
+        let organisation_id = Uuid::now_v7();
+        let seen = activity(organisation_id);
+        let missed = activity(organisation_id);
+        hub.publish(seen.clone());
+        hub.publish(missed.clone());
+        hub.publish(activity(Uuid::now_v7()));
+
+        let mut subscription = hub.subscribe(organisation_id, Some(seen.id));
+        assert_eq!(subscription.missed.unwrap().iter().map(|a| a.id).collect::<Vec<_>>(), vec![missed.id]);
+        assert!(hub.subscribe(organisation_id, Some(Uuid::now_v7())).missed.is_none());
+
+        // Published again by the outbox, only the new one goes out
+        hub.publish(missed);
+        let next = activity(organisation_id);
+        hub.publish(next.clone());
+        assert_eq!(subscription.receiver.recv().await.unwrap().id, next.id);
+    }
+
+    #[test]
+    fn organisations_without_streams_are_dropped_once_idle() {
+        let hub = ActivityHub::default();
+        let (watched, idle) = (Uuid::now_v7(), Uuid::now_v7());
+        let seen = activity(idle);
+        hub.publish(seen.clone());
+        let subscription = hub.subscribe(watched, None);
+
+        let mut organisations = hub.organisations.lock().unwrap();
+        evict_idle(&mut organisations, Instant::now() + IDLE_RETENTION / 2);
+        assert_eq!(organisations.len(), 2);
+        evict_idle(&mut organisations, Instant::now() + IDLE_RETENTION);
+        assert_eq!(organisations.keys().collect::<Vec<_>>(), vec![&watched]);
+
+        // Idle from the moment its last stream closed
+        drop(subscription);
+        evict_idle(&mut organisations, Instant::now() + IDLE_RETENTION * 3 / 2);
+        assert_eq!(organisations.len(), 1);
+        evict_idle(&mut organisations, Instant::now() + IDLE_RETENTION * 5 / 2);
+        assert!(organisations.is_empty());
+        drop(organisations);
+
+        // Nothing left to resume from, the stream is told to refetch
+        assert!(hub.subscribe(idle, Some(seen.id)).missed.is_none());
+    }
+
+    #[test]
+    fn connections_are_limited_per_user() {
+        let hub = Arc::new(ActivityHub::default());
+        let user_id = Uuid::now_v7();
+
+        let first = hub.connect(user_id, 2).unwrap();
+        let _second = hub.connect(user_id, 2).unwrap();
+        assert!(hub.connect(user_id, 2).is_none());
+        assert!(hub.connect(Uuid::now_v7(), 2).is_some());
+
+        drop(first);
+        assert!(hub.connect(user_id, 2).is_some());
+    }
+}
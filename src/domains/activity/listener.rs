@@ -0,0 +1,47 @@
+use std::env;
+use futures::StreamExt;
+use tokio::sync::mpsc;
+use tokio_postgres::{AsyncMessage, Client, NoTls};
+
+/*
+    LISTENs on a channel over a connection of its own, outside the pools as it stays open for good
+*/
+pub struct Listener {
+    // The connection closes with the client
+    _client: Client,
+    notifications: mpsc::UnboundedReceiver<Result<String, tokio_postgres::Error>>,
+}
+
+impl Listener {
+    pub async fn connect(channel: &str) -> Result<Self, tokio_postgres::Error> {
+        Self::connect_to(&env::var("DATABASE_URL").unwrap(), channel).await
+    }
+
+    pub async fn connect_to(database_url: &str, channel: &str) -> Result<Self, tokio_postgres::Error> {
+        let (client, mut connection) = tokio_postgres::connect(database_url, NoTls).await?;
+
+        // Notifications only arrive while the connection is polled, which also sends the LISTEN below
+        let (sender, notifications) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let mut messages = futures::stream::poll_fn(move |cx| connection.poll_message(cx));
+            while let Some(message) = messages.next().await {
+                let payload = match message {
+                    Ok(AsyncMessage::Notification(notification)) => Ok(notification.payload().to_string()),
+                    Ok(_) => continue,
+                    Err(e) => Err(e),
+                };
+                if sender.send(payload).is_err() {
+                    return;
+                }
+            }
+        });
+
+        client.batch_execute(&format!("LISTEN {}", channel)).await?;
+        Ok(Listener { _client: client, notifications })
+    }
+
+    // The payload of the next notification, None once the connection is closed
+    pub async fn recv(&mut self) -> Option<Result<String, tokio_postgres::Error>> {
+        self.notifications.recv().await
+    }
+}
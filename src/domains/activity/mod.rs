@@ -0,0 +1,6 @@
+pub mod events;
+pub mod handlers;
+pub mod hub;
+pub mod listener;
+pub mod repository;
+pub mod services;
@@ -0,0 +1,36 @@
+use diesel::sql_query;
+use diesel::sql_types::Text;
+use crate::common::query::RunQueryDsl;
+use tracing::instrument;
+use crate::common::errors::db_error::DbError;
+use crate::common::repository::BaseRepository;
+use crate::config::diesel_config::DbPools;
+
+#[derive(Clone)]
+pub struct ActivityRepository {
+    pools: DbPools,
+}
+
+impl ActivityRepository {
+    pub fn new(pools: DbPools) -> Self {
+        ActivityRepository { pools }
+    }
+
+    // Every connection listening on the channel gets the payload, at most 8000 bytes
+    #[instrument(skip_all)]
+    pub async fn notify(&self, channel: &str, payload: &str) -> Result<usize, DbError> {
+        let mut conn = self.conn().await?;
+        sql_query("SELECT pg_notify($1, $2)")
+            .bind::<Text, _>(channel)
+            .bind::<Text, _>(payload)
+            .execute(&mut conn)
+            .await
+            .map_err(DbError::from)
+    }
+}
+
+impl BaseRepository for ActivityRepository {
+    fn pools(&self) -> &DbPools {
+        &self.pools
+    }
+}
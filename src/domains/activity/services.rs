@@ -0,0 +1,144 @@
+use std::sync::Arc;
+use std::time::Duration;
+use axum::async_trait;
+use futures::{Stream, StreamExt};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+use crate::common::errors::application_error::ApplicationError;
+use crate::config::app_env::EVENT_STREAM_MAX_CONNECTIONS_PER_USER;
+use crate::domains::activity::events::Activity;
+use crate::domains::activity::hub::{ActivityHub, Subscription};
+use crate::domains::activity::listener::Listener;
+use crate::domains::activity::repository::ActivityRepository;
+use crate::domains::outbox::events::OutboxEvent;
+use crate::domains::outbox::services::EventSubscriber;
+use crate::server::shutdown::ShutdownSignal;
+
+const CHANNEL: &str = "organisation_activity";
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+pub enum StreamEvent {
+    Activity(Arc<Activity>),
+    // Activities were missed, the client fetches what it shows again
+    Resync,
+}
+
+#[derive(Clone)]
+pub struct ActivityService {
+    activity_repository: ActivityRepository,
+    hub: Arc<ActivityHub>,
+    // Open streams end with the server
+    shutdown: ShutdownSignal,
+}
+
+impl ActivityService {
+    pub fn new(activity_repository: ActivityRepository, shutdown: ShutdownSignal) -> Self {
+        ActivityService { activity_repository, hub: Arc::new(ActivityHub::default()), shutdown }
+    }
+
+    /*
+        The activities of the organisation from now on, preceded by the ones after `last_event_id`.
+        Fails with TooManyConnections when the user has EVENT_STREAM_MAX_CONNECTIONS_PER_USER streams
+        open on this instance
+    */
+    pub fn stream(&self, user_id: Uuid, organisation_id: Uuid, last_event_id: Option<Uuid>) -> Result<impl Stream<Item = StreamEvent>, ApplicationError> {
+        let connection = self.hub
+            .connect(user_id, *EVENT_STREAM_MAX_CONNECTIONS_PER_USER)
+            .ok_or(ApplicationError::TooManyConnections)?;
+
+        let Subscription { missed, receiver } = self.hub.subscribe(organisation_id, last_event_id);
+        let missed = match missed {
+            Some(missed) => missed.into_iter().map(StreamEvent::Activity).collect(),
+            None => vec![StreamEvent::Resync],
+        };
+        let live = futures::stream::unfold(receiver, |mut receiver| async move {
+            match receiver.recv().await {
+                Ok(activity) => Some((StreamEvent::Activity(activity), receiver)),
+                Err(RecvError::Lagged(_)) => Some((StreamEvent::Resync, receiver)),
+                Err(RecvError::Closed) => None,
+            }
+        });
+
+        let shutdown = self.shutdown.clone();
+        Ok(futures::stream::iter(missed)
+            .chain(live)
+            .take_until(async move { shutdown.wait().await })
+            // The connection counts until the stream is dropped
+            .map(move |event| {
+                let _connection = &connection;
+                event
+            }))
+    }
+
+    // The activities of the organisation from now on, for connections reacting to them
+    pub fn subscribe(&self, organisation_id: Uuid) -> broadcast::Receiver<Arc<Activity>> {
+        self.hub.subscribe(organisation_id, None).receiver
+    }
+
+    /*
+        Publishes the activities notified by any instance to the streams open on this one until shutdown,
+        reconnecting when the connection is lost. Activities notified while disconnected are missed
+    */
+    pub async fn listen(self, shutdown: ShutdownSignal) {
+        loop {
+            match Listener::connect(CHANNEL).await {
+                Ok(mut listener) => loop {
+                    tokio::select! {
+                        notification = listener.recv() => match notification {
+                            Some(Ok(payload)) => self.receive(&payload),
+                            Some(Err(e)) => {
+                                warn!("Activity listener failed: {}", e);
+                                break;
+                            },
+                            None => {
+                                warn!("Activity listener disconnected");
+                                break;
+                            },
+                        },
+                        _ = shutdown.wait() => return,
+                    }
+                },
+                Err(e) => warn!("Unable to listen for activity: {}", e),
+            }
+
+            tokio::select! {
+                _ = tokio::time::sleep(RECONNECT_DELAY) => info!("Reconnecting the activity listener"),
+                _ = shutdown.wait() => return,
+            }
+        }
+    }
+
+    fn receive(&self, payload: &str) {
+        match serde_json::from_str::<Activity>(payload) {
+            Ok(activity) => self.hub.publish(activity),
+            Err(e) => warn!("Unreadable activity {:?}: {}", payload, e),
+        }
+    }
+}
+
+/*
+    Notifies every instance, the outbox dispatches each event on one of them only
+*/
+#[async_trait]
+impl EventSubscriber for ActivityService {
+    fn name(&self) -> &'static str {
+        "activity"
+    }
+
+    async fn handle(&self, event: &OutboxEvent) -> Result<(), ApplicationError> {
+        match Activity::from_outbox(event) {
+            Some(activity) => {
+                debug!("Notifying activity: {:?}", activity.event_type);
+                let payload = serde_json::to_string(&activity).expect("Activities serialize to JSON");
+                self.activity_repository
+                    .notify(CHANNEL, &payload)
+                    .await
+                    .map(|_| ())
+                    .map_err(ApplicationError::from)
+            },
+            None => Ok(()),
+        }
+    }
+}
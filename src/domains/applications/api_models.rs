@@ -1,15 +1,16 @@
 use chrono::{NaiveDateTime, Utc};
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 use uuid::Uuid;
 use crate::domains::applications::db_models::{Application, PutApplication};
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
 pub struct ApplicationCreateRequest {
     pub name: String,
     pub description: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
 pub struct ApplicationPutRequest {
     pub name: String,
     pub description: Option<String>,
@@ -17,17 +18,19 @@ pub struct ApplicationPutRequest {
 
 impl ApplicationCreateRequest {
     pub fn into_with_organisation(self, organisation_id: Uuid) -> Application {
+        let now = Utc::now().naive_utc();
         Application {
             id: Uuid::now_v7(),
             organisation_id,
             name: self.name,
             description: self.description,
-            created_at: Utc::now().naive_utc(),
+            created_at: now,
+            updated_at: now,
         }
     }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct ApplicationResponse {
     pub id: Uuid,
     pub organisation_id: Uuid,
@@ -37,28 +40,29 @@ pub struct ApplicationResponse {
     pub created_at: NaiveDateTime
 }
 
-impl Into<ApplicationResponse> for Application {
-    fn into(self) -> ApplicationResponse {
+impl From<Application> for ApplicationResponse {
+    fn from(application: Application) -> Self {
         ApplicationResponse {
-            id: self.id,
-            organisation_id: self.organisation_id,
-            name: self.name,
-            description: self.description,
-            created_at: self.created_at,
+            id: application.id,
+            organisation_id: application.organisation_id,
+            name: application.name,
+            description: application.description,
+            created_at: application.created_at,
         }
     }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct ApplicationsResponse {
     pub data: Vec<ApplicationResponse>
 }
 
-impl Into<PutApplication> for ApplicationPutRequest {
-    fn into(self) -> PutApplication {
+impl From<ApplicationPutRequest> for PutApplication {
+    fn from(request: ApplicationPutRequest) -> Self {
         PutApplication {
-            name: self.name,
-            description: self.description,
+            name: request.name,
+            description: request.description,
+            updated_at: Utc::now().naive_utc(),
         }
     }
 }
\ No newline at end of file
@@ -1,11 +1,11 @@
 use chrono::NaiveDateTime;
-use diesel::{AsChangeset, Identifiable, Insertable, Queryable};
+use diesel::{AsChangeset, Identifiable, Insertable, Queryable, Selectable};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use crate::common::schema;
 
-#[derive(Insertable, Queryable, Debug, AsChangeset, Identifiable, Serialize, Deserialize)]
+#[derive(Insertable, Queryable, Selectable, Debug, AsChangeset, Identifiable, Serialize, Deserialize)]
 #[diesel(table_name = schema::application)]
 pub struct Application {
     pub id: Uuid,
@@ -13,6 +13,7 @@ pub struct Application {
     pub name: String,
     pub description: Option<String>,
     pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
 }
 
 #[derive(Debug, AsChangeset, Serialize)]
@@ -20,4 +21,5 @@ pub struct Application {
 pub struct PutApplication {
     pub name: String,
     pub description: Option<String>,
+    pub updated_at: NaiveDateTime,
 }
\ No newline at end of file
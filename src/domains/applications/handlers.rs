@@ -1,27 +1,48 @@
-use axum::extract::{Path, State};
+use axum::extract::{Path, Query, State};
+use axum::extract::rejection::QueryRejection;
 use axum::{Json, Router};
 use axum::routing::get;
-use http::{StatusCode};
+use axum::response::Response;
+use http::{HeaderMap, StatusCode};
 use uuid::Uuid;
 use crate::config::AppState;
 
+use crate::common::errors::api_error_response::ErrorResponse;
 use crate::common::errors::application_error::ApplicationError;
 use crate::common::errors::global_api_error::ApiError;
+use crate::common::errors::request_error::RequestError;
 use crate::common::extract::request::SwiftJson;
-use crate::common::models::models::{OrganisationId};
+use crate::common::models::models::{ExpectedVersion, OrganisationId};
 use crate::common::security;
+use crate::common::utils::etag;
 use crate::domains::applications::api_models::{ApplicationCreateRequest, ApplicationPutRequest, ApplicationResponse, ApplicationsResponse};
 use crate::domains::applications::db_models::Application;
 use crate::domains::applications::services::ApplicationService;
+use crate::domains::search::api_models::SearchFilter;
+use crate::domains::search::services::validate_search;
+use crate::domains::docs::params::{OrganisationHeader, IdempotencyKeyHeader, IfMatchHeader, IfNoneMatchHeader};
 
 
 pub fn routes() -> Router<AppState> {
-    return Router::new()
+    Router::new()
         .route("/applications", get(fetch_applications).post(create_applications))
         .route("/applications/:id", get(fetch_application).put(update_application).delete(delete_application))
         .route_layer(axum::middleware::from_fn(security::middleware::inject_organisation_id))
 }
 
+#[utoipa::path(
+    post,
+    path = "/applications",
+    tag = "applications",
+    params(OrganisationHeader, IdempotencyKeyHeader),
+    request_body = ApplicationCreateRequest,
+    responses(
+        (status = 201, description = "Application created", body = ApplicationResponse),
+        (status = 400, response = ErrorResponse),
+        (status = 401, response = ErrorResponse),
+        (status = 409, response = ErrorResponse),
+    ),
+)]
 async fn create_applications(
     State(application_service): State<ApplicationService>,
     organisation_id: OrganisationId,
@@ -36,55 +57,115 @@ async fn create_applications(
         .map(|response| (StatusCode::CREATED, Json(response)))
 }
 
+#[utoipa::path(
+    get,
+    path = "/applications",
+    tag = "applications",
+    params(("q" = Option<String>, Query, description = "Only the matches of these words, best first, at most 200 characters"), OrganisationHeader),
+    responses(
+        (status = 200, description = "Applications of the organisation", body = ApplicationsResponse),
+        (status = 400, response = ErrorResponse),
+        (status = 401, response = ErrorResponse),
+    ),
+)]
 async fn fetch_applications(
     State(application_service): State<ApplicationService>,
     organisation_id: OrganisationId,
-) -> Result<Json<ApplicationsResponse>, ApplicationError> {
+    filter: Result<Query<SearchFilter>, QueryRejection>,
+) -> Result<Json<ApplicationsResponse>, ApiError> {
+    let Query(filter) = filter.map_err(RequestError::from)?;
+    let search = filter.q.as_deref().map(validate_search).transpose()?;
 
-    application_service.find_all_by_organisation_id(organisation_id.0)
+    application_service.find_all_by_organisation_id(organisation_id.0, search)
         .await
+        .map_err(ApiError::from)
         .map(|applications|
             ApplicationsResponse {
                 data: applications.into_iter().map(Application::into).collect()
             }
         )
-        .map(|response| Json(response))
+        .map(Json)
 }
 
+#[utoipa::path(
+    get,
+    path = "/applications/{id}",
+    tag = "applications",
+    params(("id" = Uuid, Path), OrganisationHeader, IfNoneMatchHeader),
+    responses(
+        (status = 200, description = "The application", body = ApplicationResponse, headers(("ETag" = String))),
+        (status = 304, description = "Not modified since the ETag in If-None-Match"),
+        (status = 401, response = ErrorResponse),
+        (status = 404, response = ErrorResponse),
+    ),
+)]
 async fn fetch_application(
     State(application_service): State<ApplicationService>,
     organisation_id: OrganisationId,
     Path(application_id): Path<Uuid>,
-) -> Result<Json<ApplicationResponse>, ApplicationError> {
+    headers: HeaderMap,
+) -> Result<Response, ApplicationError> {
 
     application_service.find_by_id_and_organisation_id(&application_id, &organisation_id.0)
         .await
-        .map(Application::into)
-        .map(|response| Json(response))
+        .map(|application| {
+            let updated_at = application.updated_at;
+            etag::respond_with_version(&headers, &updated_at, Json::<ApplicationResponse>(application.into()))
+        })
 }
 
+#[utoipa::path(
+    put,
+    path = "/applications/{id}",
+    tag = "applications",
+    params(("id" = Uuid, Path), OrganisationHeader, IfMatchHeader),
+    request_body = ApplicationPutRequest,
+    responses(
+        (status = 204, description = "Application updated", headers(("ETag" = String, description = "The new version"))),
+        (status = 400, response = ErrorResponse),
+        (status = 401, response = ErrorResponse),
+        (status = 404, response = ErrorResponse),
+        (status = 412, response = ErrorResponse),
+        (status = 428, response = ErrorResponse),
+    ),
+)]
 async fn update_application(
     State(application_service): State<ApplicationService>,
     organisation_id: OrganisationId,
     Path(application_id): Path<Uuid>,
+    expected_version: ExpectedVersion,
     SwiftJson(application_put_request): SwiftJson<ApplicationPutRequest>,
-) -> Result<StatusCode, ApplicationError> {
+) -> Result<Response, ApplicationError> {
 
-    application_service.update_by_id_and_organisation_id(application_id, organisation_id.0, application_put_request)
+    application_service.update_by_id_and_organisation_id(application_id, organisation_id.0, expected_version, application_put_request)
         .await
-        .map(|_| StatusCode::NO_CONTENT)
+        .map(|updated_at| etag::updated(&updated_at))
 }
 
 /*
 Only admin is allowed to delete application
 */
+#[utoipa::path(
+    delete,
+    path = "/applications/{id}",
+    tag = "applications",
+    params(("id" = Uuid, Path), OrganisationHeader, IfMatchHeader),
+    responses(
+        (status = 204, description = "Application deleted"),
+        (status = 401, response = ErrorResponse),
+        (status = 404, response = ErrorResponse),
+        (status = 412, response = ErrorResponse),
+        (status = 428, response = ErrorResponse),
+    ),
+)]
 async fn delete_application(
     State(application_service): State<ApplicationService>,
     organisation_id: OrganisationId,
     Path(application_id): Path<Uuid>,
+    expected_version: ExpectedVersion,
 ) -> Result<StatusCode, ApplicationError> {
 
-    application_service.delete_by_id_and_organisation_id(application_id, organisation_id.0)
+    application_service.delete_by_id_and_organisation_id(application_id, organisation_id.0, expected_version)
         .await
         .map(|_| StatusCode::NO_CONTENT)
 }
\ No newline at end of file
@@ -1,81 +1,134 @@
+use chrono::NaiveDateTime;
+use diesel::dsl::sql;
 use diesel::prelude::*;
+use diesel::sql_types::{Bool, Float, Text};
 
-use bb8::{Pool};
-use diesel_async::{ AsyncPgConnection, RunQueryDsl};
-use diesel_async::pooled_connection::AsyncDieselConnectionManager;
+use diesel_async::AsyncPgConnection;
+use diesel_async::scoped_futures::ScopedFutureExt;
+use crate::common::query::RunQueryDsl;
+use tracing::instrument;
 use uuid::Uuid;
 use crate::common::errors::db_error::DbError;
 use crate::common::schema::application;
-use crate::common::repository::BaseRepository;
+use crate::common::repository::{self, BaseRepository};
+use crate::config::diesel_config::DbPools;
 use crate::domains::applications::db_models::{Application, PutApplication};
 
 #[derive(Clone)]
 pub struct ApplicationRepository {
-    pool: Pool<AsyncDieselConnectionManager<AsyncPgConnection>>,
+    pools: DbPools,
 }
 
 impl ApplicationRepository {
-    pub fn new(pool: Pool<AsyncDieselConnectionManager<AsyncPgConnection>>) -> Self {
-        ApplicationRepository { pool }
-    }
-
-    pub async fn insert(&self, application: &Application) -> Result<Application, DbError> {
-        let mut conn = self.conn().await?;
-
-        self.insert_with_conn(&mut conn, application).await
+    pub fn new(pools: DbPools) -> Self {
+        ApplicationRepository { pools }
     }
 
+    #[instrument(skip_all)]
     pub async fn insert_with_conn(&self,
                                   conn: &mut AsyncPgConnection,
                                   application: &Application
     ) -> Result<Application, DbError> {
         diesel::insert_into(application::table)
             .values(application)
+            .returning(Application::as_returning())
             .get_result(conn)
             .await
             .map_err(DbError::from)
     }
 
-    pub async fn find_all_by_organisation_id(&self, organisation_id: &Uuid) -> Result<Vec<Application>, DbError> {
-        let mut conn = self.conn().await?;
-        application::table
+    // With a search only the matching applications, ranked like /search
+    #[instrument(skip_all)]
+    pub async fn find_all_by_organisation_id(&self, organisation_id: &Uuid, search: Option<&str>) -> Result<Vec<Application>, DbError> {
+        let mut conn = self.read_conn().await?;
+        let mut query = application::table
             .filter(application::organisation_id.eq(organisation_id))
-            .get_results(&mut conn)
-            .await
-            .map_err(DbError::from)
+            .select(Application::as_select())
+            .into_boxed();
+        if let Some(search) = search {
+            query = query
+                .filter(sql::<Bool>("(application.search_vector @@ prefix_tsquery(").bind::<Text, _>(search)
+                    .sql(") OR ").bind::<Text, _>(search).sql(" <% (application.name || ' ' || coalesce(application.description, '')))"))
+                .order(sql::<Float>("ts_rank_cd(application.search_vector, prefix_tsquery(").bind::<Text, _>(search)
+                    .sql(")) + word_similarity(").bind::<Text, _>(search).sql(", application.name || ' ' || coalesce(application.description, ''))")
+                    .desc());
+        }
+
+        repository::scoped(&mut conn, |conn| async move {
+            query.get_results(conn)
+                .await
+                .map_err(DbError::from)
+        }.scope_boxed()).await
     }
 
+    #[instrument(skip_all)]
     pub async fn find_by_id_and_organisation_id(&self, application_id: &Uuid, organisation_id: &Uuid) -> Result<Option<Application>, DbError> {
-        let mut conn = self.conn().await?;
-        application::table
-            .filter(application::id.eq(application_id))
-            .filter(application::organisation_id.eq(organisation_id))
-            .get_result(&mut conn)
-            .await
-            .optional()
-            .map_err(DbError::from)
+        let mut conn = self.read_conn().await?;
+        repository::scoped(&mut conn, |conn| async move {
+            application::table
+                .filter(application::id.eq(application_id))
+                .filter(application::organisation_id.eq(organisation_id))
+                .select(Application::as_select())
+                .get_result(conn)
+                .await
+                .optional()
+                .map_err(DbError::from)
+        }.scope_boxed()).await
     }
 
-    pub async fn update_by_id_and_organisation_id(&self, id: &Uuid, organisation_id: &Uuid, application: PutApplication) -> Result<usize, DbError> {
-        let mut conn = self.conn().await?;
-        diesel::update(application::table.find(id).filter(application::organisation_id.eq(organisation_id)))
-            .set(&application)
-            .execute(&mut conn)
+    // Always the primary, a conditional change must compare against the current version
+    #[instrument(skip_all)]
+    pub async fn find_version(&self, id: &Uuid, organisation_id: &Uuid) -> Result<Option<NaiveDateTime>, DbError> {
+        let mut conn = self.fresh_read_conn().await?;
+        repository::scoped(&mut conn, |conn| async move {
+            application::table
+                .filter(application::id.eq(id))
+                .filter(application::organisation_id.eq(organisation_id))
+                .select(application::updated_at)
+                .get_result(conn)
+                .await
+                .optional()
+                .map_err(DbError::from)
+        }.scope_boxed()).await
+    }
+
+    #[instrument(skip_all)]
+    pub async fn update_by_id_and_organisation_id_with_conn(&self,
+                                                            conn: &mut AsyncPgConnection,
+                                                            id: &Uuid,
+                                                            organisation_id: &Uuid,
+                                                            versions: &[NaiveDateTime],
+                                                            application: &PutApplication
+    ) -> Result<Option<NaiveDateTime>, DbError> {
+        diesel::update(application::table.find(id)
+                .filter(application::organisation_id.eq(organisation_id))
+                .filter(application::updated_at.eq_any(versions)))
+            .set(application)
+            .returning(application::updated_at)
+            .get_result(conn)
             .await
+            .optional()
             .map_err(DbError::from)
     }
 
-    pub async fn delete_by_id_and_organisation_id(&self, id: &Uuid, organisation_id: &Uuid) -> Result<usize, DbError> {
-        let mut conn = self.conn().await?;
-        diesel::delete(application::table.filter(application::id.eq(id)).filter(application::organisation_id.eq(organisation_id)))
-            .execute(&mut conn)
+    #[instrument(skip_all)]
+    pub async fn delete_by_id_and_organisation_id_with_conn(&self,
+                                                            conn: &mut AsyncPgConnection,
+                                                            id: &Uuid,
+                                                            organisation_id: &Uuid,
+                                                            versions: &[NaiveDateTime]
+    ) -> Result<usize, DbError> {
+        diesel::delete(application::table.filter(application::id.eq(id))
+                .filter(application::organisation_id.eq(organisation_id))
+                .filter(application::updated_at.eq_any(versions)))
+            .execute(conn)
             .await
             .map_err(DbError::from)
     }
 }
 
 impl BaseRepository for ApplicationRepository {
-    fn pool(&self) -> &Pool<AsyncDieselConnectionManager<AsyncPgConnection>> {
-        &self.pool
+    fn pools(&self) -> &DbPools {
+        &self.pools
     }
 }
\ No newline at end of file
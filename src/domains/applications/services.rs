@@ -1,36 +1,53 @@
+use chrono::NaiveDateTime;
+use diesel_async::scoped_futures::ScopedFutureExt;
 use tracing::{debug, info};
 use uuid::Uuid;
 
 use crate::common::errors::application_error::ApplicationError;
 use crate::common::errors::application_error::ApplicationError::NotFound;
+use crate::common::models::models::ExpectedVersion;
+use crate::common::repository::{BaseRepository, TransactionOptions};
 use crate::domains::applications::api_models::ApplicationPutRequest;
-use crate::domains::applications::db_models::Application;
+use crate::domains::applications::db_models::{Application, PutApplication};
 use crate::domains::applications::repository::ApplicationRepository;
+use crate::domains::outbox::events::DomainEvent;
+use crate::domains::outbox::repository::OutboxRepository;
 
 #[derive(Clone)]
 pub struct ApplicationService {
     application_repository: ApplicationRepository,
+    outbox_repository: OutboxRepository,
 }
 
 impl ApplicationService {
-    pub fn new(application_repository: ApplicationRepository) -> Self {
-        ApplicationService { application_repository }
+    pub fn new(application_repository: ApplicationRepository, outbox_repository: OutboxRepository) -> Self {
+        ApplicationService { application_repository, outbox_repository }
     }
 
     pub async fn create(&self, application: Application) -> Result<Application, ApplicationError> {
-        let created_application = self.application_repository
-            .insert(&application)
-            .await
-            .map_err(ApplicationError::from);
+        let application = &application;
+        let created_application = self.application_repository.in_transaction(TransactionOptions::default(), |mut uow| async move {
+            let created = self.application_repository
+                .insert_with_conn(uow.conn(), application)
+                .await?;
+
+            self.outbox_repository.insert_with_conn(uow.conn(), &DomainEvent::ApplicationCreated {
+                organisation_id: created.organisation_id,
+                application_id: created.id,
+                name: created.name.clone(),
+            }).await?;
+
+            Ok(created)
+        }.scope_boxed()).await.map_err(ApplicationError::from)?;
 
         info!("Application successfully created");
-        created_application
+        Ok(created_application)
     }
 
-    pub async fn find_all_by_organisation_id(&self, organisation_id: Uuid) -> Result<Vec<Application>, ApplicationError> {
+    pub async fn find_all_by_organisation_id(&self, organisation_id: Uuid, search: Option<&str>) -> Result<Vec<Application>, ApplicationError> {
         debug!("Finding applications...");
         self.application_repository
-            .find_all_by_organisation_id(&organisation_id)
+            .find_all_by_organisation_id(&organisation_id, search)
             .await
             .map_err(ApplicationError::from)
     }
@@ -54,34 +71,55 @@ impl ApplicationService {
         }
     }
 
-    pub async fn update_by_id_and_organisation_id(&self, id: Uuid, organisation_id: Uuid, organisation_request: ApplicationPutRequest) -> Result<(), ApplicationError> {
+    // Conditional on the expected version, see ExpectedVersion::apply
+    pub async fn update_by_id_and_organisation_id(&self,
+                                                  id: Uuid,
+                                                  organisation_id: Uuid,
+                                                  expected_version: ExpectedVersion,
+                                                  organisation_request: ApplicationPutRequest
+    ) -> Result<NaiveDateTime, ApplicationError> {
         debug!("Updating application by id: {:?}", id);
+        let application: &PutApplication = &organisation_request.into();
 
-        let row_updated = self.application_repository
-            .update_by_id_and_organisation_id(&id, &organisation_id, organisation_request.into())
-            .await
-            .map_err(ApplicationError::from)?;
+        expected_version.apply(|| self.current_version(&id, &organisation_id), |versions| async move {
+            let versions = &versions;
+            self.application_repository.in_transaction(TransactionOptions::default(), |mut uow| async move {
+                let updated_at = self.application_repository
+                    .update_by_id_and_organisation_id_with_conn(uow.conn(), &id, &organisation_id, versions, application)
+                    .await?;
 
-        debug!("Rows updated {:?}", row_updated);
-        if row_updated == 0 {
-            Err(NotFound)
-        } else {
-            Ok(())
-        }
+                if updated_at.is_some() {
+                    self.outbox_repository.insert_with_conn(uow.conn(), &DomainEvent::ApplicationUpdated { organisation_id, application_id: id }).await?;
+                }
+                Ok(updated_at)
+            }.scope_boxed()).await.map_err(ApplicationError::from)
+        }).await
     }
 
-    pub async fn delete_by_id_and_organisation_id(&self, id: Uuid, organisation_id: Uuid) -> Result<(), ApplicationError> {
+    // Conditional on the expected version, see ExpectedVersion::apply
+    pub async fn delete_by_id_and_organisation_id(&self, id: Uuid, organisation_id: Uuid, expected_version: ExpectedVersion) -> Result<(), ApplicationError> {
         debug!("Deleting application by id: {:?}", id);
 
-        let row_updated = self.application_repository
-            .delete_by_id_and_organisation_id(&id, &organisation_id)
-            .await
-            .map_err(ApplicationError::from)?;
+        expected_version.apply(|| self.current_version(&id, &organisation_id), |versions| async move {
+            let versions = &versions;
+            let row_updated = self.application_repository.in_transaction(TransactionOptions::default(), |mut uow| async move {
+                let row_updated = self.application_repository
+                    .delete_by_id_and_organisation_id_with_conn(uow.conn(), &id, &organisation_id, versions)
+                    .await?;
 
-        if row_updated == 0 {
-            Err(NotFound)
-        } else {
-            Ok(())
-        }
+                if row_updated > 0 {
+                    self.outbox_repository.insert_with_conn(uow.conn(), &DomainEvent::ApplicationDeleted { organisation_id, application_id: id }).await?;
+                }
+                Ok(row_updated)
+            }.scope_boxed()).await?;
+            Ok((row_updated > 0).then_some(()))
+        }).await
+    }
+
+    async fn current_version(&self, id: &Uuid, organisation_id: &Uuid) -> Result<NaiveDateTime, ApplicationError> {
+        self.application_repository
+            .find_version(id, organisation_id)
+            .await?
+            .ok_or(NotFound)
     }
 }
\ No newline at end of file
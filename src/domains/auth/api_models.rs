@@ -1,12 +1,32 @@
+use std::fmt;
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use crate::common::utils::constants::REDACTED;
 
-#[derive(Debug, Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct LoginRequest {
     pub email: String,
     pub password: String,
 }
 
-#[derive(Debug, Serialize)]
+impl fmt::Debug for LoginRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LoginRequest")
+            .field("email", &self.email)
+            .field("password", &REDACTED)
+            .finish()
+    }
+}
+
+#[derive(Serialize, ToSchema)]
 pub struct LoginResponse {
     pub access_token: String,
+}
+
+impl fmt::Debug for LoginResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LoginResponse")
+            .field("access_token", &REDACTED)
+            .finish()
+    }
 }
\ No newline at end of file
@@ -2,17 +2,17 @@ use axum::extract::State;
 use axum::{Json, Router};
 use axum::routing::post;
 use http::{header, StatusCode};
-use argon2::{self, Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
-use argon2::password_hash::rand_core::OsRng;
-use argon2::password_hash::SaltString;
+use argon2::{self, Argon2, PasswordHash, PasswordVerifier};
 use axum::response::{IntoResponse, Response};
-use tracing::{debug, error};
+use tracing::debug;
 use crate::config::AppState;
+use crate::common::errors::api_error_response::ErrorResponse;
 use crate::common::errors::application_error::ApplicationError;
 use crate::common::errors::global_api_error::ApiError;
 use crate::common::extract::request::SwiftJson;
 use crate::common::security::jwt;
 use crate::common::security::jwt::{Claims};
+use crate::common::security::password;
 use crate::common::utils;
 use crate::config::app_env::JWT_AUD;
 use crate::domains::auth::api_models::{LoginRequest, LoginResponse};
@@ -22,11 +22,23 @@ use crate::domains::users::db_models::SwiftUser;
 use crate::domains::users::services::UserService;
 
 pub fn routes() -> Router<AppState> {
-    return Router::new()
+    Router::new()
         .route("/auth/signup", post(auth_signup_user))
         .route("/auth/login", post(auth_login_user))
 }
 
+#[utoipa::path(
+    post,
+    path = "/auth/signup",
+    tag = "auth",
+    request_body = LoginRequest,
+    responses(
+        (status = 201, description = "Signed up, the token is also set as the access_token cookie", body = LoginResponse),
+        (status = 400, response = ErrorResponse),
+        (status = 409, response = ErrorResponse),
+    ),
+    security(()),
+)]
 async fn auth_signup_user(
     State(user_service): State<UserService>,
     SwiftJson(signup_request): SwiftJson<LoginRequest>,
@@ -50,21 +62,36 @@ async fn auth_signup_user(
     Ok(response)
 }
 
+#[utoipa::path(
+    post,
+    path = "/auth/login",
+    tag = "auth",
+    request_body = LoginRequest,
+    responses(
+        (status = 200, description = "Logged in, the token is also set as the access_token cookie", body = LoginResponse),
+        (status = 400, response = ErrorResponse),
+        (status = 404, response = ErrorResponse),
+    ),
+    security(()),
+)]
 async fn auth_login_user(
     State(user_service): State<UserService>,
     State(organisation_service): State<OrganisationService>,
     SwiftJson(login_request): SwiftJson<LoginRequest>,
 ) -> Result<impl IntoResponse, ApiError> {
 
-    let user: SwiftUser = user_service.find_by_email(&login_request.email).await?;
+    let user: SwiftUser = user_service.find_by_email(&login_request.email)
+        .await
+        .inspect_err(|_| record_login("failure"))?;
 
     debug!("Comparing password...");
     user.password.as_ref()
         .ok_or(ApplicationError::LoginError) // Maybe add passwordDoesNotExist
         .and_then(|p| PasswordHash::new(p).map_err(|_| ApplicationError::InternalServerError))
         .and_then(|user_password| Argon2::default()
-            .verify_password(&login_request.password.as_bytes(), &user_password)
-            .map_err(|_| ApplicationError::LoginError))?;
+            .verify_password(login_request.password.as_bytes(), &user_password)
+            .map_err(|_| ApplicationError::LoginError))
+        .inspect_err(|_| record_login("failure"))?;
     debug!("Finished comparing password.");
 
     let organisations = organisation_service.find_all(&user.id).await?;
@@ -76,29 +103,25 @@ async fn auth_login_user(
 
     set_cookie(&claims, access_token, &mut response);
 
+    record_login("success");
     Ok(response)
 }
 
+fn record_login(outcome: &'static str) {
+    metrics::counter!("auth_login_total", "outcome" => outcome).increment(1);
+}
+
 fn set_cookie(claims: &Claims, access_token: String, response: &mut Response) {
     let cookie = utils::cookie::build_cookie(claims, "access_token".to_string(), access_token, JWT_AUD.clone());
     response.headers_mut().insert(header::SET_COOKIE, cookie.to_string().parse().unwrap());
 }
 
 fn convert_to_user_request(signup_request: LoginRequest) -> Result<UserCreateRequest, ApiError> {
-    let salt = SaltString::generate(&mut OsRng);
-
     Ok(UserCreateRequest {
         email: signup_request.email,
-        password:
-        Some(Argon2::default()
-            .hash_password(signup_request.password.as_bytes(), &salt)
-            .map_err(|e| {
-                error!("Failed to hash password: {:?}", e);
-                ApplicationError::InternalServerError
-            })
-            .map(|password_hash| password_hash.to_string())?
-        ),
+        password: Some(password::hash_password(&signup_request.password)?),
         first_name: "John".to_string(),
         last_name: Some("Doe".to_string()),
+        locale: None,
     })
 }
\ No newline at end of file
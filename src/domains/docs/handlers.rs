@@ -0,0 +1,23 @@
+use axum::{Json, Router};
+use axum::response::Html;
+use axum::routing::get;
+use utoipa::OpenApi;
+use crate::config::AppState;
+use crate::domains::docs::openapi::ApiDoc;
+
+/*
+    Public so the docs can be browsed before logging in
+*/
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/openapi.json", get(fetch_openapi))
+        .route("/docs", get(fetch_docs))
+}
+
+async fn fetch_openapi() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+async fn fetch_docs() -> Html<&'static str> {
+    Html(include_str!("docs.html"))
+}
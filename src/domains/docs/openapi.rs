@@ -0,0 +1,110 @@
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+use crate::common::errors::api_error_response::{ErrorMessage, ErrorResponse};
+use crate::domains::{activity, applications, auth, health, jobs, logging, mail, organisations, realtime, search, users, webhooks};
+
+/*
+    Generated from the `#[utoipa::path]` annotations of the handlers and the schemas of their api models.
+    Every operation requires a bearer token or the access_token cookie unless it opts out.
+    Only the versioned paths are listed, the unversioned aliases serve the default version
+*/
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Swift API", description = "Organisations, their users and applications"),
+    paths(
+        health::handlers::live,
+        health::handlers::ready,
+    ),
+    nest((path = "/v1", api = V1Api)),
+    components(schemas(ErrorMessage), responses(ErrorResponse)),
+    modifiers(&SecuritySchemes),
+    security(("bearer" = []), ("cookie" = [])),
+    tags(
+        (name = "auth", description = "Sign up and log in, the only operations without a token"),
+        (name = "organisations", description = "Organisations the caller is a member of"),
+        (name = "users", description = "Users of the organisation in x-organisation-id"),
+        (name = "applications", description = "Applications of the organisation in x-organisation-id"),
+        (name = "search", description = "Users and applications of the organisation in x-organisation-id by name, email or description"),
+        (name = "webhooks", description = "Endpoints the organisation in x-organisation-id receives signed events on, organisation admins only"),
+        (name = "events", description = "Live changes and presence in an organisation"),
+        (name = "health", description = "Probes and health details"),
+        (name = "admin", description = "Super admin operations"),
+    ),
+)]
+pub struct ApiDoc;
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        auth::handlers::auth_signup_user,
+        auth::handlers::auth_login_user,
+        organisations::handlers::create_organisation,
+        organisations::handlers::fetch_organisations,
+        organisations::handlers::fetch_organisation,
+        organisations::handlers::fetch_organisation_users,
+        organisations::handlers::update_organisation,
+        organisations::handlers::delete_organisation,
+        users::handlers::create_user,
+        users::handlers::fetch_users,
+        users::handlers::fetch_user,
+        users::handlers::update_user,
+        users::handlers::delete_user,
+        applications::handlers::create_applications,
+        applications::handlers::fetch_applications,
+        applications::handlers::fetch_application,
+        applications::handlers::update_application,
+        applications::handlers::delete_application,
+        webhooks::handlers::create_webhook,
+        webhooks::handlers::fetch_webhooks,
+        webhooks::handlers::fetch_webhook,
+        webhooks::handlers::update_webhook,
+        webhooks::handlers::delete_webhook,
+        webhooks::handlers::fetch_webhook_deliveries,
+        webhooks::handlers::redeliver_webhook_delivery,
+        health::handlers::health,
+        logging::handlers::fetch_log_level,
+        logging::handlers::update_log_level,
+        jobs::handlers::fetch_failed_jobs,
+        jobs::handlers::retry_job,
+        mail::handlers::fetch_email_suppressions,
+        mail::handlers::suppress_email,
+        mail::handlers::unsuppress_email,
+        activity::handlers::stream_events,
+        realtime::handlers::connect_websocket,
+        search::handlers::search,
+    ),
+)]
+struct V1Api;
+
+struct SecuritySchemes;
+
+impl Modify for SecuritySchemes {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        // Cargo.toml has no license to fill it from
+        openapi.info.license = None;
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme("bearer", SecurityScheme::Http(
+            HttpBuilder::new().scheme(HttpAuthScheme::Bearer).bearer_format("JWT").build()));
+        components.add_security_scheme("cookie", SecurityScheme::ApiKey(
+            ApiKey::Cookie(ApiKeyValue::new("access_token"))));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SNAPSHOT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/openapi.json");
+
+    // After an intended API change: UPDATE_OPENAPI_SNAPSHOT=1 cargo test openapi, then commit openapi.json
+    #[test]
+    fn openapi_matches_snapshot() {
+        let generated = ApiDoc::openapi().to_pretty_json().unwrap() + "\n";
+        if std::env::var("UPDATE_OPENAPI_SNAPSHOT").is_ok() {
+            std::fs::write(SNAPSHOT, &generated).unwrap();
+        }
+
+        let committed = std::fs::read_to_string(SNAPSHOT).unwrap_or_default();
+        assert!(committed == generated, "The OpenAPI spec changed, run UPDATE_OPENAPI_SNAPSHOT=1 cargo test openapi and commit openapi.json");
+    }
+}
@@ -0,0 +1,42 @@
+use utoipa::IntoParams;
+use uuid::Uuid;
+
+/*
+    Request headers shared by many operations, listed in the `#[utoipa::path]` params of the handlers.
+    Never constructed, the handlers read the headers through their extractors and middleware
+*/
+#[allow(dead_code)]
+#[derive(IntoParams)]
+#[into_params(parameter_in = Header)]
+pub struct OrganisationHeader {
+    /// Organisation the request operates in, one of the organisations in the access token
+    #[param(rename = "x-organisation-id")]
+    organisation_id: Uuid,
+}
+
+#[allow(dead_code)]
+#[derive(IntoParams)]
+#[into_params(parameter_in = Header)]
+pub struct IdempotencyKeyHeader {
+    /// Makes retries safe, the first response for the key is replayed
+    #[param(rename = "Idempotency-Key")]
+    idempotency_key: Option<String>,
+}
+
+#[allow(dead_code)]
+#[derive(IntoParams)]
+#[into_params(parameter_in = Header)]
+pub struct IfMatchHeader {
+    /// ETag from the last read, `*` skips the version check
+    #[param(rename = "If-Match")]
+    if_match: String,
+}
+
+#[allow(dead_code)]
+#[derive(IntoParams)]
+#[into_params(parameter_in = Header)]
+pub struct IfNoneMatchHeader {
+    /// ETag from the last read, answered with 304 while it is current
+    #[param(rename = "If-None-Match")]
+    if_none_match: Option<String>,
+}
@@ -0,0 +1,48 @@
+use serde::Serialize;
+use utoipa::ToSchema;
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, ToSchema)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum HealthStatus {
+    Up,
+    Down,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct HealthCheck {
+    pub name: String,
+    pub status: HealthStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct LivenessResponse {
+    pub status: HealthStatus,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ReadinessResponse {
+    pub status: HealthStatus,
+    pub checks: Vec<HealthCheck>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PoolStatsResponse {
+    pub connections: u32,
+    pub idle_connections: u32,
+    pub waiters: u64,
+    pub get_direct: u64,
+    pub get_waited: u64,
+    pub get_timed_out: u64,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct HealthResponse {
+    pub status: HealthStatus,
+    pub version: String,
+    pub git_sha: String,
+    pub uptime_in_secs: u64,
+    pub pool: PoolStatsResponse,
+    pub checks: Vec<HealthCheck>,
+}
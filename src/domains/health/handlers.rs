@@ -0,0 +1,89 @@
+use axum::extract::State;
+use axum::{Json, Router};
+use axum::routing::get;
+use http::StatusCode;
+use crate::config::AppState;
+use crate::common::errors::api_error_response::ErrorResponse;
+use crate::common::errors::application_error::ApplicationError;
+use crate::common::models::models::Identity;
+use crate::domains::health::api_models::{HealthResponse, HealthStatus, LivenessResponse, ReadinessResponse};
+use crate::domains::health::services::HealthService;
+use crate::domains::users::services::UserService;
+
+/*
+    Probes for the orchestrator, must stay unauthenticated
+*/
+pub fn public_routes() -> Router<AppState> {
+    Router::new()
+        .route("/health/live", get(live))
+        .route("/health/ready", get(ready))
+}
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/health", get(health))
+}
+
+#[utoipa::path(
+    get,
+    path = "/health/live",
+    tag = "health",
+    responses(
+        (status = 200, description = "The process is running", body = LivenessResponse),
+    ),
+    security(()),
+)]
+async fn live() -> Json<LivenessResponse> {
+    Json(LivenessResponse { status: HealthStatus::Up })
+}
+
+#[utoipa::path(
+    get,
+    path = "/health/ready",
+    tag = "health",
+    responses(
+        (status = 200, description = "Ready to serve traffic", body = ReadinessResponse),
+        (status = 503, description = "Not ready, see the failing checks", body = ReadinessResponse),
+    ),
+    security(()),
+)]
+async fn ready(
+    State(health_service): State<HealthService>,
+) -> (StatusCode, Json<ReadinessResponse>) {
+    let readiness = health_service.readiness().await;
+    (status_code(readiness.status), Json(readiness))
+}
+
+/*
+Only super admin is allowed to see pool and build details
+*/
+#[utoipa::path(
+    get,
+    path = "/health",
+    tag = "health",
+    responses(
+        (status = 200, description = "Healthy", body = HealthResponse),
+        (status = 503, description = "Unhealthy, see the failing checks", body = HealthResponse),
+        (status = 401, response = ErrorResponse),
+        (status = 403, response = ErrorResponse),
+    ),
+)]
+async fn health(
+    identity: Identity,
+    State(health_service): State<HealthService>,
+    State(user_service): State<UserService>,
+) -> Result<(StatusCode, Json<HealthResponse>), ApplicationError> {
+    if !user_service.is_super_admin(&identity.user_id).await? {
+        return Err(ApplicationError::Forbidden);
+    }
+
+    let health = health_service.health().await;
+    Ok((status_code(health.status), Json(health)))
+}
+
+fn status_code(status: HealthStatus) -> StatusCode {
+    match status {
+        HealthStatus::Up => StatusCode::OK,
+        HealthStatus::Down => StatusCode::SERVICE_UNAVAILABLE,
+    }
+}
@@ -0,0 +1,4 @@
+pub mod handlers;
+pub mod services;
+pub mod repository;
+pub mod api_models;
@@ -0,0 +1,52 @@
+use bb8::State;
+use diesel::sql_types::Text;
+use diesel::{QueryableByName, sql_query};
+use crate::common::query::RunQueryDsl;
+use crate::common::errors::db_error::DbError;
+use crate::common::repository::BaseRepository;
+use crate::config::diesel_config::DbPools;
+
+#[derive(QueryableByName)]
+struct MigrationVersion {
+    #[diesel(sql_type = Text)]
+    version: String,
+}
+
+#[derive(Clone)]
+pub struct HealthRepository {
+    pools: DbPools,
+}
+
+impl HealthRepository {
+    pub fn new(pools: DbPools) -> Self {
+        HealthRepository { pools }
+    }
+
+    pub async fn ping(&self) -> Result<(), DbError> {
+        let mut conn = self.fresh_read_conn().await?;
+        sql_query("SELECT 1")
+            .execute(&mut conn)
+            .await
+            .map(|_| ())
+            .map_err(DbError::from)
+    }
+
+    pub async fn find_applied_migrations(&self) -> Result<Vec<String>, DbError> {
+        let mut conn = self.fresh_read_conn().await?;
+        sql_query("SELECT version FROM __diesel_schema_migrations")
+            .load::<MigrationVersion>(&mut conn)
+            .await
+            .map(|versions| versions.into_iter().map(|v| v.version).collect())
+            .map_err(DbError::from)
+    }
+
+    pub fn pool_state(&self) -> State {
+        self.pools.primary.state()
+    }
+}
+
+impl BaseRepository for HealthRepository {
+    fn pools(&self) -> &DbPools {
+        &self.pools
+    }
+}
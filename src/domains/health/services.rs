@@ -0,0 +1,127 @@
+use std::collections::HashSet;
+use std::time::Instant;
+use diesel::pg::Pg;
+use diesel::migration::MigrationSource;
+use tracing::{debug, error};
+use crate::common::repository;
+use crate::common::security::jwt;
+use crate::config::diesel_config::MIGRATIONS;
+use crate::domains::health::api_models::{HealthCheck, HealthResponse, HealthStatus, PoolStatsResponse, ReadinessResponse};
+use crate::domains::health::repository::HealthRepository;
+use crate::server::shutdown::ShutdownSignal;
+
+#[derive(Clone)]
+pub struct HealthService {
+    health_repository: HealthRepository,
+    shutdown: ShutdownSignal,
+    started_at: Instant,
+}
+
+impl HealthService {
+    pub fn new(health_repository: HealthRepository, shutdown: ShutdownSignal) -> Self {
+        HealthService { health_repository, shutdown, started_at: Instant::now() }
+    }
+
+    pub async fn readiness(&self) -> ReadinessResponse {
+        debug!("Checking readiness...");
+        let checks = vec![
+            self.check_shutdown(),
+            self.check_database().await,
+            self.check_migrations().await,
+            check_keys(),
+        ];
+
+        ReadinessResponse {
+            status: overall_status(&checks),
+            checks,
+        }
+    }
+
+    pub async fn health(&self) -> HealthResponse {
+        let readiness = self.readiness().await;
+        let state = self.health_repository.pool_state();
+
+        HealthResponse {
+            status: readiness.status,
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            git_sha: env!("GIT_SHA").to_string(),
+            uptime_in_secs: self.started_at.elapsed().as_secs(),
+            pool: PoolStatsResponse {
+                connections: state.connections,
+                idle_connections: state.idle_connections,
+                waiters: repository::pool_waiters(),
+                get_direct: state.statistics.get_direct,
+                get_waited: state.statistics.get_waited,
+                get_timed_out: state.statistics.get_timed_out,
+            },
+            checks: readiness.checks,
+        }
+    }
+
+    fn check_shutdown(&self) -> HealthCheck {
+        match self.shutdown.is_shutting_down() {
+            true => down("shutdown", "Server is shutting down".to_string()),
+            false => up("shutdown"),
+        }
+    }
+
+    async fn check_database(&self) -> HealthCheck {
+        match self.health_repository.ping().await {
+            Ok(_) => up("database"),
+            Err(e) => {
+                error!("Database readiness check failed: {:?}", e);
+                down("database", "Unable to query database".to_string())
+            }
+        }
+    }
+
+    async fn check_migrations(&self) -> HealthCheck {
+        let applied: HashSet<String> = match self.health_repository.find_applied_migrations().await {
+            Ok(versions) => versions.into_iter().collect(),
+            Err(e) => {
+                error!("Migration readiness check failed: {:?}", e);
+                return down("migrations", "Unable to read applied migrations".to_string());
+            }
+        };
+
+        let embedded = match MigrationSource::<Pg>::migrations(&MIGRATIONS) {
+            Ok(migrations) => migrations,
+            Err(e) => {
+                error!("Unable to read embedded migrations: {:?}", e);
+                return down("migrations", "Unable to read embedded migrations".to_string());
+            }
+        };
+
+        let pending: Vec<String> = embedded.iter()
+            .map(|migration| migration.name().version().to_string())
+            .filter(|version| !applied.contains(version))
+            .collect();
+
+        match pending.is_empty() {
+            true => up("migrations"),
+            false => down("migrations", format!("Pending migrations: {}", pending.join(", "))),
+        }
+    }
+}
+
+fn check_keys() -> HealthCheck {
+    match jwt::check_keys() {
+        true => up("keys"),
+        false => down("keys", "JWT keys are not usable".to_string()),
+    }
+}
+
+fn overall_status(checks: &[HealthCheck]) -> HealthStatus {
+    match checks.iter().all(|check| check.status == HealthStatus::Up) {
+        true => HealthStatus::Up,
+        false => HealthStatus::Down,
+    }
+}
+
+fn up(name: &str) -> HealthCheck {
+    HealthCheck { name: name.to_string(), status: HealthStatus::Up, detail: None }
+}
+
+fn down(name: &str, detail: String) -> HealthCheck {
+    HealthCheck { name: name.to_string(), status: HealthStatus::Down, detail: Some(detail) }
+}
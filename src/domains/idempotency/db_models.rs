@@ -0,0 +1,13 @@
+use diesel::Queryable;
+
+/*
+    What a retry is compared against and replayed from, the response columns stay empty
+    while the first request is still being handled
+*/
+#[derive(Queryable, Debug)]
+pub struct IdempotencyKey {
+    pub request_fingerprint: String,
+    pub response_status: Option<i16>,
+    pub response_headers: Option<serde_json::Value>,
+    pub response_body: Option<Vec<u8>>,
+}
@@ -0,0 +1,3 @@
+pub mod db_models;
+pub mod repository;
+pub mod services;
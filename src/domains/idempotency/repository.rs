@@ -0,0 +1,127 @@
+use std::time::Duration;
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::{Double, Text, Uuid as SqlUuid};
+use crate::common::query::RunQueryDsl;
+use tracing::instrument;
+use uuid::Uuid;
+use crate::common::errors::db_error::DbError;
+use crate::common::schema::idempotency_key;
+use crate::common::repository::BaseRepository;
+use crate::config::diesel_config::DbPools;
+use crate::domains::idempotency::db_models::IdempotencyKey;
+
+#[derive(Clone)]
+pub struct IdempotencyRepository {
+    pools: DbPools,
+}
+
+impl IdempotencyRepository {
+    pub fn new(pools: DbPools) -> Self {
+        IdempotencyRepository { pools }
+    }
+
+    /*
+        Locks the key for the caller, returning the token that completes or releases it, None when the key
+        is already taken. An expired key, or one whose lock outlived `lock_timeout` for the same request, is taken over
+    */
+    #[instrument(skip_all)]
+    pub async fn claim(&self,
+                       swift_user_id: &Uuid,
+                       key: &str,
+                       request_fingerprint: &str,
+                       ttl: Duration,
+                       lock_timeout: Duration
+    ) -> Result<Option<Uuid>, DbError> {
+        let claim_token = Uuid::now_v7();
+        let mut conn = self.conn().await?;
+        sql_query("INSERT INTO idempotency_key (swift_user_id, key, request_fingerprint, expires_at, claim_token) \
+                   VALUES ($1, $2, $3, NOW() + make_interval(secs => $4), $6) \
+                   ON CONFLICT (swift_user_id, key) DO UPDATE \
+                   SET request_fingerprint = EXCLUDED.request_fingerprint, \
+                       response_status = NULL, response_headers = NULL, response_body = NULL, \
+                       locked_at = NOW(), created_at = NOW(), expires_at = EXCLUDED.expires_at, \
+                       claim_token = EXCLUDED.claim_token \
+                   WHERE idempotency_key.expires_at < NOW() \
+                      OR (idempotency_key.response_status IS NULL \
+                          AND idempotency_key.request_fingerprint = EXCLUDED.request_fingerprint \
+                          AND idempotency_key.locked_at < NOW() - make_interval(secs => $5))")
+            .bind::<SqlUuid, _>(swift_user_id)
+            .bind::<Text, _>(key)
+            .bind::<Text, _>(request_fingerprint)
+            .bind::<Double, _>(ttl.as_secs_f64())
+            .bind::<Double, _>(lock_timeout.as_secs_f64())
+            .bind::<SqlUuid, _>(claim_token)
+            .execute(&mut conn)
+            .await
+            .map(|rows| (rows == 1).then_some(claim_token))
+            .map_err(DbError::from)
+    }
+
+    // Always the primary, a replica may not have seen the claim or the stored response yet
+    #[instrument(skip_all)]
+    pub async fn find_by_key(&self, swift_user_id: &Uuid, key: &str) -> Result<Option<IdempotencyKey>, DbError> {
+        let mut conn = self.fresh_read_conn().await?;
+        idempotency_key::table
+            .find((swift_user_id, key))
+            .select((
+                idempotency_key::request_fingerprint,
+                idempotency_key::response_status,
+                idempotency_key::response_headers,
+                idempotency_key::response_body,
+            ))
+            .get_result(&mut conn)
+            .await
+            .optional()
+            .map_err(DbError::from)
+    }
+
+    #[instrument(skip_all)]
+    pub async fn complete(&self,
+                          swift_user_id: &Uuid,
+                          key: &str,
+                          claim_token: &Uuid,
+                          status: i16,
+                          headers: serde_json::Value,
+                          body: &[u8]
+    ) -> Result<usize, DbError> {
+        let mut conn = self.conn().await?;
+        diesel::update(idempotency_key::table.find((swift_user_id, key))
+                .filter(idempotency_key::claim_token.eq(claim_token))
+                .filter(idempotency_key::response_status.is_null()))
+            .set((
+                idempotency_key::response_status.eq(status),
+                idempotency_key::response_headers.eq(headers),
+                idempotency_key::response_body.eq(body),
+            ))
+            .execute(&mut conn)
+            .await
+            .map_err(DbError::from)
+    }
+
+    #[instrument(skip_all)]
+    pub async fn release(&self, swift_user_id: &Uuid, key: &str, claim_token: &Uuid) -> Result<usize, DbError> {
+        let mut conn = self.conn().await?;
+        diesel::delete(idempotency_key::table.find((swift_user_id, key))
+                .filter(idempotency_key::claim_token.eq(claim_token))
+                .filter(idempotency_key::response_status.is_null()))
+            .execute(&mut conn)
+            .await
+            .map_err(DbError::from)
+    }
+
+    #[instrument(skip_all)]
+    pub async fn delete_expired(&self) -> Result<usize, DbError> {
+        let mut conn = self.conn().await?;
+        diesel::delete(idempotency_key::table.filter(idempotency_key::expires_at.lt(diesel::dsl::now)))
+            .execute(&mut conn)
+            .await
+            .map_err(DbError::from)
+    }
+}
+
+impl BaseRepository for IdempotencyRepository {
+    fn pools(&self) -> &DbPools {
+        &self.pools
+    }
+}
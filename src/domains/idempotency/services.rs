@@ -0,0 +1,135 @@
+use std::time::Duration;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+use crate::common::errors::application_error::ApplicationError;
+use crate::config::app_env::IDEMPOTENCY_KEY_TTL;
+use crate::domains::idempotency::db_models::IdempotencyKey;
+use crate::domains::idempotency::repository::IdempotencyRepository;
+use crate::server::shutdown::ShutdownSignal;
+
+// A request still holding its key after this long is assumed to have died, a retry may take over
+const LOCK_TIMEOUT: Duration = Duration::from_secs(60);
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug)]
+pub enum Claim {
+    // The caller handles the request and must complete or release the key with the token afterwards
+    Acquired(Uuid),
+    Completed(StoredResponse),
+    InProgress,
+    // The key was used for a different request
+    Mismatch,
+}
+
+#[derive(Debug)]
+pub struct StoredResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+#[derive(Clone)]
+pub struct IdempotencyService {
+    idempotency_repository: IdempotencyRepository,
+}
+
+impl IdempotencyService {
+    pub fn new(idempotency_repository: IdempotencyRepository) -> Self {
+        IdempotencyService { idempotency_repository }
+    }
+
+    pub async fn claim(&self, swift_user_id: &Uuid, key: &str, request_fingerprint: &str) -> Result<Claim, ApplicationError> {
+        debug!("Claiming idempotency key: {:?}", key);
+        // A second attempt covers the key being released or purged between the claim and the lookup
+        for _ in 0..2 {
+            let claim_token = self.idempotency_repository
+                .claim(swift_user_id, key, request_fingerprint, *IDEMPOTENCY_KEY_TTL, LOCK_TIMEOUT)
+                .await
+                .map_err(ApplicationError::from)?;
+            if let Some(claim_token) = claim_token {
+                return Ok(Claim::Acquired(claim_token));
+            }
+
+            let existing = self.idempotency_repository
+                .find_by_key(swift_user_id, key)
+                .await
+                .map_err(ApplicationError::from)?;
+            if let Some(existing) = existing {
+                return Ok(to_claim(existing, request_fingerprint));
+            }
+        }
+
+        Ok(Claim::InProgress)
+    }
+
+    pub async fn complete(&self, swift_user_id: &Uuid, key: &str, claim_token: &Uuid, response: StoredResponse) -> Result<(), ApplicationError> {
+        debug!("Storing response for idempotency key: {:?}", key);
+        let headers = serde_json::to_value(response.headers).map_err(|e| {
+            warn!("Unable to serialize response headers: {:?}", e);
+            ApplicationError::InternalServerError
+        })?;
+
+        let row_updated = self.idempotency_repository
+            .complete(swift_user_id, key, claim_token, response.status as i16, headers, &response.body)
+            .await
+            .map_err(ApplicationError::from)?;
+
+        if row_updated == 0 {
+            /*
+                The lock timed out and a retry took the key over, or it expired and was purged.
+                The response isn't stored, a retry gets the one of the request holding the key now or runs again
+            */
+            warn!("Idempotency key {:?} was no longer held when storing the response", key);
+        }
+        Ok(())
+    }
+
+    /*
+        Frees the key without storing a response so a retry runs the request again
+    */
+    pub async fn release(&self, swift_user_id: &Uuid, key: &str, claim_token: &Uuid) -> Result<(), ApplicationError> {
+        debug!("Releasing idempotency key: {:?}", key);
+        self.idempotency_repository
+            .release(swift_user_id, key, claim_token)
+            .await
+            .map(|_| ())
+            .map_err(ApplicationError::from)
+    }
+
+    /*
+        Deletes expired keys until shutdown, expired keys are already ignored by `claim`
+        so this only keeps the table small
+    */
+    pub async fn purge_expired(self, shutdown: ShutdownSignal) {
+        let mut interval = tokio::time::interval(PURGE_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {},
+                _ = shutdown.wait() => return,
+            }
+
+            match self.idempotency_repository.delete_expired().await {
+                Ok(0) => {},
+                Ok(deleted) => info!("Purged {} expired idempotency keys", deleted),
+                Err(e) => warn!("Unable to purge expired idempotency keys: {:?}", e),
+            }
+        }
+    }
+}
+
+fn to_claim(existing: IdempotencyKey, request_fingerprint: &str) -> Claim {
+    if existing.request_fingerprint != request_fingerprint {
+        return Claim::Mismatch;
+    }
+
+    match existing.response_status {
+        None => Claim::InProgress,
+        Some(status) => Claim::Completed(StoredResponse {
+            status: status as u16,
+            headers: existing.response_headers
+                .and_then(|headers| serde_json::from_value(headers).ok())
+                .unwrap_or_default(),
+            body: existing.response_body.unwrap_or_default(),
+        }),
+    }
+}
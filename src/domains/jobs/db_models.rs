@@ -0,0 +1,68 @@
+use chrono::NaiveDateTime;
+use diesel::{Insertable, Queryable, QueryableByName, Selectable};
+use diesel::sql_types::{Integer, Jsonb, Text, Uuid as SqlUuid};
+use uuid::Uuid;
+
+use crate::common::schema;
+use crate::domains::jobs::registry::Job;
+
+pub const JOB_PENDING: &str = "pending";
+pub const JOB_SUCCEEDED: &str = "succeeded";
+// Failed on every attempt, only a manual retry runs it again
+pub const JOB_FAILED: &str = "failed";
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = schema::job)]
+pub struct NewJob {
+    pub id: Uuid,
+    pub kind: String,
+    pub payload: serde_json::Value,
+    pub max_attempts: i32,
+    pub run_at: NaiveDateTime,
+    pub unique_key: Option<String>,
+}
+
+impl NewJob {
+    pub fn new<J: Job>(job: &J, run_at: NaiveDateTime) -> Self {
+        NewJob {
+            id: Uuid::now_v7(),
+            kind: J::KIND.to_string(),
+            payload: serde_json::to_value(job).expect("Jobs serialize to JSON"),
+            max_attempts: J::MAX_ATTEMPTS,
+            run_at,
+            unique_key: None,
+        }
+    }
+}
+
+#[derive(Queryable, Selectable, Debug)]
+#[diesel(table_name = schema::job)]
+pub struct JobRecord {
+    pub id: Uuid,
+    pub kind: String,
+    pub payload: serde_json::Value,
+    pub status: String,
+    pub attempts: i32,
+    pub max_attempts: i32,
+    pub run_at: NaiveDateTime,
+    pub last_error: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub finished_at: Option<NaiveDateTime>,
+}
+
+/*
+    A due job taken by a worker. `attempts` includes this one
+*/
+#[derive(QueryableByName, Debug)]
+pub struct ClaimedJob {
+    #[diesel(sql_type = SqlUuid)]
+    pub id: Uuid,
+    #[diesel(sql_type = Text)]
+    pub kind: String,
+    #[diesel(sql_type = Jsonb)]
+    pub payload: serde_json::Value,
+    #[diesel(sql_type = Integer)]
+    pub attempts: i32,
+    #[diesel(sql_type = Integer)]
+    pub max_attempts: i32,
+}
@@ -0,0 +1,79 @@
+use axum::extract::{Path, State};
+use axum::{Json, Router};
+use axum::routing::{get, post};
+use http::StatusCode;
+use uuid::Uuid;
+use crate::config::AppState;
+use crate::common::errors::api_error_response::ErrorResponse;
+use crate::common::errors::application_error::ApplicationError;
+use crate::common::models::models::Identity;
+use crate::domains::jobs::api_models::{JobResponse, JobsResponse};
+use crate::domains::jobs::db_models::JobRecord;
+use crate::domains::jobs::services::JobService;
+use crate::domains::users::services::UserService;
+use crate::domains::docs::params::IdempotencyKeyHeader;
+
+/*
+    Only super admins see and retry background jobs, they run for every organisation
+*/
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/admin/jobs/failed", get(fetch_failed_jobs))
+        .route("/admin/jobs/:id/retry", post(retry_job))
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/jobs/failed",
+    tag = "admin",
+    responses(
+        (status = 200, description = "The 100 most recently failed jobs, newest first", body = JobsResponse),
+        (status = 401, response = ErrorResponse),
+        (status = 403, response = ErrorResponse),
+    ),
+)]
+async fn fetch_failed_jobs(
+    identity: Identity,
+    State(user_service): State<UserService>,
+    State(job_service): State<JobService>,
+) -> Result<Json<JobsResponse>, ApplicationError> {
+    if !user_service.is_super_admin(&identity.user_id).await? {
+        return Err(ApplicationError::Forbidden);
+    }
+
+    job_service.find_failed()
+        .await
+        .map(|jobs| JobsResponse {
+            data: jobs.into_iter().map(JobRecord::into).collect(),
+        })
+        .map(Json)
+}
+
+// 404 unless the job exists and failed
+#[utoipa::path(
+    post,
+    path = "/admin/jobs/{id}/retry",
+    tag = "admin",
+    params(("id" = Uuid, Path), IdempotencyKeyHeader),
+    responses(
+        (status = 202, description = "The job is queued again with a fresh set of attempts", body = JobResponse),
+        (status = 401, response = ErrorResponse),
+        (status = 403, response = ErrorResponse),
+        (status = 404, response = ErrorResponse),
+    ),
+)]
+async fn retry_job(
+    identity: Identity,
+    State(user_service): State<UserService>,
+    State(job_service): State<JobService>,
+    Path(id): Path<Uuid>,
+) -> Result<(StatusCode, Json<JobResponse>), ApplicationError> {
+    if !user_service.is_super_admin(&identity.user_id).await? {
+        return Err(ApplicationError::Forbidden);
+    }
+
+    job_service.retry(&id)
+        .await
+        .map(JobRecord::into)
+        .map(|response| (StatusCode::ACCEPTED, Json(response)))
+}
@@ -0,0 +1,33 @@
+use axum::async_trait;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+use crate::common::errors::application_error::ApplicationError;
+use crate::domains::jobs::db_models::{JOB_FAILED, JOB_SUCCEEDED};
+use crate::domains::jobs::registry::{Job, JobHandler};
+use crate::domains::jobs::repository::JobRepository;
+
+const SUCCEEDED_RETENTION: chrono::Duration = chrono::Duration::days(7);
+const FAILED_RETENTION: chrono::Duration = chrono::Duration::days(30);
+
+// Deletes finished jobs, succeeded ones after 7 days and failed ones after 30
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PurgeFinishedJobs;
+
+impl Job for PurgeFinishedJobs {
+    const KIND: &'static str = "purge_finished_jobs";
+}
+
+#[async_trait]
+impl JobHandler<PurgeFinishedJobs> for JobRepository {
+    async fn handle(&self, _job: PurgeFinishedJobs) -> Result<(), ApplicationError> {
+        let now = Utc::now().naive_utc();
+        let succeeded = self.delete_finished_before(JOB_SUCCEEDED, now - SUCCEEDED_RETENTION).await?;
+        let failed = self.delete_finished_before(JOB_FAILED, now - FAILED_RETENTION).await?;
+
+        if succeeded + failed > 0 {
+            info!("Purged {} succeeded and {} failed jobs", succeeded, failed);
+        }
+        Ok(())
+    }
+}
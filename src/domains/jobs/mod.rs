@@ -0,0 +1,7 @@
+pub mod api_models;
+pub mod db_models;
+pub mod handlers;
+pub mod maintenance;
+pub mod registry;
+pub mod repository;
+pub mod services;
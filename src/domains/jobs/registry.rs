@@ -0,0 +1,171 @@
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::str::FromStr;
+use std::sync::Arc;
+use axum::async_trait;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use crate::common::errors::application_error::ApplicationError;
+
+/*
+    A kind of background job, the struct is its payload. Stored as JSON, add fields with a default
+    so jobs enqueued by an older version still read. KIND must never change once jobs were enqueued
+*/
+pub trait Job: Serialize + DeserializeOwned + Send + Sync + 'static {
+    const KIND: &'static str;
+    // Attempts before the job is marked failed
+    const MAX_ATTEMPTS: i32 = 5;
+}
+
+/*
+    Runs the jobs of one kind. A job may run more than once (a retry, a worker that died),
+    so handling must be idempotent
+*/
+#[async_trait]
+pub trait JobHandler<J: Job>: Send + Sync + 'static {
+    async fn handle(&self, job: J) -> Result<(), ApplicationError>;
+}
+
+#[async_trait]
+pub(super) trait ErasedHandler: Send + Sync {
+    async fn run(&self, payload: serde_json::Value) -> Result<(), String>;
+}
+
+struct TypedHandler<J, H> {
+    handler: H,
+    job: PhantomData<fn() -> J>,
+}
+
+#[async_trait]
+impl<J: Job, H: JobHandler<J>> ErasedHandler for TypedHandler<J, H> {
+    async fn run(&self, payload: serde_json::Value) -> Result<(), String> {
+        let job = serde_json::from_value::<J>(payload).map_err(|e| format!("Unreadable payload: {}", e))?;
+        self.handler.handle(job).await.map_err(|e| format!("{:?}", e))
+    }
+}
+
+pub(super) struct ScheduledJob {
+    pub kind: &'static str,
+    pub schedule: cron::Schedule,
+    pub payload: serde_json::Value,
+    pub max_attempts: i32,
+}
+
+/*
+    The handler of every job kind and the recurring jobs, built once at startup
+*/
+#[derive(Clone, Default)]
+pub struct JobRegistry {
+    handlers: HashMap<&'static str, Arc<dyn ErasedHandler>>,
+    schedules: Arc<Vec<ScheduledJob>>,
+}
+
+impl JobRegistry {
+    pub fn register<J: Job, H: JobHandler<J>>(mut self, handler: H) -> Self {
+        let handler = TypedHandler::<J, H> { handler, job: PhantomData };
+        assert!(self.handlers.insert(J::KIND, Arc::new(handler)).is_none(), "Job kind {} registered twice", J::KIND);
+        self
+    }
+
+    /*
+        Enqueues the job on the cron schedule (UTC, with seconds: `sec min hour day month weekday`),
+        once per occurrence however many instances run
+    */
+    pub fn schedule<J: Job>(mut self, expression: &str, job: J) -> Self {
+        assert!(self.handlers.contains_key(J::KIND), "Job kind {} is scheduled but not registered", J::KIND);
+        let schedule = cron::Schedule::from_str(expression)
+            .unwrap_or_else(|e| panic!("Invalid schedule {:?} for {}: {}", expression, J::KIND, e));
+
+        Arc::get_mut(&mut self.schedules)
+            .expect("Schedules are only added while building the registry")
+            .push(ScheduledJob {
+                kind: J::KIND,
+                schedule,
+                payload: serde_json::to_value(job).expect("Jobs serialize to JSON"),
+                max_attempts: J::MAX_ATTEMPTS,
+            });
+        self
+    }
+
+    pub(super) fn handler(&self, kind: &str) -> Option<&Arc<dyn ErasedHandler>> {
+        self.handlers.get(kind)
+    }
+
+    pub(super) fn schedules(&self) -> &[ScheduledJob] {
+        &self.schedules
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+    use tokio::sync::mpsc;
+    use super::*;
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Greet {
+        name: String,
+    }
+
+    impl Job for Greet {
+        const KIND: &'static str = "greet";
+    }
+
+    struct Greeter(mpsc::UnboundedSender<String>);
+
+    #[async_trait]
+    impl JobHandler<Greet> for Greeter {
+        async fn handle(&self, job: Greet) -> Result<(), ApplicationError> {
+            match job.name.as_str() {
+                "" => Err(ApplicationError::InternalServerError),
+                name => {
+                    let _ = self.0.send(name.to_string());
+                    Ok(())
+                },
+            }
+        }
+    }
+
+    fn registry() -> (JobRegistry, mpsc::UnboundedReceiver<String>) {
+        let (sender, greeted) = mpsc::unbounded_channel();
+        (JobRegistry::default().register::<Greet, _>(Greeter(sender)), greeted)
+    }
+
+    #[tokio::test]
+    async fn payload_reaches_the_handler_of_its_kind() {
+        let (registry, mut greeted) = registry();
+        let payload = serde_json::to_value(Greet { name: "Ada".to_string() }).unwrap();
+
+        registry.handler(Greet::KIND).unwrap().run(payload).await.unwrap();
+        assert_eq!(greeted.recv().await.unwrap(), "Ada");
+        assert!(registry.handler("unknown").is_none());
+    }
+
+    #[tokio::test]
+    async fn handler_and_payload_errors_fail_the_job() {
+        let (registry, _greeted) = registry();
+        let handler = registry.handler(Greet::KIND).unwrap();
+
+        assert!(handler.run(serde_json::json!({ "name": "" })).await.is_err());
+        let unreadable = handler.run(serde_json::json!({ "nom": "Ada" })).await.unwrap_err();
+        assert!(unreadable.starts_with("Unreadable payload"), "{}", unreadable);
+    }
+
+    #[test]
+    fn schedules_are_parsed_when_added() {
+        let (registry, _greeted) = registry();
+        let registry = registry.schedule("0 30 4 * * *", Greet { name: "Ada".to_string() });
+
+        let scheduled = &registry.schedules()[0];
+        assert_eq!(scheduled.kind, Greet::KIND);
+        assert_eq!(scheduled.payload, serde_json::json!({ "name": "Ada" }));
+        let next = scheduled.schedule.upcoming(chrono::Utc).next().unwrap();
+        assert_eq!(next.format("%H:%M:%S").to_string(), "04:30:00");
+    }
+
+    #[test]
+    #[should_panic(expected = "not registered")]
+    fn unregistered_jobs_cant_be_scheduled() {
+        JobRegistry::default().schedule("0 0 * * * *", Greet { name: "Ada".to_string() });
+    }
+}
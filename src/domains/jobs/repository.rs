@@ -0,0 +1,159 @@
+use std::time::Duration;
+use chrono::NaiveDateTime;
+use diesel::dsl::now;
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::{BigInt, Double};
+use diesel_async::AsyncPgConnection;
+use crate::common::query::RunQueryDsl;
+use tracing::instrument;
+use uuid::Uuid;
+use crate::common::errors::db_error::DbError;
+use crate::common::schema::job;
+use crate::common::repository::BaseRepository;
+use crate::config::diesel_config::DbPools;
+use crate::domains::jobs::db_models::{ClaimedJob, JobRecord, NewJob, JOB_FAILED, JOB_PENDING, JOB_SUCCEEDED};
+
+#[derive(Clone)]
+pub struct JobRepository {
+    pools: DbPools,
+}
+
+impl JobRepository {
+    pub fn new(pools: DbPools) -> Self {
+        JobRepository { pools }
+    }
+
+    #[instrument(skip_all)]
+    pub async fn insert(&self, new_job: &NewJob) -> Result<usize, DbError> {
+        let mut conn = self.conn().await?;
+
+        self.insert_with_conn(&mut conn, new_job).await
+    }
+
+    /*
+        Enqueues the job in the transaction of the change, it only runs once that commits.
+        A job with the unique key of an existing one is skipped
+    */
+    #[instrument(skip_all)]
+    pub async fn insert_with_conn(&self, conn: &mut AsyncPgConnection, new_job: &NewJob) -> Result<usize, DbError> {
+        diesel::insert_into(job::table)
+            .values(new_job)
+            .on_conflict(job::unique_key)
+            .do_nothing()
+            .execute(conn)
+            .await
+            .map_err(DbError::from)
+    }
+
+    /*
+        Takes up to `limit` due jobs, the oldest first. A running job whose lock ran out is taken again,
+        the worker that had it is gone. Locked for `lease` so other workers skip them
+    */
+    #[instrument(skip_all)]
+    pub async fn claim_due(&self, limit: i64, lease: Duration) -> Result<Vec<ClaimedJob>, DbError> {
+        let mut conn = self.conn().await?;
+        sql_query("UPDATE job \
+                   SET status = 'running', attempts = attempts + 1, locked_until = NOW() + make_interval(secs => $2), updated_at = NOW() \
+                   WHERE id IN ( \
+                       SELECT id FROM job \
+                       WHERE (status = 'pending' AND run_at <= NOW()) \
+                          OR (status = 'running' AND locked_until < NOW()) \
+                       ORDER BY run_at \
+                       LIMIT $1 \
+                       FOR UPDATE SKIP LOCKED) \
+                   RETURNING id, kind, payload, attempts, max_attempts")
+            .bind::<BigInt, _>(limit)
+            .bind::<Double, _>(lease.as_secs_f64())
+            .get_results(&mut conn)
+            .await
+            .map_err(DbError::from)
+    }
+
+    #[instrument(skip_all)]
+    pub async fn mark_succeeded(&self, id: &Uuid) -> Result<usize, DbError> {
+        let mut conn = self.conn().await?;
+        diesel::update(job::table.find(id))
+            .set((
+                job::status.eq(JOB_SUCCEEDED),
+                job::locked_until.eq(None::<NaiveDateTime>),
+                job::finished_at.eq(now),
+                job::updated_at.eq(now),
+            ))
+            .execute(&mut conn)
+            .await
+            .map_err(DbError::from)
+    }
+
+    /*
+        Records a failed attempt, the job runs again after `retry_in` or is marked failed without it
+    */
+    #[instrument(skip_all)]
+    pub async fn mark_failed(&self, id: &Uuid, error: &str, retry_in: Option<Duration>) -> Result<usize, DbError> {
+        use diesel::dsl::IntervalDsl;
+
+        let mut conn = self.conn().await?;
+        let failed = (job::last_error.eq(error), job::locked_until.eq(None::<NaiveDateTime>), job::updated_at.eq(now));
+        match retry_in {
+            Some(retry_in) => diesel::update(job::table.find(id))
+                .set((failed, job::status.eq(JOB_PENDING), job::run_at.eq(now + (retry_in.as_secs() as i64).seconds())))
+                .execute(&mut conn)
+                .await,
+            None => diesel::update(job::table.find(id))
+                .set((failed, job::status.eq(JOB_FAILED), job::finished_at.eq(now)))
+                .execute(&mut conn)
+                .await,
+        }.map_err(DbError::from)
+    }
+
+    #[instrument(skip_all)]
+    pub async fn find_failed(&self, limit: i64) -> Result<Vec<JobRecord>, DbError> {
+        let mut conn = self.read_conn().await?;
+        job::table
+            .filter(job::status.eq(JOB_FAILED))
+            .order(job::finished_at.desc())
+            .limit(limit)
+            .select(JobRecord::as_select())
+            .get_results(&mut conn)
+            .await
+            .map_err(DbError::from)
+    }
+
+    /*
+        Queues a failed job again with a fresh set of attempts, None when no failed job has the id
+    */
+    #[instrument(skip_all)]
+    pub async fn retry_failed(&self, id: &Uuid) -> Result<Option<JobRecord>, DbError> {
+        let mut conn = self.conn().await?;
+        diesel::update(job::table.find(id).filter(job::status.eq(JOB_FAILED)))
+            .set((
+                job::status.eq(JOB_PENDING),
+                job::attempts.eq(0),
+                job::run_at.eq(now),
+                job::finished_at.eq(None::<NaiveDateTime>),
+                job::updated_at.eq(now),
+            ))
+            .returning(JobRecord::as_returning())
+            .get_result(&mut conn)
+            .await
+            .optional()
+            .map_err(DbError::from)
+    }
+
+    #[instrument(skip_all)]
+    pub async fn delete_finished_before(&self, status: &str, before: NaiveDateTime) -> Result<usize, DbError> {
+        let mut conn = self.conn().await?;
+        diesel::delete(job::table
+                .filter(job::status.eq(status))
+                .filter(job::finished_at.lt(before)))
+            .execute(&mut conn)
+            .await
+            .map_err(DbError::from)
+    }
+}
+
+impl BaseRepository for JobRepository {
+    fn pools(&self) -> &DbPools {
+        &self.pools
+    }
+}
@@ -0,0 +1,363 @@
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use chrono::{DateTime, Utc};
+use futures::FutureExt;
+use tokio::sync::{Notify, Semaphore};
+use tokio::task::JoinSet;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+use crate::common::errors::application_error::ApplicationError;
+use crate::common::errors::application_error::ApplicationError::NotFound;
+use crate::common::utils::backoff;
+use crate::config::app_env::{JOB_CONCURRENCY, JOB_TIMEOUT, SHUTDOWN_TIMEOUT};
+use crate::domains::jobs::db_models::{ClaimedJob, JobRecord, NewJob};
+use crate::domains::jobs::registry::JobRegistry;
+use crate::domains::jobs::repository::JobRepository;
+use crate::server::shutdown::ShutdownSignal;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+const FAILED_JOBS_LIMIT: i64 = 100;
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(10);
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Clone)]
+pub struct JobService {
+    job_repository: JobRepository,
+    registry: JobRegistry,
+    // Wakes the workers of this instance when something was enqueued
+    enqueued: Arc<Notify>,
+}
+
+impl JobService {
+    pub fn new(job_repository: JobRepository, registry: JobRegistry) -> Self {
+        JobService { job_repository, registry, enqueued: Arc::new(Notify::new()) }
+    }
+
+    // Most recently failed first
+    pub async fn find_failed(&self) -> Result<Vec<JobRecord>, ApplicationError> {
+        self.job_repository
+            .find_failed(FAILED_JOBS_LIMIT)
+            .await
+            .map_err(ApplicationError::from)
+    }
+
+    pub async fn retry(&self, id: &Uuid) -> Result<JobRecord, ApplicationError> {
+        info!("Retrying failed job: {:?}", id);
+        let job = self.job_repository
+            .retry_failed(id)
+            .await
+            .map_err(ApplicationError::from)?
+            .ok_or(NotFound)?;
+
+        self.enqueued.notify_one();
+        Ok(job)
+    }
+
+    /*
+        Runs due jobs until shutdown, at most JOB_CONCURRENCY at a time. Instances share the queue,
+        each job is taken by one of them. Failed jobs are retried with exponential backoff until their
+        MAX_ATTEMPTS, then marked failed. On shutdown nothing new is taken and running jobs get
+        SHUTDOWN_TIMEOUT to finish, the ones still running then are taken again once their lock runs out
+    */
+    pub async fn work(self, shutdown: ShutdownSignal) {
+        // A job can't take longer than the timeout, the lease leaves room for recording the outcome
+        let lease = *JOB_TIMEOUT + Duration::from_secs(60);
+        let permits = Arc::new(Semaphore::new(*JOB_CONCURRENCY));
+        let mut running = JoinSet::new();
+
+        loop {
+            let available = permits.available_permits();
+            if available > 0 {
+                match self.job_repository.claim_due(available as i64, lease).await {
+                    Ok(claimed) => for job in claimed {
+                        let permit = permits.clone().try_acquire_owned().expect("Only this loop takes permits");
+                        let service = self.clone();
+                        running.spawn(async move {
+                            service.run(job).await;
+                            drop(permit);
+                        });
+                    },
+                    Err(e) => warn!("Unable to claim jobs: {:?}", e),
+                }
+            }
+
+            tokio::select! {
+                _ = tokio::time::sleep(POLL_INTERVAL) => {},
+                _ = self.enqueued.notified() => {},
+                // A worker is free again
+                Some(_) = running.join_next(), if !running.is_empty() => {},
+                _ = shutdown.wait() => break,
+            }
+        }
+
+        if running.is_empty() {
+            return;
+        }
+        info!("Waiting up to {:?} for {} running jobs", *SHUTDOWN_TIMEOUT, running.len());
+        let drained = tokio::time::timeout(*SHUTDOWN_TIMEOUT, async {
+            while running.join_next().await.is_some() {}
+        }).await;
+        if drained.is_err() {
+            warn!("{} jobs still running at shutdown, they run again once their lock runs out", running.len());
+            running.abort_all();
+        }
+    }
+
+    async fn run(&self, job: ClaimedJob) {
+        let started = Instant::now();
+        let result = match self.registry.handler(&job.kind) {
+            Some(handler) => {
+                let run = AssertUnwindSafe(handler.run(job.payload)).catch_unwind();
+                match tokio::time::timeout(*JOB_TIMEOUT, run).await {
+                    Ok(Ok(result)) => result,
+                    Ok(Err(_)) => Err("Panicked".to_string()),
+                    Err(_) => Err(format!("Timed out after {:?}", *JOB_TIMEOUT)),
+                }
+            },
+            None => Err(format!("No handler for job kind {:?}", job.kind)),
+        };
+        metrics::histogram!("job_duration_seconds", "kind" => job.kind.clone()).record(started.elapsed().as_secs_f64());
+
+        let recorded = match result {
+            Ok(()) => {
+                debug!("Job {:?} ({}) succeeded", job.id, job.kind);
+                metrics::counter!("jobs_total", "kind" => job.kind.clone(), "outcome" => "succeeded").increment(1);
+                self.job_repository.mark_succeeded(&job.id).await
+            },
+            Err(error) => {
+                let retry_in = (job.attempts < job.max_attempts).then(|| backoff::retry_delay(job.attempts, RETRY_BASE_DELAY, RETRY_MAX_DELAY));
+                match retry_in {
+                    Some(retry_in) => debug!("Job {:?} ({}) failed, retrying in {:?}: {}", job.id, job.kind, retry_in, error),
+                    None => warn!("Job {:?} ({}) failed {} times, giving up: {}", job.id, job.kind, job.attempts, error),
+                }
+                metrics::counter!("jobs_total", "kind" => job.kind.clone(), "outcome" => if retry_in.is_some() { "retry" } else { "failed" }).increment(1);
+                self.job_repository.mark_failed(&job.id, &error, retry_in).await
+            },
+        };
+
+        if let Err(e) = recorded {
+            // The lock runs out and the job runs again
+            warn!("Unable to record the outcome of job {:?}: {:?}", job.id, e);
+        }
+    }
+
+    /*
+        Enqueues the scheduled jobs as their time comes until shutdown. Every instance does, the unique key
+        of an occurrence lets only the first one through
+    */
+    pub async fn schedule(self, shutdown: ShutdownSignal) {
+        let schedules = self.registry.schedules();
+        if schedules.is_empty() {
+            return;
+        }
+
+        let mut next_runs: Vec<Option<DateTime<Utc>>> = schedules.iter()
+            .map(|scheduled| scheduled.schedule.upcoming(Utc).next())
+            .collect();
+        loop {
+            let Some(next_run) = next_runs.iter().flatten().min().copied() else {
+                return;
+            };
+
+            let wait = (next_run - Utc::now()).to_std().unwrap_or(Duration::ZERO);
+            tokio::select! {
+                _ = tokio::time::sleep(wait) => {},
+                _ = shutdown.wait() => return,
+            }
+
+            for (scheduled, next) in schedules.iter().zip(next_runs.iter_mut()) {
+                let Some(run_at) = *next else { continue };
+                if run_at > Utc::now() {
+                    continue;
+                }
+
+                let new_job = NewJob {
+                    id: Uuid::now_v7(),
+                    kind: scheduled.kind.to_string(),
+                    payload: scheduled.payload.clone(),
+                    max_attempts: scheduled.max_attempts,
+                    run_at: run_at.naive_utc(),
+                    unique_key: Some(format!("{}@{}", scheduled.kind, run_at.to_rfc3339())),
+                };
+                match self.job_repository.insert(&new_job).await {
+                    Ok(_) => self.enqueued.notify_one(),
+                    Err(e) => warn!("Unable to enqueue scheduled job {}: {:?}", scheduled.kind, e),
+                }
+                *next = scheduled.schedule.after(&run_at).next();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::async_trait;
+    use diesel::prelude::*;
+    use diesel::sql_query;
+    use serde::{Deserialize, Serialize};
+    use tokio::sync::mpsc;
+    use crate::common::query::RunQueryDsl;
+    use crate::common::schema::job;
+    use crate::common::test_database::TestDatabase;
+    use crate::domains::jobs::db_models::{JOB_FAILED, JOB_PENDING, JOB_SUCCEEDED};
+    use crate::domains::jobs::registry::{Job, JobHandler};
+    use super::*;
+
+    const LEASE: Duration = Duration::from_secs(60);
+
+    #[derive(Serialize, Deserialize)]
+    struct Chore {
+        fail: bool,
+        #[serde(default)]
+        sleep_millis: u64,
+    }
+
+    impl Job for Chore {
+        const KIND: &'static str = "chore";
+        const MAX_ATTEMPTS: i32 = 2;
+    }
+
+    // Reports every chore it starts
+    struct Worker(mpsc::UnboundedSender<()>);
+
+    #[async_trait]
+    impl JobHandler<Chore> for Worker {
+        async fn handle(&self, chore: Chore) -> Result<(), ApplicationError> {
+            let _ = self.0.send(());
+            tokio::time::sleep(Duration::from_millis(chore.sleep_millis)).await;
+            match chore.fail {
+                true => Err(ApplicationError::InternalServerError),
+                false => Ok(()),
+            }
+        }
+    }
+
+    fn service(database: &TestDatabase, registry: impl FnOnce(JobRegistry) -> JobRegistry) -> (JobService, mpsc::UnboundedReceiver<()>) {
+        let (sender, started) = mpsc::unbounded_channel();
+        let registry = registry(JobRegistry::default().register::<Chore, _>(Worker(sender)));
+        (JobService::new(JobRepository::new(database.pools.clone()), registry), started)
+    }
+
+    async fn enqueue(service: &JobService, chore: Chore) -> Uuid {
+        let new_job = NewJob::new(&chore, Utc::now().naive_utc());
+        service.job_repository.insert(&new_job).await.unwrap();
+        new_job.id
+    }
+
+    async fn status(database: &TestDatabase, id: Uuid) -> (String, i32) {
+        job::table
+            .find(id)
+            .select((job::status, job::attempts))
+            .get_result(&mut database.admin().await)
+            .await
+            .unwrap()
+    }
+
+    async fn claim(service: &JobService) -> Vec<Uuid> {
+        service.job_repository.claim_due(10, LEASE).await.unwrap().iter().map(|job| job.id).collect()
+    }
+
+    #[tokio::test]
+    async fn claiming_skips_locked_jobs_and_takes_expired_ones_again() {
+        let Some(database) = TestDatabase::create().await else { return };
+        let (service, _started) = service(&database, |registry| registry);
+        let first = enqueue(&service, Chore { fail: false, sleep_millis: 0 }).await;
+        let second = enqueue(&service, Chore { fail: false, sleep_millis: 0 }).await;
+
+        // Another worker is claiming the first one, the claim moves on instead of waiting for it
+        let mut other = database.admin().await;
+        sql_query("BEGIN").execute(&mut other).await.unwrap();
+        sql_query(format!("SELECT id FROM job WHERE id = '{}' FOR UPDATE", first)).execute(&mut other).await.unwrap();
+        let claimed = tokio::time::timeout(Duration::from_secs(5), claim(&service)).await.unwrap();
+        assert_eq!(claimed, vec![second]);
+        sql_query("ROLLBACK").execute(&mut other).await.unwrap();
+
+        assert_eq!(claim(&service).await, vec![first]);
+        // Both are leased now
+        assert!(claim(&service).await.is_empty());
+
+        // The worker that had the second one died
+        sql_query(format!("UPDATE job SET locked_until = NOW() - INTERVAL '1 second' WHERE id = '{}'", second))
+            .execute(&mut other)
+            .await
+            .unwrap();
+        assert_eq!(claim(&service).await, vec![second]);
+        assert_eq!(status(&database, second).await, ("running".to_string(), 2));
+    }
+
+    #[tokio::test]
+    async fn failing_jobs_are_retried_until_their_max_attempts_then_fail() {
+        let Some(database) = TestDatabase::create().await else { return };
+        let (service, _started) = service(&database, |registry| registry);
+        let id = enqueue(&service, Chore { fail: true, sleep_millis: 0 }).await;
+        let mut admin = database.admin().await;
+
+        service.run(service.job_repository.claim_due(1, LEASE).await.unwrap().pop().unwrap()).await;
+        assert_eq!(status(&database, id).await, (JOB_PENDING.to_string(), 1));
+        // Backing off
+        assert!(claim(&service).await.is_empty());
+
+        sql_query("UPDATE job SET run_at = NOW()").execute(&mut admin).await.unwrap();
+        service.run(service.job_repository.claim_due(1, LEASE).await.unwrap().pop().unwrap()).await;
+        assert_eq!(status(&database, id).await, (JOB_FAILED.to_string(), 2));
+        assert!(claim(&service).await.is_empty());
+
+        let failed = service.find_failed().await.unwrap();
+        assert_eq!(failed.iter().map(|job| job.id).collect::<Vec<_>>(), vec![id]);
+        assert_eq!(failed[0].last_error.as_deref(), Some("InternalServerError"));
+
+        let retried = service.retry(&id).await.unwrap();
+        assert_eq!((retried.status.as_str(), retried.attempts), (JOB_PENDING, 0));
+        assert_eq!(claim(&service).await, vec![id]);
+        assert!(matches!(service.retry(&id).await, Err(NotFound)));
+    }
+
+    #[tokio::test]
+    async fn every_instance_schedules_but_an_occurrence_is_enqueued_once() {
+        let Some(database) = TestDatabase::create().await else { return };
+        let (service, _started) = service(&database, |registry| registry.schedule("* * * * * *", Chore { fail: false, sleep_millis: 0 }));
+        let (stop, shutdown) = ShutdownSignal::channel();
+
+        let instances = [
+            tokio::spawn(service.clone().schedule(shutdown.clone())),
+            tokio::spawn(service.clone().schedule(shutdown.clone())),
+        ];
+        tokio::time::sleep(Duration::from_millis(2500)).await;
+        stop.send(true).unwrap();
+        for instance in instances {
+            tokio::time::timeout(Duration::from_secs(1), instance).await.unwrap().unwrap();
+        }
+
+        let keys: Vec<Option<String>> = job::table
+            .filter(job::kind.eq(Chore::KIND))
+            .order(job::unique_key)
+            .select(job::unique_key)
+            .get_results(&mut database.admin().await)
+            .await
+            .unwrap();
+        assert!(keys.len() >= 2, "{:?}", keys);
+        let mut unique = keys.clone();
+        unique.dedup();
+        assert_eq!(unique, keys);
+        assert!(keys.iter().all(|key| key.as_deref().is_some_and(|key| key.starts_with("chore@"))));
+    }
+
+    #[tokio::test]
+    async fn running_jobs_finish_before_the_workers_stop() {
+        let Some(database) = TestDatabase::create().await else { return };
+        let (service, mut started) = service(&database, |registry| registry);
+        let id = enqueue(&service, Chore { fail: false, sleep_millis: 500 }).await;
+        let (stop, shutdown) = ShutdownSignal::channel();
+
+        let worker = tokio::spawn(service.clone().work(shutdown));
+        tokio::time::timeout(Duration::from_secs(5), started.recv()).await.unwrap().unwrap();
+        // Enqueued after the shutdown, left for the next instance
+        stop.send(true).unwrap();
+        let left = enqueue(&service, Chore { fail: false, sleep_millis: 0 }).await;
+
+        tokio::time::timeout(Duration::from_secs(5), worker).await.unwrap().unwrap();
+        assert_eq!(status(&database, id).await, (JOB_SUCCEEDED.to_string(), 1));
+        assert_eq!(status(&database, left).await, (JOB_PENDING.to_string(), 0));
+    }
+}
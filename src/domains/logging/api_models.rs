@@ -0,0 +1,12 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct LogLevelRequest {
+    pub filter: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct LogLevelResponse {
+    pub filter: String,
+}
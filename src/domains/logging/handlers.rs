@@ -0,0 +1,77 @@
+use axum::extract::State;
+use axum::{Json, Router};
+use axum::routing::get;
+use tracing::{debug, info};
+use crate::config::AppState;
+use crate::common::errors::api_error_response::{ErrorMessage, ErrorResponse};
+use crate::common::errors::application_error::ApplicationError;
+use crate::common::errors::error_code;
+use crate::common::errors::global_api_error::ApiError;
+use crate::common::errors::request_error::RequestError::ValidationError;
+use crate::common::extract::request::SwiftJson;
+use crate::common::models::models::Identity;
+use crate::config::logging_config;
+use crate::domains::logging::api_models::{LogLevelRequest, LogLevelResponse};
+use crate::domains::users::services::UserService;
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/admin/log-level", get(fetch_log_level).put(update_log_level))
+}
+
+/*
+Only super admin is allowed to read or change the log level
+*/
+#[utoipa::path(
+    get,
+    path = "/admin/log-level",
+    tag = "admin",
+    responses(
+        (status = 200, description = "The current log filter", body = LogLevelResponse),
+        (status = 401, response = ErrorResponse),
+        (status = 403, response = ErrorResponse),
+    ),
+)]
+async fn fetch_log_level(
+    identity: Identity,
+    State(user_service): State<UserService>,
+) -> Result<Json<LogLevelResponse>, ApplicationError> {
+    if !user_service.is_super_admin(&identity.user_id).await? {
+        return Err(ApplicationError::Forbidden);
+    }
+
+    logging_config::current_filter()
+        .map(|filter| Json(LogLevelResponse { filter }))
+        .ok_or(ApplicationError::InternalServerError)
+}
+
+#[utoipa::path(
+    put,
+    path = "/admin/log-level",
+    tag = "admin",
+    request_body = LogLevelRequest,
+    responses(
+        (status = 200, description = "The new log filter", body = LogLevelResponse),
+        (status = 400, response = ErrorResponse),
+        (status = 401, response = ErrorResponse),
+        (status = 403, response = ErrorResponse),
+    ),
+)]
+async fn update_log_level(
+    identity: Identity,
+    State(user_service): State<UserService>,
+    SwiftJson(log_level_request): SwiftJson<LogLevelRequest>,
+) -> Result<Json<LogLevelResponse>, ApiError> {
+    if !user_service.is_super_admin(&identity.user_id).await? {
+        return Err(ApplicationError::Forbidden.into());
+    }
+
+    logging_config::set_filter(&log_level_request.filter)
+        .map_err(|message| {
+            debug!("Rejected log filter {:?}: {}", log_level_request.filter, message);
+            ValidationError(vec![ErrorMessage::new(Some("filter"), error_code::VALIDATION_LOG_FILTER_INVALID)])
+        })?;
+    info!("Log filter changed to {:?} by user {:?}", log_level_request.filter, identity.user_id);
+
+    Ok(Json(LogLevelResponse { filter: log_level_request.filter }))
+}
@@ -0,0 +1,33 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use crate::domains::mail::db_models::EmailSuppression;
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct EmailSuppressionPutRequest {
+    // Why no email should go to the address, e.g. a bounce or complaint reported by the mail provider
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct EmailSuppressionResponse {
+    pub email: String,
+    // hard_bounce when the mail server refused the address
+    pub reason: String,
+    pub created_at: NaiveDateTime,
+}
+
+impl From<EmailSuppression> for EmailSuppressionResponse {
+    fn from(suppression: EmailSuppression) -> Self {
+        EmailSuppressionResponse {
+            email: suppression.email,
+            reason: suppression.reason,
+            created_at: suppression.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct EmailSuppressionsResponse {
+    pub data: Vec<EmailSuppressionResponse>,
+}
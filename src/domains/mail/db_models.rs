@@ -0,0 +1,13 @@
+use chrono::NaiveDateTime;
+use diesel::{Insertable, Queryable, Selectable};
+
+use crate::common::schema;
+
+#[derive(Queryable, Selectable, Insertable, Debug)]
+#[diesel(table_name = schema::email_suppression)]
+pub struct EmailSuppression {
+    // Lowercase
+    pub email: String,
+    pub reason: String,
+    pub created_at: NaiveDateTime,
+}
@@ -0,0 +1,111 @@
+use axum::extract::{Path, State};
+use axum::{Json, Router};
+use axum::routing::{get, put};
+use http::StatusCode;
+use crate::config::AppState;
+use crate::common::errors::api_error_response::ErrorResponse;
+use crate::common::errors::application_error::ApplicationError;
+use crate::common::errors::global_api_error::ApiError;
+use crate::common::extract::request::SwiftJson;
+use crate::common::models::models::Identity;
+use crate::domains::mail::api_models::{EmailSuppressionPutRequest, EmailSuppressionResponse, EmailSuppressionsResponse};
+use crate::domains::mail::db_models::EmailSuppression;
+use crate::domains::mail::services::MailService;
+use crate::domains::users::services::UserService;
+
+const MANUAL: &str = "manual";
+
+/*
+    Only super admins manage the suppression list, it applies to every organisation.
+    Bounces reported later by the mail provider are added here
+*/
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/admin/email-suppressions", get(fetch_email_suppressions))
+        .route("/admin/email-suppressions/:email", put(suppress_email).delete(unsuppress_email))
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/email-suppressions",
+    tag = "admin",
+    responses(
+        (status = 200, description = "Addresses no email is sent to, most recently suppressed first", body = EmailSuppressionsResponse),
+        (status = 401, response = ErrorResponse),
+        (status = 403, response = ErrorResponse),
+    ),
+)]
+async fn fetch_email_suppressions(
+    identity: Identity,
+    State(user_service): State<UserService>,
+    State(mail_service): State<MailService>,
+) -> Result<Json<EmailSuppressionsResponse>, ApplicationError> {
+    if !user_service.is_super_admin(&identity.user_id).await? {
+        return Err(ApplicationError::Forbidden);
+    }
+
+    mail_service.find_suppressions()
+        .await
+        .map(|suppressions| EmailSuppressionsResponse {
+            data: suppressions.into_iter().map(EmailSuppression::into).collect(),
+        })
+        .map(Json)
+}
+
+#[utoipa::path(
+    put,
+    path = "/admin/email-suppressions/{email}",
+    tag = "admin",
+    params(("email" = String, Path)),
+    request_body = EmailSuppressionPutRequest,
+    responses(
+        (status = 200, description = "No email is sent to the address anymore, queued ones are dropped", body = EmailSuppressionResponse),
+        (status = 400, response = ErrorResponse),
+        (status = 401, response = ErrorResponse),
+        (status = 403, response = ErrorResponse),
+    ),
+)]
+async fn suppress_email(
+    identity: Identity,
+    State(user_service): State<UserService>,
+    State(mail_service): State<MailService>,
+    Path(email): Path<String>,
+    SwiftJson(suppression_request): SwiftJson<EmailSuppressionPutRequest>,
+) -> Result<Json<EmailSuppressionResponse>, ApiError> {
+    if !user_service.is_super_admin(&identity.user_id).await? {
+        return Err(ApplicationError::Forbidden.into());
+    }
+
+    let reason = suppression_request.reason.unwrap_or_else(|| MANUAL.to_string());
+    mail_service.suppress(&email, &reason)
+        .await
+        .map(EmailSuppression::into)
+        .map(Json)
+}
+
+#[utoipa::path(
+    delete,
+    path = "/admin/email-suppressions/{email}",
+    tag = "admin",
+    params(("email" = String, Path)),
+    responses(
+        (status = 204, description = "Email is sent to the address again"),
+        (status = 401, response = ErrorResponse),
+        (status = 403, response = ErrorResponse),
+        (status = 404, response = ErrorResponse),
+    ),
+)]
+async fn unsuppress_email(
+    identity: Identity,
+    State(user_service): State<UserService>,
+    State(mail_service): State<MailService>,
+    Path(email): Path<String>,
+) -> Result<StatusCode, ApplicationError> {
+    if !user_service.is_super_admin(&identity.user_id).await? {
+        return Err(ApplicationError::Forbidden);
+    }
+
+    mail_service.unsuppress(&email)
+        .await
+        .map(|_| StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,32 @@
+use axum::async_trait;
+use serde::{Deserialize, Serialize};
+use crate::common::errors::application_error::ApplicationError;
+use crate::common::i18n::locale::Locale;
+use crate::domains::jobs::registry::{Job, JobHandler};
+use crate::domains::mail::services::MailService;
+use crate::domains::mail::templates::EmailTemplate;
+
+/*
+    Enqueue it in the transaction of the change the email is about, so it only goes out once that commits.
+    Rendered when sent, the job keeps the variables rather than the email
+*/
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SendEmail {
+    pub to: String,
+    pub template: EmailTemplate,
+    pub locale: Locale,
+    pub variables: serde_json::Value,
+}
+
+impl Job for SendEmail {
+    const KIND: &'static str = "send_email";
+    // With the job backoff that covers a mail server being down for a few hours
+    const MAX_ATTEMPTS: i32 = 10;
+}
+
+#[async_trait]
+impl JobHandler<SendEmail> for MailService {
+    async fn handle(&self, job: SendEmail) -> Result<(), ApplicationError> {
+        self.send(job).await
+    }
+}
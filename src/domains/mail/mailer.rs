@@ -0,0 +1,231 @@
+use std::sync::Arc;
+use axum::async_trait;
+use lettre::message::{Mailbox, MultiPart};
+use lettre::transport::smtp::response::Code;
+use lettre::{AsyncFileTransport, AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use tracing::info;
+use crate::config::app_env::{MAIL_FROM, MAIL_OUTBOX_DIR, RUN_MODE, SMTP_URL};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub text: String,
+    pub html: String,
+}
+
+#[derive(Debug)]
+pub enum MailError {
+    // The address can't receive email (unknown mailbox, invalid address), sending again won't help
+    Bounced(String),
+    // Nothing was sent (server unreachable, temporary failure, ...), worth another attempt
+    Unavailable(String),
+}
+
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, email: &Email) -> Result<(), MailError>;
+}
+
+/*
+    SMTP when SMTP_URL is set. Local mode falls back to MAIL_OUTBOX_DIR or the log,
+    anywhere else emails would be lost so an SMTP server is required
+*/
+pub fn from_env() -> Result<Arc<dyn Mailer>, String> {
+    let from: Mailbox = MAIL_FROM.parse().map_err(|e| format!("MAIL_FROM must be a valid address: {}", e))?;
+    match SMTP_URL.as_deref() {
+        Some(url) => Ok(Arc::new(SmtpMailer::new(url, from)?)),
+        None if *RUN_MODE == "local" => Ok(Arc::new(LocalMailer::new(MAIL_OUTBOX_DIR.as_deref(), from))),
+        None => Err(format!("SMTP_URL is required in RUN_MODE={}", *RUN_MODE)),
+    }
+}
+
+pub struct SmtpMailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl SmtpMailer {
+    pub fn new(url: &str, from: Mailbox) -> Result<Self, String> {
+        let transport = AsyncSmtpTransport::<Tokio1Executor>::from_url(url)
+            .map_err(|e| format!("SMTP_URL must be a valid smtp:// or smtps:// URL: {}", e))?
+            .build();
+        Ok(SmtpMailer { transport, from })
+    }
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, email: &Email) -> Result<(), MailError> {
+        let message = message(&self.from, email)?;
+        self.transport.send(message)
+            .await
+            .map(|_| ())
+            .map_err(|e| if is_bounce(e.status()) {
+                MailError::Bounced(e.to_string())
+            } else {
+                MailError::Unavailable(e.to_string())
+            })
+    }
+}
+
+/*
+    Keeps emails on this machine: .eml files in the outbox directory to open in a mail client,
+    or the text version in the log
+*/
+pub struct LocalMailer {
+    outbox: Option<AsyncFileTransport<Tokio1Executor>>,
+    from: Mailbox,
+}
+
+impl LocalMailer {
+    pub fn new(outbox_dir: Option<&str>, from: Mailbox) -> Self {
+        let outbox = outbox_dir.map(|dir| {
+            std::fs::create_dir_all(dir).expect("Unable to create MAIL_OUTBOX_DIR");
+            AsyncFileTransport::<Tokio1Executor>::new(dir)
+        });
+        LocalMailer { outbox, from }
+    }
+}
+
+#[async_trait]
+impl Mailer for LocalMailer {
+    async fn send(&self, email: &Email) -> Result<(), MailError> {
+        let message = message(&self.from, email)?;
+        match &self.outbox {
+            Some(outbox) => {
+                let id = outbox.send(message).await.map_err(|e| MailError::Unavailable(e.to_string()))?;
+                info!("Email to {:?} written to {}.eml", email.to, id);
+            },
+            None => info!("Email to {:?}: {}\n\n{}", email.to, email.subject, email.text),
+        }
+        Ok(())
+    }
+}
+
+fn message(from: &Mailbox, email: &Email) -> Result<Message, MailError> {
+    let to: Mailbox = email.to.parse().map_err(|e| MailError::Bounced(format!("Invalid address: {}", e)))?;
+    Message::builder()
+        .from(from.clone())
+        .to(to)
+        .subject(&email.subject)
+        .multipart(MultiPart::alternative_plain_html(email.text.clone(), email.html.clone()))
+        .map_err(|e| MailError::Unavailable(format!("Unable to build the email: {}", e)))
+}
+
+/*
+    Whether the server refused the recipient for good: mailbox unavailable (550), not local (551)
+    or not a valid mailbox name (553). Other permanent errors, like failed authentication, are about us
+*/
+fn is_bounce(code: Option<Code>) -> bool {
+    code.is_some_and(|code| matches!(code.to_string().as_str(), "550" | "551" | "553"))
+}
+
+#[cfg(test)]
+mod tests {
+    use lettre::transport::smtp::response::{Category, Detail, Severity};
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc;
+    use super::*;
+
+    /*
+        A local SMTP server accepting every recipient but `refused`, the messages it receives come out
+        of the channel. Returns its smtp:// URL
+    */
+    async fn smtp_sink(refused: &'static str) -> (String, mpsc::UnboundedReceiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("smtp://{}", listener.local_addr().unwrap());
+        let (sender, received) = mpsc::unbounded_channel();
+
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let sender = sender.clone();
+                tokio::spawn(async move {
+                    let (reader, mut writer) = stream.into_split();
+                    let mut lines = BufReader::new(reader).lines();
+                    writer.write_all(b"220 sink ESMTP\r\n").await.unwrap();
+                    while let Ok(Some(line)) = lines.next_line().await {
+                        let reply = match line.get(..4).unwrap_or_default().to_ascii_uppercase().as_str() {
+                            "EHLO" | "HELO" | "MAIL" | "RSET" | "NOOP" => "250 OK",
+                            "RCPT" if line.contains(refused) => "550 No such mailbox",
+                            "RCPT" => "250 OK",
+                            "DATA" => {
+                                writer.write_all(b"354 Go ahead\r\n").await.unwrap();
+                                let mut data = String::new();
+                                while let Ok(Some(line)) = lines.next_line().await {
+                                    if line == "." {
+                                        break;
+                                    }
+                                    data.push_str(&line);
+                                    data.push('\n');
+                                }
+                                let _ = sender.send(data);
+                                "250 Queued"
+                            },
+                            "QUIT" => {
+                                let _ = writer.write_all(b"221 Bye\r\n").await;
+                                break;
+                            },
+                            _ => "502 Not implemented",
+                        };
+                        writer.write_all(format!("{}\r\n", reply).as_bytes()).await.unwrap();
+                    }
+                });
+            }
+        });
+        (url, received)
+    }
+
+    fn email(to: &str) -> Email {
+        Email {
+            to: to.to_string(),
+            subject: "Welcome".to_string(),
+            text: "Hi Ada".to_string(),
+            html: "<p>Hi Ada</p>".to_string(),
+        }
+    }
+
+    fn from() -> Mailbox {
+        "Swift API <no-reply@swiftapi.com>".parse().unwrap()
+    }
+
+    #[tokio::test]
+    async fn smtp_delivers_the_text_and_html_versions() {
+        let (url, mut received) = smtp_sink("nobody@example.com").await;
+        let mailer = SmtpMailer::new(&url, from()).unwrap();
+
+        mailer.send(&email("ada@example.com")).await.unwrap();
+        let message = received.recv().await.unwrap();
+        assert!(message.contains("To: ada@example.com"), "{}", message);
+        assert!(message.contains("Subject: Welcome"), "{}", message);
+        assert!(message.contains("Content-Type: text/plain") && message.contains("Content-Type: text/html"), "{}", message);
+        assert!(message.contains("<p>Hi Ada</p>"), "{}", message);
+    }
+
+    #[tokio::test]
+    async fn smtp_refusals_bounce_and_unreachable_servers_are_unavailable() {
+        let (url, _received) = smtp_sink("nobody@example.com").await;
+        let mailer = SmtpMailer::new(&url, from()).unwrap();
+        assert!(matches!(mailer.send(&email("nobody@example.com")).await, Err(MailError::Bounced(_))));
+        assert!(matches!(mailer.send(&email("not an address")).await, Err(MailError::Bounced(_))));
+
+        let closed = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap();
+        let mailer = SmtpMailer::new(&format!("smtp://{}", closed), from()).unwrap();
+        assert!(matches!(mailer.send(&email("ada@example.com")).await, Err(MailError::Unavailable(_))));
+
+        assert!(SmtpMailer::new("http://localhost", from()).is_err());
+    }
+
+    #[test]
+    fn only_refused_recipients_bounce() {
+        let code = |category, detail| Some(Code::new(Severity::PermanentNegativeCompletion, category, detail));
+
+        assert!(is_bounce(code(Category::MailSystem, Detail::Zero)));
+        assert!(is_bounce(code(Category::MailSystem, Detail::Three)));
+        // 535, authentication failed
+        assert!(!is_bounce(code(Category::Unspecified3, Detail::Five)));
+        assert!(!is_bounce(Some(Code::new(Severity::TransientNegativeCompletion, Category::MailSystem, Detail::Zero))));
+        assert!(!is_bounce(None));
+    }
+}
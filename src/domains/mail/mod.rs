@@ -0,0 +1,8 @@
+pub mod api_models;
+pub mod db_models;
+pub mod handlers;
+pub mod jobs;
+pub mod mailer;
+pub mod repository;
+pub mod services;
+pub mod templates;
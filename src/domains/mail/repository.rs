@@ -0,0 +1,71 @@
+use diesel::dsl::{exists, select};
+use diesel::prelude::*;
+use diesel::upsert::excluded;
+use crate::common::query::RunQueryDsl;
+use tracing::instrument;
+use crate::common::errors::db_error::DbError;
+use crate::common::schema::email_suppression;
+use crate::common::repository::BaseRepository;
+use crate::config::diesel_config::DbPools;
+use crate::domains::mail::db_models::EmailSuppression;
+
+#[derive(Clone)]
+pub struct EmailSuppressionRepository {
+    pools: DbPools,
+}
+
+impl EmailSuppressionRepository {
+    pub fn new(pools: DbPools) -> Self {
+        EmailSuppressionRepository { pools }
+    }
+
+    // Suppressing an address again only replaces the reason
+    #[instrument(skip_all)]
+    pub async fn upsert(&self, suppression: &EmailSuppression) -> Result<EmailSuppression, DbError> {
+        let mut conn = self.conn().await?;
+        diesel::insert_into(email_suppression::table)
+            .values(suppression)
+            .on_conflict(email_suppression::email)
+            .do_update()
+            .set(email_suppression::reason.eq(excluded(email_suppression::reason)))
+            .returning(EmailSuppression::as_returning())
+            .get_result(&mut conn)
+            .await
+            .map_err(DbError::from)
+    }
+
+    #[instrument(skip_all)]
+    pub async fn exists(&self, email: &str) -> Result<bool, DbError> {
+        let mut conn = self.conn().await?;
+        select(exists(email_suppression::table.find(email)))
+            .get_result(&mut conn)
+            .await
+            .map_err(DbError::from)
+    }
+
+    #[instrument(skip_all)]
+    pub async fn find_all(&self) -> Result<Vec<EmailSuppression>, DbError> {
+        let mut conn = self.read_conn().await?;
+        email_suppression::table
+            .order(email_suppression::created_at.desc())
+            .select(EmailSuppression::as_select())
+            .get_results(&mut conn)
+            .await
+            .map_err(DbError::from)
+    }
+
+    #[instrument(skip_all)]
+    pub async fn delete(&self, email: &str) -> Result<usize, DbError> {
+        let mut conn = self.conn().await?;
+        diesel::delete(email_suppression::table.find(email))
+            .execute(&mut conn)
+            .await
+            .map_err(DbError::from)
+    }
+}
+
+impl BaseRepository for EmailSuppressionRepository {
+    fn pools(&self) -> &DbPools {
+        &self.pools
+    }
+}
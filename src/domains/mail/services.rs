@@ -0,0 +1,106 @@
+use std::sync::Arc;
+use chrono::Utc;
+use tracing::{debug, info, warn};
+use crate::common::errors::api_error_response::ErrorMessage;
+use crate::common::errors::application_error::ApplicationError;
+use crate::common::errors::application_error::ApplicationError::NotFound;
+use crate::common::errors::error_code;
+use crate::common::errors::global_api_error::ApiError;
+use crate::common::errors::request_error::RequestError::ValidationError;
+use crate::domains::mail::db_models::EmailSuppression;
+use crate::domains::mail::jobs::SendEmail;
+use crate::domains::mail::mailer::{MailError, Mailer};
+use crate::domains::mail::repository::EmailSuppressionRepository;
+use crate::domains::mail::templates;
+
+const HARD_BOUNCE: &str = "hard_bounce";
+
+#[derive(Clone)]
+pub struct MailService {
+    mailer: Arc<dyn Mailer>,
+    email_suppression_repository: EmailSuppressionRepository,
+}
+
+impl MailService {
+    pub fn new(mailer: Arc<dyn Mailer>, email_suppression_repository: EmailSuppressionRepository) -> Self {
+        MailService { mailer, email_suppression_repository }
+    }
+
+    /*
+        Renders and sends the email unless the address is suppressed. An address that hard bounces
+        is suppressed and the email dropped, other failures are retried by the job
+    */
+    pub async fn send(&self, job: SendEmail) -> Result<(), ApplicationError> {
+        let to = job.to.to_lowercase();
+        if self.email_suppression_repository.exists(&to).await.map_err(ApplicationError::from)? {
+            info!("Not sending {:?} email to suppressed address {:?}", job.template, to);
+            metrics::counter!("emails_total", "outcome" => "suppressed").increment(1);
+            return Ok(());
+        }
+
+        let email = templates::render(&job.to, job.template, job.locale, &job.variables).map_err(|e| {
+            warn!("Unable to render {:?} email: {:#}", job.template, e);
+            ApplicationError::InternalServerError
+        })?;
+
+        debug!("Sending {:?} email to {:?}", job.template, to);
+        match self.mailer.send(&email).await {
+            Ok(()) => {
+                metrics::counter!("emails_total", "outcome" => "sent").increment(1);
+                Ok(())
+            },
+            Err(MailError::Bounced(reason)) => {
+                warn!("Email to {:?} bounced, suppressing the address: {}", to, reason);
+                metrics::counter!("emails_total", "outcome" => "bounced").increment(1);
+                self.suppress_address(&to, HARD_BOUNCE).await.map(|_| ())
+            },
+            Err(MailError::Unavailable(error)) => {
+                warn!("Unable to send email to {:?}: {}", to, error);
+                metrics::counter!("emails_total", "outcome" => "retry").increment(1);
+                Err(ApplicationError::ServiceUnavailable)
+            },
+        }
+    }
+
+    // Most recently suppressed first
+    pub async fn find_suppressions(&self) -> Result<Vec<EmailSuppression>, ApplicationError> {
+        self.email_suppression_repository
+            .find_all()
+            .await
+            .map_err(ApplicationError::from)
+    }
+
+    pub async fn suppress(&self, email: &str, reason: &str) -> Result<EmailSuppression, ApiError> {
+        if !validator::ValidateEmail::validate_email(&email) {
+            return Err(ValidationError(vec![ErrorMessage::new(Some("email"), error_code::VALIDATION_EMAIL_INVALID)]).into());
+        }
+
+        Ok(self.suppress_address(email, reason).await?)
+    }
+
+    async fn suppress_address(&self, email: &str, reason: &str) -> Result<EmailSuppression, ApplicationError> {
+        info!("Suppressing email address: {:?} ({})", email, reason);
+        self.email_suppression_repository
+            .upsert(&EmailSuppression {
+                email: email.to_lowercase(),
+                reason: reason.to_string(),
+                created_at: Utc::now().naive_utc(),
+            })
+            .await
+            .map_err(ApplicationError::from)
+    }
+
+    pub async fn unsuppress(&self, email: &str) -> Result<(), ApplicationError> {
+        info!("Removing email address from the suppression list: {:?}", email);
+        let row_updated = self.email_suppression_repository
+            .delete(&email.to_lowercase())
+            .await
+            .map_err(ApplicationError::from)?;
+
+        if row_updated == 0 {
+            Err(NotFound)
+        } else {
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use lazy_static::lazy_static;
+use minijinja::{Environment, UndefinedBehavior};
+use serde::{Deserialize, Serialize};
+use crate::common::i18n::locale::Locale;
+use crate::config::app_env::SUPPORT_CONTACT;
+use crate::domains::mail::mailer::Email;
+
+/*
+    Every template has a subject, a text and an HTML version per locale under templates/<locale>/.
+    Variables are the same across locales, `locale` and `support_contact` are always available
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EmailTemplate {
+    // Variables: first_name, organisation
+    Invite,
+    // Variables: first_name
+    PasswordChanged,
+}
+
+impl EmailTemplate {
+    fn name(&self) -> &'static str {
+        match self {
+            EmailTemplate::Invite => "invite",
+            EmailTemplate::PasswordChanged => "password_changed",
+        }
+    }
+}
+
+macro_rules! templates {
+    ($($locale:literal / $name:literal),* $(,)?) => {
+        [$(
+            (concat!($locale, "/", $name, ".subject.txt"), include_str!(concat!("templates/", $locale, "/", $name, ".subject.txt"))),
+            (concat!($locale, "/", $name, ".txt"), include_str!(concat!("templates/", $locale, "/", $name, ".txt"))),
+            (concat!($locale, "/", $name, ".html"), include_str!(concat!("templates/", $locale, "/", $name, ".html"))),
+        )*]
+    };
+}
+
+lazy_static! {
+    static ref TEMPLATES: Environment<'static> = {
+        let mut environment = Environment::new();
+        // A typo in a variable fails the email instead of sending it with a blank
+        environment.set_undefined_behavior(UndefinedBehavior::Strict);
+        environment.add_global("support_contact", SUPPORT_CONTACT.as_str());
+        environment.add_template("layout.html", include_str!("templates/layout.html")).expect("Invalid email layout");
+        let templates = templates!(
+            "en" / "invite", "de" / "invite", "fr" / "invite",
+            "en" / "password_changed", "de" / "password_changed", "fr" / "password_changed",
+        );
+        for (name, source) in templates {
+            environment.add_template(name, source).unwrap_or_else(|e| panic!("Invalid email template {}: {}", name, e));
+        }
+        environment
+    };
+}
+
+/*
+    The email in the given locale, HTML variables are escaped. Falls back to English when the template
+    has no translation for the locale
+*/
+pub fn render(to: &str, template: EmailTemplate, locale: Locale, variables: &serde_json::Value) -> Result<Email, minijinja::Error> {
+    let locale = if TEMPLATES.get_template(&path(locale, template, "txt")).is_ok() { locale } else { Locale::En };
+    let context = minijinja::context! { locale => locale.as_str(), ..minijinja::Value::from_serialize(variables) };
+    let render = |extension| TEMPLATES.get_template(&path(locale, template, extension))?.render(&context);
+
+    Ok(Email {
+        to: to.to_string(),
+        subject: render("subject.txt")?.trim().to_string(),
+        text: render("txt")?,
+        html: render("html")?,
+    })
+}
+
+fn path(locale: Locale, template: EmailTemplate, extension: &str) -> String {
+    format!("{}/{}.{}", locale, template.name(), extension)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::i18n::locale::SUPPORTED_LOCALES;
+    use super::*;
+
+    #[test]
+    fn every_template_renders_in_every_locale() {
+        let templates = [
+            (EmailTemplate::Invite, serde_json::json!({ "first_name": "Ada", "organisation": "<Acme>" })),
+            (EmailTemplate::PasswordChanged, serde_json::json!({ "first_name": "Ada" })),
+        ];
+
+        for (template, variables) in templates {
+            for locale in SUPPORTED_LOCALES {
+                let email = render("ada@example.com", template, locale, &variables).unwrap();
+                assert!(!email.subject.is_empty() && !email.subject.contains('\n'), "{:?} {}", template, locale);
+                assert!(email.text.contains("Ada"), "{:?} {}", template, locale);
+                assert!(email.html.contains(&format!("lang=\"{}\"", locale)), "{:?} {}", template, locale);
+            }
+        }
+    }
+
+    #[test]
+    fn html_is_escaped_and_variables_required() {
+        let variables = serde_json::json!({ "first_name": "Ada", "organisation": "<Acme>" });
+        let email = render("ada@example.com", EmailTemplate::Invite, Locale::En, &variables).unwrap();
+        assert!(email.html.contains("&lt;Acme&gt;"));
+        assert!(email.text.contains("<Acme>"));
+
+        assert!(render("ada@example.com", EmailTemplate::Invite, Locale::En, &serde_json::json!({ "first_name": "Ada" })).is_err());
+    }
+}
@@ -0,0 +1,24 @@
+use axum::extract::State;
+use axum::Router;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use http::header;
+use crate::config::AppState;
+use crate::domains::metrics::services::MetricsService;
+
+/*
+    Unauthenticated for the scraper, served on METRICS_PORT instead of the main router when it is set
+*/
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/metrics", get(fetch_metrics))
+}
+
+async fn fetch_metrics(
+    State(metrics_service): State<MetricsService>,
+) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics_service.render(),
+    )
+}
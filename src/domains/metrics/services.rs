@@ -0,0 +1,26 @@
+use metrics_exporter_prometheus::PrometheusHandle;
+use crate::common::repository;
+use crate::domains::health::repository::HealthRepository;
+
+#[derive(Clone)]
+pub struct MetricsService {
+    prometheus_handle: PrometheusHandle,
+    health_repository: HealthRepository,
+}
+
+impl MetricsService {
+    pub fn new(prometheus_handle: PrometheusHandle, health_repository: HealthRepository) -> Self {
+        MetricsService { prometheus_handle, health_repository }
+    }
+
+    // Pool gauges are sampled on scrape, bb8 has no hook to push them on change
+    pub fn render(&self) -> String {
+        let state = self.health_repository.pool_state();
+        metrics::gauge!("db_pool_connections").set(state.connections as f64);
+        metrics::gauge!("db_pool_idle_connections").set(state.idle_connections as f64);
+        metrics::gauge!("db_pool_waiters").set(repository::pool_waiters() as f64);
+        metrics::gauge!("db_pool_checkout_timeouts").set(state.statistics.get_timed_out as f64);
+
+        self.prometheus_handle.render()
+    }
+}
@@ -2,4 +2,16 @@ pub mod favicon;
 pub mod users;
 pub mod organisations;
 pub mod auth;
-pub mod applications;
\ No newline at end of file
+pub mod applications;
+pub mod health;
+pub mod metrics;
+pub mod logging;
+pub mod idempotency;
+pub mod docs;
+pub mod webhooks;
+pub mod outbox;
+pub mod jobs;
+pub mod mail;
+pub mod activity;
+pub mod realtime;
+pub mod search;
\ No newline at end of file
@@ -1,14 +1,15 @@
 use chrono::{NaiveDateTime, Utc};
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 use uuid::Uuid;
-use crate::domains::organisations::db_models::{Organisation, PutOrganisation};
+use crate::domains::organisations::db_models::{DEFAULT_PLAN, Organisation, PutOrganisation};
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
 pub struct OrganisationCreateRequest {
     pub name: String,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
 pub struct OrganisationPutRequest {
     pub owner: Option<Uuid>,
     pub name: String,
@@ -23,39 +24,42 @@ impl OrganisationCreateRequest {
             is_archived: false,
             created_at: Utc::now().naive_utc(),
             updated_at: Utc::now().naive_utc(),
+            plan: DEFAULT_PLAN.to_string(),
         }
     }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct OrganisationResponse {
     pub id: Uuid,
     pub owner: Uuid,
     pub name: String,
+    pub plan: String,
     pub created_at: NaiveDateTime
 }
 
-impl Into<OrganisationResponse> for Organisation {
-    fn into(self) -> OrganisationResponse {
+impl From<Organisation> for OrganisationResponse {
+    fn from(organisation: Organisation) -> Self {
         OrganisationResponse {
-            id: self.id,
-            owner: self.owner,
-            name: self.name,
-            created_at: self.created_at,
+            id: organisation.id,
+            owner: organisation.owner,
+            name: organisation.name,
+            plan: organisation.plan,
+            created_at: organisation.created_at,
         }
     }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct OrganisationsResponse {
     pub data: Vec<OrganisationResponse>
 }
 
-impl Into<PutOrganisation> for OrganisationPutRequest {
-    fn into(self) -> PutOrganisation {
+impl From<OrganisationPutRequest> for PutOrganisation {
+    fn from(request: OrganisationPutRequest) -> Self {
         PutOrganisation {
-            owner: self.owner,
-            name: self.name,
+            owner: request.owner,
+            name: request.name,
             updated_at: Utc::now().naive_utc(),
         }
     }
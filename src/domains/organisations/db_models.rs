@@ -5,6 +5,10 @@ use uuid::Uuid;
 
 use crate::common::schema;
 
+// Must match organisation_plan_check
+pub const PLANS: [&str; 3] = ["free", "pro", "enterprise"];
+pub const DEFAULT_PLAN: &str = "free";
+
 #[derive(Insertable, Queryable, Debug, AsChangeset, Identifiable, Serialize, Deserialize)]
 #[diesel(table_name = schema::organisation)]
 pub struct Organisation {
@@ -14,6 +18,7 @@ pub struct Organisation {
     pub is_archived: bool,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
+    pub plan: String,
 }
 
 #[derive(Debug, AsChangeset, Serialize)]
@@ -1,18 +1,23 @@
 use axum::extract::{Path, State};
 use axum::{Json, Router};
 use axum::routing::get;
-use http::StatusCode;
+use axum::response::Response;
+use http::{HeaderMap, StatusCode};
 use uuid::Uuid;
 use crate::config::AppState;
 
+use crate::common::errors::api_error_response::ErrorResponse;
 use crate::common::errors::application_error::ApplicationError;
 use crate::common::extract::request::SwiftJson;
-use crate::common::models::models::Identity;
+use crate::common::models::models::{ExpectedVersion, Identity};
+use crate::common::repository;
+use crate::common::utils::etag;
 use crate::domains::organisations::api_models::{OrganisationCreateRequest, OrganisationPutRequest, OrganisationResponse, OrganisationsResponse};
 use crate::domains::organisations::db_models::Organisation;
 use crate::domains::organisations::services::OrganisationService;
 use crate::domains::users::api_models::UsersResponse;
 use crate::domains::users::services::UserService;
+use crate::domains::docs::params::{IdempotencyKeyHeader, IfMatchHeader, IfNoneMatchHeader};
 
 pub fn routes() -> Router<AppState> {
     Router::new()
@@ -21,23 +26,40 @@ pub fn routes() -> Router<AppState> {
         .route("/organisations/:organisation_id/users", get(fetch_organisation_users)) // todo move to users and read organisation via header
 }
 
+#[utoipa::path(
+    post,
+    path = "/organisations",
+    tag = "organisations",
+    params(IdempotencyKeyHeader),
+    request_body = OrganisationCreateRequest,
+    responses(
+        (status = 201, description = "Organisation created, owned by the caller", body = OrganisationResponse),
+        (status = 400, response = ErrorResponse),
+        (status = 401, response = ErrorResponse),
+        (status = 403, response = ErrorResponse),
+        (status = 409, response = ErrorResponse),
+    ),
+)]
 async fn create_organisation(
     identity: Identity,
     State(organisation_service): State<OrganisationService>,
     SwiftJson(organisation_request): SwiftJson<OrganisationCreateRequest>,
 ) -> Result<(StatusCode, Json<OrganisationResponse>), ApplicationError> {
-    // TODO check plan if they can create more than 1 organisation, free user should only able to create 1
-    let organisations = organisation_service.find_by_owner(identity.user_id).await?;
-    if organisations.capacity() > 1 {
-        return Err(ApplicationError::LimitReached("Only 1 owned organisation per user allowed for this plan.".to_string()).into());
-    }
-
-    organisation_service.create_by_user(organisation_request.into_with_owner(identity.user_id.clone()), identity)
+    organisation_service.create_by_user(organisation_request.into_with_owner(identity.user_id), identity)
         .await
         .map(Organisation::into)
         .map(|response| (StatusCode::CREATED, Json(response)))
 }
 
+#[utoipa::path(
+    get,
+    path = "/organisations",
+    tag = "organisations",
+    responses(
+        (status = 200, description = "Organisations the caller is a member of", body = OrganisationsResponse),
+        (status = 401, response = ErrorResponse),
+    ),
+)]
 async fn fetch_organisations(
     identity: Identity,
     State(organisation_service): State<OrganisationService>,
@@ -49,21 +71,47 @@ async fn fetch_organisations(
                 data: organisations.into_iter().map(Organisation::into).collect()
             }
         )
-        .map(|response| Json(response))
+        .map(Json)
 }
 
+#[utoipa::path(
+    get,
+    path = "/organisations/{organisation_id}",
+    tag = "organisations",
+    params(("organisation_id" = Uuid, Path), IfNoneMatchHeader),
+    responses(
+        (status = 200, description = "The organisation", body = OrganisationResponse, headers(("ETag" = String))),
+        (status = 304, description = "Not modified since the ETag in If-None-Match"),
+        (status = 401, response = ErrorResponse),
+        (status = 404, response = ErrorResponse),
+    ),
+)]
 async fn fetch_organisation(
     identity: Identity,
     State(organisation_service): State<OrganisationService>,
     Path(organisation_id): Path<Uuid>,
-) -> Result<Json<OrganisationResponse>, ApplicationError> {
+    headers: HeaderMap,
+) -> Result<Response, ApplicationError> {
 
     organisation_service.find_by_id_and_accessible_user_id(&identity, &organisation_id)
         .await
-        .map(Organisation::into)
-        .map(|response| Json(response))
+        .map(|organisation| {
+            let updated_at = organisation.updated_at;
+            etag::respond_with_version(&headers, &updated_at, Json::<OrganisationResponse>(organisation.into()))
+        })
 }
 
+#[utoipa::path(
+    get,
+    path = "/organisations/{organisation_id}/users",
+    tag = "organisations",
+    params(("organisation_id" = Uuid, Path)),
+    responses(
+        (status = 200, description = "Users of the organisation", body = UsersResponse),
+        (status = 401, response = ErrorResponse),
+        (status = 404, response = ErrorResponse),
+    ),
+)]
 async fn fetch_organisation_users(
     identity: Identity,
     State(organisation_service): State<OrganisationService>,
@@ -72,28 +120,46 @@ async fn fetch_organisation_users(
 ) -> Result<Json<UsersResponse>, ApplicationError> {
     let organisation: Organisation = organisation_service.find_by_id_and_accessible_user_id(&identity, &organisation_id).await?;
 
-    user_service.find_all_by_organisation_id(&organisation.id)
+    // No X-Organisation-Id on this route, the organisation in the path is checked above
+    repository::tenant_scope(organisation.id, user_service.find_all_by_organisation_id(&organisation.id, None))
         .await
         .map(|users| UsersResponse {
             data: users.into_iter().map(|user| user.into()).collect(),
         })
-        .map(|response| Json(response))
+        .map(Json)
 }
 
 /*
 Only admin is allowed to update organisation
 */
+#[utoipa::path(
+    put,
+    path = "/organisations/{organisation_id}",
+    tag = "organisations",
+    params(("organisation_id" = Uuid, Path), IfMatchHeader),
+    request_body = OrganisationPutRequest,
+    responses(
+        (status = 204, description = "Organisation updated", headers(("ETag" = String, description = "The new version"))),
+        (status = 400, response = ErrorResponse),
+        (status = 401, response = ErrorResponse),
+        (status = 403, response = ErrorResponse),
+        (status = 404, response = ErrorResponse),
+        (status = 412, response = ErrorResponse),
+        (status = 428, response = ErrorResponse),
+    ),
+)]
 async fn update_organisation(
     identity: Identity,
     Path(organisation_id): Path<Uuid>,
     State(organisation_service): State<OrganisationService>,
+    expected_version: ExpectedVersion,
     SwiftJson(user_request): SwiftJson<OrganisationPutRequest>,
-) -> Result<StatusCode, ApplicationError> {
+) -> Result<Response, ApplicationError> {
 
     match organisation_service.is_admin(&identity.user_id, &organisation_id).await? {
-        true => organisation_service.update_by_id(organisation_id, user_request)
+        true => organisation_service.update_by_id(organisation_id, expected_version, user_request)
             .await
-            .map(|_| StatusCode::NO_CONTENT),
+            .map(|updated_at| etag::updated(&updated_at)),
         false => Err(ApplicationError::Forbidden)
     }
 }
@@ -101,14 +167,28 @@ async fn update_organisation(
 /*
 Only admin is allowed to delete organisation
 */
+#[utoipa::path(
+    delete,
+    path = "/organisations/{organisation_id}",
+    tag = "organisations",
+    params(("organisation_id" = Uuid, Path), IfMatchHeader),
+    responses(
+        (status = 204, description = "Organisation deleted"),
+        (status = 401, response = ErrorResponse),
+        (status = 404, response = ErrorResponse),
+        (status = 412, response = ErrorResponse),
+        (status = 428, response = ErrorResponse),
+    ),
+)]
 async fn delete_organisation(
     identity: Identity,
     Path(organisation_id): Path<Uuid>,
     State(organisation_service): State<OrganisationService>,
+    expected_version: ExpectedVersion,
 ) -> Result<StatusCode, ApplicationError> {
 
     match organisation_service.is_admin(&identity.user_id, &organisation_id).await? {
-        true => organisation_service.delete_by_id(organisation_id)
+        true => organisation_service.delete_by_id(organisation_id, expected_version)
             .await
             .map(|_| StatusCode::NO_CONTENT),
         false => Err(ApplicationError::Unauthorized)
@@ -0,0 +1,25 @@
+use axum::async_trait;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use crate::common::errors::application_error::ApplicationError;
+use crate::domains::jobs::registry::{Job, JobHandler};
+use crate::domains::organisations::services::OrganisationService;
+
+/*
+    Enqueued when an organisation is archived, runs once ARCHIVED_ORGANISATION_RETENTION passed
+*/
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PurgeArchivedOrganisation {
+    pub organisation_id: Uuid,
+}
+
+impl Job for PurgeArchivedOrganisation {
+    const KIND: &'static str = "purge_archived_organisation";
+}
+
+#[async_trait]
+impl JobHandler<PurgeArchivedOrganisation> for OrganisationService {
+    async fn handle(&self, job: PurgeArchivedOrganisation) -> Result<(), ApplicationError> {
+        self.purge_archived_by_id(&job.organisation_id).await
+    }
+}
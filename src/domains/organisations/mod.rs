@@ -3,3 +3,4 @@ pub mod services;
 pub mod repository;
 pub mod db_models;
 pub mod api_models;
+pub mod jobs;
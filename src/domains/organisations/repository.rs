@@ -1,24 +1,27 @@
+use chrono::{NaiveDateTime, Utc};
 use diesel::prelude::*;
 
-use bb8::{Pool};
-use diesel_async::{ AsyncPgConnection, RunQueryDsl};
-use diesel_async::pooled_connection::AsyncDieselConnectionManager;
+use diesel_async::AsyncPgConnection;
+use crate::common::query::RunQueryDsl;
+use tracing::instrument;
 use uuid::Uuid;
 use crate::common::errors::db_error::DbError;
 use crate::common::schema::{organisation, swift_user_accessible_organisation};
 use crate::common::repository::BaseRepository;
+use crate::config::diesel_config::DbPools;
 use crate::domains::organisations::db_models::{Organisation, PutOrganisation};
 
 #[derive(Clone)]
 pub struct OrganisationRepository {
-    pool: Pool<AsyncDieselConnectionManager<AsyncPgConnection>>,
+    pools: DbPools,
 }
 
 impl OrganisationRepository {
-    pub fn new(pool: Pool<AsyncDieselConnectionManager<AsyncPgConnection>>) -> Self {
-        OrganisationRepository { pool }
+    pub fn new(pools: DbPools) -> Self {
+        OrganisationRepository { pools }
     }
 
+    #[instrument(skip_all)]
     pub async fn insert_with_conn(&self,
                                   conn: &mut AsyncPgConnection,
                                   organisation: &Organisation
@@ -30,8 +33,10 @@ impl OrganisationRepository {
             .map_err(DbError::from)
     }
 
+    // Memberships of a user span organisations, so these lookups run as the admin role
+    #[instrument(skip_all)]
     pub async fn find_all_accessible(&self, user_id: &Uuid) -> Result<Vec<Organisation>, DbError> {
-        let mut conn = self.conn().await?;
+        let mut conn = self.admin_conn().await?;
         organisation::table
             .inner_join(swift_user_accessible_organisation::table.on(organisation::id.eq(swift_user_accessible_organisation::organisation_id)))
             .filter(swift_user_accessible_organisation::swift_user_id.eq(user_id))
@@ -41,8 +46,9 @@ impl OrganisationRepository {
             .map_err(DbError::from)
     }
 
+    #[instrument(skip_all)]
     pub async fn find_by_accessible_user_id(&self, organisation_id: &Uuid, user_id: &Uuid) -> Result<Option<Organisation>, DbError> {
-        let mut conn = self.conn().await?;
+        let mut conn = self.admin_conn().await?;
         organisation::table
             .inner_join(swift_user_accessible_organisation::table.on(organisation::id.eq(swift_user_accessible_organisation::organisation_id)))
             .filter(organisation::id.eq(organisation_id))
@@ -54,33 +60,90 @@ impl OrganisationRepository {
             .map_err(DbError::from)
     }
 
-    pub async fn find_by_owner(&self, id: &Uuid) -> Result<Vec<Organisation>, DbError> {
-        let mut conn = self.conn().await?;
-        organisation::table.filter(organisation::owner.eq(id))
-            .get_results(&mut conn)
+    #[instrument(skip_all)]
+    pub async fn count_by_owner_with_conn(&self, conn: &mut AsyncPgConnection, owner: &Uuid) -> Result<i64, DbError> {
+        organisation::table.filter(organisation::owner.eq(owner))
+            .count()
+            .get_result(conn)
+            .await
+            .map_err(DbError::from)
+    }
+
+    // Always the primary, a conditional change must compare against the current version
+    #[instrument(skip_all)]
+    pub async fn find_version(&self, id: &Uuid) -> Result<Option<NaiveDateTime>, DbError> {
+        let mut conn = self.fresh_read_conn().await?;
+        organisation::table.find(id)
+            .select(organisation::updated_at)
+            .get_result(&mut conn)
+            .await
+            .optional()
+            .map_err(DbError::from)
+    }
+
+    #[instrument(skip_all)]
+    pub async fn update_by_id_with_conn(&self, conn: &mut AsyncPgConnection, id: &Uuid, versions: &[NaiveDateTime], organisation: &PutOrganisation) -> Result<Option<NaiveDateTime>, DbError> {
+        diesel::update(organisation::table.find(id).filter(organisation::updated_at.eq_any(versions)))
+            .set(organisation)
+            .returning(organisation::updated_at)
+            .get_result(conn)
             .await
+            .optional()
             .map_err(DbError::from)
     }
 
-    pub async fn update_by_id(&self, id: &Uuid, organisation: PutOrganisation) -> Result<usize, DbError> {
-        let mut conn = self.conn().await?;
+    #[instrument(skip_all)]
+    pub async fn delete_by_id_with_conn(&self, conn: &mut AsyncPgConnection, id: &Uuid, versions: &[NaiveDateTime]) -> Result<usize, DbError> {
+        diesel::delete(organisation::table.filter(organisation::id.eq(id)).filter(organisation::updated_at.eq_any(versions)))
+            .execute(conn)
+            .await
+            .map_err(DbError::from)
+    }
+
+    #[instrument(skip_all)]
+    pub async fn find_all(&self, include_archived: bool) -> Result<Vec<Organisation>, DbError> {
+        let mut conn = self.read_conn().await?;
+        let mut query = organisation::table.order(organisation::created_at).into_boxed();
+        if !include_archived {
+            query = query.filter(organisation::is_archived.eq(false));
+        }
+
+        query.load(&mut conn)
+            .await
+            .map_err(DbError::from)
+    }
+
+    #[instrument(skip_all)]
+    pub async fn archive_by_id_with_conn(&self, conn: &mut AsyncPgConnection, id: &Uuid) -> Result<usize, DbError> {
         diesel::update(organisation::table.find(id))
-            .set(&organisation)
-            .execute(&mut conn)
+            .set((organisation::is_archived.eq(true), organisation::updated_at.eq(Utc::now().naive_utc())))
+            .execute(conn)
+            .await
+            .map_err(DbError::from)
+    }
+
+    // Only while the organisation is still archived
+    #[instrument(skip_all)]
+    pub async fn delete_archived_by_id_with_conn(&self, conn: &mut AsyncPgConnection, id: &Uuid) -> Result<usize, DbError> {
+        diesel::delete(organisation::table.find(id).filter(organisation::is_archived.eq(true)))
+            .execute(conn)
             .await
             .map_err(DbError::from)
     }
 
-    pub async fn delete_by_id(&self, id: &Uuid) -> Result<usize, DbError> {
-        let mut conn = self.conn().await?;
-        diesel::delete(organisation::table.filter(organisation::id.eq(id)))
-            .execute(&mut conn)
+    #[instrument(skip_all)]
+    pub async fn update_plan_by_id_with_conn(&self, conn: &mut AsyncPgConnection, id: &Uuid, plan: &str) -> Result<usize, DbError> {
+        diesel::update(organisation::table.find(id))
+            .set((organisation::plan.eq(plan), organisation::updated_at.eq(Utc::now().naive_utc())))
+            .execute(conn)
             .await
             .map_err(DbError::from)
     }
 
+    #[instrument(skip_all)]
     pub async fn find_role(&self, organisation_id: &Uuid, user_id: &Uuid) -> Result<Option<i64>, DbError> {
-        let mut conn = self.conn().await?;
+        // Admin role on the primary, a removed member must lose access right away
+        let mut conn = self.admin_conn().await?;
         organisation::table
             .inner_join(swift_user_accessible_organisation::table.on(organisation::id.eq(swift_user_accessible_organisation::organisation_id)))
             .filter(organisation::id.eq(organisation_id))
@@ -94,7 +157,7 @@ impl OrganisationRepository {
 }
 
 impl BaseRepository for OrganisationRepository {
-    fn pool(&self) -> &Pool<AsyncDieselConnectionManager<AsyncPgConnection>> {
-        &self.pool
+    fn pools(&self) -> &DbPools {
+        &self.pools
     }
 }
\ No newline at end of file
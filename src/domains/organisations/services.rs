@@ -1,54 +1,81 @@
-use diesel_async::{AsyncConnection};
+use chrono::{NaiveDateTime, Utc};
+use diesel_async::scoped_futures::ScopedFutureExt;
 use tracing::{debug, info};
 use uuid::Uuid;
 
 use crate::common::errors::application_error::ApplicationError;
 use crate::common::errors::application_error::ApplicationError::NotFound;
-use crate::common::models::models::Identity;
-use crate::common::repository::BaseRepository;
+use crate::common::errors::error_code;
+use crate::common::models::models::{ExpectedVersion, Identity};
+use crate::common::repository::{self, BaseRepository, TransactionOptions};
+use crate::config::app_env::ARCHIVED_ORGANISATION_RETENTION;
+use crate::domains::jobs::db_models::NewJob;
+use crate::domains::jobs::repository::JobRepository;
 use crate::domains::organisations::api_models::{OrganisationPutRequest};
-use crate::domains::organisations::db_models::Organisation;
+use crate::domains::organisations::db_models::{Organisation, PutOrganisation};
+use crate::domains::organisations::jobs::PurgeArchivedOrganisation;
 use crate::domains::organisations::repository::OrganisationRepository;
+use crate::domains::outbox::events::DomainEvent;
+use crate::domains::outbox::repository::OutboxRepository;
 use crate::domains::users::db_models::SwiftUserOrganisation;
 use crate::domains::users::repository::UserRepository;
 
+const MAX_OWNED_ORGANISATIONS: i64 = 1;
+
 #[derive(Clone)]
 pub struct OrganisationService {
     organisation_repository: OrganisationRepository,
     user_repository: UserRepository, 
+    outbox_repository: OutboxRepository,
+    job_repository: JobRepository,
 }
 
 impl OrganisationService {
-    pub fn new(organisation_repository: OrganisationRepository,  user_repository: UserRepository) -> Self {
-        OrganisationService { organisation_repository, user_repository }
+    pub fn new(organisation_repository: OrganisationRepository,  user_repository: UserRepository, outbox_repository: OutboxRepository, job_repository: JobRepository) -> Self {
+        OrganisationService { organisation_repository, user_repository, outbox_repository, job_repository }
     }
 
     pub async fn create_by_user(&self, organisation: Organisation, identity: Identity) -> Result<Organisation, ApplicationError> {
-        let mut conn = self.organisation_repository.conn().await?;
-
-        let created_organisation = conn.transaction(|tx| {
-            Box::pin(async move {
-                debug!("Creating organisation: {:?}", organisation);
-                let result = self.organisation_repository
-                    .insert_with_conn(tx, &organisation)
-                    .await
-                    .map_err(ApplicationError::from);
-
-                debug!("Inserting user accessible organisation...");
-                self.user_repository.insert_user_accessible_organisation_with_conn(tx, SwiftUserOrganisation {
-                    swift_user_id: identity.user_id.clone(),
-                    organisation_id: organisation.id,
-                    role_id: 1, // Admin
-                })
-                .await
-                .map_err(ApplicationError::from)?;
-
-                result
+        let organisation = &organisation;
+        let user_id = identity.user_id;
+
+        // Serializable so concurrent requests can't both pass the owned organisation limit.
+        // Scoped to the new organisation, the owner's membership is its first row
+        let created_organisation = repository::tenant_scope(organisation.id, self.organisation_repository.in_transaction(TransactionOptions::serializable(), |mut uow| async move {
+            // TODO check plan if they can create more than 1 organisation, free user should only able to create 1
+            let owned = self.organisation_repository
+                .count_by_owner_with_conn(uow.conn(), &user_id)
+                .await?;
+            if owned > MAX_OWNED_ORGANISATIONS {
+                return Ok(None);
+            }
+
+            debug!("Creating organisation: {:?}", organisation);
+            let created = self.organisation_repository
+                .insert_with_conn(uow.conn(), organisation)
+                .await?;
+
+            debug!("Inserting user accessible organisation...");
+            self.user_repository.insert_user_accessible_organisation_with_conn(uow.conn(), SwiftUserOrganisation {
+                swift_user_id: user_id,
+                organisation_id: organisation.id,
+                role_id: 1, // Admin
             })
-        }).await;
+            .await?;
+
+            self.outbox_repository.insert_with_conn(uow.conn(), &DomainEvent::OrganisationCreated { organisation_id: created.id, owner: user_id }).await?;
+            self.outbox_repository.insert_with_conn(uow.conn(), &DomainEvent::MemberAdded { organisation_id: created.id, user_id, role_id: 1 }).await?;
+
+            Ok(Some(created))
+        }.scope_boxed())).await.map_err(ApplicationError::from)?;
 
-        info!("Organisation successfully created");
-        created_organisation
+        match created_organisation {
+            Some(created_organisation) => {
+                info!("Organisation successfully created");
+                Ok(created_organisation)
+            },
+            None => Err(ApplicationError::LimitReached(error_code::ORGANISATION_OWNED_LIMIT_REACHED)),
+        }
     }
 
     pub async fn find_all(&self, user_id: &Uuid) -> Result<Vec<Organisation>, ApplicationError> {
@@ -78,21 +105,70 @@ impl OrganisationService {
         }
     }
 
-    pub async fn find_by_owner(&self, user_id: Uuid) -> Result<Vec<Organisation>, ApplicationError> {
-        self.organisation_repository.find_by_owner(&user_id)
+    pub async fn find_all_unscoped(&self, include_archived: bool) -> Result<Vec<Organisation>, ApplicationError> {
+        self.organisation_repository
+            .find_all(include_archived)
             .await
             .map_err(ApplicationError::from)
     }
 
-    pub async fn update_by_id(&self, id: Uuid, organisation_request: OrganisationPutRequest) -> Result<(), ApplicationError> {
-        debug!("Updating organisation by id: {:?}", id);
+    /*
+        The organisation is deleted for good once archived for ARCHIVED_ORGANISATION_RETENTION
+    */
+    pub async fn archive_by_id(&self, id: &Uuid) -> Result<(), ApplicationError> {
+        info!("Archiving organisation: {:?}", id);
+        let purge_at = Utc::now().naive_utc() + *ARCHIVED_ORGANISATION_RETENTION;
+        let row_updated = self.organisation_repository.in_transaction(TransactionOptions::default(), |mut uow| async move {
+            let row_updated = self.organisation_repository
+                .archive_by_id_with_conn(uow.conn(), id)
+                .await?;
+
+            if row_updated > 0 {
+                self.outbox_repository.insert_with_conn(uow.conn(), &DomainEvent::OrganisationArchived { organisation_id: *id }).await?;
+                self.job_repository.insert_with_conn(uow.conn(), &NewJob::new(&PurgeArchivedOrganisation { organisation_id: *id }, purge_at)).await?;
+            }
+            Ok(row_updated)
+        }.scope_boxed()).await.map_err(ApplicationError::from)?;
 
-        let row_updated = self.organisation_repository
-            .update_by_id(&id, organisation_request.into())
-            .await
-            .map_err(ApplicationError::from)?;
+        if row_updated == 0 {
+            Err(NotFound)
+        } else {
+            Ok(())
+        }
+    }
+
+    // Nothing to do when the organisation is gone or no longer archived
+    pub async fn purge_archived_by_id(&self, id: &Uuid) -> Result<(), ApplicationError> {
+        let row_updated = self.organisation_repository.in_transaction(TransactionOptions::default(), |mut uow| async move {
+            let row_updated = self.organisation_repository
+                .delete_archived_by_id_with_conn(uow.conn(), id)
+                .await?;
+
+            if row_updated > 0 {
+                self.outbox_repository.insert_with_conn(uow.conn(), &DomainEvent::OrganisationDeleted { organisation_id: *id }).await?;
+            }
+            Ok(row_updated)
+        }.scope_boxed()).await.map_err(ApplicationError::from)?;
+
+        if row_updated > 0 {
+            info!("Purged archived organisation: {:?}", id);
+        }
+        Ok(())
+    }
+
+    pub async fn update_plan_by_id(&self, id: &Uuid, plan: &str) -> Result<(), ApplicationError> {
+        info!("Assigning plan {:?} to organisation: {:?}", plan, id);
+        let row_updated = self.organisation_repository.in_transaction(TransactionOptions::default(), |mut uow| async move {
+            let row_updated = self.organisation_repository
+                .update_plan_by_id_with_conn(uow.conn(), id, plan)
+                .await?;
+
+            if row_updated > 0 {
+                self.outbox_repository.insert_with_conn(uow.conn(), &DomainEvent::OrganisationPlanChanged { organisation_id: *id, plan: plan.to_string() }).await?;
+            }
+            Ok(row_updated)
+        }.scope_boxed()).await.map_err(ApplicationError::from)?;
 
-        debug!("Rows updated {:?}", row_updated);
         if row_updated == 0 {
             Err(NotFound)
         } else {
@@ -100,6 +176,26 @@ impl OrganisationService {
         }
     }
 
+    // Conditional on the expected version, see ExpectedVersion::apply
+    pub async fn update_by_id(&self, id: Uuid, expected_version: ExpectedVersion, organisation_request: OrganisationPutRequest) -> Result<NaiveDateTime, ApplicationError> {
+        debug!("Updating organisation by id: {:?}", id);
+        let organisation: &PutOrganisation = &organisation_request.into();
+
+        expected_version.apply(|| self.current_version(&id), |versions| async move {
+            let versions = &versions;
+            self.organisation_repository.in_transaction(TransactionOptions::default(), |mut uow| async move {
+                let updated_at = self.organisation_repository
+                    .update_by_id_with_conn(uow.conn(), &id, versions, organisation)
+                    .await?;
+
+                if updated_at.is_some() {
+                    self.outbox_repository.insert_with_conn(uow.conn(), &DomainEvent::OrganisationUpdated { organisation_id: id }).await?;
+                }
+                Ok(updated_at)
+            }.scope_boxed()).await.map_err(ApplicationError::from)
+        }).await
+    }
+
     pub async fn is_admin(&self, user_id: &Uuid, organisation_id: &Uuid) -> Result<bool, ApplicationError> {
         let role_id: Result<Option<i64>, ApplicationError> = self.organisation_repository
             .find_role(organisation_id, user_id)
@@ -113,18 +209,38 @@ impl OrganisationService {
         }
     }
 
-    pub async fn delete_by_id(&self, id: Uuid) -> Result<(), ApplicationError> {
+    pub async fn is_member(&self, user_id: &Uuid, organisation_id: &Uuid) -> Result<bool, ApplicationError> {
+        self.organisation_repository
+            .find_role(organisation_id, user_id)
+            .await
+            .map(|role_id| role_id.is_some())
+            .map_err(ApplicationError::from)
+    }
+
+    // Conditional on the expected version, see ExpectedVersion::apply
+    pub async fn delete_by_id(&self, id: Uuid, expected_version: ExpectedVersion) -> Result<(), ApplicationError> {
         debug!("Deleting organisation by id: {:?}", id);
 
-        let row_updated = self.organisation_repository
-            .delete_by_id(&id)
-            .await
-            .map_err(ApplicationError::from)?;
+        expected_version.apply(|| self.current_version(&id), |versions| async move {
+            let versions = &versions;
+            let row_updated = self.organisation_repository.in_transaction(TransactionOptions::default(), |mut uow| async move {
+                let row_updated = self.organisation_repository
+                    .delete_by_id_with_conn(uow.conn(), &id, versions)
+                    .await?;
+
+                if row_updated > 0 {
+                    self.outbox_repository.insert_with_conn(uow.conn(), &DomainEvent::OrganisationDeleted { organisation_id: id }).await?;
+                }
+                Ok(row_updated)
+            }.scope_boxed()).await?;
+            Ok((row_updated > 0).then_some(()))
+        }).await
+    }
 
-        if row_updated == 0 {
-            Err(NotFound)
-        } else {
-            Ok(())
-        }
+    async fn current_version(&self, id: &Uuid) -> Result<NaiveDateTime, ApplicationError> {
+        self.organisation_repository
+            .find_version(id)
+            .await?
+            .ok_or(NotFound)
     }
 }
\ No newline at end of file
@@ -0,0 +1,51 @@
+use chrono::{NaiveDateTime, Utc};
+use diesel::{Insertable, QueryableByName};
+use diesel::sql_types::{BigInt, Integer, Jsonb, Timestamp, Uuid as SqlUuid};
+use uuid::Uuid;
+
+use crate::common::schema;
+use crate::domains::outbox::events::DomainEvent;
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = schema::outbox_event)]
+pub struct NewOutboxEvent {
+    pub id: Uuid,
+    pub aggregate_type: String,
+    pub aggregate_id: Uuid,
+    pub event_type: String,
+    pub payload: serde_json::Value,
+    pub organisation_id: Option<Uuid>,
+    pub occurred_at: NaiveDateTime,
+}
+
+impl From<&DomainEvent> for NewOutboxEvent {
+    fn from(event: &DomainEvent) -> Self {
+        let (aggregate_type, aggregate_id) = event.aggregate();
+        NewOutboxEvent {
+            id: Uuid::now_v7(),
+            aggregate_type: aggregate_type.to_string(),
+            aggregate_id,
+            event_type: event.event_type().to_string(),
+            payload: serde_json::to_value(event).expect("Domain events serialize to JSON"),
+            organisation_id: event.organisation_id(),
+            occurred_at: Utc::now().naive_utc(),
+        }
+    }
+}
+
+/*
+    A due event taken by a dispatcher. `attempts` includes this one
+*/
+#[derive(QueryableByName, Debug)]
+pub struct ClaimedEvent {
+    #[diesel(sql_type = BigInt)]
+    pub sequence: i64,
+    #[diesel(sql_type = SqlUuid)]
+    pub id: Uuid,
+    #[diesel(sql_type = Jsonb)]
+    pub payload: serde_json::Value,
+    #[diesel(sql_type = Timestamp)]
+    pub occurred_at: NaiveDateTime,
+    #[diesel(sql_type = Integer)]
+    pub attempts: i32,
+}
@@ -0,0 +1,123 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+pub const USER: &str = "user";
+pub const ORGANISATION: &str = "organisation";
+pub const APPLICATION: &str = "application";
+
+/*
+    Something that changed, recorded in the same transaction as the change. Stored as
+    `{"type": ..., "data": {...}}`, add fields with a default so events already in the outbox still read
+*/
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum DomainEvent {
+    UserCreated { user_id: Uuid, email: String },
+    UserUpdated { user_id: Uuid },
+    UserDeleted { user_id: Uuid },
+    MemberAdded { organisation_id: Uuid, user_id: Uuid, role_id: i64 },
+    MemberRemoved { organisation_id: Uuid, user_id: Uuid },
+    OrganisationCreated { organisation_id: Uuid, owner: Uuid },
+    OrganisationUpdated { organisation_id: Uuid },
+    OrganisationArchived { organisation_id: Uuid },
+    OrganisationPlanChanged { organisation_id: Uuid, plan: String },
+    OrganisationDeleted { organisation_id: Uuid },
+    ApplicationCreated { organisation_id: Uuid, application_id: Uuid, name: String },
+    ApplicationUpdated { organisation_id: Uuid, application_id: Uuid },
+    ApplicationDeleted { organisation_id: Uuid, application_id: Uuid },
+}
+
+impl DomainEvent {
+    pub fn event_type(&self) -> &'static str {
+        match self {
+            DomainEvent::UserCreated { .. } => "user_created",
+            DomainEvent::UserUpdated { .. } => "user_updated",
+            DomainEvent::UserDeleted { .. } => "user_deleted",
+            DomainEvent::MemberAdded { .. } => "member_added",
+            DomainEvent::MemberRemoved { .. } => "member_removed",
+            DomainEvent::OrganisationCreated { .. } => "organisation_created",
+            DomainEvent::OrganisationUpdated { .. } => "organisation_updated",
+            DomainEvent::OrganisationArchived { .. } => "organisation_archived",
+            DomainEvent::OrganisationPlanChanged { .. } => "organisation_plan_changed",
+            DomainEvent::OrganisationDeleted { .. } => "organisation_deleted",
+            DomainEvent::ApplicationCreated { .. } => "application_created",
+            DomainEvent::ApplicationUpdated { .. } => "application_updated",
+            DomainEvent::ApplicationDeleted { .. } => "application_deleted",
+        }
+    }
+
+    /*
+        What the event is about, subscribers see the events of an aggregate in the order they happened.
+        Membership changes belong to the organisation
+    */
+    pub fn aggregate(&self) -> (&'static str, Uuid) {
+        match self {
+            DomainEvent::UserCreated { user_id, .. }
+            | DomainEvent::UserUpdated { user_id }
+            | DomainEvent::UserDeleted { user_id } => (USER, *user_id),
+            DomainEvent::MemberAdded { organisation_id, .. }
+            | DomainEvent::MemberRemoved { organisation_id, .. }
+            | DomainEvent::OrganisationCreated { organisation_id, .. }
+            | DomainEvent::OrganisationUpdated { organisation_id }
+            | DomainEvent::OrganisationArchived { organisation_id }
+            | DomainEvent::OrganisationPlanChanged { organisation_id, .. }
+            | DomainEvent::OrganisationDeleted { organisation_id } => (ORGANISATION, *organisation_id),
+            DomainEvent::ApplicationCreated { application_id, .. }
+            | DomainEvent::ApplicationUpdated { application_id, .. }
+            | DomainEvent::ApplicationDeleted { application_id, .. } => (APPLICATION, *application_id),
+        }
+    }
+
+    // None for users, they aren't part of a single organisation
+    pub fn organisation_id(&self) -> Option<Uuid> {
+        match self {
+            DomainEvent::UserCreated { .. }
+            | DomainEvent::UserUpdated { .. }
+            | DomainEvent::UserDeleted { .. } => None,
+            DomainEvent::MemberAdded { organisation_id, .. }
+            | DomainEvent::MemberRemoved { organisation_id, .. }
+            | DomainEvent::OrganisationCreated { organisation_id, .. }
+            | DomainEvent::OrganisationUpdated { organisation_id }
+            | DomainEvent::OrganisationArchived { organisation_id }
+            | DomainEvent::OrganisationPlanChanged { organisation_id, .. }
+            | DomainEvent::OrganisationDeleted { organisation_id }
+            | DomainEvent::ApplicationCreated { organisation_id, .. }
+            | DomainEvent::ApplicationUpdated { organisation_id, .. }
+            | DomainEvent::ApplicationDeleted { organisation_id, .. } => Some(*organisation_id),
+        }
+    }
+}
+
+/*
+    A dispatched event as subscribers get it. The id stays the same on every attempt,
+    subscribers use it to skip events they already handled
+*/
+#[derive(Debug, Clone)]
+pub struct OutboxEvent {
+    pub id: Uuid,
+    pub occurred_at: NaiveDateTime,
+    pub event: DomainEvent,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stored_type_is_the_event_type() {
+        let id = Uuid::now_v7();
+        let events = [
+            DomainEvent::UserCreated { user_id: id, email: "a@b.com".to_string() },
+            DomainEvent::MemberRemoved { organisation_id: id, user_id: id },
+            DomainEvent::OrganisationPlanChanged { organisation_id: id, plan: "pro".to_string() },
+            DomainEvent::ApplicationDeleted { organisation_id: id, application_id: id },
+        ];
+
+        for event in events {
+            let stored = serde_json::to_value(&event).unwrap();
+            assert_eq!(stored["type"], event.event_type());
+            assert_eq!(serde_json::from_value::<DomainEvent>(stored).unwrap(), event);
+        }
+    }
+}
@@ -0,0 +1,4 @@
+pub mod db_models;
+pub mod events;
+pub mod repository;
+pub mod services;
@@ -0,0 +1,122 @@
+use std::time::Duration;
+use chrono::NaiveDateTime;
+use diesel::dsl::now;
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::{BigInt, Double};
+use diesel_async::AsyncPgConnection;
+use crate::common::query::RunQueryDsl;
+use tracing::instrument;
+use crate::common::errors::db_error::DbError;
+use crate::common::schema::outbox_event;
+use crate::common::repository::BaseRepository;
+use crate::config::diesel_config::DbPools;
+use crate::domains::outbox::db_models::{ClaimedEvent, NewOutboxEvent};
+use crate::domains::outbox::events::DomainEvent;
+
+#[derive(Clone)]
+pub struct OutboxRepository {
+    pools: DbPools,
+}
+
+impl OutboxRepository {
+    pub fn new(pools: DbPools) -> Self {
+        OutboxRepository { pools }
+    }
+
+    /*
+        Records the event in the transaction of the change, it is only dispatched once that commits
+    */
+    #[instrument(skip_all)]
+    pub async fn insert_with_conn(&self, conn: &mut AsyncPgConnection, event: &DomainEvent) -> Result<usize, DbError> {
+        let new_event: NewOutboxEvent = event.into();
+        diesel::insert_into(outbox_event::table)
+            .values(&new_event)
+            .execute(conn)
+            .await
+            .map_err(DbError::from)
+    }
+
+    /*
+        Takes up to `limit` due events, oldest first. Only the oldest pending event of an aggregate
+        is due so its events are dispatched in order, dead events are skipped. They are pushed back by `lease`
+        so other dispatchers skip them, one taken by a dispatcher that died is dispatched again after it
+    */
+    #[instrument(skip_all)]
+    pub async fn claim_due(&self, limit: i64, lease: Duration) -> Result<Vec<ClaimedEvent>, DbError> {
+        let mut conn = self.conn().await?;
+        sql_query("WITH claimed AS ( \
+                       UPDATE outbox_event \
+                       SET attempts = attempts + 1, next_attempt_at = NOW() + make_interval(secs => $2) \
+                       WHERE sequence IN ( \
+                           SELECT pending.sequence FROM outbox_event pending \
+                           WHERE pending.dispatched_at IS NULL \
+                             AND pending.dead_at IS NULL \
+                             AND pending.next_attempt_at <= NOW() \
+                             AND NOT EXISTS ( \
+                                 SELECT 1 FROM outbox_event earlier \
+                                 WHERE earlier.aggregate_type = pending.aggregate_type \
+                                   AND earlier.aggregate_id = pending.aggregate_id \
+                                   AND earlier.dispatched_at IS NULL \
+                                   AND earlier.dead_at IS NULL \
+                                   AND earlier.sequence < pending.sequence) \
+                           ORDER BY pending.sequence \
+                           LIMIT $1 \
+                           FOR UPDATE SKIP LOCKED) \
+                       RETURNING sequence, id, payload, occurred_at, attempts) \
+                   SELECT * FROM claimed ORDER BY sequence")
+            .bind::<BigInt, _>(limit)
+            .bind::<Double, _>(lease.as_secs_f64())
+            .get_results(&mut conn)
+            .await
+            .map_err(DbError::from)
+    }
+
+    #[instrument(skip_all)]
+    pub async fn mark_dispatched(&self, sequence: i64) -> Result<usize, DbError> {
+        let mut conn = self.conn().await?;
+        diesel::update(outbox_event::table.find(sequence))
+            .set((outbox_event::dispatched_at.eq(now), outbox_event::last_error.eq(None::<String>)))
+            .execute(&mut conn)
+            .await
+            .map_err(DbError::from)
+    }
+
+    /*
+        Records a failed attempt, the event is dispatched again after `retry_in` or given up on without it
+    */
+    #[instrument(skip_all)]
+    pub async fn mark_failed(&self, sequence: i64, error: &str, retry_in: Option<Duration>) -> Result<usize, DbError> {
+        use diesel::dsl::IntervalDsl;
+
+        let mut conn = self.conn().await?;
+        match retry_in {
+            Some(retry_in) => diesel::update(outbox_event::table.find(sequence))
+                .set((
+                    outbox_event::last_error.eq(error),
+                    outbox_event::next_attempt_at.eq(now + (retry_in.as_secs() as i64).seconds()),
+                ))
+                .execute(&mut conn)
+                .await,
+            None => diesel::update(outbox_event::table.find(sequence))
+                .set((outbox_event::last_error.eq(error), outbox_event::dead_at.eq(now)))
+                .execute(&mut conn)
+                .await,
+        }.map_err(DbError::from)
+    }
+
+    #[instrument(skip_all)]
+    pub async fn delete_finished_before(&self, dispatched_before: NaiveDateTime, dead_before: NaiveDateTime) -> Result<usize, DbError> {
+        let mut conn = self.conn().await?;
+        diesel::delete(outbox_event::table.filter(outbox_event::dispatched_at.lt(dispatched_before).or(outbox_event::dead_at.lt(dead_before))))
+            .execute(&mut conn)
+            .await
+            .map_err(DbError::from)
+    }
+}
+
+impl BaseRepository for OutboxRepository {
+    fn pools(&self) -> &DbPools {
+        &self.pools
+    }
+}
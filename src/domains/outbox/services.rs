@@ -0,0 +1,254 @@
+use std::sync::Arc;
+use std::time::Duration;
+use axum::async_trait;
+use chrono::Utc;
+use tracing::{debug, info, warn};
+use crate::common::errors::application_error::ApplicationError;
+use crate::common::errors::db_error::DbError;
+use crate::common::utils::backoff;
+use crate::config::app_env::OUTBOX_MAX_ATTEMPTS;
+use crate::domains::outbox::db_models::ClaimedEvent;
+use crate::domains::outbox::events::{DomainEvent, OutboxEvent};
+use crate::domains::outbox::repository::OutboxRepository;
+use crate::server::shutdown::ShutdownSignal;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+const BATCH_SIZE: i64 = 50;
+// Longer than any subscriber should take, an event still claimed after it is dispatched again
+const LEASE: Duration = Duration::from_secs(5 * 60);
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(1);
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(5 * 60);
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const RETENTION: chrono::Duration = chrono::Duration::days(7);
+// Longer, dead events are kept for inspection
+const DEAD_RETENTION: chrono::Duration = chrono::Duration::days(30);
+
+/*
+    Receives every event of the outbox. Delivery is at least once, an event is handed over again
+    when the subscriber (or any subscriber before it) failed, so handling must be idempotent
+*/
+#[async_trait]
+pub trait EventSubscriber: Send + Sync {
+    // Identifies the subscriber in logs
+    fn name(&self) -> &'static str;
+
+    async fn handle(&self, event: &OutboxEvent) -> Result<(), ApplicationError>;
+}
+
+#[derive(Clone)]
+pub struct OutboxService {
+    outbox_repository: OutboxRepository,
+    subscribers: Vec<Arc<dyn EventSubscriber>>,
+    max_attempts: i32,
+}
+
+impl OutboxService {
+    pub fn new(outbox_repository: OutboxRepository, subscribers: Vec<Arc<dyn EventSubscriber>>) -> Self {
+        OutboxService { outbox_repository, subscribers, max_attempts: *OUTBOX_MAX_ATTEMPTS }
+    }
+
+    /*
+        Hands due events to the subscribers until shutdown. Replicas share the outbox, each event is
+        taken by one of them. A failed event is retried with exponential backoff and holds back the
+        later events of its aggregate until it goes through, or is marked dead after OUTBOX_MAX_ATTEMPTS
+    */
+    pub async fn dispatch_pending(self, shutdown: ShutdownSignal) {
+        loop {
+            let batch_full = match self.outbox_repository.claim_due(BATCH_SIZE, LEASE).await {
+                Ok(claimed) => {
+                    let batch_full = claimed.len() as i64 == BATCH_SIZE;
+                    // At most one event per aggregate is due, so they can go out concurrently
+                    futures::future::join_all(claimed.into_iter().map(|event| self.dispatch(event))).await;
+                    batch_full
+                },
+                Err(e) => {
+                    warn!("Unable to claim outbox events: {:?}", e);
+                    false
+                },
+            };
+
+            if shutdown.is_shutting_down() {
+                return;
+            }
+            if batch_full {
+                continue;
+            }
+
+            tokio::select! {
+                _ = tokio::time::sleep(POLL_INTERVAL) => {},
+                _ = shutdown.wait() => return,
+            }
+        }
+    }
+
+    async fn dispatch(&self, claimed: ClaimedEvent) {
+        let result = match self.handle(&claimed).await {
+            Ok(()) => {
+                debug!("Outbox event {:?} dispatched", claimed.id);
+                metrics::counter!("outbox_events_total", "outcome" => "dispatched").increment(1);
+                self.outbox_repository.mark_dispatched(claimed.sequence).await
+            },
+            Err(error) => {
+                let retry_in = (claimed.attempts < self.max_attempts)
+                    .then(|| backoff::retry_delay(claimed.attempts, RETRY_BASE_DELAY, RETRY_MAX_DELAY));
+                match retry_in {
+                    Some(retry_in) => warn!("Outbox event {:?} failed {} times, retrying in {:?}: {}", claimed.id, claimed.attempts, retry_in, error),
+                    // The later events of its aggregate go out without it
+                    None => warn!("Outbox event {:?} failed {} times, giving up: {}", claimed.id, claimed.attempts, error),
+                }
+                metrics::counter!("outbox_events_total", "outcome" => if retry_in.is_some() { "retry" } else { "dead" }).increment(1);
+                self.outbox_repository.mark_failed(claimed.sequence, &error, retry_in).await
+            },
+        };
+
+        if let Err(e) = result {
+            // The lease runs out and the event is dispatched again
+            warn!("Unable to record the outcome of outbox event {:?}: {:?}", claimed.id, e);
+        }
+    }
+
+    async fn handle(&self, claimed: &ClaimedEvent) -> Result<(), String> {
+        let event = serde_json::from_value::<DomainEvent>(claimed.payload.clone())
+            .map_err(|e| format!("Unreadable event: {}", e))?;
+        let event = OutboxEvent { id: claimed.id, occurred_at: claimed.occurred_at, event };
+
+        for subscriber in &self.subscribers {
+            subscriber.handle(&event)
+                .await
+                .map_err(|e| format!("{} failed: {:?}", subscriber.name(), e))?;
+        }
+        Ok(())
+    }
+
+    /*
+        Deletes dispatched and dead events once they are older than their retention, every hour until shutdown
+    */
+    pub async fn purge_finished(self, shutdown: ShutdownSignal) {
+        let mut interval = tokio::time::interval(PURGE_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {},
+                _ = shutdown.wait() => return,
+            }
+
+            match self.purge_expired().await {
+                Ok(0) => {},
+                Ok(deleted) => info!("Purged {} dispatched or dead outbox events", deleted),
+                Err(e) => warn!("Unable to purge outbox events: {:?}", e),
+            }
+        }
+    }
+
+    async fn purge_expired(&self) -> Result<usize, DbError> {
+        let now = Utc::now().naive_utc();
+        self.outbox_repository.delete_finished_before(now - RETENTION, now - DEAD_RETENTION).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use diesel::prelude::*;
+    use diesel::sql_query;
+    use uuid::Uuid;
+    use crate::common::query::RunQueryDsl;
+    use crate::common::schema::outbox_event;
+    use crate::common::test_database::TestDatabase;
+    use super::*;
+
+    // No subscriber can handle the payload, dispatching it fails
+    const UNREADABLE: &str = "{}";
+
+    async fn insert(database: &TestDatabase, aggregate_id: Uuid) -> Uuid {
+        let id = Uuid::now_v7();
+        sql_query(format!("INSERT INTO outbox_event (id, aggregate_type, aggregate_id, event_type, payload) \
+                           VALUES ('{}', 'application', '{}', 'test', '{}')", id, aggregate_id, UNREADABLE))
+            .execute(&mut database.admin().await)
+            .await
+            .unwrap();
+        id
+    }
+
+    async fn claim(outbox: &OutboxService) -> Vec<ClaimedEvent> {
+        outbox.outbox_repository.claim_due(BATCH_SIZE, LEASE).await.unwrap()
+    }
+
+    fn ids(claimed: &[ClaimedEvent]) -> Vec<Uuid> {
+        claimed.iter().map(|event| event.id).collect()
+    }
+
+    fn outbox(database: &TestDatabase, max_attempts: i32) -> OutboxService {
+        OutboxService { max_attempts, ..OutboxService::new(OutboxRepository::new(database.pools.clone()), vec![]) }
+    }
+
+    #[tokio::test]
+    async fn events_of_an_aggregate_are_claimed_in_order() {
+        let Some(database) = TestDatabase::create().await else { return };
+        let outbox = outbox(&database, 3);
+        let (a, b) = (Uuid::now_v7(), Uuid::now_v7());
+        let (a1, a2, b1) = (insert(&database, a).await, insert(&database, a).await, insert(&database, b).await);
+
+        let claimed = claim(&outbox).await;
+        assert_eq!(ids(&claimed), vec![a1, b1]);
+        // Leased, and the second event of a waits for the first
+        assert!(claim(&outbox).await.is_empty());
+
+        outbox.outbox_repository.mark_dispatched(claimed[0].sequence).await.unwrap();
+        assert_eq!(ids(&claim(&outbox).await), vec![a2]);
+    }
+
+    #[tokio::test]
+    async fn a_failing_event_holds_back_its_aggregate_until_it_is_dead() {
+        let Some(database) = TestDatabase::create().await else { return };
+        let outbox = outbox(&database, 2);
+        let a = Uuid::now_v7();
+        let (a1, a2) = (insert(&database, a).await, insert(&database, a).await);
+        let mut admin = database.admin().await;
+
+        let first = claim(&outbox).await.pop().unwrap();
+        assert_eq!((first.id, first.attempts), (a1, 1));
+        outbox.dispatch(first).await;
+        // Backing off, the second event still waits
+        assert!(claim(&outbox).await.is_empty());
+
+        sql_query("UPDATE outbox_event SET next_attempt_at = NOW()").execute(&mut admin).await.unwrap();
+        let second = claim(&outbox).await.pop().unwrap();
+        assert_eq!((second.id, second.attempts), (a1, 2));
+        outbox.dispatch(second).await;
+
+        let dead: Vec<Uuid> = outbox_event::table
+            .filter(outbox_event::dead_at.is_not_null())
+            .select(outbox_event::id)
+            .get_results(&mut admin)
+            .await
+            .unwrap();
+        assert_eq!(dead, vec![a1]);
+        assert_eq!(ids(&claim(&outbox).await), vec![a2]);
+    }
+
+    #[tokio::test]
+    async fn dispatched_and_dead_events_are_purged_after_their_retention() {
+        let Some(database) = TestDatabase::create().await else { return };
+        let outbox = outbox(&database, 3);
+        let mut admin = database.admin().await;
+        let finish = |id: Uuid, column: &str, days_ago: i64| format!("UPDATE outbox_event SET {} = NOW() - INTERVAL '{} days' WHERE id = '{}'", column, days_ago, id);
+
+        let dispatched_expired = insert(&database, Uuid::now_v7()).await;
+        let dispatched_kept = insert(&database, Uuid::now_v7()).await;
+        let dead_expired = insert(&database, Uuid::now_v7()).await;
+        let dead_kept = insert(&database, Uuid::now_v7()).await;
+        let pending = insert(&database, Uuid::now_v7()).await;
+        for statement in [finish(dispatched_expired, "dispatched_at", 8), finish(dispatched_kept, "dispatched_at", 1),
+                          finish(dead_expired, "dead_at", 31), finish(dead_kept, "dead_at", 8)] {
+            sql_query(statement).execute(&mut admin).await.unwrap();
+        }
+
+        assert_eq!(outbox.purge_expired().await.unwrap(), 2);
+        let remaining: Vec<Uuid> = outbox_event::table
+            .order(outbox_event::sequence)
+            .select(outbox_event::id)
+            .get_results(&mut admin)
+            .await
+            .unwrap();
+        assert_eq!(remaining, vec![dispatched_kept, dead_kept, pending]);
+    }
+}
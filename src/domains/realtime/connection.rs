@@ -0,0 +1,459 @@
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+use axum::extract::ws::{close_code, CloseFrame, Message, WebSocket};
+use chrono::Utc;
+use futures::SinkExt;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{debug, warn};
+use uuid::Uuid;
+use crate::common::errors::application_error::ApplicationError;
+use crate::common::errors::authentication_error::AuthenticationError;
+use crate::common::errors::error_code;
+use crate::common::errors::global_api_error::ApiError;
+use crate::common::i18n::{catalogue, locale};
+use crate::common::repository;
+use crate::domains::activity::events::{Activity, MEMBER_LEFT};
+use crate::domains::realtime::messages::{Channel, ClientMessage, PresenceChange, ServerMessage};
+use crate::domains::realtime::services::{RealtimeService, Session};
+
+const AUTHENTICATION_TIMEOUT: Duration = Duration::from_secs(10);
+// A connection that didn't answer the previous ping by the next one is dropped
+const PING_INTERVAL: Duration = Duration::from_secs(30);
+// Catches revoked sessions and memberships whose activity was missed
+const REVALIDATE_INTERVAL: Duration = Duration::from_secs(60);
+const MAX_CHANNELS: usize = 20;
+
+const CLOSE_UNAUTHORIZED: u16 = 4001;
+const CLOSE_FORBIDDEN: u16 = 4003;
+const CLOSE_AUTHENTICATION_TIMEOUT: u16 = 4008;
+
+/*
+    Why the server ends a connection. Sent as the close code with the error code as the reason
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Close {
+    // The client closed the connection or stopped answering
+    Gone,
+    // The token is invalid, expired or revoked
+    Unauthorized(&'static str),
+    // The user is no longer a member of the organisation
+    Forbidden,
+    AuthenticationTimeout,
+    ShuttingDown,
+    InternalError,
+}
+
+impl Close {
+    fn frame(self) -> Option<CloseFrame<'static>> {
+        let (code, reason) = match self {
+            Close::Gone => return None,
+            Close::Unauthorized(reason) => (CLOSE_UNAUTHORIZED, reason),
+            Close::Forbidden => (CLOSE_FORBIDDEN, error_code::AUTH_FORBIDDEN),
+            Close::AuthenticationTimeout => (CLOSE_AUTHENTICATION_TIMEOUT, error_code::REALTIME_AUTHENTICATION_TIMEOUT),
+            Close::ShuttingDown => (close_code::AWAY, error_code::SERVICE_UNAVAILABLE),
+            Close::InternalError => (close_code::ERROR, error_code::INTERNAL_ERROR),
+        };
+        Some(CloseFrame { code, reason: Cow::Borrowed(reason) })
+    }
+
+    // None when the error doesn't say the user lost access, the database may just be unavailable
+    fn rejection(error: &ApiError) -> Option<Self> {
+        match error {
+            ApiError::AuthenticationError(AuthenticationError::SerializationError) => None,
+            ApiError::AuthenticationError(e) => Some(Close::Unauthorized(e.code())),
+            ApiError::ApplicationError(ApplicationError::Unauthorized | ApplicationError::Forbidden) => Some(Close::Forbidden),
+            _ => None,
+        }
+    }
+}
+
+/*
+    Serves a WebSocket until either side closes it. Without a session from the request,
+    the first message must authenticate the connection
+*/
+pub async fn serve(realtime_service: RealtimeService, mut socket: WebSocket, organisation_id: Uuid, session: Option<Session>) {
+    let session = match session {
+        Some(session) => session,
+        None => match authenticate(&realtime_service, &mut socket, organisation_id).await {
+            Ok(session) => session,
+            Err(close) => {
+                let _ = socket.send(Message::Close(close.frame())).await;
+                return;
+            },
+        },
+    };
+
+    let locale = session.locale;
+    let connection = Connection {
+        id: Uuid::now_v7(),
+        socket,
+        session,
+        channels: HashSet::new(),
+        realtime_service,
+    };
+    let run = repository::tenant_scope(organisation_id, connection.run());
+    match locale {
+        Some(locale) => locale::scope(locale, run).await,
+        None => run.await,
+    }
+}
+
+async fn authenticate(realtime_service: &RealtimeService, socket: &mut WebSocket, organisation_id: Uuid) -> Result<Session, Close> {
+    let first_message = tokio::time::timeout(AUTHENTICATION_TIMEOUT, async {
+        loop {
+            match socket.recv().await {
+                Some(Ok(Message::Text(text))) => return Some(text),
+                Some(Ok(Message::Ping(_) | Message::Pong(_))) => continue,
+                _ => return None,
+            }
+        }
+    }).await;
+
+    match first_message {
+        Err(_) => Err(Close::AuthenticationTimeout),
+        Ok(None) => Err(Close::Gone),
+        Ok(Some(text)) => match serde_json::from_str::<ClientMessage>(&text) {
+            Ok(ClientMessage::Authenticate { token }) => realtime_service.authenticate(token, organisation_id)
+                .await
+                .map_err(|e| Close::rejection(&e).unwrap_or_else(|| {
+                    warn!("Unable to authenticate WebSocket: {}", e);
+                    Close::InternalError
+                })),
+            _ => Err(Close::Unauthorized(error_code::AUTH_UNAUTHORIZED)),
+        },
+    }
+}
+
+struct Connection {
+    // Identifies the connection's presence, a user may have several connections open
+    id: Uuid,
+    socket: WebSocket,
+    session: Session,
+    channels: HashSet<Channel>,
+    realtime_service: RealtimeService,
+}
+
+impl Connection {
+    async fn run(mut self) {
+        debug!("WebSocket {:?} opened by {:?}", self.id, self.session.user_id);
+        metrics::gauge!("websocket_connections").increment(1.0);
+        let close = self.serve().await;
+        metrics::gauge!("websocket_connections").decrement(1.0);
+        debug!("WebSocket {:?} closed: {:?}", self.id, close);
+
+        // The presence goes stale and is purged otherwise
+        if let Err(e) = self.realtime_service.leave_all(&self.id).await {
+            warn!("Unable to remove the presence of WebSocket {:?}: {:?}", self.id, e);
+        }
+        match close.frame() {
+            Some(frame) => { let _ = self.socket.send(Message::Close(Some(frame))).await; },
+            // Sends the answer the socket queued to a close of the client
+            None => { let _ = self.socket.flush().await; },
+        }
+    }
+
+    async fn serve(&mut self) -> Close {
+        let mut presence_changes = self.realtime_service.presence_changes();
+        let mut activities = self.realtime_service.activities(self.session.organisation_id);
+        let mut ping = tokio::time::interval(PING_INTERVAL);
+        ping.tick().await;
+        let mut revalidate = tokio::time::interval(REVALIDATE_INTERVAL);
+        revalidate.tick().await;
+        let mut answered_ping = true;
+
+        let ready = ServerMessage::Ready { user_id: self.session.user_id, organisation_id: self.session.organisation_id };
+        if let Err(close) = self.send(&ready).await {
+            return close;
+        }
+
+        loop {
+            let expires_in = (self.session.expires_at - Utc::now()).to_std().unwrap_or(Duration::ZERO);
+            let handled = tokio::select! {
+                message = self.socket.recv() => match message {
+                    Some(Ok(Message::Text(text))) => self.receive(&text).await,
+                    Some(Ok(Message::Binary(_))) => self.send_error(error_code::REQUEST_INVALID_JSON).await,
+                    Some(Ok(Message::Pong(_))) => {
+                        answered_ping = true;
+                        Ok(())
+                    },
+                    // Pings are answered by the socket itself
+                    Some(Ok(Message::Ping(_))) => Ok(()),
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => Err(Close::Gone),
+                },
+                _ = ping.tick() => {
+                    if !answered_ping {
+                        debug!("WebSocket {:?} didn't answer the last ping", self.id);
+                        return Close::Gone;
+                    }
+                    answered_ping = false;
+                    if let Err(e) = self.realtime_service.touch(&self.id).await {
+                        warn!("Unable to refresh the presence of WebSocket {:?}: {:?}", self.id, e);
+                    }
+                    self.socket.send(Message::Ping(Vec::new())).await.map_err(|_| Close::Gone)
+                },
+                _ = revalidate.tick() => match self.realtime_service.revalidate(&self.session).await {
+                    Ok(()) => Ok(()),
+                    Err(e) => match Close::rejection(&e) {
+                        Some(close) => Err(close),
+                        None => {
+                            warn!("Unable to revalidate WebSocket {:?}: {}", self.id, e);
+                            Ok(())
+                        },
+                    },
+                },
+                change = presence_changes.recv() => match change {
+                    Ok(change) => self.forward(&change).await,
+                    // Changes were missed, what is in the channels is sent again
+                    Err(RecvError::Lagged(_)) => self.resend_presence().await,
+                    Err(RecvError::Closed) => Err(Close::ShuttingDown),
+                },
+                activity = activities.recv() => match activity {
+                    Ok(activity) if self.removes_user(&activity) => Err(Close::Forbidden),
+                    // A missed removal is caught when revalidating
+                    Ok(_) | Err(RecvError::Lagged(_)) => Ok(()),
+                    Err(RecvError::Closed) => Err(Close::ShuttingDown),
+                },
+                _ = tokio::time::sleep(expires_in) => Err(Close::Unauthorized(error_code::AUTH_TOKEN_EXPIRED)),
+                _ = self.realtime_service.shutting_down() => Err(Close::ShuttingDown),
+            };
+
+            if let Err(close) = handled {
+                return close;
+            }
+        }
+    }
+
+    async fn receive(&mut self, text: &str) -> Result<(), Close> {
+        let message = match serde_json::from_str::<ClientMessage>(text) {
+            Ok(message) => message,
+            Err(_) => return self.send_error(error_code::REQUEST_INVALID_JSON).await,
+        };
+
+        match message {
+            // A fresh token before the current one expires, for the same user
+            ClientMessage::Authenticate { token } => match self.realtime_service.authenticate(token, self.session.organisation_id).await {
+                Ok(session) if session.user_id == self.session.user_id => {
+                    self.session = session;
+                    Ok(())
+                },
+                Ok(_) => Err(Close::Unauthorized(error_code::AUTH_TOKEN_INVALID)),
+                Err(e) => match Close::rejection(&e) {
+                    Some(close) => Err(close),
+                    None => {
+                        warn!("Unable to authenticate WebSocket {:?}: {}", self.id, e);
+                        self.send_error(error_code::INTERNAL_ERROR).await
+                    },
+                },
+            },
+            ClientMessage::Join { channel } => {
+                let Ok(channel) = channel.parse::<Channel>() else {
+                    return self.send_error(error_code::REALTIME_CHANNEL_INVALID).await;
+                };
+                if !self.channels.contains(&channel) && self.channels.len() >= MAX_CHANNELS {
+                    return self.send_error(error_code::REALTIME_CHANNEL_LIMIT_REACHED).await;
+                }
+
+                match self.realtime_service.join(&self.session, self.id, channel).await {
+                    Ok(user_ids) => {
+                        self.channels.insert(channel);
+                        self.send(&ServerMessage::Presence { channel: channel.to_string(), user_ids }).await
+                    },
+                    Err(ApplicationError::NotFound) => self.send_error(error_code::RESOURCE_NOT_FOUND).await,
+                    Err(e) => {
+                        warn!("Unable to join {} on WebSocket {:?}: {:?}", channel, self.id, e);
+                        self.send_error(error_code::INTERNAL_ERROR).await
+                    },
+                }
+            },
+            ClientMessage::Leave { channel } => {
+                let Ok(channel) = channel.parse::<Channel>() else {
+                    return self.send_error(error_code::REALTIME_CHANNEL_INVALID).await;
+                };
+                if !self.channels.remove(&channel) {
+                    return Ok(());
+                }
+
+                match self.realtime_service.leave(&self.id, channel).await {
+                    Ok(()) => Ok(()),
+                    Err(e) => {
+                        warn!("Unable to leave {} on WebSocket {:?}: {:?}", channel, self.id, e);
+                        self.send_error(error_code::INTERNAL_ERROR).await
+                    },
+                }
+            },
+        }
+    }
+
+    // Changes of the other users in the channels the connection is in
+    async fn forward(&mut self, change: &PresenceChange) -> Result<(), Close> {
+        let in_channel = change.channel.parse::<Channel>().is_ok_and(|channel| self.channels.contains(&channel));
+        if change.organisation_id != self.session.organisation_id || change.user_id == self.session.user_id || !in_channel {
+            return Ok(());
+        }
+
+        let message = if change.present {
+            ServerMessage::Joined { channel: change.channel.clone(), user_id: change.user_id }
+        } else {
+            ServerMessage::Left { channel: change.channel.clone(), user_id: change.user_id }
+        };
+        self.send(&message).await
+    }
+
+    async fn resend_presence(&mut self) -> Result<(), Close> {
+        for channel in self.channels.clone() {
+            match self.realtime_service.find_user_ids(&self.session.organisation_id, channel).await {
+                Ok(user_ids) => self.send(&ServerMessage::Presence { channel: channel.to_string(), user_ids }).await?,
+                Err(e) => warn!("Unable to read the presence in {} for WebSocket {:?}: {:?}", channel, self.id, e),
+            }
+        }
+        Ok(())
+    }
+
+    fn removes_user(&self, activity: &Arc<Activity>) -> bool {
+        activity.event_type == MEMBER_LEFT && activity.data["id"] == serde_json::json!(self.session.user_id)
+    }
+
+    async fn send_error(&mut self, code: &'static str) -> Result<(), Close> {
+        self.send(&ServerMessage::Error { code, message: catalogue::translate(code, &[]) }).await
+    }
+
+    async fn send(&mut self, message: &ServerMessage) -> Result<(), Close> {
+        let text = serde_json::to_string(message).expect("Server messages serialize to JSON");
+        self.socket
+            .send(Message::Text(text))
+            .await
+            .map_err(|_| Close::Gone)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::extract::WebSocketUpgrade;
+    use axum::Router;
+    use axum::routing::get;
+    use futures::StreamExt;
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio_tungstenite::tungstenite::Message as ClientFrame;
+    use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+    use crate::common::test_database::TestDatabase;
+    use crate::domains::realtime::services::tests::{realtime_service, relay_presence, seed, session};
+    use crate::server::shutdown::ShutdownSignal;
+    use super::*;
+
+    type Client = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+    const WAIT: Duration = Duration::from_secs(5);
+
+    // Serves one WebSocket like the /ws handler once it authenticated the request, or not
+    async fn open(realtime_service: &RealtimeService, organisation_id: Uuid, session: Option<Session>) -> Client {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let realtime_service = realtime_service.clone();
+        let router = Router::new().route("/ws", get(move |upgrade: WebSocketUpgrade| async move {
+            upgrade.on_upgrade(move |socket| serve(realtime_service, socket, organisation_id, session))
+        }));
+        tokio::spawn(async move { axum::serve(listener, router).await });
+
+        tokio_tungstenite::connect_async(format!("ws://{}/ws", address)).await.unwrap().0
+    }
+
+    async fn send(client: &mut Client, message: serde_json::Value) {
+        client.send(ClientFrame::Text(message.to_string())).await.unwrap();
+    }
+
+    async fn receive(client: &mut Client) -> serde_json::Value {
+        loop {
+            match tokio::time::timeout(WAIT, client.next()).await.unwrap().unwrap().unwrap() {
+                ClientFrame::Text(text) => return serde_json::from_str(&text).unwrap(),
+                ClientFrame::Ping(_) => continue,
+                frame => panic!("Expected a message, got {:?}", frame),
+            }
+        }
+    }
+
+    async fn closed_with(client: &mut Client) -> (u16, String) {
+        loop {
+            match tokio::time::timeout(WAIT, client.next()).await.unwrap().unwrap().unwrap() {
+                ClientFrame::Close(Some(frame)) => return (frame.code.into(), frame.reason.to_string()),
+                ClientFrame::Text(_) | ClientFrame::Ping(_) => continue,
+                frame => panic!("Expected the connection to close, got {:?}", frame),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn members_see_each_other_in_a_channel() {
+        let Some(database) = TestDatabase::create().await else { return };
+        let tenant = seed(&database).await;
+        let (_stop, shutdown) = ShutdownSignal::channel();
+        let realtime_service = realtime_service(&database, shutdown);
+        relay_presence(&database, &realtime_service).await;
+        let [ada, grace] = tenant.user_ids;
+        let channel = Channel::Application(tenant.application_id).to_string();
+
+        let mut first = open(&realtime_service, tenant.organisation_id, Some(session(tenant.organisation_id, ada, chrono::Duration::hours(1)))).await;
+        assert_eq!(receive(&mut first).await, serde_json::json!({ "type": "ready", "user_id": ada, "organisation_id": tenant.organisation_id }));
+        send(&mut first, serde_json::json!({ "type": "join", "channel": channel })).await;
+        assert_eq!(receive(&mut first).await, serde_json::json!({ "type": "presence", "channel": channel, "user_ids": [ada] }));
+
+        let mut second = open(&realtime_service, tenant.organisation_id, Some(session(tenant.organisation_id, grace, chrono::Duration::hours(1)))).await;
+        receive(&mut second).await;
+        send(&mut second, serde_json::json!({ "type": "join", "channel": channel })).await;
+        assert_eq!(receive(&mut second).await["type"], "presence");
+        assert_eq!(receive(&mut first).await, serde_json::json!({ "type": "joined", "channel": channel, "user_id": grace }));
+
+        send(&mut second, serde_json::json!({ "type": "leave", "channel": channel })).await;
+        assert_eq!(receive(&mut first).await, serde_json::json!({ "type": "left", "channel": channel, "user_id": grace }));
+
+        send(&mut first, serde_json::json!({ "type": "join", "channel": "application:nope" })).await;
+        assert_eq!(receive(&mut first).await["code"], error_code::REALTIME_CHANNEL_INVALID);
+        send(&mut first, serde_json::json!({ "type": "join", "channel": Channel::Application(Uuid::now_v7()).to_string() })).await;
+        assert_eq!(receive(&mut first).await["code"], error_code::RESOURCE_NOT_FOUND);
+        send(&mut first, serde_json::json!({ "type": "wave" })).await;
+        assert_eq!(receive(&mut first).await["code"], error_code::REQUEST_INVALID_JSON);
+    }
+
+    #[tokio::test]
+    async fn connections_close_when_the_token_expires() {
+        let Some(database) = TestDatabase::create().await else { return };
+        let tenant = seed(&database).await;
+        let (_stop, shutdown) = ShutdownSignal::channel();
+        let realtime_service = realtime_service(&database, shutdown);
+        let session = session(tenant.organisation_id, tenant.user_ids[0], chrono::Duration::seconds(1));
+
+        let mut client = open(&realtime_service, tenant.organisation_id, Some(session)).await;
+        assert_eq!(closed_with(&mut client).await, (CLOSE_UNAUTHORIZED, error_code::AUTH_TOKEN_EXPIRED.to_string()));
+    }
+
+    #[tokio::test]
+    async fn connections_without_a_token_must_authenticate_first() {
+        let Some(database) = TestDatabase::create().await else { return };
+        let tenant = seed(&database).await;
+        let (_stop, shutdown) = ShutdownSignal::channel();
+        let realtime_service = realtime_service(&database, shutdown);
+
+        let mut client = open(&realtime_service, tenant.organisation_id, None).await;
+        send(&mut client, serde_json::json!({ "type": "join", "channel": Channel::Application(tenant.application_id).to_string() })).await;
+        assert_eq!(closed_with(&mut client).await, (CLOSE_UNAUTHORIZED, error_code::AUTH_UNAUTHORIZED.to_string()));
+    }
+
+    #[tokio::test]
+    async fn connections_leave_their_channels_on_shutdown() {
+        let Some(database) = TestDatabase::create().await else { return };
+        let tenant = seed(&database).await;
+        let (stop, shutdown) = ShutdownSignal::channel();
+        let realtime_service = realtime_service(&database, shutdown);
+        let channel = Channel::Application(tenant.application_id);
+
+        let mut client = open(&realtime_service, tenant.organisation_id, Some(session(tenant.organisation_id, tenant.user_ids[0], chrono::Duration::hours(1)))).await;
+        receive(&mut client).await;
+        send(&mut client, serde_json::json!({ "type": "join", "channel": channel.to_string() })).await;
+        receive(&mut client).await;
+
+        stop.send(true).unwrap();
+        assert_eq!(closed_with(&mut client).await, (close_code::AWAY, error_code::SERVICE_UNAVAILABLE.to_string()));
+        assert!(realtime_service.find_user_ids(&tenant.organisation_id, channel).await.unwrap().is_empty());
+    }
+}
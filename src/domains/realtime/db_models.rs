@@ -0,0 +1,15 @@
+use chrono::NaiveDateTime;
+use diesel::{Insertable, Queryable, Selectable};
+use uuid::Uuid;
+
+use crate::common::schema;
+
+#[derive(Queryable, Selectable, Insertable, Debug, Clone)]
+#[diesel(table_name = schema::presence)]
+pub struct Presence {
+    pub connection_id: Uuid,
+    pub channel: String,
+    pub organisation_id: Uuid,
+    pub user_id: Uuid,
+    pub seen_at: NaiveDateTime,
+}
@@ -0,0 +1,107 @@
+use axum::extract::{Query, State, WebSocketUpgrade};
+use axum::extract::rejection::QueryRejection;
+use axum::Router;
+use axum::response::Response;
+use axum::routing::get;
+use axum_extra::extract::CookieJar;
+use http::header::ORIGIN;
+use http::{HeaderMap, HeaderValue};
+use serde::Deserialize;
+use tracing::debug;
+use uuid::Uuid;
+use crate::config::AppState;
+use crate::config::app_env::WEBSOCKET_ALLOWED_ORIGINS;
+use crate::common::errors::application_error::ApplicationError;
+use crate::common::errors::api_error_response::ErrorResponse;
+use crate::common::errors::global_api_error::ApiError;
+use crate::common::errors::request_error::RequestError;
+use crate::common::security::jwt;
+use crate::domains::realtime::connection;
+use crate::domains::realtime::services::RealtimeService;
+
+// Not behind authenticate, browsers can't set headers on a WebSocket so the token may come later
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/ws", get(connect_websocket))
+}
+
+#[derive(Deserialize)]
+struct ConnectParams {
+    organisation_id: Uuid,
+    access_token: Option<String>,
+}
+
+/*
+    WebSocket for presence in an organisation, messages are JSON objects with a `type`.
+    Browsers must open it from one of WEBSOCKET_ALLOWED_ORIGINS, clients without an Origin aren't browsers.
+    The access token comes from the query, the cookie or the Authorization header and is checked before
+    upgrading. Without one, the first message must be `{"type": "authenticate", "token": ...}`.
+    Clients `join` and `leave` channels (`application:<id>`) and get who is in them (`presence`)
+    and who comes and goes (`joined`, `left`). The server pings every 30 seconds and closes the connection
+    when the token expires (4001), the session is revoked (4001) or the user leaves the organisation (4003).
+    Sending a new token with `authenticate` keeps the connection open past the expiry of the first
+*/
+#[utoipa::path(
+    get,
+    path = "/ws",
+    tag = "events",
+    params(("organisation_id" = Uuid, Query, description = "Organisation the connection operates in, one of the organisations in the access token"), ("access_token" = Option<String>, Query, description = "Access token, when not sent as a cookie, header or first message")),
+    responses(
+        (status = 101, description = "Switched to the WebSocket protocol"),
+        (status = 400, response = ErrorResponse),
+        (status = 401, response = ErrorResponse),
+        (status = 403, response = ErrorResponse),
+    ),
+)]
+async fn connect_websocket(
+    State(realtime_service): State<RealtimeService>,
+    params: Result<Query<ConnectParams>, QueryRejection>,
+    cookie_jar: CookieJar,
+    headers: HeaderMap,
+    upgrade: WebSocketUpgrade,
+) -> Result<Response, ApiError> {
+    let Query(params) = params.map_err(RequestError::from)?;
+    if let Some(origin) = headers.get(ORIGIN) {
+        if !is_allowed_origin(origin, &WEBSOCKET_ALLOWED_ORIGINS) {
+            debug!("WebSocket refused from origin {:?}", origin);
+            return Err(ApplicationError::Forbidden.into());
+        }
+    }
+
+    let access_token = params.access_token.or_else(|| jwt::get_token(&cookie_jar, &headers).ok());
+    let session = match access_token {
+        Some(access_token) => Some(realtime_service.authenticate(access_token, params.organisation_id).await?),
+        None => None,
+    };
+
+    // Shutdown waits for the connection, it isn't a request once upgraded
+    let connections = realtime_service.clone();
+    Ok(upgrade.on_upgrade(move |socket| {
+        connections.track(connection::serve(realtime_service, socket, params.organisation_id, session))
+    }))
+}
+
+// Origins are scheme, host and port, compared like hosts without case
+fn is_allowed_origin(origin: &HeaderValue, allowed: &[String]) -> bool {
+    origin.to_str().is_ok_and(|origin| allowed.iter().any(|allowed| allowed.eq_ignore_ascii_case(origin)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_listed_origins_are_allowed() {
+        let allowed = vec!["https://app.swiftapi.com".to_string(), "http://localhost:3000".to_string()];
+        let is_allowed = |origin: &'static str| is_allowed_origin(&HeaderValue::from_static(origin), &allowed);
+
+        assert!(is_allowed("https://app.swiftapi.com"));
+        assert!(is_allowed("https://APP.swiftapi.com"));
+        assert!(is_allowed("http://localhost:3000"));
+        assert!(!is_allowed("http://app.swiftapi.com"));
+        assert!(!is_allowed("https://app.swiftapi.com.evil.test"));
+        assert!(!is_allowed("http://localhost:3001"));
+        assert!(!is_allowed("null"));
+        assert!(!is_allowed_origin(&HeaderValue::from_static("https://app.swiftapi.com"), &[]));
+    }
+}
@@ -0,0 +1,96 @@
+use std::fmt;
+use std::str::FromStr;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+const APPLICATION: &str = "application";
+
+/*
+    What a presence is tracked for, written `<kind>:<id>` on the wire. Channels are scoped to the
+    organisation of the connection, the resource must belong to it
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Channel {
+    Application(Uuid),
+}
+
+impl FromStr for Channel {
+    type Err = ();
+
+    fn from_str(channel: &str) -> Result<Self, Self::Err> {
+        match channel.split_once(':') {
+            Some((APPLICATION, id)) => Uuid::parse_str(id).map(Channel::Application).map_err(|_| ()),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for Channel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Channel::Application(id) => write!(f, "{}:{}", APPLICATION, id),
+        }
+    }
+}
+
+/*
+    Messages of the client, JSON text frames `{"type": ..., ...}`
+*/
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    // First message when the token isn't part of the request, later ones replace a token about to expire
+    Authenticate { token: String },
+    Join { channel: String },
+    Leave { channel: String },
+}
+
+/*
+    Messages of the server, JSON text frames `{"type": ..., ...}`
+*/
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    Ready { user_id: Uuid, organisation_id: Uuid },
+    // Who is in the channel, sent once joined
+    Presence { channel: String, user_ids: Vec<Uuid> },
+    Joined { channel: String, user_id: Uuid },
+    // Sent once the user's last connection left the channel
+    Left { channel: String, user_id: Uuid },
+    Error { code: &'static str, message: String },
+}
+
+/*
+    A user entering or leaving a channel, notified to every instance
+*/
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PresenceChange {
+    pub organisation_id: Uuid,
+    pub channel: String,
+    pub user_id: Uuid,
+    pub present: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn channels_are_parsed_from_what_they_display() {
+        let channel = Channel::Application(Uuid::now_v7());
+
+        assert_eq!(channel.to_string().parse::<Channel>(), Ok(channel));
+        assert!("application:nope".parse::<Channel>().is_err());
+        assert!(format!("user:{}", Uuid::now_v7()).parse::<Channel>().is_err());
+    }
+
+    #[test]
+    fn messages_are_tagged_with_their_type() {
+        let message = serde_json::from_str::<ClientMessage>(r#"{"type": "join", "channel": "application:1"}"#).unwrap();
+        assert_eq!(message, ClientMessage::Join { channel: "application:1".to_string() });
+
+        let user_id = Uuid::now_v7();
+        let message = serde_json::to_value(ServerMessage::Left { channel: "application:1".to_string(), user_id }).unwrap();
+        assert_eq!(message, serde_json::json!({ "type": "left", "channel": "application:1", "user_id": user_id }));
+    }
+}
@@ -0,0 +1,6 @@
+pub mod connection;
+pub mod db_models;
+pub mod handlers;
+pub mod messages;
+pub mod repository;
+pub mod services;
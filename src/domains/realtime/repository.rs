@@ -0,0 +1,114 @@
+use chrono::NaiveDateTime;
+use diesel::dsl::{exists, now, select};
+use diesel::prelude::*;
+use crate::common::query::RunQueryDsl;
+use tracing::instrument;
+use uuid::Uuid;
+use crate::common::errors::db_error::DbError;
+use crate::common::schema::presence;
+use crate::common::repository::BaseRepository;
+use crate::config::diesel_config::DbPools;
+use crate::domains::realtime::db_models::Presence;
+
+/*
+    Presence is read from the primary, a replica lagging behind would show who just left
+*/
+#[derive(Clone)]
+pub struct PresenceRepository {
+    pools: DbPools,
+}
+
+impl PresenceRepository {
+    pub fn new(pools: DbPools) -> Self {
+        PresenceRepository { pools }
+    }
+
+    // Joining a channel the connection is already in only refreshes it
+    #[instrument(skip_all)]
+    pub async fn join(&self, new_presence: &Presence) -> Result<usize, DbError> {
+        let mut conn = self.conn().await?;
+        diesel::insert_into(presence::table)
+            .values(new_presence)
+            .on_conflict((presence::connection_id, presence::channel))
+            .do_update()
+            .set(presence::seen_at.eq(now))
+            .execute(&mut conn)
+            .await
+            .map_err(DbError::from)
+    }
+
+    // None when the connection wasn't in the channel
+    #[instrument(skip_all)]
+    pub async fn leave(&self, connection_id: &Uuid, channel: &str) -> Result<Option<Presence>, DbError> {
+        let mut conn = self.conn().await?;
+        diesel::delete(presence::table.find((connection_id, channel)))
+            .returning(Presence::as_returning())
+            .get_result(&mut conn)
+            .await
+            .optional()
+            .map_err(DbError::from)
+    }
+
+    #[instrument(skip_all)]
+    pub async fn leave_all(&self, connection_id: &Uuid) -> Result<Vec<Presence>, DbError> {
+        let mut conn = self.conn().await?;
+        diesel::delete(presence::table.filter(presence::connection_id.eq(connection_id)))
+            .returning(Presence::as_returning())
+            .get_results(&mut conn)
+            .await
+            .map_err(DbError::from)
+    }
+
+    #[instrument(skip_all)]
+    pub async fn find_user_ids(&self, organisation_id: &Uuid, channel: &str) -> Result<Vec<Uuid>, DbError> {
+        let mut conn = self.conn().await?;
+        presence::table
+            .filter(presence::organisation_id.eq(organisation_id))
+            .filter(presence::channel.eq(channel))
+            .select(presence::user_id)
+            .distinct()
+            .get_results(&mut conn)
+            .await
+            .map_err(DbError::from)
+    }
+
+    // Whether any connection of the user is in the channel
+    #[instrument(skip_all)]
+    pub async fn is_present(&self, organisation_id: &Uuid, channel: &str, user_id: &Uuid) -> Result<bool, DbError> {
+        let mut conn = self.conn().await?;
+        select(exists(presence::table
+                .filter(presence::organisation_id.eq(organisation_id))
+                .filter(presence::channel.eq(channel))
+                .filter(presence::user_id.eq(user_id))))
+            .get_result(&mut conn)
+            .await
+            .map_err(DbError::from)
+    }
+
+    // Keeps the channels of a live connection from going stale
+    #[instrument(skip_all)]
+    pub async fn touch(&self, connection_id: &Uuid) -> Result<usize, DbError> {
+        let mut conn = self.conn().await?;
+        diesel::update(presence::table.filter(presence::connection_id.eq(connection_id)))
+            .set(presence::seen_at.eq(now))
+            .execute(&mut conn)
+            .await
+            .map_err(DbError::from)
+    }
+
+    #[instrument(skip_all)]
+    pub async fn delete_stale(&self, before: NaiveDateTime) -> Result<Vec<Presence>, DbError> {
+        let mut conn = self.conn().await?;
+        diesel::delete(presence::table.filter(presence::seen_at.lt(before)))
+            .returning(Presence::as_returning())
+            .get_results(&mut conn)
+            .await
+            .map_err(DbError::from)
+    }
+}
+
+impl BaseRepository for PresenceRepository {
+    fn pools(&self) -> &DbPools {
+        &self.pools
+    }
+}
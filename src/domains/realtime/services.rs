@@ -0,0 +1,470 @@
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use tokio::sync::broadcast;
+use tokio_util::task::TaskTracker;
+use tokio_util::task::task_tracker::TrackedFuture;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+use crate::common::errors::application_error::ApplicationError;
+use crate::common::errors::global_api_error::ApiError;
+use crate::common::i18n::locale::Locale;
+use crate::common::security::jwt;
+use crate::config::app_env::SHUTDOWN_TIMEOUT;
+use crate::domains::activity::events::Activity;
+use crate::domains::activity::listener::Listener;
+use crate::domains::activity::repository::ActivityRepository;
+use crate::domains::activity::services::ActivityService;
+use crate::domains::applications::services::ApplicationService;
+use crate::domains::organisations::services::OrganisationService;
+use crate::domains::realtime::db_models::Presence;
+use crate::domains::realtime::messages::{Channel, PresenceChange};
+use crate::domains::realtime::repository::PresenceRepository;
+use crate::domains::users::services::UserService;
+use crate::server::shutdown::ShutdownSignal;
+
+const CHANNEL: &str = "presence";
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+const CHANGES_CAPACITY: usize = 1024;
+const PURGE_INTERVAL: Duration = Duration::from_secs(60);
+// Open connections refresh their presence on every ping, the rows of connections lost with their instance get this old
+const STALE_AFTER: chrono::Duration = chrono::Duration::minutes(2);
+
+/*
+    Who a connection is authenticated as, in one of the organisations of the access token
+*/
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub user_id: Uuid,
+    pub organisation_id: Uuid,
+    pub locale: Option<Locale>,
+    pub expires_at: DateTime<Utc>,
+    // Verified again while the connection is open, the session can be revoked in the meantime
+    pub(super) access_token: String,
+}
+
+#[derive(Clone)]
+pub struct RealtimeService {
+    presence_repository: PresenceRepository,
+    activity_repository: ActivityRepository,
+    user_service: UserService,
+    organisation_service: OrganisationService,
+    application_service: ApplicationService,
+    activity_service: ActivityService,
+    // Presence changes notified by any instance
+    changes: broadcast::Sender<Arc<PresenceChange>>,
+    // Open connections close with the server, which waits for them
+    shutdown: ShutdownSignal,
+    connections: TaskTracker,
+}
+
+impl RealtimeService {
+    pub fn new(
+        presence_repository: PresenceRepository,
+        activity_repository: ActivityRepository,
+        user_service: UserService,
+        organisation_service: OrganisationService,
+        application_service: ApplicationService,
+        activity_service: ActivityService,
+        shutdown: ShutdownSignal,
+    ) -> Self {
+        RealtimeService {
+            presence_repository,
+            activity_repository,
+            user_service,
+            organisation_service,
+            application_service,
+            activity_service,
+            changes: broadcast::channel(CHANGES_CAPACITY).0,
+            shutdown,
+            connections: TaskTracker::new(),
+        }
+    }
+
+    /*
+        The session of an access token in the organisation, which must be one of the token's
+        and which the user must still be a member of
+    */
+    pub async fn authenticate(&self, access_token: String, organisation_id: Uuid) -> Result<Session, ApiError> {
+        let (identity, expires_at) = jwt::verify(&self.user_service, access_token.clone()).await?;
+        if !identity.organisation_ids.contains(&organisation_id) {
+            return Err(ApplicationError::Unauthorized.into());
+        }
+        let is_member = self.organisation_service.is_member(&identity.user_id, &organisation_id).await?;
+        if !is_member {
+            debug!("User {:?} is no longer a member of {:?}", identity.user_id, organisation_id);
+            return Err(ApplicationError::Forbidden.into());
+        }
+
+        Ok(Session {
+            user_id: identity.user_id,
+            organisation_id,
+            locale: identity.locale,
+            expires_at: DateTime::from_timestamp(expires_at as i64, 0).unwrap_or_default(),
+            access_token,
+        })
+    }
+
+    // Fails once the session was revoked or the user removed from the organisation
+    pub async fn revalidate(&self, session: &Session) -> Result<(), ApiError> {
+        self.authenticate(session.access_token.clone(), session.organisation_id)
+            .await
+            .map(|_| ())
+    }
+
+    /*
+        Puts the connection in the channel and returns who is in it, including the user.
+        The channel's resource must be in the organisation of the session, joining is only
+        notified when no other connection of the user was in the channel
+    */
+    pub async fn join(&self, session: &Session, connection_id: Uuid, channel: Channel) -> Result<Vec<Uuid>, ApplicationError> {
+        match channel {
+            Channel::Application(application_id) => {
+                self.application_service
+                    .find_by_id_and_organisation_id(&application_id, &session.organisation_id)
+                    .await?;
+            },
+        }
+
+        // Other connections of the user may already be in the channel
+        let was_present = self.presence_repository
+            .is_present(&session.organisation_id, &channel.to_string(), &session.user_id)
+            .await
+            .map_err(ApplicationError::from)?;
+        let presence = Presence {
+            connection_id,
+            channel: channel.to_string(),
+            organisation_id: session.organisation_id,
+            user_id: session.user_id,
+            seen_at: Utc::now().naive_utc(),
+        };
+        self.presence_repository
+            .join(&presence)
+            .await
+            .map_err(ApplicationError::from)?;
+        if !was_present {
+            self.notify(&presence, true).await?;
+        }
+
+        self.find_user_ids(&session.organisation_id, channel).await
+    }
+
+    pub async fn find_user_ids(&self, organisation_id: &Uuid, channel: Channel) -> Result<Vec<Uuid>, ApplicationError> {
+        self.presence_repository
+            .find_user_ids(organisation_id, &channel.to_string())
+            .await
+            .map_err(ApplicationError::from)
+    }
+
+    pub async fn leave(&self, connection_id: &Uuid, channel: Channel) -> Result<(), ApplicationError> {
+        let presence = self.presence_repository
+            .leave(connection_id, &channel.to_string())
+            .await
+            .map_err(ApplicationError::from)?;
+
+        match presence {
+            Some(presence) => self.left(&presence).await,
+            None => Ok(()),
+        }
+    }
+
+    // Takes a closed connection out of every channel it was in
+    pub async fn leave_all(&self, connection_id: &Uuid) -> Result<(), ApplicationError> {
+        let presences = self.presence_repository
+            .leave_all(connection_id)
+            .await
+            .map_err(ApplicationError::from)?;
+
+        for presence in &presences {
+            self.left(presence).await?;
+        }
+        Ok(())
+    }
+
+    // Keeps the presence of an open connection from being purged
+    pub async fn touch(&self, connection_id: &Uuid) -> Result<(), ApplicationError> {
+        self.presence_repository
+            .touch(connection_id)
+            .await
+            .map(|_| ())
+            .map_err(ApplicationError::from)
+    }
+
+    pub fn presence_changes(&self) -> broadcast::Receiver<Arc<PresenceChange>> {
+        self.changes.subscribe()
+    }
+
+    pub fn activities(&self, organisation_id: Uuid) -> broadcast::Receiver<Arc<Activity>> {
+        self.activity_service.subscribe(organisation_id)
+    }
+
+    pub async fn shutting_down(&self) {
+        self.shutdown.wait().await
+    }
+
+    // The connection counts as open until the future completes
+    pub fn track<F: Future>(&self, connection: F) -> TrackedFuture<F> {
+        self.connections.track_future(connection)
+    }
+
+    /*
+        Waits up to SHUTDOWN_TIMEOUT for the open connections to close once the server shut down,
+        upgraded connections aren't drained with the requests
+    */
+    pub async fn drain(&self) {
+        self.connections.close();
+        if tokio::time::timeout(*SHUTDOWN_TIMEOUT, self.connections.wait()).await.is_err() {
+            warn!("{} WebSockets still open at shutdown, their presence goes stale", self.connections.len());
+        }
+    }
+
+    // Only notified once the last connection of the user left the channel
+    async fn left(&self, presence: &Presence) -> Result<(), ApplicationError> {
+        let is_present = self.presence_repository
+            .is_present(&presence.organisation_id, &presence.channel, &presence.user_id)
+            .await
+            .map_err(ApplicationError::from)?;
+
+        if is_present {
+            Ok(())
+        } else {
+            self.notify(presence, false).await
+        }
+    }
+
+    async fn notify(&self, presence: &Presence, present: bool) -> Result<(), ApplicationError> {
+        let change = PresenceChange {
+            organisation_id: presence.organisation_id,
+            channel: presence.channel.clone(),
+            user_id: presence.user_id,
+            present,
+        };
+        let payload = serde_json::to_string(&change).expect("Presence changes serialize to JSON");
+        self.activity_repository
+            .notify(CHANNEL, &payload)
+            .await
+            .map(|_| ())
+            .map_err(ApplicationError::from)
+    }
+
+    /*
+        Hands the presence changes notified by any instance to the connections open on this one until shutdown,
+        reconnecting when the connection is lost. Changes notified while disconnected are missed
+    */
+    pub async fn listen(self, shutdown: ShutdownSignal) {
+        loop {
+            match Listener::connect(CHANNEL).await {
+                Ok(mut listener) => loop {
+                    tokio::select! {
+                        notification = listener.recv() => match notification {
+                            Some(Ok(payload)) => self.receive(&payload),
+                            Some(Err(e)) => {
+                                warn!("Presence listener failed: {}", e);
+                                break;
+                            },
+                            None => {
+                                warn!("Presence listener disconnected");
+                                break;
+                            },
+                        },
+                        _ = shutdown.wait() => return,
+                    }
+                },
+                Err(e) => warn!("Unable to listen for presence: {}", e),
+            }
+
+            tokio::select! {
+                _ = tokio::time::sleep(RECONNECT_DELAY) => info!("Reconnecting the presence listener"),
+                _ = shutdown.wait() => return,
+            }
+        }
+    }
+
+    pub(super) fn receive(&self, payload: &str) {
+        match serde_json::from_str::<PresenceChange>(payload) {
+            // No receivers only means no connection is open on this instance
+            Ok(change) => { let _ = self.changes.send(Arc::new(change)); },
+            Err(e) => warn!("Unreadable presence change {:?}: {}", payload, e),
+        }
+    }
+
+    /*
+        Takes the connections that stopped refreshing their presence (their instance went away)
+        out of their channels, every minute until shutdown
+    */
+    pub async fn purge_stale(self, shutdown: ShutdownSignal) {
+        let mut interval = tokio::time::interval(PURGE_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {},
+                _ = shutdown.wait() => return,
+            }
+
+            let stale = match self.presence_repository.delete_stale(Utc::now().naive_utc() - STALE_AFTER).await {
+                Ok(stale) => stale,
+                Err(e) => {
+                    warn!("Unable to purge stale presence: {:?}", e);
+                    continue;
+                },
+            };
+            if !stale.is_empty() {
+                info!("Purged {} stale presences", stale.len());
+            }
+            for presence in &stale {
+                if let Err(e) = self.left(presence).await {
+                    warn!("Unable to notify stale presence of {:?} left: {:?}", presence.user_id, e);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use diesel::sql_query;
+    use crate::common::query::RunQueryDsl;
+    use crate::common::repository::tenant_scope;
+    use crate::common::test_database::TestDatabase;
+    use crate::domains::activity::repository::ActivityRepository;
+    use crate::domains::applications::repository::ApplicationRepository;
+    use crate::domains::jobs::repository::JobRepository;
+    use crate::domains::organisations::repository::OrganisationRepository;
+    use crate::domains::outbox::repository::OutboxRepository;
+    use crate::domains::users::repository::UserRepository;
+    use super::*;
+
+    const QUIET: Duration = Duration::from_millis(300);
+
+    // An organisation with two members and an application
+    pub(in crate::domains::realtime) struct Tenant {
+        pub organisation_id: Uuid,
+        pub user_ids: [Uuid; 2],
+        pub application_id: Uuid,
+    }
+
+    pub(in crate::domains::realtime) async fn seed(database: &TestDatabase) -> Tenant {
+        let tenant = Tenant { organisation_id: Uuid::now_v7(), user_ids: [Uuid::now_v7(), Uuid::now_v7()], application_id: Uuid::now_v7() };
+        let mut admin = database.admin().await;
+        for user_id in tenant.user_ids {
+            sql_query(format!("INSERT INTO swift_user (id, email, first_name) VALUES ('{}', '{}@example.com', 'Present')", user_id, user_id))
+                .execute(&mut admin).await.unwrap();
+        }
+        sql_query(format!("INSERT INTO organisation (id, owner, name) VALUES ('{}', '{}', 'Present')", tenant.organisation_id, tenant.user_ids[0]))
+            .execute(&mut admin).await.unwrap();
+        for user_id in tenant.user_ids {
+            sql_query(format!("INSERT INTO swift_user_accessible_organisation (organisation_id, swift_user_id, role_id) VALUES ('{}', '{}', 1)", tenant.organisation_id, user_id))
+                .execute(&mut admin).await.unwrap();
+        }
+        sql_query(format!("INSERT INTO application (id, organisation_id, name) VALUES ('{}', '{}', 'Present')", tenant.application_id, tenant.organisation_id))
+            .execute(&mut admin).await.unwrap();
+        tenant
+    }
+
+    pub(in crate::domains::realtime) fn realtime_service(database: &TestDatabase, shutdown: ShutdownSignal) -> RealtimeService {
+        let pools = database.pools.clone();
+        let (user_repository, outbox_repository, job_repository) = (UserRepository::new(pools.clone()), OutboxRepository::new(pools.clone()), JobRepository::new(pools.clone()));
+        RealtimeService::new(
+            PresenceRepository::new(pools.clone()),
+            ActivityRepository::new(pools.clone()),
+            UserService::new(user_repository.clone(), outbox_repository.clone(), job_repository.clone()),
+            OrganisationService::new(OrganisationRepository::new(pools.clone()), user_repository, outbox_repository.clone(), job_repository),
+            ApplicationService::new(ApplicationRepository::new(pools.clone()), outbox_repository),
+            ActivityService::new(ActivityRepository::new(pools), shutdown.clone()),
+            shutdown,
+        )
+    }
+
+    // What `listen` does, from the scratch database instead of DATABASE_URL
+    pub(in crate::domains::realtime) async fn relay_presence(database: &TestDatabase, realtime_service: &RealtimeService) {
+        let mut listener = Listener::connect_to(&database.app_url, CHANNEL).await.unwrap();
+        let realtime_service = realtime_service.clone();
+        tokio::spawn(async move {
+            while let Some(Ok(payload)) = listener.recv().await {
+                realtime_service.receive(&payload);
+            }
+        });
+    }
+
+    pub(in crate::domains::realtime) fn session(organisation_id: Uuid, user_id: Uuid, expires_in: chrono::Duration) -> Session {
+        Session { user_id, organisation_id, locale: None, expires_at: Utc::now() + expires_in, access_token: String::new() }
+    }
+
+    async fn change(changes: &mut broadcast::Receiver<Arc<PresenceChange>>) -> Option<(Uuid, bool)> {
+        tokio::time::timeout(QUIET, changes.recv()).await.ok().map(|change| {
+            let change = change.unwrap();
+            (change.user_id, change.present)
+        })
+    }
+
+    #[tokio::test]
+    async fn users_join_and_leave_once_whatever_their_connections() {
+        let Some(database) = TestDatabase::create().await else { return };
+        let tenant = seed(&database).await;
+        let (_stop, shutdown) = ShutdownSignal::channel();
+        let realtime_service = realtime_service(&database, shutdown);
+        relay_presence(&database, &realtime_service).await;
+        let mut changes = realtime_service.presence_changes();
+        let (ada, grace) = (session(tenant.organisation_id, tenant.user_ids[0], chrono::Duration::hours(1)), session(tenant.organisation_id, tenant.user_ids[1], chrono::Duration::hours(1)));
+        let channel = Channel::Application(tenant.application_id);
+        let (first, second, other) = (Uuid::now_v7(), Uuid::now_v7(), Uuid::now_v7());
+
+        tenant_scope(tenant.organisation_id, async {
+            assert_eq!(realtime_service.join(&ada, first, channel).await.unwrap(), vec![ada.user_id]);
+            assert_eq!(change(&mut changes).await, Some((ada.user_id, true)));
+            // A second connection of the same user
+            realtime_service.join(&ada, second, channel).await.unwrap();
+            assert_eq!(change(&mut changes).await, None);
+
+            let mut present = realtime_service.join(&grace, other, channel).await.unwrap();
+            present.sort();
+            let mut expected = vec![ada.user_id, grace.user_id];
+            expected.sort();
+            assert_eq!(present, expected);
+            assert_eq!(change(&mut changes).await, Some((grace.user_id, true)));
+
+            realtime_service.leave(&first, channel).await.unwrap();
+            assert_eq!(change(&mut changes).await, None);
+            realtime_service.leave_all(&second).await.unwrap();
+            assert_eq!(change(&mut changes).await, Some((ada.user_id, false)));
+            assert_eq!(realtime_service.find_user_ids(&tenant.organisation_id, channel).await.unwrap(), vec![grace.user_id]);
+        }).await;
+    }
+
+    #[tokio::test]
+    async fn only_channels_of_the_organisation_can_be_joined() {
+        let Some(database) = TestDatabase::create().await else { return };
+        let (tenant, other) = (seed(&database).await, seed(&database).await);
+        let (_stop, shutdown) = ShutdownSignal::channel();
+        let realtime_service = realtime_service(&database, shutdown);
+        let ada = session(tenant.organisation_id, tenant.user_ids[0], chrono::Duration::hours(1));
+
+        let joined = tenant_scope(tenant.organisation_id, realtime_service.join(&ada, Uuid::now_v7(), Channel::Application(other.application_id))).await;
+        assert!(matches!(joined, Err(ApplicationError::NotFound)), "{:?}", joined);
+    }
+
+    #[tokio::test]
+    async fn presence_of_lost_connections_is_purged_and_announced() {
+        let Some(database) = TestDatabase::create().await else { return };
+        let tenant = seed(&database).await;
+        let (stop, shutdown) = ShutdownSignal::channel();
+        let realtime_service = realtime_service(&database, shutdown.clone());
+        relay_presence(&database, &realtime_service).await;
+        let mut changes = realtime_service.presence_changes();
+        let ada = session(tenant.organisation_id, tenant.user_ids[0], chrono::Duration::hours(1));
+        let channel = Channel::Application(tenant.application_id);
+
+        tenant_scope(tenant.organisation_id, realtime_service.join(&ada, Uuid::now_v7(), channel)).await.unwrap();
+        assert_eq!(change(&mut changes).await, Some((ada.user_id, true)));
+        // Its instance went away two minutes ago
+        sql_query("UPDATE presence SET seen_at = NOW() - INTERVAL '3 minutes'").execute(&mut database.admin().await).await.unwrap();
+
+        // Purges at once, then every minute
+        let purging = tokio::spawn(realtime_service.clone().purge_stale(shutdown));
+        assert_eq!(change(&mut changes).await, Some((ada.user_id, false)));
+        assert!(realtime_service.find_user_ids(&tenant.organisation_id, channel).await.unwrap().is_empty());
+
+        stop.send(true).unwrap();
+        tokio::time::timeout(Duration::from_secs(1), purging).await.unwrap().unwrap();
+    }
+}
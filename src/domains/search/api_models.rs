@@ -0,0 +1,67 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+use crate::domains::search::db_models::SearchHit;
+use crate::domains::search::highlight;
+
+#[derive(Debug, Deserialize)]
+pub struct SearchParams {
+    pub q: String,
+    pub limit: Option<i64>,
+}
+
+// Narrows a list endpoint to the matches of `q`, best first
+#[derive(Debug, Deserialize)]
+pub struct SearchFilter {
+    pub q: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchResultType {
+    User,
+    Application,
+}
+
+// HTML escaped, matches are wrapped in <mark>
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SearchHighlight {
+    pub title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subtitle: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SearchResultResponse {
+    #[serde(rename = "type")]
+    pub result_type: SearchResultType,
+    pub id: Uuid,
+    // Name of the user or application
+    pub title: String,
+    // Email of the user, description of the application
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subtitle: Option<String>,
+    pub highlight: SearchHighlight,
+    pub rank: f32,
+}
+
+impl SearchResultResponse {
+    pub fn from_hit(result_type: SearchResultType, hit: SearchHit, search: &str) -> Self {
+        SearchResultResponse {
+            result_type,
+            id: hit.id,
+            highlight: SearchHighlight {
+                title: highlight::html(&hit.title, search),
+                subtitle: hit.subtitle.as_deref().map(|subtitle| highlight::html(subtitle, search)),
+            },
+            title: hit.title,
+            subtitle: hit.subtitle,
+            rank: hit.rank,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SearchResponse {
+    pub data: Vec<SearchResultResponse>
+}
@@ -0,0 +1,16 @@
+use diesel::QueryableByName;
+use diesel::sql_types::{Float, Nullable, Text, Uuid as SqlUuid};
+use uuid::Uuid;
+
+// A user or application matching a search, the name as title and the email or description as subtitle
+#[derive(QueryableByName, Debug)]
+pub struct SearchHit {
+    #[diesel(sql_type = SqlUuid)]
+    pub id: Uuid,
+    #[diesel(sql_type = Text)]
+    pub title: String,
+    #[diesel(sql_type = Nullable<Text>)]
+    pub subtitle: Option<String>,
+    #[diesel(sql_type = Float)]
+    pub rank: f32,
+}
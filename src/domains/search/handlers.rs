@@ -0,0 +1,46 @@
+use axum::extract::{Query, State};
+use axum::extract::rejection::QueryRejection;
+use axum::{Json, Router};
+use axum::routing::get;
+use crate::config::AppState;
+use crate::common::errors::api_error_response::ErrorResponse;
+use crate::common::errors::global_api_error::ApiError;
+use crate::common::errors::request_error::RequestError;
+use crate::common::models::models::OrganisationId;
+use crate::common::security;
+use crate::domains::search::api_models::{SearchParams, SearchResponse};
+use crate::domains::search::services::SearchService;
+use crate::domains::docs::params::OrganisationHeader;
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/search", get(search))
+        .route_layer(axum::middleware::from_fn(security::middleware::inject_organisation_id))
+}
+
+/*
+    Users and applications of the organisation matching `q`, best first. Every word matches as a prefix,
+    misspelt names still match closely enough. `highlight` has the matches wrapped in <mark>
+*/
+#[utoipa::path(
+    get,
+    path = "/search",
+    tag = "search",
+    params(("q" = String, Query, description = "Words to search for, at most 200 characters"), ("limit" = Option<i64>, Query, description = "Maximum number of results, 20 by default and at most 50"), OrganisationHeader),
+    responses(
+        (status = 200, description = "Matching users and applications, best first", body = SearchResponse),
+        (status = 400, response = ErrorResponse),
+        (status = 401, response = ErrorResponse),
+    ),
+)]
+async fn search(
+    State(search_service): State<SearchService>,
+    organisation_id: OrganisationId,
+    params: Result<Query<SearchParams>, QueryRejection>,
+) -> Result<Json<SearchResponse>, ApiError> {
+    let Query(params) = params.map_err(RequestError::from)?;
+
+    search_service.search(&params.q, &organisation_id.0, params.limit)
+        .await
+        .map(|data| Json(SearchResponse { data }))
+}
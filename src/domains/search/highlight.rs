@@ -0,0 +1,52 @@
+/*
+    Marks the words of a text that start with one of the searched words, the way prefix_tsquery matches them.
+    Done here rather than with ts_headline, which drops anything looking like a tag from the text
+*/
+pub fn html(text: &str, search: &str) -> String {
+    let prefixes: Vec<String> = words(search).map(|(_, word)| word.to_lowercase()).collect();
+
+    let mut html = String::with_capacity(text.len());
+    let mut escaped_until = 0;
+    for (start, word) in words(text) {
+        if prefixes.iter().any(|prefix| word.to_lowercase().starts_with(prefix.as_str())) {
+            escape_into(&mut html, &text[escaped_until..start]);
+            html.push_str("<mark>");
+            escape_into(&mut html, word);
+            html.push_str("</mark>");
+            escaped_until = start + word.len();
+        }
+    }
+    escape_into(&mut html, &text[escaped_until..]);
+    html
+}
+
+fn words(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.split(|char: char| !char.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(move |word| (word.as_ptr() as usize - text.as_ptr() as usize, word))
+}
+
+fn escape_into(html: &mut String, text: &str) {
+    for char in text.chars() {
+        match char {
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            '\'' => html.push_str("&#39;"),
+            char => html.push(char),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn html_escapes_text_and_marks_words_starting_with_a_searched_word() {
+        assert_eq!(html("Tom & <b>Jerry</b> jerrycan", "jer"), "Tom &amp; &lt;b&gt;<mark>Jerry</mark>&lt;/b&gt; <mark>jerrycan</mark>");
+        assert_eq!(html("Zoë Ångström", "ång zo"), "<mark>Zoë</mark> <mark>Ångström</mark>");
+        assert_eq!(html("no match", "other"), "no match");
+    }
+}
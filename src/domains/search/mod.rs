@@ -0,0 +1,6 @@
+pub mod api_models;
+pub mod db_models;
+pub mod handlers;
+pub mod highlight;
+pub mod repository;
+pub mod services;
@@ -0,0 +1,115 @@
+use diesel::sql_query;
+use diesel::sql_types::{BigInt, Text, Uuid as SqlUuid};
+use diesel_async::scoped_futures::ScopedFutureExt;
+use crate::common::query::RunQueryDsl;
+use tracing::instrument;
+use uuid::Uuid;
+use crate::common::errors::db_error::DbError;
+use crate::common::repository::{self, BaseRepository};
+use crate::config::diesel_config::DbPools;
+use crate::domains::search::db_models::SearchHit;
+
+#[derive(Clone)]
+pub struct SearchRepository {
+    pools: DbPools,
+}
+
+/*
+    Words match by prefix on the search_vector columns, typos by trigram similarity on the same text.
+    The rank adds both so exact words come before close ones
+*/
+impl SearchRepository {
+    pub fn new(pools: DbPools) -> Self {
+        SearchRepository { pools }
+    }
+
+    #[instrument(skip_all)]
+    pub async fn find_users(&self, search: &str, organisation_id: &Uuid, limit: i64) -> Result<Vec<SearchHit>, DbError> {
+        let mut conn = self.read_conn().await?;
+        repository::scoped(&mut conn, |conn| async move {
+            sql_query("SELECT u.id, \
+                              u.first_name || coalesce(' ' || u.last_name, '') AS title, \
+                              u.email AS subtitle, \
+                              (ts_rank_cd(u.search_vector, query) + word_similarity($1, u.first_name || ' ' || coalesce(u.last_name, '') || ' ' || u.email))::real AS rank \
+                       FROM swift_user u \
+                       JOIN swift_user_accessible_organisation o ON o.swift_user_id = u.id, \
+                            prefix_tsquery($1) AS query \
+                       WHERE o.organisation_id = $2 \
+                         AND (u.search_vector @@ query OR $1 <% (u.first_name || ' ' || coalesce(u.last_name, '') || ' ' || u.email)) \
+                       ORDER BY rank DESC, u.id \
+                       LIMIT $3")
+                .bind::<Text, _>(search)
+                .bind::<SqlUuid, _>(organisation_id)
+                .bind::<BigInt, _>(limit)
+                .get_results(conn)
+                .await
+                .map_err(DbError::from)
+        }.scope_boxed()).await
+    }
+
+    #[instrument(skip_all)]
+    pub async fn find_applications(&self, search: &str, organisation_id: &Uuid, limit: i64) -> Result<Vec<SearchHit>, DbError> {
+        let mut conn = self.read_conn().await?;
+        repository::scoped(&mut conn, |conn| async move {
+            sql_query("SELECT a.id, \
+                              a.name AS title, \
+                              a.description AS subtitle, \
+                              (ts_rank_cd(a.search_vector, query) + word_similarity($1, a.name || ' ' || coalesce(a.description, '')))::real AS rank \
+                       FROM application a, prefix_tsquery($1) AS query \
+                       WHERE a.organisation_id = $2 \
+                         AND (a.search_vector @@ query OR $1 <% (a.name || ' ' || coalesce(a.description, ''))) \
+                       ORDER BY rank DESC, a.id \
+                       LIMIT $3")
+                .bind::<Text, _>(search)
+                .bind::<SqlUuid, _>(organisation_id)
+                .bind::<BigInt, _>(limit)
+                .get_results(conn)
+                .await
+                .map_err(DbError::from)
+        }.scope_boxed()).await
+    }
+}
+
+impl BaseRepository for SearchRepository {
+    fn pools(&self) -> &DbPools {
+        &self.pools
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::repository::tenant_scope;
+    use crate::common::test_database::TestDatabase;
+    use crate::domains::applications::repository::ApplicationRepository;
+    use super::*;
+
+    async fn find_applications(search: &SearchRepository, words: &str, organisation_id: Uuid) -> Vec<Uuid> {
+        let hits = tenant_scope(organisation_id, search.find_applications(words, &organisation_id, 10)).await.unwrap();
+        hits.into_iter().map(|hit| hit.id).collect()
+    }
+
+    #[tokio::test]
+    async fn misspelt_words_of_names_and_descriptions_match() {
+        let Some(database) = TestDatabase::create().await else { return };
+        let (user_id, organisation_id) = (Uuid::now_v7(), Uuid::now_v7());
+        let (billing, reports) = (Uuid::now_v7(), Uuid::now_v7());
+        let mut admin = database.admin().await;
+        for statement in [
+            format!("INSERT INTO swift_user (id, email, first_name) VALUES ('{}', 'searcher@example.com', 'Searcher')", user_id),
+            format!("INSERT INTO organisation (id, owner, name) VALUES ('{}', '{}', 'Search')", organisation_id, user_id),
+            format!("INSERT INTO application (id, organisation_id, name, description) VALUES ('{}', '{}', 'Billing', 'Sends the monthly invoices')", billing, organisation_id),
+            format!("INSERT INTO application (id, organisation_id, name, description) VALUES ('{}', '{}', 'Reports', NULL)", reports, organisation_id),
+        ] {
+            sql_query(statement).execute(&mut admin).await.unwrap();
+        }
+        let applications = ApplicationRepository::new(database.pools.clone());
+
+        let search = SearchRepository::new(database.pools.clone());
+        assert_eq!(find_applications(&search, "invoics", organisation_id).await, vec![billing]);
+        assert_eq!(find_applications(&search, "reportz", organisation_id).await, vec![reports]);
+        assert!(find_applications(&search, "payroll", organisation_id).await.is_empty());
+
+        let listed = tenant_scope(organisation_id, applications.find_all_by_organisation_id(&organisation_id, Some("monthy"))).await.unwrap();
+        assert_eq!(listed.into_iter().map(|application| application.id).collect::<Vec<_>>(), vec![billing]);
+    }
+}
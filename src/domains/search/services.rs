@@ -0,0 +1,57 @@
+use tracing::debug;
+use uuid::Uuid;
+use crate::common::errors::api_error_response::ErrorMessage;
+use crate::common::errors::application_error::ApplicationError;
+use crate::common::errors::error_code;
+use crate::common::errors::global_api_error::ApiError;
+use crate::common::errors::request_error::RequestError::ValidationError;
+use crate::domains::search::api_models::{SearchResultResponse, SearchResultType};
+use crate::domains::search::repository::SearchRepository;
+
+pub const DEFAULT_LIMIT: i64 = 20;
+pub const MAX_LIMIT: i64 = 50;
+const MAX_QUERY_LENGTH: usize = 200;
+
+#[derive(Clone)]
+pub struct SearchService {
+    search_repository: SearchRepository,
+}
+
+impl SearchService {
+    pub fn new(search_repository: SearchRepository) -> Self {
+        SearchService { search_repository }
+    }
+
+    // Users and applications of the organisation, the best matches of both first
+    pub async fn search(&self, search: &str, organisation_id: &Uuid, limit: Option<i64>) -> Result<Vec<SearchResultResponse>, ApiError> {
+        let search = validate_search(search)?;
+        let limit = limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+        debug!("Searching organisation {:?}", organisation_id);
+
+        let (users, applications) = tokio::try_join!(
+            self.search_repository.find_users(search, organisation_id, limit),
+            self.search_repository.find_applications(search, organisation_id, limit),
+        ).map_err(ApplicationError::from)?;
+
+        let mut results: Vec<SearchResultResponse> = users.into_iter()
+            .map(|hit| SearchResultResponse::from_hit(SearchResultType::User, hit, search))
+            .chain(applications.into_iter().map(|hit| SearchResultResponse::from_hit(SearchResultType::Application, hit, search)))
+            .collect();
+        results.sort_by(|a, b| b.rank.total_cmp(&a.rank));
+        results.truncate(limit as usize);
+        Ok(results)
+    }
+}
+
+// The `q` of /search and of the list endpoints, trimmed
+pub fn validate_search(search: &str) -> Result<&str, ApiError> {
+    let search = search.trim();
+    if search.is_empty() {
+        return Err(ValidationError(vec![ErrorMessage::new(Some("q"), error_code::VALIDATION_FIELD_REQUIRED)]).into());
+    }
+    if search.chars().count() > MAX_QUERY_LENGTH {
+        let max = MAX_QUERY_LENGTH.to_string();
+        return Err(ValidationError(vec![ErrorMessage::with_args(Some("q"), error_code::VALIDATION_SEARCH_TOO_LONG, &[("max", &max)])]).into());
+    }
+    Ok(search)
+}
@@ -1,43 +1,74 @@
+use std::fmt;
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 use uuid::Uuid;
+use crate::common::utils::constants::REDACTED;
 use crate::domains::users::db_models::SwiftUser;
 
-#[derive(Debug, Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct UserCreateRequest {
     pub email: String,
     pub password: Option<String>,
     pub first_name: String,
     pub last_name: Option<String>,
+    pub locale: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
+impl fmt::Debug for UserCreateRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UserCreateRequest")
+            .field("email", &self.email)
+            .field("password", &self.password.as_ref().map(|_| REDACTED))
+            .field("first_name", &self.first_name)
+            .field("last_name", &self.last_name)
+            .field("locale", &self.locale)
+            .finish()
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
 pub struct UserPutRequest {
     pub password: Option<String>,
     pub first_name: String,
     pub last_name: Option<String>,
+    pub locale: Option<String>,
 }
 
-#[derive(Debug, Serialize)]
+impl fmt::Debug for UserPutRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UserPutRequest")
+            .field("password", &self.password.as_ref().map(|_| REDACTED))
+            .field("first_name", &self.first_name)
+            .field("last_name", &self.last_name)
+            .field("locale", &self.locale)
+            .finish()
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
 pub struct UserResponse {
     pub id: Uuid,
     pub email: String,
     pub first_name: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub last_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub locale: Option<String>,
 }
 
-impl Into<UserResponse> for SwiftUser {
-    fn into(self) -> UserResponse {
+impl From<SwiftUser> for UserResponse {
+    fn from(user: SwiftUser) -> Self {
         UserResponse {
-            id: self.id,
-            email: self.email,
-            first_name: self.first_name,
-            last_name: self.last_name,
+            id: user.id,
+            email: user.email,
+            first_name: user.first_name,
+            last_name: user.last_name,
+            locale: user.locale,
         }
     }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct UsersResponse {
     pub data: Vec<UserResponse>
 }
\ No newline at end of file
@@ -1,57 +1,108 @@
+use std::fmt;
 use chrono::{NaiveDateTime, Utc};
-use diesel::{AsChangeset, Identifiable, Insertable, Queryable};
+use diesel::{AsChangeset, Identifiable, Insertable, Queryable, Selectable};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use crate::common::schema;
+use crate::common::utils::constants::REDACTED;
 use crate::domains::users::api_models::{UserCreateRequest, UserPutRequest};
 
-#[derive(Insertable, Queryable, Debug, AsChangeset, Identifiable, Serialize, Deserialize)]
+// Selected explicitly, the table also has the generated search_vector
+#[derive(Insertable, Queryable, Selectable, AsChangeset, Identifiable, Serialize, Deserialize)]
 #[diesel(table_name = schema::swift_user)]
 pub struct SwiftUser {
     pub id: Uuid,
     pub email: String,
     pub password: Option<String>,
+    pub is_super_admin: bool,
     pub first_name: String,
     pub last_name: Option<String>,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
+    pub locale: Option<String>,
+    pub sessions_revoked_at: Option<NaiveDateTime>,
+}
+
+impl fmt::Debug for SwiftUser {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SwiftUser")
+            .field("id", &self.id)
+            .field("email", &self.email)
+            .field("password", &self.password.as_ref().map(|_| REDACTED))
+            .field("is_super_admin", &self.is_super_admin)
+            .field("first_name", &self.first_name)
+            .field("last_name", &self.last_name)
+            .field("created_at", &self.created_at)
+            .field("updated_at", &self.updated_at)
+            .field("locale", &self.locale)
+            .field("sessions_revoked_at", &self.sessions_revoked_at)
+            .finish()
+    }
 }
 
-impl Into<SwiftUser> for UserCreateRequest {
-    fn into(self) -> SwiftUser {
+impl From<UserCreateRequest> for SwiftUser {
+    fn from(request: UserCreateRequest) -> Self {
         SwiftUser {
             id: Uuid::now_v7(),
-            email: self.email,
-            password: self.password,
-            first_name: self.first_name,
-            last_name: self.last_name,
+            email: request.email,
+            password: request.password,
+            is_super_admin: false,
+            first_name: request.first_name,
+            last_name: request.last_name,
             created_at: Utc::now().naive_utc(),
             updated_at: Utc::now().naive_utc(),
+            locale: request.locale,
+            sessions_revoked_at: None,
         }
     }
 }
 
-#[derive(Debug, AsChangeset, Serialize)]
+// What authentication needs from the user on every request
+#[derive(Debug, Clone, Queryable, Selectable)]
+#[diesel(table_name = schema::swift_user)]
+pub struct SessionState {
+    pub sessions_revoked_at: Option<NaiveDateTime>,
+    pub locale: Option<String>,
+}
+
+#[derive(AsChangeset, Serialize)]
 #[diesel(table_name = schema::swift_user)]
 pub struct PutSwiftUser {
     pub password: Option<String>,
     pub first_name: String,
     pub last_name: Option<String>,
     pub updated_at: NaiveDateTime,
+    pub locale: Option<String>,
 }
 
-impl Into<PutSwiftUser> for UserPutRequest {
-    fn into(self) -> PutSwiftUser {
+impl fmt::Debug for PutSwiftUser {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PutSwiftUser")
+            .field("password", &self.password.as_ref().map(|_| REDACTED))
+            .field("first_name", &self.first_name)
+            .field("last_name", &self.last_name)
+            .field("updated_at", &self.updated_at)
+            .field("locale", &self.locale)
+            .finish()
+    }
+}
+
+impl From<UserPutRequest> for PutSwiftUser {
+    fn from(request: UserPutRequest) -> Self {
         PutSwiftUser {
-            password: self.password,
-            first_name: self.first_name,
+            password: request.password,
+            first_name: request.first_name,
             last_name: None,
             updated_at: Utc::now().naive_utc(),
+            locale: request.locale,
         }
     }
 }
 
+// Role users added to an organisation get, the 'User' row of organisation_user_role
+pub const DEFAULT_ROLE_ID: i64 = 2;
+
 #[derive(Insertable, Queryable, Debug, AsChangeset, Identifiable, Serialize, Deserialize)]
 #[diesel(table_name = schema::swift_user_accessible_organisation)]
 #[diesel(primary_key(swift_user_id, organisation_id))]
@@ -1,27 +1,48 @@
-use axum::extract::{Path, State};
+use axum::extract::{Path, Query, State};
+use axum::extract::rejection::QueryRejection;
 use axum::{Json, Router};
 use axum::routing::get;
-use http::StatusCode;
+use axum::response::Response;
+use http::{HeaderMap, StatusCode};
 use uuid::Uuid;
 use crate::config::AppState;
 use crate::domains::users::api_models::{UserPutRequest, UserCreateRequest, UserResponse, UsersResponse};
 use crate::domains::users::services::UserService;
+use crate::common::errors::api_error_response::ErrorResponse;
 use crate::common::errors::application_error::ApplicationError;
 use crate::common::errors::global_api_error::ApiError;
+use crate::common::errors::request_error::RequestError;
 use crate::common::extract::request::SwiftJson;
-use crate::common::models::models::{Identity, OrganisationId};
+use crate::common::models::models::{ExpectedVersion, Identity, OrganisationId};
 use crate::common::security;
+use crate::common::utils::etag;
 use crate::domains::organisations::services::OrganisationService;
+use crate::domains::search::api_models::SearchFilter;
+use crate::domains::search::services::validate_search;
 use crate::domains::users::db_models::SwiftUser;
+use crate::domains::docs::params::{OrganisationHeader, IdempotencyKeyHeader, IfMatchHeader, IfNoneMatchHeader};
 
 pub fn routes() -> Router<AppState> {
-    return Router::new()
+    Router::new()
         .route("/users", get(fetch_users).post(create_user))
         .route("/users/:id", get(fetch_user).put(update_user).delete(delete_user))
-        .route_layer(axum::middleware::from_fn(security::middleware::inject_organisation_id));
+        .route_layer(axum::middleware::from_fn(security::middleware::inject_organisation_id))
 }
 
-// todo "able" to send invite/email to that user
+#[utoipa::path(
+    post,
+    path = "/users",
+    tag = "users",
+    params(OrganisationHeader, IdempotencyKeyHeader),
+    request_body = UserCreateRequest,
+    responses(
+        (status = 201, description = "User created and added to the organisation, they are emailed an invite", body = UserResponse),
+        (status = 400, response = ErrorResponse),
+        (status = 401, response = ErrorResponse),
+        (status = 403, response = ErrorResponse),
+        (status = 409, response = ErrorResponse),
+    ),
+)]
 async fn create_user(
     State(user_service): State<UserService>,
     State(organisation_service): State<OrganisationService>,
@@ -33,66 +54,130 @@ async fn create_user(
         return Err(ApplicationError::Forbidden.into());
     }
 
-    user_service.create_with_organisation_id(user_request, organisation_id.0)
+    let organisation = organisation_service.find_by_id_and_accessible_user_id(&identity, &organisation_id.0).await?;
+    user_service.create_with_organisation(user_request, &organisation)
         .await
         .map(SwiftUser::into)
         .map(|created_user| (StatusCode::CREATED, Json(created_user)))
 }
 
+#[utoipa::path(
+    get,
+    path = "/users",
+    tag = "users",
+    params(("q" = Option<String>, Query, description = "Only the matches of these words, best first, at most 200 characters"), OrganisationHeader),
+    responses(
+        (status = 200, description = "Users of the organisation", body = UsersResponse),
+        (status = 400, response = ErrorResponse),
+        (status = 401, response = ErrorResponse),
+    ),
+)]
 async fn fetch_users(
     State(user_service): State<UserService>,
     organisation_id: OrganisationId,
-) -> Result<Json<UsersResponse>, ApplicationError> {
-    user_service.find_all_by_organisation_id(&organisation_id.0)
+    filter: Result<Query<SearchFilter>, QueryRejection>,
+) -> Result<Json<UsersResponse>, ApiError> {
+    let Query(filter) = filter.map_err(RequestError::from)?;
+    let search = filter.q.as_deref().map(validate_search).transpose()?;
+
+    user_service.find_all_by_organisation_id(&organisation_id.0, search)
         .await
+        .map_err(ApiError::from)
         .map(|swift_users| {
             UsersResponse {
                 data: swift_users.into_iter().map(SwiftUser::into).collect()
             }
         })
-        .map(|users_response| Json(users_response))
+        .map(Json)
 }
 
+#[utoipa::path(
+    get,
+    path = "/users/{id}",
+    tag = "users",
+    params(("id" = Uuid, Path), OrganisationHeader, IfNoneMatchHeader),
+    responses(
+        (status = 200, description = "The user", body = UserResponse, headers(("ETag" = String))),
+        (status = 304, description = "Not modified since the ETag in If-None-Match"),
+        (status = 401, response = ErrorResponse),
+        (status = 404, response = ErrorResponse),
+    ),
+)]
 async fn fetch_user(
     State(user_service): State<UserService>,
     organisation_id: OrganisationId,
     Path(id): Path<Uuid>,
-) -> Result<Json<UserResponse>, ApplicationError> {
+    headers: HeaderMap,
+) -> Result<Response, ApplicationError> {
     user_service.find_by_id_and_organisation_id(&id, &organisation_id.0)
         .await
-        .map(SwiftUser::into)
-        .map(|response| Json(response))
+        .map(|user| {
+            let updated_at = user.updated_at;
+            etag::respond_with_version(&headers, &updated_at, Json::<UserResponse>(user.into()))
+        })
 }
 
+#[utoipa::path(
+    put,
+    path = "/users/{id}",
+    tag = "users",
+    params(("id" = Uuid, Path), OrganisationHeader, IfMatchHeader),
+    request_body = UserPutRequest,
+    responses(
+        (status = 204, description = "User updated", headers(("ETag" = String, description = "The new version"))),
+        (status = 400, response = ErrorResponse),
+        (status = 401, response = ErrorResponse),
+        (status = 403, response = ErrorResponse),
+        (status = 404, response = ErrorResponse),
+        (status = 412, response = ErrorResponse),
+        (status = 428, response = ErrorResponse),
+    ),
+)]
 async fn update_user(
     Path(id): Path<Uuid>,
     State(organisation_service): State<OrganisationService>,
     identity: Identity,
     organisation_id: OrganisationId,
     State(user_service): State<UserService>,
+    expected_version: ExpectedVersion,
     SwiftJson(user_request): SwiftJson<UserPutRequest>,
-) -> Result<StatusCode, ApplicationError> {
+) -> Result<Response, ApiError> {
     if !organisation_service.is_admin(&identity.user_id, &organisation_id.0).await? {
         return Err(ApplicationError::Forbidden.into());
     }
 
-    user_service.update_by_id(id, user_request)
+    user_service.update_by_id(id, expected_version, user_request)
         .await
-        .map(|_| StatusCode::NO_CONTENT)
+        .map(|updated_at| etag::updated(&updated_at))
 }
 
+#[utoipa::path(
+    delete,
+    path = "/users/{id}",
+    tag = "users",
+    params(("id" = Uuid, Path), OrganisationHeader, IfMatchHeader),
+    responses(
+        (status = 204, description = "User deleted"),
+        (status = 401, response = ErrorResponse),
+        (status = 403, response = ErrorResponse),
+        (status = 404, response = ErrorResponse),
+        (status = 412, response = ErrorResponse),
+        (status = 428, response = ErrorResponse),
+    ),
+)]
 async fn delete_user(
     Path(id): Path<Uuid>,
     State(organisation_service): State<OrganisationService>,
     identity: Identity,
     organisation_id: OrganisationId,
     State(user_service): State<UserService>,
+    expected_version: ExpectedVersion,
 ) -> Result<StatusCode, ApplicationError> {
     if !organisation_service.is_admin(&identity.user_id, &organisation_id.0).await? {
-        return Err(ApplicationError::Forbidden.into());
+        return Err(ApplicationError::Forbidden);
     }
 
-    user_service.delete_by_id(id)
+    user_service.delete_by_id(id, expected_version)
         .await
         .map(|_| StatusCode::NO_CONTENT)
 }
\ No newline at end of file
@@ -1,5 +1,6 @@
 pub mod handlers;
 pub mod services;
 pub mod repository;
+pub mod session_cache;
 pub mod db_models;
 pub mod api_models;
\ No newline at end of file
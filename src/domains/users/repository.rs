@@ -1,81 +1,164 @@
+use chrono::{NaiveDateTime, Utc};
+use diesel::dsl::sql;
 use diesel::prelude::*;
+use diesel::sql_types::{Bool, Float, Text};
 
-use bb8::{Pool};
-use diesel_async::{ AsyncPgConnection, RunQueryDsl};
-use diesel_async::pooled_connection::AsyncDieselConnectionManager;
+use diesel_async::AsyncPgConnection;
+use diesel_async::scoped_futures::ScopedFutureExt;
+use crate::common::query::RunQueryDsl;
+use tracing::instrument;
 use uuid::Uuid;
 use crate::common::errors::db_error::DbError;
 
-use crate::common::repository::BaseRepository;
-use crate::domains::users::db_models::{PutSwiftUser, SwiftUser, SwiftUserOrganisation};
+use crate::common::repository::{self, BaseRepository};
+use crate::config::diesel_config::DbPools;
+use crate::domains::users::db_models::{PutSwiftUser, SessionState, SwiftUser, SwiftUserOrganisation};
 use crate::common::schema::{organisation, swift_user, swift_user_accessible_organisation};
 
 #[derive(Clone)]
 pub struct UserRepository {
-    pool: Pool<AsyncDieselConnectionManager<AsyncPgConnection>>,
+    pools: DbPools,
 }
 
 impl UserRepository {
-    pub fn new(pool: Pool<AsyncDieselConnectionManager<AsyncPgConnection>>) -> Self {
-        UserRepository { pool }
+    pub fn new(pools: DbPools) -> Self {
+        UserRepository { pools }
     }
 
-    pub async fn insert(&self, user: &SwiftUser) -> Result<usize, DbError> {
-        let mut conn = self.conn().await?;
-
+    #[instrument(skip_all)]
+    pub async fn insert_with_conn(&self, conn: &mut AsyncPgConnection, user: &SwiftUser) -> Result<usize, DbError> {
         diesel::insert_into(swift_user::table)
             .values(user)
-            .execute(&mut conn)
+            .execute(conn)
             .await
             .map_err(DbError::from)
     }
 
-    pub async fn find_by_id_and_organisation_id(&self, id: &Uuid, organisation_id: &Uuid) -> Result<Option<SwiftUser>, DbError> {
-        let mut conn = self.conn().await?;
-        swift_user::table
-            .inner_join(swift_user_accessible_organisation::table.on(swift_user_accessible_organisation::swift_user_id.eq(swift_user::id)))
-            .filter(swift_user_accessible_organisation::organisation_id.eq(organisation_id))
-            .filter(swift_user::id.eq(id))
-            .select(swift_user::all_columns)
+    // Across organisations and always the primary, permission checks must not act on a lagging replica
+    #[instrument(skip_all)]
+    pub async fn find_is_super_admin(&self, id: &Uuid) -> Result<Option<bool>, DbError> {
+        let mut conn = self.admin_conn().await?;
+        swift_user::table.find(id)
+            .select(swift_user::is_super_admin)
             .get_result(&mut conn)
             .await
             .optional()
             .map_err(DbError::from)
     }
 
+    #[instrument(skip_all)]
+    pub async fn find_by_id_and_organisation_id(&self, id: &Uuid, organisation_id: &Uuid) -> Result<Option<SwiftUser>, DbError> {
+        let mut conn = self.read_conn().await?;
+        repository::scoped(&mut conn, |conn| async move {
+            swift_user::table
+                .inner_join(swift_user_accessible_organisation::table.on(swift_user_accessible_organisation::swift_user_id.eq(swift_user::id)))
+                .filter(swift_user_accessible_organisation::organisation_id.eq(organisation_id))
+                .filter(swift_user::id.eq(id))
+                .select(SwiftUser::as_select())
+                .get_result(conn)
+                .await
+                .optional()
+                .map_err(DbError::from)
+        }.scope_boxed()).await
+    }
+
+    // Across organisations, for logins and password resets
+    #[instrument(skip_all)]
     pub async fn find_by_email(&self, email: &String) -> Result<Option<SwiftUser>, DbError> {
-        let mut conn = self.conn().await?;
+        let mut conn = self.admin_conn().await?;
         swift_user::table.filter(swift_user::email.eq(email))
+            .select(SwiftUser::as_select())
             .get_result(&mut conn)
             .await
             .optional()
             .map_err(DbError::from)
     }
 
-    pub async fn update_by_id(&self, id: &Uuid, user: PutSwiftUser) -> Result<usize, DbError> {
-        let mut conn = self.conn().await?;
-        diesel::update(swift_user::table.find(id))
-            .set(&user)
-            .execute(&mut conn)
+    // Always the primary, a conditional change must compare against the current version
+    #[instrument(skip_all)]
+    pub async fn find_version(&self, id: &Uuid) -> Result<Option<NaiveDateTime>, DbError> {
+        let mut conn = self.fresh_read_conn().await?;
+        repository::scoped(&mut conn, |conn| async move {
+            swift_user::table.find(id)
+                .select(swift_user::updated_at)
+                .get_result(conn)
+                .await
+                .optional()
+                .map_err(DbError::from)
+        }.scope_boxed()).await
+    }
+
+    #[instrument(skip_all)]
+    pub async fn update_by_id_with_conn(&self, conn: &mut AsyncPgConnection, id: &Uuid, versions: &[NaiveDateTime], user: &PutSwiftUser) -> Result<Option<NaiveDateTime>, DbError> {
+        diesel::update(swift_user::table.find(id).filter(swift_user::updated_at.eq_any(versions)))
+            .set(user)
+            .returning(swift_user::updated_at)
+            .get_result(conn)
+            .await
+            .optional()
+            .map_err(DbError::from)
+    }
+
+    #[instrument(skip_all)]
+    pub async fn delete_by_id_with_conn(&self, conn: &mut AsyncPgConnection, id: &Uuid, versions: &[NaiveDateTime]) -> Result<usize, DbError> {
+        diesel::delete(swift_user::table.filter(swift_user::id.eq(id)).filter(swift_user::updated_at.eq_any(versions)))
+            .execute(conn)
             .await
             .map_err(DbError::from)
     }
 
-    pub async fn delete_by_id(&self, id: Uuid) -> Result<usize, DbError> {
-        let mut conn = self.conn().await?;
-        diesel::delete(swift_user::table.filter(swift_user::id.eq(id)))
-            .execute(&mut conn)
+    #[instrument(skip_all)]
+    pub async fn update_password_by_email_with_conn(&self, conn: &mut AsyncPgConnection, email: &str, password_hash: &str) -> Result<usize, DbError> {
+        let now = Utc::now().naive_utc();
+
+        // Existing tokens were issued under the old password, so they are revoked as well
+        diesel::update(swift_user::table.filter(swift_user::email.eq(email)))
+            .set((
+                swift_user::password.eq(password_hash),
+                swift_user::sessions_revoked_at.eq(now),
+                swift_user::updated_at.eq(now),
+            ))
+            .execute(conn)
             .await
             .map_err(DbError::from)
     }
 
-    pub async fn insert_user_accessible_organisation(&self,
-                                                     swift_user_organisation: SwiftUserOrganisation) -> Result<usize, DbError> {
-        let mut conn = self.conn().await?;
+    #[instrument(skip_all)]
+    pub async fn revoke_sessions_by_email(&self, email: &str) -> Result<Option<Uuid>, DbError> {
+        let mut conn = self.admin_conn().await?;
 
-        self.insert_user_accessible_organisation_with_conn(&mut conn, swift_user_organisation).await
+        diesel::update(swift_user::table.filter(swift_user::email.eq(email)))
+            .set(swift_user::sessions_revoked_at.eq(Utc::now().naive_utc()))
+            .returning(swift_user::id)
+            .get_result(&mut conn)
+            .await
+            .optional()
+            .map_err(DbError::from)
     }
 
+    #[instrument(skip_all)]
+    pub async fn find_session_state(&self, id: &Uuid) -> Result<Option<SessionState>, DbError> {
+        // Before the organisation is known and always the primary, a lagging replica would accept tokens that were just revoked
+        let mut conn = self.admin_conn().await?;
+        swift_user::table.find(id)
+            .select(SessionState::as_select())
+            .get_result(&mut conn)
+            .await
+            .optional()
+            .map_err(DbError::from)
+    }
+
+    #[instrument(skip_all)]
+    pub async fn find_organisation_ids_with_conn(&self, conn: &mut AsyncPgConnection, id: &Uuid) -> Result<Vec<Uuid>, DbError> {
+        swift_user_accessible_organisation::table
+            .filter(swift_user_accessible_organisation::swift_user_id.eq(id))
+            .select(swift_user_accessible_organisation::organisation_id)
+            .get_results(conn)
+            .await
+            .map_err(DbError::from)
+    }
+
+    #[instrument(skip_all)]
     pub async fn insert_user_accessible_organisation_with_conn(&self,
                                                                conn: &mut AsyncPgConnection,
                                                                swift_user_organisation: SwiftUserOrganisation) -> Result<usize, DbError> {
@@ -86,22 +169,36 @@ impl UserRepository {
             .map_err(DbError::from)
     }
 
-    pub async fn find_all_by_organisation_id(&self, organisation_id: &Uuid) -> Result<Vec<SwiftUser>, DbError> {
-        let mut conn = self.conn().await?;
+    // With a search only the matching users, ranked like /search
+    #[instrument(skip_all)]
+    pub async fn find_all_by_organisation_id(&self, organisation_id: &Uuid, search: Option<&str>) -> Result<Vec<SwiftUser>, DbError> {
+        let mut conn = self.read_conn().await?;
 
-        organisation::table
+        let mut query = organisation::table
             .inner_join(swift_user_accessible_organisation::table.on(swift_user_accessible_organisation::organisation_id.eq(organisation::id)))
             .filter(organisation::id.eq(organisation_id))
             .inner_join(swift_user::table.on(swift_user::id.eq(swift_user_accessible_organisation::swift_user_id)))
-            .select(swift_user::all_columns)
-            .get_results(&mut conn)
-            .await
-            .map_err(DbError::from)
+            .select(SwiftUser::as_select())
+            .into_boxed();
+        if let Some(search) = search {
+            query = query
+                .filter(sql::<Bool>("(swift_user.search_vector @@ prefix_tsquery(").bind::<Text, _>(search)
+                    .sql(") OR ").bind::<Text, _>(search).sql(" <% (swift_user.first_name || ' ' || coalesce(swift_user.last_name, '') || ' ' || swift_user.email))"))
+                .order(sql::<Float>("ts_rank_cd(swift_user.search_vector, prefix_tsquery(").bind::<Text, _>(search)
+                    .sql(")) + word_similarity(").bind::<Text, _>(search).sql(", swift_user.first_name || ' ' || coalesce(swift_user.last_name, '') || ' ' || swift_user.email)")
+                    .desc());
+        }
+
+        repository::scoped(&mut conn, |conn| async move {
+            query.get_results(conn)
+                .await
+                .map_err(DbError::from)
+        }.scope_boxed()).await
     }
 }
 
 impl BaseRepository for UserRepository {
-    fn pool(&self) -> &Pool<AsyncDieselConnectionManager<AsyncPgConnection>> {
-        &self.pool
+    fn pools(&self) -> &DbPools {
+        &self.pools
     }
 }
\ No newline at end of file
@@ -1,63 +1,106 @@
-use tracing::debug;
+use std::str::FromStr;
+use chrono::{NaiveDateTime, Utc};
+use diesel_async::scoped_futures::ScopedFutureExt;
+use tracing::{debug, info};
 use uuid::Uuid;
 use crate::domains::users::api_models::{UserPutRequest, UserCreateRequest};
-use crate::domains::users::db_models::{SwiftUser, SwiftUserOrganisation};
+use crate::domains::users::db_models::{DEFAULT_ROLE_ID, PutSwiftUser, SessionState, SwiftUser, SwiftUserOrganisation};
 use crate::domains::users::repository::UserRepository;
+use crate::domains::users::session_cache::SessionCache;
+use crate::config::app_env::SESSION_CACHE_TTL;
 use crate::common::errors::api_error_response::ErrorMessage;
 use crate::common::errors::application_error::ApplicationError;
 use crate::common::errors::application_error::ApplicationError::NotFound;
 use crate::common::errors::request_error::RequestError::ValidationError;
 use crate::common::errors::global_api_error::ApiError;
+use crate::common::errors::error_code;
+use crate::common::repository::{BaseRepository, TransactionOptions};
+use crate::common::i18n::locale::{self, Locale, SUPPORTED_LOCALES};
+use crate::common::models::models::ExpectedVersion;
+use crate::domains::jobs::db_models::NewJob;
+use crate::domains::jobs::repository::JobRepository;
+use crate::domains::mail::jobs::SendEmail;
+use crate::domains::mail::templates::EmailTemplate;
+use crate::domains::organisations::db_models::Organisation;
+use crate::domains::outbox::events::DomainEvent;
+use crate::domains::outbox::repository::OutboxRepository;
 
 #[derive(Clone)]
 pub struct UserService {
-    user_repository: UserRepository
+    user_repository: UserRepository,
+    outbox_repository: OutboxRepository,
+    job_repository: JobRepository,
+    session_cache: SessionCache,
 }
 
 impl UserService {
-    pub fn new(user_repository: UserRepository) -> Self {
-        UserService { user_repository }
+    pub fn new(user_repository: UserRepository, outbox_repository: OutboxRepository, job_repository: JobRepository) -> Self {
+        UserService { user_repository, outbox_repository, job_repository, session_cache: SessionCache::new(*SESSION_CACHE_TTL) }
     }
 
-    pub async fn find_all_by_organisation_id(&self, organisation_id: &Uuid) -> Result<Vec<SwiftUser>, ApplicationError> {
+    pub async fn find_all_by_organisation_id(&self, organisation_id: &Uuid, search: Option<&str>) -> Result<Vec<SwiftUser>, ApplicationError> {
         debug!("Finding all users from organisation id: {:?}", organisation_id);
 
         self.user_repository
-            .find_all_by_organisation_id(organisation_id)
+            .find_all_by_organisation_id(organisation_id, search)
             .await
             .map_err(ApplicationError::from)
     }
 
     pub async fn create(&self, user_request: UserCreateRequest) -> Result<SwiftUser, ApiError> {
-        self.create_internal(user_request, None).await
+        self.create_internal(user_request, None, false).await
     }
 
-    pub async fn create_with_organisation_id(&self, user_request: UserCreateRequest, organisation_id: Uuid) -> Result<SwiftUser, ApiError> {
-        self.create_internal(user_request, Some(organisation_id)).await
+    // The user is emailed an invite to the organisation
+    pub async fn create_with_organisation(&self, user_request: UserCreateRequest, organisation: &Organisation) -> Result<SwiftUser, ApiError> {
+        self.create_internal(user_request, Some(organisation), false).await
+    }
+
+    pub async fn create_super_admin(&self, user_request: UserCreateRequest) -> Result<SwiftUser, ApiError> {
+        self.create_internal(user_request, None, true).await
     }
     
-    async fn create_internal(&self, user_request: UserCreateRequest, organisation_id: Option<Uuid>) -> Result<SwiftUser, ApiError> {
+    async fn create_internal(&self, user_request: UserCreateRequest, organisation: Option<&Organisation>, is_super_admin: bool) -> Result<SwiftUser, ApiError> {
         if !validator::ValidateEmail::validate_email(&user_request.email) {
-            return Err(ValidationError(vec![ErrorMessage { field: Some("email".to_string()), message: "Email address is not valid".to_string() }]).into())
+            return Err(ValidationError(vec![ErrorMessage::new(Some("email"), error_code::VALIDATION_EMAIL_INVALID)]).into())
         }
+        let mut user_request = user_request;
+        user_request.locale = validate_locale(user_request.locale)?;
 
-        let user: SwiftUser = user_request.into();
+        let mut user: SwiftUser = user_request.into();
+        user.is_super_admin = is_super_admin;
 
         debug!("Creating user: {:?}", user);
-        self.user_repository
-            .insert(&user)
-            .await
-            .map_err(ApplicationError::from)?;
+        let user_ref = &user;
+        // Users aren't owned by one organisation, so they are created as the admin role
+        self.user_repository.in_admin_transaction(TransactionOptions::default(), |mut uow| async move {
+            self.user_repository
+                .insert_with_conn(uow.conn(), user_ref)
+                .await?;
+            self.outbox_repository.insert_with_conn(uow.conn(), &DomainEvent::UserCreated { user_id: user_ref.id, email: user_ref.email.clone() }).await?;
 
-        if let Some(organisation_id) = organisation_id {
-            self.user_repository.insert_user_accessible_organisation(
-                SwiftUserOrganisation {
-                    swift_user_id: user.id,
-                    organisation_id,
-                    role_id: 2, // todo make user default constant
-                }
-            ).await.map_err(ApplicationError::from)?;
-        }
+            if let Some(organisation) = organisation {
+                self.user_repository.insert_user_accessible_organisation_with_conn(
+                    uow.conn(),
+                    SwiftUserOrganisation {
+                        swift_user_id: user_ref.id,
+                        organisation_id: organisation.id,
+                        role_id: DEFAULT_ROLE_ID,
+                    }
+                ).await?;
+                self.outbox_repository.insert_with_conn(uow.conn(), &DomainEvent::MemberAdded { organisation_id: organisation.id, user_id: user_ref.id, role_id: DEFAULT_ROLE_ID }).await?;
+
+                let invite = SendEmail {
+                    to: user_ref.email.clone(),
+                    template: EmailTemplate::Invite,
+                    locale: email_locale(user_ref),
+                    variables: serde_json::json!({ "first_name": user_ref.first_name, "organisation": organisation.name }),
+                };
+                self.job_repository.insert_with_conn(uow.conn(), &NewJob::new(&invite, Utc::now().naive_utc())).await?;
+            }
+
+            Ok(())
+        }.scope_boxed()).await.map_err(ApplicationError::from)?;
 
         debug!("User successfully created");
         Ok(user)
@@ -85,7 +128,7 @@ impl UserService {
     pub async fn find_by_email(&self, email: &String) -> Result<SwiftUser, ApiError> {
         debug!("Finding user by email: {:?}", email);
         if !validator::ValidateEmail::validate_email(&email) {
-            return Err(ValidationError(vec![ErrorMessage { field: Some("email".to_string()), message: "Email address is not valid".to_string() }]).into());
+            return Err(ValidationError(vec![ErrorMessage::new(Some("email"), error_code::VALIDATION_EMAIL_INVALID)]).into());
         }
 
         let user_option = self.user_repository
@@ -105,15 +148,42 @@ impl UserService {
         }
     }
 
-    pub async fn update_by_id(&self, id: Uuid, user_request: UserPutRequest) -> Result<(), ApplicationError> {
-        debug!("Updating user by id: {:?}", id);
-
-        let row_updated = self.user_repository
-            .update_by_id(&id, user_request.into())
+    pub async fn is_super_admin(&self, id: &Uuid) -> Result<bool, ApplicationError> {
+        let is_super_admin = self.user_repository
+            .find_is_super_admin(id)
             .await
             .map_err(ApplicationError::from)?;
 
-        debug!("Rows updated {:?}", row_updated);
+        Ok(is_super_admin.unwrap_or(false))
+    }
+
+    // The user is emailed that their password changed
+    pub async fn reset_password(&self, email: &str, password_hash: &str) -> Result<(), ApplicationError> {
+        info!("Resetting password for user: {:?}", email);
+        let user = self.user_repository
+            .find_by_email(&email.to_string())
+            .await
+            .map_err(ApplicationError::from)?
+            .ok_or(NotFound)?;
+        let notice = &SendEmail {
+            to: user.email.clone(),
+            template: EmailTemplate::PasswordChanged,
+            locale: email_locale(&user),
+            variables: serde_json::json!({ "first_name": user.first_name }),
+        };
+
+        let row_updated = self.user_repository.in_admin_transaction(TransactionOptions::default(), |mut uow| async move {
+            let row_updated = self.user_repository
+                .update_password_by_email_with_conn(uow.conn(), email, password_hash)
+                .await?;
+
+            if row_updated > 0 {
+                self.job_repository.insert_with_conn(uow.conn(), &NewJob::new(notice, Utc::now().naive_utc())).await?;
+            }
+            Ok(row_updated)
+        }.scope_boxed()).await.map_err(ApplicationError::from)?;
+        self.session_cache.invalidate(&user.id);
+
         if row_updated == 0 {
             Err(NotFound)
         } else {
@@ -121,18 +191,121 @@ impl UserService {
         }
     }
 
-    pub async fn delete_by_id(&self, id: Uuid) -> Result<(), ApplicationError> {
+    pub async fn revoke_sessions(&self, email: &str) -> Result<(), ApplicationError> {
+        info!("Revoking sessions for user: {:?}", email);
+        let user_id = self.user_repository
+            .revoke_sessions_by_email(email)
+            .await
+            .map_err(ApplicationError::from)?
+            .ok_or(NotFound)?;
+
+        self.session_cache.invalidate(&user_id);
+        Ok(())
+    }
+
+    /*
+        Current state of the session of a token issued at `issued_at` (seconds since epoch),
+        None once the user is deleted or their sessions were revoked afterwards.
+        The state is cached for SESSION_CACHE_TTL, the token is checked against it every time
+    */
+    pub async fn find_active_session(&self, id: &Uuid, issued_at: i64) -> Result<Option<SessionState>, ApplicationError> {
+        let session = match self.session_cache.get(id) {
+            Some(session) => Some(session),
+            None => {
+                let session = self.user_repository
+                    .find_session_state(id)
+                    .await
+                    .map_err(ApplicationError::from)?;
+                if let Some(session) = &session {
+                    self.session_cache.insert(*id, session.clone());
+                }
+                session
+            },
+        };
+
+        Ok(session.filter(|session| match session.sessions_revoked_at {
+            None => true,
+            Some(revoked_at) => issued_at > revoked_at.and_utc().timestamp(),
+        }))
+    }
+
+    // Conditional on the expected version, see ExpectedVersion::apply
+    pub async fn update_by_id(&self, id: Uuid, expected_version: ExpectedVersion, mut user_request: UserPutRequest) -> Result<NaiveDateTime, ApiError> {
+        debug!("Updating user by id: {:?}", id);
+        user_request.locale = validate_locale(user_request.locale)?;
+        let user: &PutSwiftUser = &user_request.into();
+
+        Ok(expected_version.apply(|| self.current_version(&id), |versions| async move {
+            let versions = &versions;
+            let updated_at = self.user_repository.in_transaction(TransactionOptions::default(), |mut uow| async move {
+                let updated_at = self.user_repository
+                    .update_by_id_with_conn(uow.conn(), &id, versions, user)
+                    .await?;
+
+                if updated_at.is_some() {
+                    self.outbox_repository.insert_with_conn(uow.conn(), &DomainEvent::UserUpdated { user_id: id }).await?;
+                }
+                Ok(updated_at)
+            }.scope_boxed()).await?;
+            self.session_cache.invalidate(&id);
+            Ok(updated_at)
+        }).await?)
+    }
+
+    // Conditional on the expected version, see ExpectedVersion::apply
+    pub async fn delete_by_id(&self, id: Uuid, expected_version: ExpectedVersion) -> Result<(), ApplicationError> {
         debug!("Deleting user by id: {:?}", id);
 
-        let row_updated = self.user_repository
-            .delete_by_id(id)
-            .await
-            .map_err(ApplicationError::from)?;
+        expected_version.apply(|| self.current_version(&id), |versions| async move {
+            let versions = &versions;
+            // The memberships go with the user, each organisation is told it lost a member
+            let row_updated = self.user_repository.in_transaction(TransactionOptions::default(), |mut uow| async move {
+                let organisation_ids = self.user_repository
+                    .find_organisation_ids_with_conn(uow.conn(), &id)
+                    .await?;
+                let row_updated = self.user_repository
+                    .delete_by_id_with_conn(uow.conn(), &id, versions)
+                    .await?;
 
-        if row_updated == 0 {
-            Err(NotFound)
-        } else {
-            Ok(())
-        }
+                if row_updated > 0 {
+                    for organisation_id in organisation_ids {
+                        self.outbox_repository.insert_with_conn(uow.conn(), &DomainEvent::MemberRemoved { organisation_id, user_id: id }).await?;
+                    }
+                    self.outbox_repository.insert_with_conn(uow.conn(), &DomainEvent::UserDeleted { user_id: id }).await?;
+                }
+                Ok(row_updated)
+            }.scope_boxed()).await?;
+            self.session_cache.invalidate(&id);
+            Ok((row_updated > 0).then_some(()))
+        }).await
+    }
+
+    async fn current_version(&self, id: &Uuid) -> Result<NaiveDateTime, ApplicationError> {
+        self.user_repository
+            .find_version(id)
+            .await?
+            .ok_or(NotFound)
     }
-}
\ No newline at end of file
+}
+
+// Their preferred locale, otherwise the one of the request that caused the email
+fn email_locale(user: &SwiftUser) -> Locale {
+    user.locale.as_deref()
+        .and_then(|preferred| Locale::from_str(preferred).ok())
+        .unwrap_or_else(locale::current)
+}
+
+/*
+    Normalises a requested locale preference to its lowercase tag, rejecting unsupported ones
+*/
+fn validate_locale(locale: Option<String>) -> Result<Option<String>, ApiError> {
+    match locale {
+        Some(locale) => Locale::from_str(&locale)
+            .map(|locale| Some(locale.to_string()))
+            .map_err(|_| {
+                let supported = SUPPORTED_LOCALES.map(|locale| locale.as_str()).join(", ");
+                ValidationError(vec![ErrorMessage::with_args(Some("locale"), error_code::VALIDATION_LOCALE_UNSUPPORTED, &[("locales", &supported)])]).into()
+            }),
+        None => Ok(None),
+    }
+}
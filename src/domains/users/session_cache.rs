@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+use crate::domains::users::db_models::SessionState;
+
+// Beyond this many users expired entries are dropped, and everything if none expired yet
+const MAX_ENTRIES: usize = 10_000;
+
+/*
+    Session state of recently authenticated users, so a user making many requests is read once per TTL.
+    Changes made through this instance invalidate their entry right away
+*/
+#[derive(Clone)]
+pub struct SessionCache {
+    ttl: Duration,
+    entries: Arc<Mutex<HashMap<Uuid, (Instant, SessionState)>>>,
+}
+
+impl SessionCache {
+    pub fn new(ttl: Duration) -> Self {
+        SessionCache { ttl, entries: Arc::default() }
+    }
+
+    pub fn get(&self, user_id: &Uuid) -> Option<SessionState> {
+        let entries = self.entries.lock().unwrap();
+        entries.get(user_id)
+            .filter(|(cached_at, _)| cached_at.elapsed() < self.ttl)
+            .map(|(_, session)| session.clone())
+    }
+
+    pub fn insert(&self, user_id: Uuid, session: SessionState) {
+        if self.ttl.is_zero() {
+            return;
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= MAX_ENTRIES {
+            entries.retain(|_, (cached_at, _)| cached_at.elapsed() < self.ttl);
+            if entries.len() >= MAX_ENTRIES {
+                entries.clear();
+            }
+        }
+        entries.insert(user_id, (Instant::now(), session));
+    }
+
+    pub fn invalidate(&self, user_id: &Uuid) {
+        self.entries.lock().unwrap().remove(user_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session() -> SessionState {
+        SessionState { sessions_revoked_at: None, locale: Some("de".to_string()) }
+    }
+
+    #[test]
+    fn entries_expire_after_the_ttl() {
+        let cache = SessionCache::new(Duration::from_millis(20));
+        let user_id = Uuid::now_v7();
+        cache.insert(user_id, session());
+        assert_eq!(cache.get(&user_id).unwrap().locale.as_deref(), Some("de"));
+
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(cache.get(&user_id).is_none());
+    }
+
+    #[test]
+    fn invalidated_entries_are_read_again() {
+        let cache = SessionCache::new(Duration::from_secs(60));
+        let user_id = Uuid::now_v7();
+        cache.insert(user_id, session());
+        cache.invalidate(&user_id);
+        assert!(cache.get(&user_id).is_none());
+    }
+
+    #[test]
+    fn a_zero_ttl_caches_nothing() {
+        let cache = SessionCache::new(Duration::ZERO);
+        let user_id = Uuid::now_v7();
+        cache.insert(user_id, session());
+        assert!(cache.get(&user_id).is_none());
+        assert!(cache.entries.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn stays_bounded() {
+        let cache = SessionCache::new(Duration::from_secs(60));
+        for _ in 0..MAX_ENTRIES + 1 {
+            cache.insert(Uuid::now_v7(), session());
+        }
+        assert!(cache.entries.lock().unwrap().len() <= MAX_ENTRIES);
+    }
+}
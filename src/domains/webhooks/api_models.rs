@@ -0,0 +1,157 @@
+use std::fmt;
+use chrono::{NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+use crate::common::utils::constants::REDACTED;
+use crate::domains::webhooks::db_models::{PutWebhookEndpoint, WebhookDelivery, WebhookEndpoint, DELIVERY_PENDING};
+
+#[derive(Deserialize, ToSchema)]
+pub struct WebhookCreateRequest {
+    pub url: String,
+    pub event_types: Vec<String>,
+    // Generated when left out, at least 16 characters otherwise
+    pub secret: Option<String>,
+    pub is_enabled: Option<bool>,
+}
+
+impl fmt::Debug for WebhookCreateRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WebhookCreateRequest")
+            .field("url", &self.url)
+            .field("event_types", &self.event_types)
+            .field("secret", &self.secret.as_ref().map(|_| REDACTED))
+            .field("is_enabled", &self.is_enabled)
+            .finish()
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct WebhookPutRequest {
+    pub url: String,
+    pub event_types: Vec<String>,
+    // Rotates the secret, the current one is kept when left out
+    pub secret: Option<String>,
+    pub is_enabled: bool,
+}
+
+impl fmt::Debug for WebhookPutRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WebhookPutRequest")
+            .field("url", &self.url)
+            .field("event_types", &self.event_types)
+            .field("secret", &self.secret.as_ref().map(|_| REDACTED))
+            .field("is_enabled", &self.is_enabled)
+            .finish()
+    }
+}
+
+impl WebhookCreateRequest {
+    pub fn into_with_organisation(self, organisation_id: Uuid, secret: String) -> WebhookEndpoint {
+        let now = Utc::now().naive_utc();
+        WebhookEndpoint {
+            id: Uuid::now_v7(),
+            organisation_id,
+            url: self.url,
+            secret,
+            event_types: self.event_types,
+            is_enabled: self.is_enabled.unwrap_or(true),
+            created_at: now,
+            updated_at: now,
+        }
+    }
+}
+
+impl From<WebhookPutRequest> for PutWebhookEndpoint {
+    fn from(request: WebhookPutRequest) -> Self {
+        PutWebhookEndpoint {
+            url: request.url,
+            secret: request.secret,
+            event_types: request.event_types,
+            is_enabled: request.is_enabled,
+            updated_at: Utc::now().naive_utc(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct WebhookResponse {
+    pub id: Uuid,
+    pub url: String,
+    pub event_types: Vec<String>,
+    pub is_enabled: bool,
+    // Only returned when the webhook is created
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+impl From<WebhookEndpoint> for WebhookResponse {
+    fn from(endpoint: WebhookEndpoint) -> Self {
+        WebhookResponse {
+            id: endpoint.id,
+            url: endpoint.url,
+            event_types: endpoint.event_types,
+            is_enabled: endpoint.is_enabled,
+            secret: None,
+            created_at: endpoint.created_at,
+        }
+    }
+}
+
+impl WebhookResponse {
+    pub fn with_secret(endpoint: WebhookEndpoint) -> Self {
+        let secret = endpoint.secret.clone();
+        WebhookResponse { secret: Some(secret), ..endpoint.into() }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct WebhooksResponse {
+    pub data: Vec<WebhookResponse>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct WebhookDeliveryResponse {
+    pub id: Uuid,
+    // Same for every delivery of the event, sent as Webhook-Id
+    pub event_id: Uuid,
+    pub event_type: String,
+    #[schema(value_type = Object)]
+    pub payload: serde_json::Value,
+    // pending, delivered or dead
+    pub status: String,
+    pub attempts: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_attempt_at: Option<NaiveDateTime>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_attempt_at: Option<NaiveDateTime>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_status: Option<i16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+impl From<WebhookDelivery> for WebhookDeliveryResponse {
+    fn from(delivery: WebhookDelivery) -> Self {
+        WebhookDeliveryResponse {
+            id: delivery.id,
+            event_id: delivery.event_id,
+            event_type: delivery.event_type,
+            payload: delivery.payload,
+            next_attempt_at: (delivery.status == DELIVERY_PENDING).then_some(delivery.next_attempt_at),
+            status: delivery.status,
+            attempts: delivery.attempts,
+            last_attempt_at: delivery.last_attempt_at,
+            response_status: delivery.response_status,
+            last_error: delivery.last_error,
+            created_at: delivery.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct WebhookDeliveriesResponse {
+    pub data: Vec<WebhookDeliveryResponse>,
+}
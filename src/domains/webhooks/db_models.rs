@@ -0,0 +1,82 @@
+use chrono::NaiveDateTime;
+use diesel::{AsChangeset, Insertable, Queryable, QueryableByName, Selectable};
+use diesel::sql_types::{Integer, Jsonb, Text, Uuid as SqlUuid};
+use uuid::Uuid;
+
+use crate::common::schema;
+
+pub const DELIVERY_PENDING: &str = "pending";
+pub const DELIVERY_DELIVERED: &str = "delivered";
+// Failed on every attempt, only a manual redelivery sends the event again
+pub const DELIVERY_DEAD: &str = "dead";
+
+// No Debug, the secret must not end up in logs
+#[derive(Insertable, Queryable, Selectable)]
+#[diesel(table_name = schema::webhook_endpoint)]
+pub struct WebhookEndpoint {
+    pub id: Uuid,
+    pub organisation_id: Uuid,
+    pub url: String,
+    pub secret: String,
+    pub event_types: Vec<String>,
+    pub is_enabled: bool,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+// Without a secret the current one is kept
+#[derive(AsChangeset)]
+#[diesel(table_name = schema::webhook_endpoint)]
+pub struct PutWebhookEndpoint {
+    pub url: String,
+    pub secret: Option<String>,
+    pub event_types: Vec<String>,
+    pub is_enabled: bool,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = schema::webhook_delivery)]
+pub struct NewWebhookDelivery {
+    pub id: Uuid,
+    pub webhook_endpoint_id: Uuid,
+    pub organisation_id: Uuid,
+    pub event_id: Uuid,
+    pub event_type: String,
+    pub payload: serde_json::Value,
+}
+
+#[derive(Queryable, Selectable, Debug)]
+#[diesel(table_name = schema::webhook_delivery)]
+pub struct WebhookDelivery {
+    pub id: Uuid,
+    pub event_id: Uuid,
+    pub event_type: String,
+    pub payload: serde_json::Value,
+    pub status: String,
+    pub attempts: i32,
+    pub next_attempt_at: NaiveDateTime,
+    pub last_attempt_at: Option<NaiveDateTime>,
+    pub response_status: Option<i16>,
+    pub last_error: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+/*
+    A due delivery taken by a worker, with what it needs to send it. `attempts` includes this one
+*/
+#[derive(QueryableByName)]
+pub struct ClaimedDelivery {
+    #[diesel(sql_type = SqlUuid)]
+    pub id: Uuid,
+    #[diesel(sql_type = SqlUuid)]
+    pub event_id: Uuid,
+    #[diesel(sql_type = Jsonb)]
+    pub payload: serde_json::Value,
+    #[diesel(sql_type = Integer)]
+    pub attempts: i32,
+    #[diesel(sql_type = Text)]
+    pub url: String,
+    #[diesel(sql_type = Text)]
+    pub secret: String,
+}
@@ -0,0 +1,319 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use chrono::Utc;
+use hmac::{Hmac, KeyInit, Mac};
+use http::header::CONTENT_TYPE;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::Url;
+use sha2::Sha256;
+use tracing::debug;
+use uuid::Uuid;
+use crate::common::utils::constants::{WEBHOOK_ID_HEADER, WEBHOOK_SIGNATURE_HEADER};
+
+/*
+    HTTP client for deliveries, redirects aren't followed. Unless `private_destinations` is set (local mode)
+    only https URLs of public addresses are sent to. Host names are checked once resolved, on every delivery,
+    so a name pointed at an internal address after the endpoint was saved is refused too
+*/
+#[derive(Clone)]
+pub struct WebhookClient {
+    client: reqwest::Client,
+    private_destinations: bool,
+}
+
+impl WebhookClient {
+    pub fn new(timeout: Duration, private_destinations: bool) -> Self {
+        let mut builder = reqwest::Client::builder()
+            .timeout(timeout)
+            .redirect(reqwest::redirect::Policy::none())
+            .user_agent(concat!("swift-webhooks/", env!("CARGO_PKG_VERSION")));
+        if !private_destinations {
+            builder = builder.dns_resolver(Arc::new(PublicResolver));
+        }
+        let client = builder.build().expect("Unable to build the webhook HTTP client");
+        WebhookClient { client, private_destinations }
+    }
+
+    /*
+        Whether the URL may be sent to before resolving it, its scheme and an address in place of the host.
+        The error describes why not
+    */
+    pub fn check_destination(&self, url: &Url) -> Result<(), String> {
+        if self.private_destinations {
+            return Ok(());
+        }
+        if url.scheme() != "https" {
+            return Err("Only https endpoints are sent to".to_string());
+        }
+        // IPv6 hosts are in brackets, names are checked once resolved
+        let ip = url.host_str()
+            .map(|host| host.trim_start_matches('[').trim_end_matches(']'))
+            .and_then(|host| host.parse::<IpAddr>().ok());
+        match ip {
+            Some(ip) if !is_public(ip) => Err(format!("{} is not a public address", ip)),
+            _ => Ok(()),
+        }
+    }
+}
+
+// Resolves host names to their public addresses only, failing when there is none
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((name.as_str(), 0))
+                .await?
+                .filter(|addr| is_public(addr.ip()))
+                .collect();
+            if addrs.is_empty() {
+                return Err(format!("{} doesn't resolve to a public address", name.as_str()).into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/*
+    False for the addresses of this host and its networks: loopback, private, link-local (including the
+    169.254.169.254 cloud metadata endpoint), carrier-grade NAT, benchmarking, reserved, unspecified,
+    broadcast and multicast ones. IPv6 addresses embedding an IPv4 one are judged by that one
+*/
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [first, second, ..] = ip.octets();
+            !(ip.is_unspecified() || ip.is_loopback() || ip.is_private() || ip.is_link_local()
+                || ip.is_broadcast() || ip.is_multicast() || ip.is_documentation()
+                || first == 0 || (first == 100 && second & 0xc0 == 64)
+                // Benchmarking 198.18.0.0/15 and reserved 240.0.0.0/4
+                || (first == 198 && second & 0xfe == 18) || first >= 240)
+        },
+        IpAddr::V6(ip) => match embedded_ipv4(ip) {
+            Some(ip) => is_public(IpAddr::V4(ip)),
+            // Unique local fc00::/7 and link-local fe80::/10
+            None => !(ip.is_unspecified() || ip.is_loopback() || ip.is_multicast()
+                || ip.segments()[0] & 0xfe00 == 0xfc00 || ip.segments()[0] & 0xffc0 == 0xfe80),
+        },
+    }
+}
+
+/*
+    IPv4 address behind IPv4-mapped ::ffff:a.b.c.d, IPv4-compatible ::a.b.c.d, NAT64 64:ff9b::a.b.c.d
+    and 6to4 2002:aabb:ccdd:: addresses
+*/
+fn embedded_ipv4(ip: Ipv6Addr) -> Option<Ipv4Addr> {
+    let ipv4 = |high: u16, low: u16| Some(Ipv4Addr::from(u32::from(high) << 16 | u32::from(low)));
+    match ip.segments() {
+        [0, 0, 0, 0, 0, 0xffff, high, low] | [0, 0, 0, 0, 0, 0, high, low] | [0x64, 0xff9b, 0, 0, 0, 0, high, low] => ipv4(high, low),
+        [0x2002, high, low, ..] => ipv4(high, low),
+        _ => None,
+    }
+}
+
+#[derive(Debug)]
+pub struct DeliveryFailure {
+    // None when no response arrived (connection refused, timeout, ...)
+    pub response_status: Option<u16>,
+    pub error: String,
+}
+
+/*
+    Posts the payload to the endpoint, any 2xx response counts as delivered. Redirects aren't followed.
+    The Webhook-Id header carries the event id so receivers can drop events they already handled
+*/
+pub async fn send(client: &WebhookClient, url: &str, secret: &str, event_id: &Uuid, body: Vec<u8>) -> Result<u16, DeliveryFailure> {
+    let url = Url::parse(url)
+        .map_err(|e| e.to_string())
+        .and_then(|url| client.check_destination(&url).map(|_| url))
+        .map_err(|error| DeliveryFailure { response_status: None, error })?;
+
+    let timestamp = Utc::now().timestamp();
+    let response = client.client.post(url.clone())
+        .header(CONTENT_TYPE, "application/json")
+        .header(WEBHOOK_ID_HEADER.clone(), event_id.to_string())
+        .header(WEBHOOK_SIGNATURE_HEADER.clone(), signature(secret, timestamp, &body))
+        .body(body)
+        .send()
+        .await
+        .map_err(|e| {
+            debug!("Webhook request to {:?} failed: {:?}", url, e);
+            DeliveryFailure { response_status: None, error: describe(e.without_url()) }
+        })?;
+
+    let status = response.status();
+    if status.is_success() {
+        Ok(status.as_u16())
+    } else {
+        Err(DeliveryFailure { response_status: Some(status.as_u16()), error: format!("Endpoint responded with {}", status) })
+    }
+}
+
+// reqwest only names the outermost error, the cause (refused, timed out, ...) is further down
+fn describe(error: reqwest::Error) -> String {
+    let mut description = error.to_string();
+    let mut source = std::error::Error::source(&error);
+    while let Some(cause) = source {
+        description.push_str(": ");
+        description.push_str(&cause.to_string());
+        source = cause.source();
+    }
+    description
+}
+
+/*
+    Webhook-Signature value, `t=<unix seconds>,v1=<hex HMAC-SHA256 of "<t>.<body>" keyed with the secret>`.
+    Receivers recompute it and reject old timestamps so a captured request can't be replayed
+*/
+pub fn signature(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    let digest: String = mac.finalize().into_bytes().iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("t={},v1={}", timestamp, digest)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Bytes;
+    use axum::extract::State;
+    use axum::routing::post;
+    use axum::Router;
+    use http::{HeaderMap, StatusCode};
+    use tokio::sync::mpsc;
+    use super::*;
+
+    const SECRET: &str = "whsec_test";
+
+    struct Receiver {
+        url: String,
+        requests: mpsc::UnboundedReceiver<(HeaderMap, Bytes)>,
+    }
+
+    // Local endpoint answering every request with `status` after `delay`, requests are handed to the test
+    async fn receiver(status: StatusCode, delay: Duration) -> Receiver {
+        let (sender, requests) = mpsc::unbounded_channel();
+        let app = Router::new()
+            .route("/hook", post(move |State(sender): State<mpsc::UnboundedSender<(HeaderMap, Bytes)>>, headers: HeaderMap, body: Bytes| async move {
+                let _ = sender.send((headers, body));
+                tokio::time::sleep(delay).await;
+                status
+            }))
+            .with_state(sender);
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        Receiver { url, requests }
+    }
+
+    fn client() -> WebhookClient {
+        WebhookClient::new(Duration::from_millis(500), true)
+    }
+
+    fn public_client() -> WebhookClient {
+        WebhookClient::new(Duration::from_millis(500), false)
+    }
+
+    #[test]
+    fn signature_matches_known_vector() {
+        assert_eq!(
+            signature(SECRET, 1_700_000_000, br#"{"id":1}"#),
+            "t=1700000000,v1=2f441ba4b3b2d50d28a9ab9d9fd8880376ecd1eb5d0435401553f5d8d0a5dcf8");
+    }
+
+    #[tokio::test]
+    async fn receiver_gets_a_verifiable_signed_payload() {
+        let mut receiver = receiver(StatusCode::NO_CONTENT, Duration::ZERO).await;
+        let event_id = Uuid::now_v7();
+        let body = br#"{"type":"application.created"}"#.to_vec();
+
+        let status = send(&client(), &receiver.url, SECRET, &event_id, body.clone()).await.unwrap();
+        assert_eq!(status, 204);
+
+        let (headers, received) = receiver.requests.recv().await.unwrap();
+        assert_eq!(received, body);
+        assert_eq!(headers[CONTENT_TYPE], "application/json");
+        assert_eq!(headers[&WEBHOOK_ID_HEADER], event_id.to_string().as_str());
+
+        let signature_header = headers[&WEBHOOK_SIGNATURE_HEADER].to_str().unwrap();
+        let timestamp: i64 = signature_header.strip_prefix("t=").unwrap().split(',').next().unwrap().parse().unwrap();
+        assert!((Utc::now().timestamp() - timestamp).abs() <= 5);
+        assert_eq!(signature_header, signature(SECRET, timestamp, &received));
+        assert_ne!(signature_header, signature("another secret", timestamp, &received));
+    }
+
+    #[tokio::test]
+    async fn error_responses_are_failures() {
+        let receiver = receiver(StatusCode::SERVICE_UNAVAILABLE, Duration::ZERO).await;
+
+        let failure = send(&client(), &receiver.url, SECRET, &Uuid::now_v7(), b"{}".to_vec()).await.unwrap_err();
+        assert_eq!(failure.response_status, Some(503));
+    }
+
+    #[tokio::test]
+    async fn slow_endpoints_time_out() {
+        let receiver = receiver(StatusCode::OK, Duration::from_secs(5)).await;
+
+        let failure = send(&client(), &receiver.url, SECRET, &Uuid::now_v7(), b"{}".to_vec()).await.unwrap_err();
+        assert_eq!(failure.response_status, None);
+    }
+
+    #[tokio::test]
+    async fn unreachable_endpoints_are_failures() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        drop(listener);
+
+        let failure = send(&client(), &url, SECRET, &Uuid::now_v7(), b"{}".to_vec()).await.unwrap_err();
+        assert_eq!(failure.response_status, None);
+    }
+
+    #[test]
+    fn internal_addresses_are_not_public() {
+        for ip in ["127.0.0.1", "10.1.2.3", "172.16.0.1", "192.168.1.1", "169.254.169.254", "100.64.0.1", "0.0.0.0",
+                   "255.255.255.255", "240.0.0.1", "198.18.0.1", "198.19.255.255", "::1", "::", "fc00::1", "fe80::1",
+                   "::ffff:127.0.0.1", "::ffff:169.254.169.254", "64:ff9b::127.0.0.1", "64:ff9b::10.0.0.1",
+                   "2002:7f00:1::", "2002:a9fe:a9fe::1", "::127.0.0.1", "::192.168.1.1"] {
+            assert!(!is_public(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in ["93.184.216.34", "1.1.1.1", "100.128.0.1", "198.20.0.1", "2606:4700::1111", "::ffff:1.1.1.1",
+                   "64:ff9b::1.1.1.1", "2002:101:101::1", "::1.1.1.1"] {
+            assert!(is_public(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[tokio::test]
+    async fn internal_destinations_are_refused_outside_local_mode() {
+        let mut receiver = receiver(StatusCode::NO_CONTENT, Duration::ZERO).await;
+        let port = Url::parse(&receiver.url).unwrap().port().unwrap();
+
+        for url in [
+            receiver.url.clone(),
+            format!("https://127.0.0.1:{}/hook", port),
+            format!("https://[::ffff:127.0.0.1]:{}/hook", port),
+            // Resolves to loopback
+            format!("https://localhost:{}/hook", port),
+        ] {
+            let failure = send(&public_client(), &url, SECRET, &Uuid::now_v7(), b"{}".to_vec()).await.unwrap_err();
+            assert_eq!(failure.response_status, None, "{}", url);
+        }
+        assert!(receiver.requests.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn redirects_are_not_followed() {
+        let mut target = receiver(StatusCode::NO_CONTENT, Duration::ZERO).await;
+        let location = target.url.clone();
+        let app = Router::new().route("/hook", post(move || async move { (StatusCode::FOUND, [(http::header::LOCATION, location)]) }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let failure = send(&client(), &url, SECRET, &Uuid::now_v7(), b"{}".to_vec()).await.unwrap_err();
+        assert_eq!(failure.response_status, Some(302));
+        assert!(target.requests.try_recv().is_err());
+    }
+}
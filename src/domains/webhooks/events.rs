@@ -0,0 +1,71 @@
+use chrono::NaiveDateTime;
+use serde_json::json;
+use uuid::Uuid;
+use crate::domains::outbox::events::{DomainEvent, OutboxEvent};
+
+pub const USER_JOINED: &str = "user.joined";
+pub const APPLICATION_CREATED: &str = "application.created";
+pub const APPLICATION_UPDATED: &str = "application.updated";
+pub const APPLICATION_DELETED: &str = "application.deleted";
+pub const ORGANISATION_UPDATED: &str = "organisation.updated";
+pub const ORGANISATION_ARCHIVED: &str = "organisation.archived";
+
+// Event types an endpoint can subscribe to, clients rely on these so never rename one
+pub const EVENT_TYPES: [&str; 6] = [
+    USER_JOINED,
+    APPLICATION_CREATED,
+    APPLICATION_UPDATED,
+    APPLICATION_DELETED,
+    ORGANISATION_UPDATED,
+    ORGANISATION_ARCHIVED,
+];
+
+/*
+    Something that happened in an organisation. `data` identifies what changed,
+    receivers fetch the resource when they need its current state
+*/
+#[derive(Debug)]
+pub struct WebhookEvent {
+    pub id: Uuid,
+    pub event_type: &'static str,
+    pub organisation_id: Uuid,
+    pub data: serde_json::Value,
+    pub created_at: NaiveDateTime,
+}
+
+impl WebhookEvent {
+    /*
+        The webhook event for a domain event, None when endpoints can't subscribe to it.
+        It keeps the id of the domain event, a dispatch repeated by the outbox queues nothing twice
+    */
+    pub fn from_outbox(outbox_event: &OutboxEvent) -> Option<Self> {
+        let (event_type, organisation_id, data) = match &outbox_event.event {
+            DomainEvent::MemberAdded { organisation_id, user_id, .. } => (USER_JOINED, organisation_id, json!({ "id": user_id })),
+            DomainEvent::ApplicationCreated { organisation_id, application_id, name } => (APPLICATION_CREATED, organisation_id, json!({ "id": application_id, "name": name })),
+            DomainEvent::ApplicationUpdated { organisation_id, application_id } => (APPLICATION_UPDATED, organisation_id, json!({ "id": application_id })),
+            DomainEvent::ApplicationDeleted { organisation_id, application_id } => (APPLICATION_DELETED, organisation_id, json!({ "id": application_id })),
+            DomainEvent::OrganisationUpdated { organisation_id } => (ORGANISATION_UPDATED, organisation_id, json!({ "id": organisation_id })),
+            DomainEvent::OrganisationArchived { organisation_id } => (ORGANISATION_ARCHIVED, organisation_id, json!({ "id": organisation_id })),
+            _ => return None,
+        };
+
+        Some(WebhookEvent {
+            id: outbox_event.id,
+            event_type,
+            organisation_id: *organisation_id,
+            data,
+            created_at: outbox_event.occurred_at,
+        })
+    }
+
+    // Body posted to the endpoints
+    pub fn payload(&self) -> serde_json::Value {
+        json!({
+            "id": self.id,
+            "type": self.event_type,
+            "organisation_id": self.organisation_id,
+            "created_at": self.created_at,
+            "data": self.data,
+        })
+    }
+}
@@ -0,0 +1,244 @@
+use axum::extract::{Path, State};
+use axum::{Extension, Json, Router};
+use axum::routing::{get, post};
+use axum::response::Response;
+use http::{HeaderMap, StatusCode};
+use uuid::Uuid;
+use crate::config::AppState;
+
+use crate::common::errors::api_error_response::ErrorResponse;
+use crate::common::errors::application_error::ApplicationError;
+use crate::common::errors::global_api_error::ApiError;
+use crate::common::extract::request::SwiftJson;
+use crate::common::models::models::{ExpectedVersion, Identity, OrganisationId, ReplayBody};
+use crate::common::security;
+use crate::common::utils::etag;
+use crate::domains::organisations::services::OrganisationService;
+use crate::domains::webhooks::api_models::{WebhookCreateRequest, WebhookDeliveriesResponse, WebhookDeliveryResponse, WebhookPutRequest, WebhookResponse, WebhooksResponse};
+use crate::domains::webhooks::db_models::{WebhookDelivery, WebhookEndpoint};
+use crate::domains::webhooks::services::WebhookService;
+use crate::domains::docs::params::{OrganisationHeader, IdempotencyKeyHeader, IfMatchHeader, IfNoneMatchHeader};
+
+/*
+    Only admins of the organisation manage its webhooks, they carry the signing secret
+*/
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/webhooks", get(fetch_webhooks).post(create_webhook))
+        .route("/webhooks/:id", get(fetch_webhook).put(update_webhook).delete(delete_webhook))
+        .route("/webhooks/:id/deliveries", get(fetch_webhook_deliveries))
+        .route("/webhooks/:id/deliveries/:delivery_id/redeliver", post(redeliver_webhook_delivery))
+        .route_layer(axum::middleware::from_fn(security::middleware::inject_organisation_id))
+}
+
+#[utoipa::path(
+    post,
+    path = "/webhooks",
+    tag = "webhooks",
+    params(OrganisationHeader, IdempotencyKeyHeader),
+    request_body = WebhookCreateRequest,
+    responses(
+        (status = 201, description = "Webhook created, the only response carrying its secret (not replayed for an Idempotency-Key)", body = WebhookResponse),
+        (status = 400, response = ErrorResponse),
+        (status = 401, response = ErrorResponse),
+        (status = 403, response = ErrorResponse),
+    ),
+)]
+async fn create_webhook(
+    State(webhook_service): State<WebhookService>,
+    State(organisation_service): State<OrganisationService>,
+    identity: Identity,
+    organisation_id: OrganisationId,
+    SwiftJson(webhook_request): SwiftJson<WebhookCreateRequest>,
+) -> Result<(StatusCode, Extension<ReplayBody>, Json<WebhookResponse>), ApiError> {
+    require_admin(&organisation_service, &identity, &organisation_id).await?;
+
+    let webhook = webhook_service.create(organisation_id.0, webhook_request)
+        .await
+        .map(WebhookResponse::with_secret)?;
+    // The secret isn't stored with the response for retries
+    let replay_body = ReplayBody::json(&WebhookResponse { secret: None, ..webhook.clone() });
+    Ok((StatusCode::CREATED, Extension(replay_body), Json(webhook)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/webhooks",
+    tag = "webhooks",
+    params(OrganisationHeader),
+    responses(
+        (status = 200, description = "Webhooks of the organisation", body = WebhooksResponse),
+        (status = 401, response = ErrorResponse),
+        (status = 403, response = ErrorResponse),
+    ),
+)]
+async fn fetch_webhooks(
+    State(webhook_service): State<WebhookService>,
+    State(organisation_service): State<OrganisationService>,
+    identity: Identity,
+    organisation_id: OrganisationId,
+) -> Result<Json<WebhooksResponse>, ApplicationError> {
+    require_admin(&organisation_service, &identity, &organisation_id).await?;
+
+    webhook_service.find_all_by_organisation_id(&organisation_id.0)
+        .await
+        .map(|endpoints| WebhooksResponse {
+            data: endpoints.into_iter().map(WebhookEndpoint::into).collect(),
+        })
+        .map(Json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/webhooks/{id}",
+    tag = "webhooks",
+    params(("id" = Uuid, Path), OrganisationHeader, IfNoneMatchHeader),
+    responses(
+        (status = 200, description = "The webhook", body = WebhookResponse, headers(("ETag" = String))),
+        (status = 304, description = "Not modified since the ETag in If-None-Match"),
+        (status = 401, response = ErrorResponse),
+        (status = 403, response = ErrorResponse),
+        (status = 404, response = ErrorResponse),
+    ),
+)]
+async fn fetch_webhook(
+    State(webhook_service): State<WebhookService>,
+    State(organisation_service): State<OrganisationService>,
+    identity: Identity,
+    organisation_id: OrganisationId,
+    Path(id): Path<Uuid>,
+    headers: HeaderMap,
+) -> Result<Response, ApplicationError> {
+    require_admin(&organisation_service, &identity, &organisation_id).await?;
+
+    webhook_service.find_by_id_and_organisation_id(&id, &organisation_id.0)
+        .await
+        .map(|endpoint| {
+            let updated_at = endpoint.updated_at;
+            etag::respond_with_version(&headers, &updated_at, Json::<WebhookResponse>(endpoint.into()))
+        })
+}
+
+#[utoipa::path(
+    put,
+    path = "/webhooks/{id}",
+    tag = "webhooks",
+    params(("id" = Uuid, Path), OrganisationHeader, IfMatchHeader),
+    request_body = WebhookPutRequest,
+    responses(
+        (status = 204, description = "Webhook updated", headers(("ETag" = String, description = "The new version"))),
+        (status = 400, response = ErrorResponse),
+        (status = 401, response = ErrorResponse),
+        (status = 403, response = ErrorResponse),
+        (status = 404, response = ErrorResponse),
+        (status = 412, response = ErrorResponse),
+        (status = 428, response = ErrorResponse),
+    ),
+)]
+async fn update_webhook(
+    State(webhook_service): State<WebhookService>,
+    State(organisation_service): State<OrganisationService>,
+    identity: Identity,
+    organisation_id: OrganisationId,
+    Path(id): Path<Uuid>,
+    expected_version: ExpectedVersion,
+    SwiftJson(webhook_request): SwiftJson<WebhookPutRequest>,
+) -> Result<Response, ApiError> {
+    require_admin(&organisation_service, &identity, &organisation_id).await?;
+
+    webhook_service.update_by_id_and_organisation_id(id, organisation_id.0, expected_version, webhook_request)
+        .await
+        .map(|updated_at| etag::updated(&updated_at))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/webhooks/{id}",
+    tag = "webhooks",
+    params(("id" = Uuid, Path), OrganisationHeader, IfMatchHeader),
+    responses(
+        (status = 204, description = "Webhook and its delivery log deleted"),
+        (status = 401, response = ErrorResponse),
+        (status = 403, response = ErrorResponse),
+        (status = 404, response = ErrorResponse),
+        (status = 412, response = ErrorResponse),
+        (status = 428, response = ErrorResponse),
+    ),
+)]
+async fn delete_webhook(
+    State(webhook_service): State<WebhookService>,
+    State(organisation_service): State<OrganisationService>,
+    identity: Identity,
+    organisation_id: OrganisationId,
+    Path(id): Path<Uuid>,
+    expected_version: ExpectedVersion,
+) -> Result<StatusCode, ApplicationError> {
+    require_admin(&organisation_service, &identity, &organisation_id).await?;
+
+    webhook_service.delete_by_id_and_organisation_id(id, organisation_id.0, expected_version)
+        .await
+        .map(|_| StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    get,
+    path = "/webhooks/{id}/deliveries",
+    tag = "webhooks",
+    params(("id" = Uuid, Path), OrganisationHeader),
+    responses(
+        (status = 200, description = "The 100 most recent deliveries, newest first", body = WebhookDeliveriesResponse),
+        (status = 401, response = ErrorResponse),
+        (status = 403, response = ErrorResponse),
+        (status = 404, response = ErrorResponse),
+    ),
+)]
+async fn fetch_webhook_deliveries(
+    State(webhook_service): State<WebhookService>,
+    State(organisation_service): State<OrganisationService>,
+    identity: Identity,
+    organisation_id: OrganisationId,
+    Path(id): Path<Uuid>,
+) -> Result<Json<WebhookDeliveriesResponse>, ApplicationError> {
+    require_admin(&organisation_service, &identity, &organisation_id).await?;
+
+    webhook_service.find_deliveries(&id, &organisation_id.0)
+        .await
+        .map(|deliveries| WebhookDeliveriesResponse {
+            data: deliveries.into_iter().map(WebhookDelivery::into).collect(),
+        })
+        .map(Json)
+}
+
+#[utoipa::path(
+    post,
+    path = "/webhooks/{id}/deliveries/{delivery_id}/redeliver",
+    tag = "webhooks",
+    params(("id" = Uuid, Path), ("delivery_id" = Uuid, Path), OrganisationHeader, IdempotencyKeyHeader),
+    responses(
+        (status = 202, description = "The event is queued again as a new delivery", body = WebhookDeliveryResponse),
+        (status = 401, response = ErrorResponse),
+        (status = 403, response = ErrorResponse),
+        (status = 404, response = ErrorResponse),
+    ),
+)]
+async fn redeliver_webhook_delivery(
+    State(webhook_service): State<WebhookService>,
+    State(organisation_service): State<OrganisationService>,
+    identity: Identity,
+    organisation_id: OrganisationId,
+    Path((id, delivery_id)): Path<(Uuid, Uuid)>,
+) -> Result<(StatusCode, Json<WebhookDeliveryResponse>), ApplicationError> {
+    require_admin(&organisation_service, &identity, &organisation_id).await?;
+
+    webhook_service.redeliver(&id, &organisation_id.0, &delivery_id)
+        .await
+        .map(WebhookDelivery::into)
+        .map(|response| (StatusCode::ACCEPTED, Json(response)))
+}
+
+async fn require_admin(organisation_service: &OrganisationService, identity: &Identity, organisation_id: &OrganisationId) -> Result<(), ApplicationError> {
+    match organisation_service.is_admin(&identity.user_id, &organisation_id.0).await? {
+        true => Ok(()),
+        false => Err(ApplicationError::Forbidden),
+    }
+}
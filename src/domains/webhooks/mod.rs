@@ -0,0 +1,7 @@
+pub mod api_models;
+pub mod db_models;
+pub mod delivery;
+pub mod events;
+pub mod handlers;
+pub mod repository;
+pub mod services;
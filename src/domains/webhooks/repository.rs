@@ -0,0 +1,267 @@
+use std::time::Duration;
+use chrono::NaiveDateTime;
+use diesel::dsl::now;
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::{BigInt, Double};
+use diesel_async::scoped_futures::ScopedFutureExt;
+use crate::common::query::RunQueryDsl;
+use tracing::instrument;
+use uuid::Uuid;
+use crate::common::errors::db_error::DbError;
+use crate::common::schema::{webhook_delivery, webhook_endpoint};
+use crate::common::repository::{self, BaseRepository};
+use crate::config::diesel_config::DbPools;
+use crate::domains::webhooks::db_models::{ClaimedDelivery, NewWebhookDelivery, PutWebhookEndpoint, WebhookDelivery, WebhookEndpoint, DELIVERY_DEAD, DELIVERY_DELIVERED};
+
+#[derive(Clone)]
+pub struct WebhookRepository {
+    pools: DbPools,
+}
+
+impl WebhookRepository {
+    pub fn new(pools: DbPools) -> Self {
+        WebhookRepository { pools }
+    }
+
+    #[instrument(skip_all)]
+    pub async fn insert(&self, endpoint: &WebhookEndpoint) -> Result<WebhookEndpoint, DbError> {
+        let mut conn = self.conn().await?;
+        repository::scoped(&mut conn, |conn| async move {
+            diesel::insert_into(webhook_endpoint::table)
+                .values(endpoint)
+                .get_result(conn)
+                .await
+                .map_err(DbError::from)
+        }.scope_boxed()).await
+    }
+
+    #[instrument(skip_all)]
+    pub async fn find_all_by_organisation_id(&self, organisation_id: &Uuid) -> Result<Vec<WebhookEndpoint>, DbError> {
+        let mut conn = self.read_conn().await?;
+        repository::scoped(&mut conn, |conn| async move {
+            webhook_endpoint::table
+                .filter(webhook_endpoint::organisation_id.eq(organisation_id))
+                .order(webhook_endpoint::created_at)
+                .get_results(conn)
+                .await
+                .map_err(DbError::from)
+        }.scope_boxed()).await
+    }
+
+    #[instrument(skip_all)]
+    pub async fn find_by_id_and_organisation_id(&self, id: &Uuid, organisation_id: &Uuid) -> Result<Option<WebhookEndpoint>, DbError> {
+        let mut conn = self.read_conn().await?;
+        repository::scoped(&mut conn, |conn| async move {
+            webhook_endpoint::table
+                .filter(webhook_endpoint::id.eq(id))
+                .filter(webhook_endpoint::organisation_id.eq(organisation_id))
+                .get_result(conn)
+                .await
+                .optional()
+                .map_err(DbError::from)
+        }.scope_boxed()).await
+    }
+
+    // Always the primary, a conditional change must compare against the current version
+    #[instrument(skip_all)]
+    pub async fn find_version(&self, id: &Uuid, organisation_id: &Uuid) -> Result<Option<NaiveDateTime>, DbError> {
+        let mut conn = self.fresh_read_conn().await?;
+        repository::scoped(&mut conn, |conn| async move {
+            webhook_endpoint::table
+                .filter(webhook_endpoint::id.eq(id))
+                .filter(webhook_endpoint::organisation_id.eq(organisation_id))
+                .select(webhook_endpoint::updated_at)
+                .get_result(conn)
+                .await
+                .optional()
+                .map_err(DbError::from)
+        }.scope_boxed()).await
+    }
+
+    #[instrument(skip_all)]
+    pub async fn update_by_id_and_organisation_id(&self,
+                                                  id: &Uuid,
+                                                  organisation_id: &Uuid,
+                                                  versions: &[NaiveDateTime],
+                                                  endpoint: PutWebhookEndpoint
+    ) -> Result<Option<NaiveDateTime>, DbError> {
+        let mut conn = self.conn().await?;
+        repository::scoped(&mut conn, |conn| async move {
+            diesel::update(webhook_endpoint::table.find(id)
+                    .filter(webhook_endpoint::organisation_id.eq(organisation_id))
+                    .filter(webhook_endpoint::updated_at.eq_any(versions)))
+                .set(&endpoint)
+                .returning(webhook_endpoint::updated_at)
+                .get_result(conn)
+                .await
+                .optional()
+                .map_err(DbError::from)
+        }.scope_boxed()).await
+    }
+
+    #[instrument(skip_all)]
+    pub async fn delete_by_id_and_organisation_id(&self, id: &Uuid, organisation_id: &Uuid, versions: &[NaiveDateTime]) -> Result<usize, DbError> {
+        let mut conn = self.conn().await?;
+        repository::scoped(&mut conn, |conn| async move {
+            diesel::delete(webhook_endpoint::table.find(id)
+                    .filter(webhook_endpoint::organisation_id.eq(organisation_id))
+                    .filter(webhook_endpoint::updated_at.eq_any(versions)))
+                .execute(conn)
+                .await
+                .map_err(DbError::from)
+        }.scope_boxed()).await
+    }
+
+    /*
+        Enabled endpoints of the organisation subscribed to the event type, without a delivery of the event yet.
+        Queueing runs outside of requests for events of any organisation, as the admin role like the delivery worker
+    */
+    #[instrument(skip_all)]
+    pub async fn find_subscribed_ids(&self, organisation_id: &Uuid, event_type: &str, event_id: &Uuid) -> Result<Vec<Uuid>, DbError> {
+        let mut conn = self.admin_conn().await?;
+        webhook_endpoint::table
+            .filter(webhook_endpoint::organisation_id.eq(organisation_id))
+            .filter(webhook_endpoint::is_enabled.eq(true))
+            .filter(webhook_endpoint::event_types.contains(vec![event_type]))
+            .filter(diesel::dsl::not(diesel::dsl::exists(webhook_delivery::table
+                .filter(webhook_delivery::webhook_endpoint_id.eq(webhook_endpoint::id))
+                .filter(webhook_delivery::event_id.eq(event_id)))))
+            .select(webhook_endpoint::id)
+            .get_results(&mut conn)
+            .await
+            .map_err(DbError::from)
+    }
+
+    #[instrument(skip_all)]
+    pub async fn insert_deliveries(&self, deliveries: &[NewWebhookDelivery]) -> Result<usize, DbError> {
+        let mut conn = self.admin_conn().await?;
+        diesel::insert_into(webhook_delivery::table)
+            .values(deliveries)
+            .execute(&mut conn)
+            .await
+            .map_err(DbError::from)
+    }
+
+    #[instrument(skip_all)]
+    pub async fn find_deliveries_by_endpoint_id(&self, endpoint_id: &Uuid, organisation_id: &Uuid, limit: i64) -> Result<Vec<WebhookDelivery>, DbError> {
+        let mut conn = self.read_conn().await?;
+        repository::scoped(&mut conn, |conn| async move {
+            webhook_delivery::table
+                .filter(webhook_delivery::webhook_endpoint_id.eq(endpoint_id))
+                .filter(webhook_delivery::organisation_id.eq(organisation_id))
+                .order(webhook_delivery::created_at.desc())
+                .limit(limit)
+                .select(WebhookDelivery::as_select())
+                .get_results(conn)
+                .await
+                .map_err(DbError::from)
+        }.scope_boxed()).await
+    }
+
+    /*
+        Queues the event of an earlier delivery again as a new delivery, the earlier one stays in the log
+    */
+    #[instrument(skip_all)]
+    pub async fn insert_redelivery(&self, id: Uuid, delivery_id: &Uuid, endpoint_id: &Uuid, organisation_id: &Uuid) -> Result<Option<WebhookDelivery>, DbError> {
+        let mut conn = self.conn().await?;
+        repository::scoped(&mut conn, |conn| async move {
+            diesel::insert_into(webhook_delivery::table)
+                .values(webhook_delivery::table
+                    .filter(webhook_delivery::id.eq(delivery_id))
+                    .filter(webhook_delivery::webhook_endpoint_id.eq(endpoint_id))
+                    .filter(webhook_delivery::organisation_id.eq(organisation_id))
+                    .select((
+                        id.into_sql::<diesel::sql_types::Uuid>(),
+                        webhook_delivery::webhook_endpoint_id,
+                        webhook_delivery::organisation_id,
+                        webhook_delivery::event_id,
+                        webhook_delivery::event_type,
+                        webhook_delivery::payload,
+                    )))
+                .into_columns((
+                    webhook_delivery::id,
+                    webhook_delivery::webhook_endpoint_id,
+                    webhook_delivery::organisation_id,
+                    webhook_delivery::event_id,
+                    webhook_delivery::event_type,
+                    webhook_delivery::payload,
+                ))
+                .returning(WebhookDelivery::as_returning())
+                .get_result(conn)
+                .await
+                .optional()
+                .map_err(DbError::from)
+        }.scope_boxed()).await
+    }
+
+    /*
+        Takes up to `limit` due deliveries of enabled endpoints of any organisation, as the admin role.
+        They are pushed back by `lease` so other workers skip them, one taken by a worker that died is sent again after it
+    */
+    #[instrument(skip_all)]
+    pub async fn claim_due_deliveries(&self, limit: i64, lease: Duration) -> Result<Vec<ClaimedDelivery>, DbError> {
+        let mut conn = self.admin_conn().await?;
+        sql_query("WITH claimed AS ( \
+                       UPDATE webhook_delivery \
+                       SET attempts = attempts + 1, last_attempt_at = NOW(), next_attempt_at = NOW() + make_interval(secs => $2) \
+                       WHERE id IN ( \
+                           SELECT webhook_delivery.id FROM webhook_delivery \
+                           JOIN webhook_endpoint ON webhook_endpoint.id = webhook_delivery.webhook_endpoint_id \
+                           WHERE webhook_delivery.status = 'pending' \
+                             AND webhook_delivery.next_attempt_at <= NOW() \
+                             AND webhook_endpoint.is_enabled \
+                           ORDER BY webhook_delivery.next_attempt_at \
+                           LIMIT $1 \
+                           FOR UPDATE OF webhook_delivery SKIP LOCKED) \
+                       RETURNING id, webhook_endpoint_id, event_id, payload, attempts) \
+                   SELECT claimed.id, claimed.event_id, claimed.payload, claimed.attempts, webhook_endpoint.url, webhook_endpoint.secret \
+                   FROM claimed JOIN webhook_endpoint ON webhook_endpoint.id = claimed.webhook_endpoint_id")
+            .bind::<BigInt, _>(limit)
+            .bind::<Double, _>(lease.as_secs_f64())
+            .get_results(&mut conn)
+            .await
+            .map_err(DbError::from)
+    }
+
+    #[instrument(skip_all)]
+    pub async fn mark_delivered(&self, id: &Uuid, response_status: i16) -> Result<usize, DbError> {
+        let mut conn = self.admin_conn().await?;
+        diesel::update(webhook_delivery::table.find(id))
+            .set((
+                webhook_delivery::status.eq(DELIVERY_DELIVERED),
+                webhook_delivery::response_status.eq(response_status),
+                webhook_delivery::last_error.eq(None::<String>),
+            ))
+            .execute(&mut conn)
+            .await
+            .map_err(DbError::from)
+    }
+
+    /*
+        Records a failed attempt, the delivery is retried after `retry_in` or given up on without it
+    */
+    #[instrument(skip_all)]
+    pub async fn mark_failed(&self, id: &Uuid, response_status: Option<i16>, error: &str, retry_in: Option<Duration>) -> Result<usize, DbError> {
+        use diesel::dsl::IntervalDsl;
+
+        let mut conn = self.admin_conn().await?;
+        let failed = (webhook_delivery::response_status.eq(response_status), webhook_delivery::last_error.eq(error));
+        match retry_in {
+            Some(retry_in) => diesel::update(webhook_delivery::table.find(id))
+                .set((failed, webhook_delivery::next_attempt_at.eq(now + (retry_in.as_secs() as i64).seconds())))
+                .execute(&mut conn)
+                .await,
+            None => diesel::update(webhook_delivery::table.find(id))
+                .set((failed, webhook_delivery::status.eq(DELIVERY_DEAD)))
+                .execute(&mut conn)
+                .await,
+        }.map_err(DbError::from)
+    }
+}
+
+impl BaseRepository for WebhookRepository {
+    fn pools(&self) -> &DbPools {
+        &self.pools
+    }
+}
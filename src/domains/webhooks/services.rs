@@ -0,0 +1,290 @@
+use std::sync::Arc;
+use std::time::Duration;
+use axum::async_trait;
+use chrono::NaiveDateTime;
+use tokio::sync::Notify;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+use crate::common::errors::api_error_response::ErrorMessage;
+use crate::common::errors::application_error::ApplicationError;
+use crate::common::errors::application_error::ApplicationError::NotFound;
+use crate::common::errors::error_code;
+use crate::common::errors::global_api_error::ApiError;
+use crate::common::errors::request_error::RequestError::ValidationError;
+use crate::common::models::models::ExpectedVersion;
+use crate::common::utils::backoff;
+use crate::config::app_env::{RUN_MODE, WEBHOOK_MAX_ATTEMPTS, WEBHOOK_TIMEOUT};
+use crate::domains::outbox::events::OutboxEvent;
+use crate::domains::outbox::services::EventSubscriber;
+use crate::domains::webhooks::api_models::{WebhookCreateRequest, WebhookPutRequest};
+use crate::domains::webhooks::db_models::{ClaimedDelivery, NewWebhookDelivery, WebhookDelivery, WebhookEndpoint};
+use crate::domains::webhooks::delivery;
+use crate::domains::webhooks::delivery::WebhookClient;
+use crate::domains::webhooks::events::{WebhookEvent, EVENT_TYPES};
+use crate::domains::webhooks::repository::WebhookRepository;
+use crate::server::shutdown::ShutdownSignal;
+
+const MIN_SECRET_LENGTH: usize = 16;
+const DELIVERY_LOG_LIMIT: i64 = 100;
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+const BATCH_SIZE: i64 = 20;
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(60);
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(6 * 60 * 60);
+
+#[derive(Clone)]
+pub struct WebhookService {
+    webhook_repository: WebhookRepository,
+    client: WebhookClient,
+    // Wakes the delivery worker of this instance when something was queued
+    queued: Arc<Notify>,
+}
+
+impl WebhookService {
+    pub fn new(webhook_repository: WebhookRepository) -> Self {
+        // Local endpoints are only reachable in local mode
+        let client = WebhookClient::new(*WEBHOOK_TIMEOUT, *RUN_MODE == "local");
+        WebhookService { webhook_repository, client, queued: Arc::new(Notify::new()) }
+    }
+
+    pub async fn create(&self, organisation_id: Uuid, webhook_request: WebhookCreateRequest) -> Result<WebhookEndpoint, ApiError> {
+        validate(&self.client, &webhook_request.url, &webhook_request.event_types, webhook_request.secret.as_deref())?;
+        let secret = webhook_request.secret.clone().unwrap_or_else(generate_secret);
+
+        debug!("Creating webhook for organisation: {:?}", organisation_id);
+        let endpoint = self.webhook_repository
+            .insert(&webhook_request.into_with_organisation(organisation_id, secret))
+            .await
+            .map_err(ApplicationError::from)?;
+
+        info!("Webhook successfully created");
+        Ok(endpoint)
+    }
+
+    pub async fn find_all_by_organisation_id(&self, organisation_id: &Uuid) -> Result<Vec<WebhookEndpoint>, ApplicationError> {
+        debug!("Finding webhooks...");
+        self.webhook_repository
+            .find_all_by_organisation_id(organisation_id)
+            .await
+            .map_err(ApplicationError::from)
+    }
+
+    pub async fn find_by_id_and_organisation_id(&self, id: &Uuid, organisation_id: &Uuid) -> Result<WebhookEndpoint, ApplicationError> {
+        debug!("Finding webhook by id: {:?}", id);
+        self.webhook_repository
+            .find_by_id_and_organisation_id(id, organisation_id)
+            .await
+            .map_err(ApplicationError::from)?
+            .ok_or(NotFound)
+    }
+
+    // Conditional on the expected version, see ExpectedVersion::apply
+    pub async fn update_by_id_and_organisation_id(&self,
+                                                  id: Uuid,
+                                                  organisation_id: Uuid,
+                                                  expected_version: ExpectedVersion,
+                                                  webhook_request: WebhookPutRequest
+    ) -> Result<NaiveDateTime, ApiError> {
+        debug!("Updating webhook by id: {:?}", id);
+        validate(&self.client, &webhook_request.url, &webhook_request.event_types, webhook_request.secret.as_deref())?;
+
+        Ok(expected_version.apply(|| self.current_version(&id, &organisation_id), |versions| async move {
+            self.webhook_repository
+                .update_by_id_and_organisation_id(&id, &organisation_id, &versions, webhook_request.into())
+                .await
+                .map_err(ApplicationError::from)
+        }).await?)
+    }
+
+    // Conditional on the expected version, see ExpectedVersion::apply
+    pub async fn delete_by_id_and_organisation_id(&self, id: Uuid, organisation_id: Uuid, expected_version: ExpectedVersion) -> Result<(), ApplicationError> {
+        debug!("Deleting webhook by id: {:?}", id);
+
+        expected_version.apply(|| self.current_version(&id, &organisation_id), |versions| async move {
+            let row_updated = self.webhook_repository
+                .delete_by_id_and_organisation_id(&id, &organisation_id, &versions)
+                .await?;
+            Ok((row_updated > 0).then_some(()))
+        }).await
+    }
+
+    // Most recent deliveries first
+    pub async fn find_deliveries(&self, id: &Uuid, organisation_id: &Uuid) -> Result<Vec<WebhookDelivery>, ApplicationError> {
+        self.find_by_id_and_organisation_id(id, organisation_id).await?;
+
+        self.webhook_repository
+            .find_deliveries_by_endpoint_id(id, organisation_id, DELIVERY_LOG_LIMIT)
+            .await
+            .map_err(ApplicationError::from)
+    }
+
+    /*
+        Sends the event of an earlier delivery again, whatever became of that delivery
+    */
+    pub async fn redeliver(&self, id: &Uuid, organisation_id: &Uuid, delivery_id: &Uuid) -> Result<WebhookDelivery, ApplicationError> {
+        info!("Redelivering webhook delivery: {:?}", delivery_id);
+        let redelivery = self.webhook_repository
+            .insert_redelivery(Uuid::now_v7(), delivery_id, id, organisation_id)
+            .await
+            .map_err(ApplicationError::from)?
+            .ok_or(NotFound)?;
+
+        self.queued.notify_one();
+        Ok(redelivery)
+    }
+
+    // Queues a delivery of the event for every endpoint subscribed to it that doesn't have one yet
+    async fn queue(&self, event: &WebhookEvent) -> Result<(), ApplicationError> {
+        let endpoint_ids = self.webhook_repository
+            .find_subscribed_ids(&event.organisation_id, event.event_type, &event.id)
+            .await
+            .map_err(ApplicationError::from)?;
+        if endpoint_ids.is_empty() {
+            return Ok(());
+        }
+
+        let payload = event.payload();
+        let deliveries: Vec<NewWebhookDelivery> = endpoint_ids.into_iter()
+            .map(|endpoint_id| NewWebhookDelivery {
+                id: Uuid::now_v7(),
+                webhook_endpoint_id: endpoint_id,
+                organisation_id: event.organisation_id,
+                event_id: event.id,
+                event_type: event.event_type.to_string(),
+                payload: payload.clone(),
+            })
+            .collect();
+
+        self.webhook_repository
+            .insert_deliveries(&deliveries)
+            .await
+            .map_err(ApplicationError::from)?;
+
+        self.queued.notify_one();
+        Ok(())
+    }
+
+    /*
+        Sends due deliveries until shutdown. Replicas share the queue, each delivery is taken by one of them.
+        Failed deliveries are retried with exponential backoff until WEBHOOK_MAX_ATTEMPTS, then marked dead
+    */
+    pub async fn deliver_pending(self, shutdown: ShutdownSignal) {
+        // A delivery can't take longer than the timeout, the lease leaves room for recording the outcome
+        let lease = *WEBHOOK_TIMEOUT + Duration::from_secs(60);
+        loop {
+            let batch_full = match self.webhook_repository.claim_due_deliveries(BATCH_SIZE, lease).await {
+                Ok(claimed) => {
+                    let batch_full = claimed.len() as i64 == BATCH_SIZE;
+                    futures::future::join_all(claimed.into_iter().map(|delivery| self.deliver(delivery))).await;
+                    batch_full
+                },
+                Err(e) => {
+                    warn!("Unable to claim webhook deliveries: {:?}", e);
+                    false
+                },
+            };
+
+            if shutdown.is_shutting_down() {
+                return;
+            }
+            if batch_full {
+                continue;
+            }
+
+            tokio::select! {
+                _ = tokio::time::sleep(POLL_INTERVAL) => {},
+                _ = self.queued.notified() => {},
+                _ = shutdown.wait() => return,
+            }
+        }
+    }
+
+    async fn deliver(&self, delivery: ClaimedDelivery) {
+        let body = delivery.payload.to_string().into_bytes();
+        let result = match delivery::send(&self.client, &delivery.url, &delivery.secret, &delivery.event_id, body).await {
+            Ok(status) => {
+                debug!("Webhook delivery {:?} delivered", delivery.id);
+                metrics::counter!("webhook_deliveries_total", "outcome" => "delivered").increment(1);
+                self.webhook_repository.mark_delivered(&delivery.id, status as i16).await
+            },
+            Err(failure) => {
+                let retry_in = (delivery.attempts < *WEBHOOK_MAX_ATTEMPTS).then(|| backoff::retry_delay(delivery.attempts, RETRY_BASE_DELAY, RETRY_MAX_DELAY));
+                match retry_in {
+                    Some(retry_in) => debug!("Webhook delivery {:?} failed, retrying in {:?}: {}", delivery.id, retry_in, failure.error),
+                    None => warn!("Webhook delivery {:?} failed {} times, giving up: {}", delivery.id, delivery.attempts, failure.error),
+                }
+                metrics::counter!("webhook_deliveries_total", "outcome" => if retry_in.is_some() { "retry" } else { "dead" }).increment(1);
+                self.webhook_repository
+                    .mark_failed(&delivery.id, failure.response_status.map(|status| status as i16), &failure.error, retry_in)
+                    .await
+            },
+        };
+
+        if let Err(e) = result {
+            // The lease runs out and the delivery is sent again
+            warn!("Unable to record the outcome of webhook delivery {:?}: {:?}", delivery.id, e);
+        }
+    }
+
+    async fn current_version(&self, id: &Uuid, organisation_id: &Uuid) -> Result<NaiveDateTime, ApplicationError> {
+        self.webhook_repository
+            .find_version(id, organisation_id)
+            .await?
+            .ok_or(NotFound)
+    }
+}
+
+/*
+    Turns the domain events endpoints can subscribe to into deliveries
+*/
+#[async_trait]
+impl EventSubscriber for WebhookService {
+    fn name(&self) -> &'static str {
+        "webhooks"
+    }
+
+    async fn handle(&self, event: &OutboxEvent) -> Result<(), ApplicationError> {
+        match WebhookEvent::from_outbox(event) {
+            Some(webhook_event) => {
+                debug!("Queueing webhook event: {:?}", webhook_event.event_type);
+                self.queue(&webhook_event).await
+            },
+            None => Ok(()),
+        }
+    }
+}
+
+// The URL is checked again on every delivery, its host may resolve differently by then
+fn validate(client: &WebhookClient, url: &str, event_types: &[String], secret: Option<&str>) -> Result<(), ApiError> {
+    let mut errors = Vec::new();
+
+    match reqwest::Url::parse(url) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") && url.has_host() => {
+            if client.check_destination(&url).is_err() {
+                errors.push(ErrorMessage::new(Some("url"), error_code::VALIDATION_WEBHOOK_URL_NOT_ALLOWED));
+            }
+        },
+        _ => errors.push(ErrorMessage::new(Some("url"), error_code::VALIDATION_URL_INVALID)),
+    }
+
+    if event_types.is_empty() {
+        errors.push(ErrorMessage::new(Some("event_types"), error_code::VALIDATION_FIELD_REQUIRED));
+    } else if event_types.iter().any(|event_type| !EVENT_TYPES.contains(&event_type.as_str())) {
+        let supported = EVENT_TYPES.join(", ");
+        errors.push(ErrorMessage::with_args(Some("event_types"), error_code::VALIDATION_WEBHOOK_EVENT_TYPE_UNSUPPORTED, &[("event_types", &supported)]));
+    }
+
+    if secret.is_some_and(|secret| secret.chars().count() < MIN_SECRET_LENGTH) {
+        errors.push(ErrorMessage::with_args(Some("secret"), error_code::VALIDATION_WEBHOOK_SECRET_TOO_SHORT, &[("min", &MIN_SECRET_LENGTH.to_string())]));
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(ValidationError(errors).into())
+    }
+}
+
+// 244 random bits from two v4 uuids
+fn generate_secret() -> String {
+    format!("whsec_{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
@@ -1,14 +1,20 @@
 use axum::{Router, routing::get};
+use clap::Parser;
 use tower_http::request_id::PropagateRequestIdLayer;
 use crate::common::security;
 use crate::common::utils::constants::{TRACING_ID_HEADER};
-use crate::config::app_env::RUN_MODE;
+use crate::cli::{Cli, Command};
+use crate::config::app_env::{METRICS_PORT, RUN_MODE};
 use crate::middleware::layers;
+use crate::common::models::api_version::ApiVersion;
+use crate::server::shutdown::ShutdownSignal;
 
+mod cli;
 mod config;
 mod domains;
 mod middleware;
 mod common;
+mod server;
 
 #[tokio::main]
 async fn main() {
@@ -17,12 +23,70 @@ async fn main() {
         dotenv::from_filename(env_file).ok();
     }
 
+    let cli = Cli::parse();
+    match cli.command.unwrap_or(Command::Serve) {
+        Command::Serve => serve().await,
+        command => {
+            config::logging_config::init_cli_logging();
+            if let Err(e) = cli::execute(command, cli.output).await {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        },
+    }
+}
+
+async fn serve() {
     config::init();
 
-    let app_state = config::init_app_state().await;
+    // A server that can't send email stops before connecting to anything
+    let mailer = match domains::mail::mailer::from_env() {
+        Ok(mailer) => mailer,
+        Err(e) => {
+            tracing::error!("Unable to set up email: {}", e);
+            std::process::exit(1);
+        },
+    };
+
+    // Connecting first retries until the database is reachable, migrations can then run against it
+    let db_pools = match config::diesel_config::establish_connection().await {
+        Ok(db_pools) => db_pools,
+        Err(e) => {
+            tracing::error!("Unable to connect to database: {}", e);
+            std::process::exit(1);
+        },
+    };
+
+    if let Err(e) = config::diesel_config::check_admin_role(&db_pools).await {
+        tracing::error!("Unable to use the admin role: {}", e);
+        std::process::exit(1);
+    }
+
+    if let Err(e) = config::migration_config::prepare_database().await {
+        tracing::error!("Unable to prepare database: {}", e);
+        std::process::exit(1);
+    }
+
+    let shutdown = ShutdownSignal::listen();
+    let prometheus_handle = config::metrics_config::install_recorder();
+    tokio::spawn(config::diesel_config::monitor_replica(db_pools.clone(), shutdown.clone()));
+    let app_state = config::init_app_state(db_pools.clone(), mailer, shutdown.clone(), prometheus_handle);
+    tokio::spawn(app_state.idempotency_service.clone().purge_expired(shutdown.clone()));
+    tokio::spawn(app_state.webhook_service.clone().deliver_pending(shutdown.clone()));
+    tokio::spawn(app_state.outbox_service.clone().dispatch_pending(shutdown.clone()));
+    tokio::spawn(app_state.outbox_service.clone().purge_finished(shutdown.clone()));
+    tokio::spawn(app_state.job_service.clone().schedule(shutdown.clone()));
+    tokio::spawn(app_state.activity_service.clone().listen(shutdown.clone()));
+    tokio::spawn(app_state.realtime_service.clone().listen(shutdown.clone()));
+    tokio::spawn(app_state.realtime_service.clone().purge_stale(shutdown.clone()));
+    // Awaited before the pools close so running jobs can finish
+    let job_worker = tokio::spawn(app_state.job_service.clone().work(shutdown.clone()));
+    let realtime_service = app_state.realtime_service.clone();
 
+    // Unversioned, probes and docs aren't part of the API contract
     let public_routes = Router::new()
-        .merge(domains::auth::handlers::routes())
+        .merge(domains::health::handlers::public_routes())
+        .merge(domains::docs::handlers::routes())
         .with_state(app_state.clone())
         .route("/favicon.ico", get(domains::favicon::favicon::handle));
 
@@ -30,26 +94,53 @@ async fn main() {
         .merge(domains::users::handlers::routes())
         .merge(domains::organisations::handlers::routes())
         .merge(domains::applications::handlers::routes())
-        // middleware, the order of execution is from bottom to top for request and top to bottom for response
-        .layer(PropagateRequestIdLayer::new(TRACING_ID_HEADER.clone()))
-        .layer(layers::set_span())
-        .layer(layers::set_tracing_id())
+        .merge(domains::health::handlers::routes())
+        .merge(domains::logging::handlers::routes())
+        .merge(domains::jobs::handlers::routes())
+        .merge(domains::mail::handlers::routes())
+        .merge(domains::webhooks::handlers::routes())
+        .merge(domains::activity::handlers::routes())
+        .merge(domains::search::handlers::routes())
+        .route_layer(axum::middleware::from_fn_with_state(app_state.clone(), middleware::idempotency::idempotent_post))
         .route_layer(axum::middleware::from_fn_with_state(app_state.clone(), security::jwt::authenticate))
+        .with_state(app_state.clone());
+
+    let v1_routes = Router::new()
+        .merge(domains::auth::handlers::routes())
+        .merge(domains::realtime::handlers::routes())
+        .with_state(app_state.clone())
+        .merge(authenticated_routes)
+        .route_layer(axum::middleware::from_fn_with_state(ApiVersion::V1, middleware::versioning::track_version));
+
+    let metrics_routes = domains::metrics::handlers::routes()
         .with_state(app_state);
 
-    // Combine routers, public routes remain accessible without authentication
-    let app = Router::new()
+    // Combine routers, public routes remain accessible without authentication.
+    // The API is served under its version prefix and, for the default version, without it
+    let mut app = Router::new()
         .merge(public_routes)
-        .merge(authenticated_routes);
+        .nest(ApiVersion::V1.prefix(), v1_routes.clone())
+        .merge(v1_routes.route_layer(axum::middleware::from_fn(middleware::versioning::negotiate_version)));
 
-    let listener = tokio::net::TcpListener::bind("127.0.0.1:8080")
-        .await
-        .expect("Unable to bind to port");
+    match *METRICS_PORT {
+        Some(port) => { tokio::spawn(server::serve_metrics(metrics_routes, port, shutdown.clone())); },
+        None => app = app.merge(metrics_routes),
+    }
+
+    let app = app
+        // middleware, the order of execution is from bottom to top for request and top to bottom for response
+        .layer(axum::middleware::from_fn(middleware::read_your_writes::track_writes))
+        .layer(axum::middleware::from_fn(middleware::locale::negotiate_locale))
+        .layer(PropagateRequestIdLayer::new(TRACING_ID_HEADER.clone()))
+        .layer(layers::set_tracing_id())
+        .layer(layers::set_span())
+        .route_layer(axum::middleware::from_fn(middleware::metrics::track_metrics));
 
-    tracing::info!("\n------------------------------------------\n      listening on {}\n------------------------------------------",
-        listener.local_addr()
-                .unwrap()
-    );
+    server::serve(app, shutdown).await;
+    realtime_service.drain().await;
+    let _ = job_worker.await;
 
-    axum::serve(listener, app.into_make_service()).await.unwrap();
+    config::diesel_config::release(db_pools);
+    config::otel_config::shutdown().await;
+    tracing::info!("Shutdown complete");
 }
\ No newline at end of file
@@ -0,0 +1,326 @@
+use std::time::Duration;
+use axum::body::{Body, Bytes};
+use axum::extract::{Request, State};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use http::header::{CONTENT_LANGUAGE, CONTENT_TYPE, ETAG, LOCATION, RETRY_AFTER};
+use http::request::Parts;
+use http::{HeaderName, HeaderValue, Method, StatusCode};
+use sha2::{Digest, Sha256};
+use tracing::{debug, warn};
+use uuid::Uuid;
+use crate::common::errors::application_error::ApplicationError;
+use crate::common::errors::error_code;
+use crate::common::errors::global_api_error::ApiError;
+use crate::common::errors::request_error::RequestError;
+use crate::common::models::models::{Identity, ReplayBody};
+use crate::common::utils::constants::{IDEMPOTENCY_KEY_HEADER, IDEMPOTENT_REPLAYED_HEADER, ORGANISATION_ID_HEADER};
+use crate::domains::idempotency::services::{Claim, IdempotencyService, StoredResponse};
+
+const MAX_KEY_LENGTH: usize = 255;
+// Same limit the Json extractor applies by default
+const MAX_BODY_SIZE: usize = 2 * 1024 * 1024;
+const IN_PROGRESS_POLL_INTERVAL: Duration = Duration::from_millis(100);
+const IN_PROGRESS_MAX_WAIT: Duration = Duration::from_secs(5);
+const IN_PROGRESS_RETRY_AFTER_SECS: u64 = 1;
+
+// Headers that belong to the stored response, the rest (tracing id, ...) belongs to the retry
+const REPLAYED_HEADERS: [HeaderName; 4] = [CONTENT_TYPE, CONTENT_LANGUAGE, ETAG, LOCATION];
+
+/*
+    Makes POST requests sent with an Idempotency-Key safe to retry. The first request per key and user
+    runs and its response is stored, retries with the same body get that response replayed while a
+    different body is rejected. A retry arriving while the first request still runs waits for it.
+    Server errors aren't stored, the key is released so the request can be retried. Responses
+    with a ReplayBody are stored and replayed with it instead of their own body.
+    Must run after `authenticate`, keys are scoped to the user
+*/
+pub async fn idempotent_post(
+    State(idempotency_service): State<IdempotencyService>,
+    req: Request,
+    next: Next,
+) -> Result<Response, ApiError> {
+    if req.method() != Method::POST || !req.headers().contains_key(&IDEMPOTENCY_KEY_HEADER) {
+        return Ok(next.run(req).await);
+    }
+
+    let key = validate_key(req.headers().get(&IDEMPOTENCY_KEY_HEADER))?;
+    let user_id = req.extensions()
+        .get::<Identity>()
+        .map(|identity| identity.user_id)
+        .ok_or(ApplicationError::Unauthorized)?;
+
+    let (parts, body) = req.into_parts();
+    let body = axum::body::to_bytes(body, MAX_BODY_SIZE)
+        .await
+        .map_err(|e| {
+            debug!("Unable to buffer request body: {:?}", e);
+            RequestError::PayloadTooLarge
+        })?;
+    let fingerprint = fingerprint(&parts, &body);
+
+    let claim_token = match claim(&idempotency_service, &user_id, &key, &fingerprint).await? {
+        Claim::Acquired(claim_token) => claim_token,
+        Claim::Completed(stored) => {
+            debug!("Replaying stored response for idempotency key: {:?}", key);
+            return Ok(replay(stored));
+        },
+        Claim::InProgress => {
+            let mut response = ApplicationError::ConflictError(error_code::IDEMPOTENCY_REQUEST_IN_PROGRESS).into_response();
+            response.headers_mut().insert(RETRY_AFTER, HeaderValue::from(IN_PROGRESS_RETRY_AFTER_SECS));
+            return Ok(response);
+        },
+        Claim::Mismatch => return Err(ApplicationError::ConflictError(error_code::IDEMPOTENCY_KEY_REUSED).into()),
+    };
+
+    let mut lock = KeyLock::new(idempotency_service.clone(), user_id, key.clone(), claim_token);
+    let response = next.run(Request::from_parts(parts, Body::from(body))).await;
+
+    if response.status().is_server_error() {
+        return Ok(response);
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let body = axum::body::to_bytes(body, usize::MAX)
+        .await
+        .map_err(|e| {
+            warn!("Unable to buffer response body: {:?}", e);
+            ApplicationError::InternalServerError
+        })?;
+
+    let stored = StoredResponse {
+        status: parts.status.as_u16(),
+        headers: REPLAYED_HEADERS.iter()
+            .filter_map(|name| parts.headers.get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| (name.to_string(), value.to_string())))
+            .collect(),
+        body: parts.extensions.remove::<ReplayBody>().map(|replay_body| replay_body.0).unwrap_or_else(|| body.to_vec()),
+    };
+    match idempotency_service.complete(&user_id, &key, &claim_token, stored).await {
+        Ok(()) => lock.keep(),
+        Err(e) => warn!("Unable to store response for idempotency key {:?}: {}", key, e),
+    }
+
+    Ok(Response::from_parts(parts, Body::from(body)))
+}
+
+fn validate_key(value: Option<&HeaderValue>) -> Result<String, RequestError> {
+    value
+        .and_then(|value| value.to_str().ok())
+        .filter(|key| !key.is_empty() && key.len() <= MAX_KEY_LENGTH && key.bytes().all(|b| b.is_ascii_graphic()))
+        .map(str::to_string)
+        .ok_or(RequestError::InvalidIdempotencyKey)
+}
+
+/*
+    Identifies the request a key was first used for, the organisation header is part of it
+    because the same body creates a different resource in another organisation
+*/
+fn fingerprint(parts: &Parts, body: &Bytes) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(parts.method.as_str());
+    hasher.update(b"\n");
+    hasher.update(parts.uri.path());
+    hasher.update(b"\n");
+    hasher.update(parts.headers.get(&ORGANISATION_ID_HEADER).map(HeaderValue::as_bytes).unwrap_or_default());
+    hasher.update(b"\n");
+    hasher.update(body);
+    hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// Waits a moment for a concurrent request with the same key, most finish well within it
+async fn claim(idempotency_service: &IdempotencyService, user_id: &Uuid, key: &str, fingerprint: &str) -> Result<Claim, ApplicationError> {
+    let deadline = tokio::time::Instant::now() + IN_PROGRESS_MAX_WAIT;
+    loop {
+        match idempotency_service.claim(user_id, key, fingerprint).await? {
+            Claim::InProgress if tokio::time::Instant::now() < deadline => tokio::time::sleep(IN_PROGRESS_POLL_INTERVAL).await,
+            claim => return Ok(claim),
+        }
+    }
+}
+
+fn replay(stored: StoredResponse) -> Response {
+    let mut response = Response::new(Body::from(stored.body));
+    *response.status_mut() = StatusCode::from_u16(stored.status).unwrap_or(StatusCode::OK);
+    for (name, value) in stored.headers {
+        if let (Ok(name), Ok(value)) = (HeaderName::try_from(name), HeaderValue::try_from(value)) {
+            response.headers_mut().insert(name, value);
+        }
+    }
+    response.headers_mut().insert(IDEMPOTENT_REPLAYED_HEADER.clone(), HeaderValue::from_static("true"));
+    response
+}
+
+/*
+    Releases the key unless the response was stored, including when the client disconnects
+    and the request future is dropped halfway
+*/
+struct KeyLock {
+    held: Option<(IdempotencyService, Uuid, String, Uuid)>,
+}
+
+impl KeyLock {
+    fn new(idempotency_service: IdempotencyService, user_id: Uuid, key: String, claim_token: Uuid) -> Self {
+        KeyLock { held: Some((idempotency_service, user_id, key, claim_token)) }
+    }
+
+    fn keep(&mut self) {
+        self.held = None;
+    }
+}
+
+impl Drop for KeyLock {
+    fn drop(&mut self) {
+        if let Some((idempotency_service, user_id, key, claim_token)) = self.held.take() {
+            tokio::spawn(async move {
+                if let Err(e) = idempotency_service.release(&user_id, &key, &claim_token).await {
+                    warn!("Unable to release idempotency key {:?}: {}", key, e);
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use axum::routing::post;
+    use axum::{Extension, Router};
+    use diesel::sql_query;
+    use http::HeaderMap;
+    use tower::Service;
+    use super::*;
+    use crate::common::test_database::TestDatabase;
+    use crate::domains::idempotency::repository::IdempotencyRepository;
+
+    struct Fixture {
+        database: TestDatabase,
+        service: IdempotencyService,
+        user_id: Uuid,
+        handled: Arc<AtomicUsize>,
+    }
+
+    // Seeds and rewinds rows as the superuser, the tenant policies don't apply to it
+    async fn execute(database: &TestDatabase, statement: String) {
+        diesel_async::RunQueryDsl::execute(sql_query(statement), &mut database.admin().await).await.unwrap();
+    }
+
+    async fn fixture() -> Option<Fixture> {
+        let database = TestDatabase::create().await?;
+        let user_id = Uuid::now_v7();
+        execute(&database, format!("INSERT INTO swift_user (id, email, first_name) VALUES ('{}', 'idempotent@test.test', 'Idempotent')", user_id)).await;
+        Some(Fixture {
+            service: IdempotencyService::new(IdempotencyRepository::new(database.pools.clone())),
+            database,
+            user_id,
+            handled: Arc::new(AtomicUsize::new(0)),
+        })
+    }
+
+    // Counts the requests that actually ran, each response is unique so a replay is told apart
+    fn router(fixture: &Fixture) -> Router {
+        let handled = fixture.handled.clone();
+        layered(fixture, Router::new()
+            .route("/", post(|body: String| async move {
+                tokio::time::sleep(Duration::from_millis(200)).await;
+                let count = handled.fetch_add(1, Ordering::SeqCst) + 1;
+                (StatusCode::CREATED, [(LOCATION, format!("/{}", count))], format!("{} {}", body, count))
+            })))
+    }
+
+    fn layered(fixture: &Fixture, router: Router) -> Router {
+        let identity = Identity { user_id: fixture.user_id, organisation_ids: vec![], locale: None };
+        router
+            .layer(axum::middleware::from_fn_with_state(fixture.service.clone(), idempotent_post))
+            .layer(axum::middleware::from_fn(move |mut req: Request, next: Next| {
+                req.extensions_mut().insert(identity.clone());
+                next.run(req)
+            }))
+    }
+
+    async fn send(mut router: Router, key: &str, body: &'static str) -> (StatusCode, HeaderMap, String) {
+        let request = Request::post("/").header(&IDEMPOTENCY_KEY_HEADER, key).body(Body::from(body)).unwrap();
+        let (parts, body) = router.call(request).await.unwrap().into_parts();
+        let body = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+        (parts.status, parts.headers, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn retries_get_the_stored_response_replayed() {
+        let Some(fixture) = fixture().await else { return };
+
+        let (status, headers, body) = send(router(&fixture), "retry", "app").await;
+        assert_eq!((status, body.as_str()), (StatusCode::CREATED, "app 1"));
+        assert!(!headers.contains_key(&IDEMPOTENT_REPLAYED_HEADER));
+
+        let (status, headers, body) = send(router(&fixture), "retry", "app").await;
+        assert_eq!((status, body.as_str()), (StatusCode::CREATED, "app 1"));
+        assert_eq!(headers[LOCATION], "/1");
+        assert_eq!(headers[&IDEMPOTENT_REPLAYED_HEADER], "true");
+        assert_eq!(fixture.handled.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn responses_with_a_replay_body_are_stored_and_replayed_with_it() {
+        let Some(fixture) = fixture().await else { return };
+        let router = || layered(&fixture, Router::new()
+            .route("/", post(|| async { (StatusCode::CREATED, Extension(ReplayBody(b"id".to_vec())), "id secret") })));
+
+        let (status, _, body) = send(router(), "secret", "webhook").await;
+        assert_eq!((status, body.as_str()), (StatusCode::CREATED, "id secret"));
+
+        let (status, headers, body) = send(router(), "secret", "webhook").await;
+        assert_eq!((status, body.as_str()), (StatusCode::CREATED, "id"));
+        assert_eq!(headers[&IDEMPOTENT_REPLAYED_HEADER], "true");
+    }
+
+    #[tokio::test]
+    async fn a_key_reused_with_another_body_conflicts() {
+        let Some(fixture) = fixture().await else { return };
+
+        send(router(&fixture), "reused", "app").await;
+        let (status, _, body) = send(router(&fixture), "reused", "other app").await;
+
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert!(body.contains(error_code::IDEMPOTENCY_KEY_REUSED), "{}", body);
+        assert_eq!(fixture.handled.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn concurrent_duplicates_run_once() {
+        let Some(fixture) = fixture().await else { return };
+
+        let (first, second) = tokio::join!(send(router(&fixture), "concurrent", "app"), send(router(&fixture), "concurrent", "app"));
+
+        assert_eq!((first.0, first.2.as_str()), (StatusCode::CREATED, "app 1"));
+        assert_eq!((second.0, second.2.as_str()), (StatusCode::CREATED, "app 1"));
+        assert_eq!(fixture.handled.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn a_timed_out_lock_is_taken_over_and_the_stale_holder_loses_it() {
+        let Some(fixture) = fixture().await else { return };
+        let (service, user_id) = (&fixture.service, &fixture.user_id);
+        let response = |body: &str| StoredResponse { status: 201, headers: vec![], body: body.as_bytes().to_vec() };
+
+        let Claim::Acquired(stale) = service.claim(user_id, "takeover", "fingerprint").await.unwrap() else { panic!("not acquired") };
+        assert!(matches!(service.claim(user_id, "takeover", "fingerprint").await.unwrap(), Claim::InProgress));
+
+        execute(&fixture.database, "UPDATE idempotency_key SET locked_at = locked_at - INTERVAL '1 hour'".to_string()).await;
+        let Claim::Acquired(current) = service.claim(user_id, "takeover", "fingerprint").await.unwrap() else { panic!("not taken over") };
+        assert_ne!(stale, current);
+
+        // Neither stores nor frees the key for the request holding it now
+        service.complete(user_id, "takeover", &stale, response("stale")).await.unwrap();
+        service.release(user_id, "takeover", &stale).await.unwrap();
+        assert!(matches!(service.claim(user_id, "takeover", "fingerprint").await.unwrap(), Claim::InProgress));
+
+        service.complete(user_id, "takeover", &current, response("current")).await.unwrap();
+        match service.claim(user_id, "takeover", "fingerprint").await.unwrap() {
+            Claim::Completed(stored) => assert_eq!(stored.body, b"current"),
+            claim => panic!("unexpected {:?}", claim),
+        }
+    }
+}
@@ -1,31 +1,68 @@
+use std::time::Duration;
 use axum::extract::{MatchedPath, Request};
+use axum::response::Response;
+use http::HeaderMap;
+use opentelemetry::global;
+use opentelemetry::propagation::Extractor;
 use tower::layer::util::{Identity, Stack};
 use tower::ServiceBuilder;
-use tower_http::trace::{HttpMakeClassifier, TraceLayer};
+use tower_http::trace::{DefaultOnRequest, HttpMakeClassifier, TraceLayer};
 use tracing::Span;
+use tracing::field::Empty;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
 use crate::common::utils::constants::TRACING_ID_HEADER;
-use crate::common::utils::header_utils;
 use crate::middleware::services::ForceSetRequestIdLayer;
 
-pub fn set_span() -> TraceLayer<HttpMakeClassifier, fn(&Request) -> Span> {
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|key| key.as_str()).collect()
+    }
+}
+
+type OnResponseFn = fn(&Response, Duration, &Span);
+
+pub fn set_span() -> TraceLayer<HttpMakeClassifier, fn(&Request) -> Span, DefaultOnRequest, OnResponseFn> {
     TraceLayer::new_for_http()
+        .on_response(log_access as OnResponseFn)
         .make_span_with(|request: &Request<_>| {
             let path = request
                 .extensions()
                 .get::<MatchedPath>()
                 .map(MatchedPath::as_str);
 
-            let tracing_id = header_utils::extract_tracing_id_from_request(request);
+            // Continue the caller's trace when a valid traceparent is sent
+            let parent_context = global::get_text_map_propagator(|propagator| {
+                propagator.extract(&HeaderExtractor(request.headers()))
+            });
 
-            tracing::info_span!(
+            let span = tracing::info_span!(
                         "http_request",
-                        tracing_id = tracing_id,
+                        tracing_id = Empty,
+                        user_id = Empty,
+                        organisation_id = Empty,
                         method = ?request.method(),
                         path
-                    )
+                    );
+            span.set_parent(parent_context);
+            span
         })
 }
 
+// Access log line, emitted inside the request span so it carries tracing/user/organisation ids
+fn log_access(response: &Response, latency: Duration, _span: &Span) {
+    tracing::info!(
+        status = response.status().as_u16(),
+        latency_ms = latency.as_millis() as u64,
+        "request completed"
+    );
+}
+
 pub fn set_tracing_id() -> ServiceBuilder<Stack<ForceSetRequestIdLayer, Identity>> {
     ServiceBuilder::new()
         .layer(ForceSetRequestIdLayer::new(TRACING_ID_HEADER.clone()))
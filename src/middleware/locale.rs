@@ -0,0 +1,27 @@
+use axum::extract::Request;
+use axum::middleware::Next;
+use axum::response::Response;
+use http::header::{ACCEPT_LANGUAGE, CONTENT_LANGUAGE};
+use http::HeaderValue;
+use crate::common::i18n::locale::{self, Locale};
+
+/*
+    Negotiates the locale from Accept-Language, English when nothing supported is asked for.
+    Authenticated users with a stored preference override it in `authenticate`
+*/
+pub async fn negotiate_locale(req: Request, next: Next) -> Response {
+    let locale = req.headers()
+        .get(ACCEPT_LANGUAGE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(Locale::negotiate)
+        .unwrap_or(Locale::En);
+
+    run_with_locale(locale, next, req).await
+}
+
+pub async fn run_with_locale(locale: Locale, next: Next, req: Request) -> Response {
+    let mut response = locale::scope(locale, next.run(req)).await;
+    // Innermost scope wins, it is the locale the body was rendered in
+    response.headers_mut().entry(CONTENT_LANGUAGE).or_insert(HeaderValue::from_static(locale.as_str()));
+    response
+}
@@ -0,0 +1,36 @@
+use std::time::Instant;
+use axum::extract::{MatchedPath, Request};
+use axum::middleware::Next;
+use axum::response::IntoResponse;
+
+/*
+    RED metrics per route, labeled by the matched path rather than the raw uri to keep cardinality bounded
+*/
+pub async fn track_metrics(req: Request, next: Next) -> impl IntoResponse {
+    let start = Instant::now();
+    let path = req.extensions()
+        .get::<MatchedPath>()
+        .map(|matched_path| matched_path.as_str().to_owned())
+        .unwrap_or_else(|| req.uri().path().to_owned());
+    let method = req.method().to_string();
+
+    let response = next.run(req).await;
+
+    let latency = start.elapsed().as_secs_f64();
+    let status = response.status();
+    let labels = [
+        ("method", method),
+        ("path", path),
+        ("status", status.as_u16().to_string()),
+    ];
+
+    metrics::counter!("http_requests_total", &labels).increment(1);
+    metrics::histogram!("http_request_duration_seconds", &labels).record(latency);
+
+    if status.is_client_error() || status.is_server_error() {
+        let kind = if status.is_server_error() { "server" } else { "client" };
+        metrics::counter!("http_request_errors_total", "method" => labels[0].1.clone(), "path" => labels[1].1.clone(), "kind" => kind).increment(1);
+    }
+
+    response
+}
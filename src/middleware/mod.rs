@@ -1,2 +1,7 @@
 pub mod layers;
-pub mod services;
\ No newline at end of file
+pub mod services;
+pub mod metrics;
+pub mod locale;
+pub mod read_your_writes;
+pub mod idempotency;
+pub mod versioning;
\ No newline at end of file
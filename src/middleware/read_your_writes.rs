@@ -0,0 +1,12 @@
+use axum::extract::Request;
+use axum::middleware::Next;
+use axum::response::Response;
+use crate::common::repository;
+
+/*
+    Once a request has written to the primary its remaining reads go to the primary as well,
+    so it never reads back stale data from a replica that hasn't caught up yet
+*/
+pub async fn track_writes(req: Request, next: Next) -> Response {
+    repository::read_your_writes_scope(next.run(req)).await
+}
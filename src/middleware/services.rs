@@ -2,11 +2,19 @@ use std::task::{Context, Poll};
 use axum::body::Body;
 use axum::extract::Request;
 use axum::response::Response;
+use futures::future::BoxFuture;
 use http::{HeaderName, HeaderValue};
 use tower::Service;
+use tracing::Span;
 use uuid::Uuid;
+use crate::common::utils::tracing_utils;
 
 
+/*
+    Overwrites the tracing id header with the trace id of the current request span,
+    must run inside the trace layer so upstream traceparent ids are kept.
+    The same id is scoped for the rest of the request so problem responses carry it as `instance`
+*/
 #[derive(Debug, Clone)]
 pub struct ForceSetRequestIdLayer {
     header_name: HeaderName,
@@ -38,23 +46,57 @@ pub struct ForceSetRequestIdService<S> {
 impl<S, ReqBody> Service<Request<ReqBody>> for ForceSetRequestIdService<S>
     where
         S: Service<Request<ReqBody>, Response = Response<Body>> + Clone + Send + 'static,
-        S::Future: Send,
+        S::Future: Send + 'static,
         ReqBody: Send + 'static,
 {
     type Response = S::Response;
     type Error = S::Error;
-    type Future = S::Future;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
 
     fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
         self.inner.poll_ready(cx)
     }
 
     fn call(&mut self, mut request: Request<ReqBody>) -> Self::Future {
-        let tracing_id = Uuid::new_v4().to_string();
+        let tracing_id = tracing_utils::span_trace_id()
+            .unwrap_or_else(|| Uuid::new_v4().simple().to_string());
+        Span::current().record("tracing_id", &tracing_id);
+
         request.headers_mut().insert(
             self.header_name.clone(),
             HeaderValue::from_str(&tracing_id).unwrap(),
         );
-        self.inner.call(request)
+        let future = self.inner.call(request);
+        Box::pin(tracing_utils::scope(tracing_id, future))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::response::IntoResponse;
+    use axum::routing::get;
+    use axum::Router;
+    use http::{HeaderMap, StatusCode};
+    use super::*;
+    use crate::common::errors::api_error_response::ErrorResponse;
+    use crate::common::utils::constants::TRACING_ID_HEADER;
+
+    #[tokio::test]
+    async fn problem_instance_matches_the_tracing_id_header() {
+        // No OpenTelemetry layer is installed here, so the id is the generated fallback
+        let mut router = Router::new()
+            .route("/", get(|headers: HeaderMap| async move {
+                let mut response = ErrorResponse::build(StatusCode::NOT_FOUND, "not_found").into_response();
+                response.headers_mut().insert(TRACING_ID_HEADER.clone(), headers[&TRACING_ID_HEADER].clone());
+                response
+            }))
+            .layer(ForceSetRequestIdLayer::new(TRACING_ID_HEADER.clone()));
+
+        let response = router.call(Request::get("/").body(Body::empty()).unwrap()).await.unwrap();
+        let header = response.headers().get(&TRACING_ID_HEADER).unwrap().to_str().unwrap().to_string();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let problem: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(problem["instance"], header.as_str());
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,140 @@
+use axum::extract::{MatchedPath, Request, State};
+use axum::middleware::Next;
+use axum::response::Response;
+use http::header::{ACCEPT, LINK};
+use http::{HeaderMap, HeaderValue};
+use crate::common::errors::global_api_error::ApiError;
+use crate::common::errors::request_error::RequestError;
+use crate::common::models::api_version::{ApiVersion, RouteDeprecation, SUPPORTED_VERSIONS};
+use crate::common::utils::constants::{DEPRECATION_HEADER, SUNSET_HEADER};
+use crate::config::app_env::API_DEPRECATIONS;
+
+// Accept: application/vnd.swift.v1+json
+const VENDOR_MEDIA_TYPE_PREFIX: &str = "application/vnd.swift.";
+const VENDOR_MEDIA_TYPE_SUFFIX: &str = "+json";
+
+#[derive(Debug, Clone, Copy)]
+struct Unversioned;
+
+/*
+    Unversioned paths are served by the default version, kept for clients from before versioning.
+    A vendor media type in Accept picks the version explicitly, unknown versions get 406.
+    Versioned paths ignore Accept, the path wins
+*/
+pub async fn negotiate_version(mut req: Request, next: Next) -> Result<Response, ApiError> {
+    let requested = requested_version(req.headers()).transpose()?;
+    if requested.is_some_and(|version| version != ApiVersion::DEFAULT) {
+        // Other versions are only served under their prefix
+        return Err(RequestError::UnsupportedVersion.into());
+    }
+
+    req.extensions_mut().insert(Unversioned);
+    Ok(next.run(req).await)
+}
+
+/*
+    Adds Deprecation (RFC 9745), Sunset (RFC 8594) and a deprecation Link to responses of deprecated routes,
+    and counts the callers still using them as well as those on unversioned paths
+*/
+pub async fn track_version(State(version): State<ApiVersion>, req: Request, next: Next) -> Response {
+    let route = versioned_route(version, req.extensions().get::<MatchedPath>().map(MatchedPath::as_str).unwrap_or_default());
+    let unversioned = req.extensions().get::<Unversioned>().is_some();
+
+    let mut response = next.run(req).await;
+
+    if unversioned {
+        metrics::counter!("api_unversioned_requests_total", "route" => route.clone()).increment(1);
+    }
+
+    let deprecation = API_DEPRECATIONS.iter()
+        .filter(|deprecation| deprecation.covers(&route))
+        .max_by_key(|deprecation| deprecation.route.len());
+    if let Some(deprecation) = deprecation {
+        metrics::counter!("api_deprecated_requests_total", "version" => version.as_str(), "route" => route).increment(1);
+        insert_deprecation_headers(response.headers_mut(), deprecation);
+    }
+    response
+}
+
+fn requested_version(headers: &HeaderMap) -> Option<Result<ApiVersion, RequestError>> {
+    headers.get_all(ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|media_range| media_range.split(';').next().unwrap_or_default().trim())
+        .find_map(from_media_type)
+}
+
+// None when the media type isn't a vendor one, an error for a version that isn't served
+fn from_media_type(media_type: &str) -> Option<Result<ApiVersion, RequestError>> {
+    let version = media_type.strip_prefix(VENDOR_MEDIA_TYPE_PREFIX)?.strip_suffix(VENDOR_MEDIA_TYPE_SUFFIX)?;
+    Some(SUPPORTED_VERSIONS.into_iter()
+        .find(|supported| supported.as_str() == version)
+        .ok_or(RequestError::UnsupportedVersion))
+}
+
+// The route pattern with its version prefix, whether it was requested with or without
+fn versioned_route(version: ApiVersion, route: &str) -> String {
+    match route.strip_prefix(version.prefix()) {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => route.to_string(),
+        _ => format!("{}{}", version.prefix(), route),
+    }
+}
+
+fn insert_deprecation_headers(headers: &mut HeaderMap, deprecation: &RouteDeprecation) {
+    let deprecated_at = deprecation.deprecated_at.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc();
+    headers.insert(DEPRECATION_HEADER.clone(), HeaderValue::from_str(&format!("@{}", deprecated_at.timestamp())).expect("Timestamp is a valid header value"));
+
+    if let Some(sunset_at) = deprecation.sunset_at {
+        let sunset_at = sunset_at.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc();
+        let http_date = sunset_at.format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+        headers.insert(SUNSET_HEADER.clone(), http_date.parse().expect("HTTP date is a valid header value"));
+    }
+
+    if let Some(value) = deprecation.link.as_ref().and_then(|link| format!("<{}>; rel=\"deprecation\"", link).parse().ok()) {
+        headers.append(LINK, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn accept(values: &[&str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append(ACCEPT, value.parse().unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn requested_version_is_picked_from_the_vendor_media_type() {
+        assert_eq!(requested_version(&accept(&["application/vnd.swift.v1+json"])).unwrap().unwrap(), ApiVersion::V1);
+        assert_eq!(requested_version(&accept(&["text/html, application/vnd.swift.v1+json;q=0.9"])).unwrap().unwrap(), ApiVersion::V1);
+        assert_eq!(requested_version(&accept(&["application/json", "application/vnd.swift.v1+json"])).unwrap().unwrap(), ApiVersion::V1);
+    }
+
+    #[test]
+    fn requested_version_is_none_without_a_vendor_media_type() {
+        assert!(requested_version(&HeaderMap::new()).is_none());
+        assert!(requested_version(&accept(&["application/json, */*"])).is_none());
+        assert!(requested_version(&accept(&["application/vnd.other.v1+json"])).is_none());
+    }
+
+    #[test]
+    fn an_unknown_requested_version_is_unsupported() {
+        for value in ["application/vnd.swift.v2+json", "application/vnd.swift.+json", "application/vnd.swift.V1+json"] {
+            assert!(matches!(requested_version(&accept(&[value])), Some(Err(RequestError::UnsupportedVersion))), "{}", value);
+        }
+    }
+
+    #[test]
+    fn versioned_route_adds_the_prefix_once() {
+        assert_eq!(versioned_route(ApiVersion::V1, "/users/:id"), "/v1/users/:id");
+        assert_eq!(versioned_route(ApiVersion::V1, "/v1/users/:id"), "/v1/users/:id");
+        assert_eq!(versioned_route(ApiVersion::V1, "/v1"), "/v1");
+        assert_eq!(versioned_route(ApiVersion::V1, "/v1x/users"), "/v1/v1x/users");
+        assert_eq!(versioned_route(ApiVersion::V1, ""), "/v1");
+    }
+}
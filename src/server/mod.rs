@@ -0,0 +1,79 @@
+use std::net::SocketAddr;
+use axum::Router;
+use axum_server::Handle;
+use tracing::info;
+use crate::config::app_env::{SERVER_HOST, SERVER_PORT, SHUTDOWN_DRAIN_DELAY, SHUTDOWN_TIMEOUT, TLS_CERT_PATH, TLS_KEY_PATH, UNIX_SOCKET_PATH};
+use crate::server::shutdown::ShutdownSignal;
+
+pub mod shutdown;
+pub mod tls;
+pub mod unix;
+
+/*
+    Serves the app on a unix socket when UNIX_SOCKET_PATH is set, otherwise on SERVER_HOST:SERVER_PORT,
+    over https when both TLS_CERT_PATH and TLS_KEY_PATH are set.
+    Connections are accepted for SHUTDOWN_DRAIN_DELAY after the signal while readiness reports it.
+    Returns once in-flight requests are drained or SHUTDOWN_TIMEOUT is reached.
+*/
+pub async fn serve(app: Router, shutdown: ShutdownSignal) {
+    if let Some(path) = UNIX_SOCKET_PATH.as_ref() {
+        return unix::serve(path, app, shutdown).await;
+    }
+
+    let addr: SocketAddr = format!("{}:{}", *SERVER_HOST, *SERVER_PORT)
+        .parse()
+        .expect("SERVER_HOST and SERVER_PORT must form a valid socket address");
+
+    let handle = Handle::new();
+    tokio::spawn(log_listening(handle.clone()));
+    tokio::spawn(graceful_shutdown(handle.clone(), shutdown));
+
+    match (TLS_CERT_PATH.as_ref(), TLS_KEY_PATH.as_ref()) {
+        (Some(cert_path), Some(key_path)) => {
+            let tls_config = tls::load(cert_path, key_path).await;
+            tokio::spawn(tls::reload_on_change(tls_config.clone(), cert_path.clone(), key_path.clone()));
+
+            axum_server::bind_rustls(addr, tls_config)
+                .handle(handle)
+                .serve(app.into_make_service())
+                .await
+                .expect("Server error");
+        },
+        (None, None) => {
+            axum_server::bind(addr)
+                .handle(handle)
+                .serve(app.into_make_service())
+                .await
+                .expect("Server error");
+        },
+        _ => panic!("TLS_CERT_PATH and TLS_KEY_PATH must be set together"),
+    }
+}
+
+pub async fn serve_metrics(metrics_routes: Router, port: u16, shutdown: ShutdownSignal) {
+    let addr: SocketAddr = format!("{}:{}", *SERVER_HOST, port)
+        .parse()
+        .expect("SERVER_HOST and METRICS_PORT must form a valid socket address");
+
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .expect("Unable to bind metrics port");
+    info!("Metrics listening on {}", addr);
+
+    axum::serve(listener, metrics_routes.into_make_service())
+        .with_graceful_shutdown(async move { shutdown.wait_to_stop_accepting(*SHUTDOWN_DRAIN_DELAY).await })
+        .await
+        .expect("Metrics server error");
+}
+
+async fn log_listening(handle: Handle) {
+    if let Some(addr) = handle.listening().await {
+        info!("\n------------------------------------------\n      listening on {}\n------------------------------------------", addr);
+    }
+}
+
+async fn graceful_shutdown(handle: Handle, shutdown: ShutdownSignal) {
+    shutdown.wait_to_stop_accepting(*SHUTDOWN_DRAIN_DELAY).await;
+    info!("Draining in-flight requests, waiting up to {:?}", *SHUTDOWN_TIMEOUT);
+    handle.graceful_shutdown(Some(*SHUTDOWN_TIMEOUT));
+}
@@ -0,0 +1,106 @@
+use std::time::Duration;
+use tokio::signal;
+use tokio::sync::watch;
+use tracing::info;
+
+/*
+    Cloneable handle flipped once SIGTERM or SIGINT is received,
+    anything that needs to stop gracefully (server, readiness, workers) can wait on it.
+*/
+#[derive(Clone)]
+pub struct ShutdownSignal {
+    receiver: watch::Receiver<bool>,
+}
+
+impl ShutdownSignal {
+    pub fn listen() -> Self {
+        let (sender, shutdown) = Self::channel();
+
+        tokio::spawn(async move {
+            wait_for_os_signal().await;
+            info!("Shutdown signal received");
+            let _ = sender.send(true);
+        });
+
+        shutdown
+    }
+
+    // Flipped by sending true, for the OS signal or a test
+    pub(crate) fn channel() -> (watch::Sender<bool>, Self) {
+        let (sender, receiver) = watch::channel(false);
+        (sender, ShutdownSignal { receiver })
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        *self.receiver.borrow()
+    }
+
+    pub async fn wait(&self) {
+        let mut receiver = self.receiver.clone();
+        // An error means the sender is gone, which only happens once the signal was sent
+        let _ = receiver.wait_for(|is_shutting_down| *is_shutting_down).await;
+    }
+
+    /*
+        Resolves `drain_delay` after the signal. Readiness is down from the signal on,
+        listeners keep accepting until here so requests routed before load balancers noticed still succeed
+    */
+    pub async fn wait_to_stop_accepting(&self, drain_delay: Duration) {
+        self.wait().await;
+        if !drain_delay.is_zero() {
+            info!("Waiting {:?} for load balancers to stop routing here", drain_delay);
+            tokio::time::sleep(drain_delay).await;
+        }
+    }
+}
+
+async fn wait_for_os_signal() {
+    let ctrl_c = async {
+        signal::ctrl_c()
+            .await
+            .expect("Failed to install Ctrl+C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        signal::unix::signal(signal::unix::SignalKind::terminate())
+            .expect("Failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn stops_accepting_after_the_drain_delay() {
+        let (sender, shutdown) = ShutdownSignal::channel();
+        let stopping = shutdown.clone();
+        let stopped = tokio::spawn(async move { stopping.wait_to_stop_accepting(Duration::from_millis(300)).await });
+
+        sender.send(true).unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(shutdown.is_shutting_down());
+        assert!(!stopped.is_finished());
+
+        tokio::time::timeout(Duration::from_secs(2), stopped).await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn stops_accepting_at_once_without_a_drain_delay() {
+        let (sender, shutdown) = ShutdownSignal::channel();
+        sender.send(true).unwrap();
+
+        tokio::time::timeout(Duration::from_millis(100), shutdown.wait_to_stop_accepting(Duration::ZERO)).await.unwrap();
+    }
+}
@@ -0,0 +1,46 @@
+use std::path::Path;
+use std::time::SystemTime;
+use axum_server::tls_rustls::RustlsConfig;
+use tracing::{error, info};
+use crate::config::app_env::TLS_RELOAD_INTERVAL;
+
+pub async fn load(cert_path: &str, key_path: &str) -> RustlsConfig {
+    // Only one crypto provider is compiled in, an error means it is already installed
+    let _ = rustls::crypto::ring::default_provider().install_default();
+
+    RustlsConfig::from_pem_file(cert_path, key_path)
+        .await
+        .expect("Unable to load TLS certificate/key")
+}
+
+/*
+    Polls the certificate and key modification times and swaps them in place,
+    new connections pick up the renewed certificate without a restart.
+*/
+pub async fn reload_on_change(config: RustlsConfig, cert_path: String, key_path: String) {
+    let mut last_modified = modified_at(&cert_path, &key_path);
+    let mut interval = tokio::time::interval(*TLS_RELOAD_INTERVAL);
+    interval.tick().await;
+
+    loop {
+        interval.tick().await;
+
+        let modified = modified_at(&cert_path, &key_path);
+        if modified == last_modified {
+            continue;
+        }
+
+        match config.reload_from_pem_file(&cert_path, &key_path).await {
+            Ok(_) => {
+                info!("Reloaded TLS certificate from {}", cert_path);
+                last_modified = modified;
+            },
+            Err(e) => error!("Unable to reload TLS certificate, keeping previous one: {:?}", e),
+        }
+    }
+}
+
+fn modified_at(cert_path: &str, key_path: &str) -> (Option<SystemTime>, Option<SystemTime>) {
+    let modified = |path: &str| Path::new(path).metadata().and_then(|m| m.modified()).ok();
+    (modified(cert_path), modified(key_path))
+}
@@ -0,0 +1,97 @@
+use std::io;
+use std::os::unix::fs::FileTypeExt;
+use std::time::Duration;
+use axum::Router;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto::Builder;
+use hyper_util::server::graceful::GracefulShutdown;
+use hyper_util::service::TowerToHyperService;
+use tokio::net::UnixListener;
+use tracing::{debug, error, info, warn};
+use crate::config::app_env::{SHUTDOWN_DRAIN_DELAY, SHUTDOWN_TIMEOUT};
+use crate::server::shutdown::ShutdownSignal;
+
+// Accept fails on resource exhaustion (EMFILE) until connections close, retrying at once would spin
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+pub async fn serve(path: &str, app: Router, shutdown: ShutdownSignal) {
+    // A stale socket file from a previous run would make bind fail
+    remove_socket(path).expect("Unable to remove stale unix socket");
+    let listener = UnixListener::bind(path).expect("Unable to bind to unix socket");
+
+    info!("\n------------------------------------------\n      listening on unix:{}\n------------------------------------------", path);
+
+    let builder = Builder::new(TokioExecutor::new());
+    let graceful = GracefulShutdown::new();
+    let stop_accepting = shutdown.wait_to_stop_accepting(*SHUTDOWN_DRAIN_DELAY);
+    tokio::pin!(stop_accepting);
+
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let stream = match accepted {
+                    Ok((stream, _)) => stream,
+                    Err(e) => {
+                        error!("Unable to accept unix socket connection, retrying in {:?}: {:?}", ACCEPT_BACKOFF, e);
+                        tokio::time::sleep(ACCEPT_BACKOFF).await;
+                        continue;
+                    }
+                };
+
+                let service = TowerToHyperService::new(app.clone());
+                let connection = builder.serve_connection_with_upgrades(TokioIo::new(stream), service).into_owned();
+                let connection = graceful.watch(connection);
+
+                tokio::spawn(async move {
+                    if let Err(e) = connection.await {
+                        debug!("Unix socket connection closed with error: {:?}", e);
+                    }
+                });
+            },
+            _ = &mut stop_accepting => break,
+        }
+    }
+
+    drop(listener);
+    info!("Draining in-flight requests, waiting up to {:?}", *SHUTDOWN_TIMEOUT);
+    tokio::select! {
+        _ = graceful.shutdown() => info!("All connections closed"),
+        _ = tokio::time::sleep(*SHUTDOWN_TIMEOUT) => warn!("Shutdown timeout reached, dropping remaining connections"),
+    }
+    if let Err(e) = remove_socket(path) {
+        warn!("Unable to remove unix socket {}: {:?}", path, e);
+    }
+}
+
+// Only ever a socket, anything else at the path is a misconfiguration and is left alone
+fn remove_socket(path: &str) -> io::Result<()> {
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(path),
+        Ok(_) => Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{} exists and is not a socket", path))),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remove_socket_leaves_other_files_alone() {
+        let directory = std::env::temp_dir().join(format!("unix-socket-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&directory).unwrap();
+        let file = directory.join("file");
+        std::fs::write(&file, "keep").unwrap();
+        let socket = directory.join("socket");
+        drop(std::os::unix::net::UnixListener::bind(&socket).unwrap());
+
+        assert!(remove_socket(file.to_str().unwrap()).is_err());
+        assert!(file.exists());
+        assert!(remove_socket(socket.to_str().unwrap()).is_ok());
+        assert!(!socket.exists());
+        assert!(remove_socket(socket.to_str().unwrap()).is_ok());
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,52 @@
+//! Fails when `src/common/schema.rs` is not what `diesel print-schema` prints for the schema the migrations produce.
+//! Migrations are applied to a scratch database created next to the one in DATABASE_URL. The test returns early
+//! when DATABASE_URL isn't set or diesel CLI isn't installed, unless CI is set:
+//!
+//! cargo install diesel_cli --no-default-features --features postgres
+//! DATABASE_URL=postgres://postgres@localhost/swift cargo test --test schema_drift
+//!
+//! After changing the migrations, `diesel migration run` regenerates the file with the settings in diesel.toml.
+
+use std::env;
+use std::process::Command;
+use diesel::{Connection, PgConnection, RunQueryDsl, sql_query};
+use diesel_migrations::{FileBasedMigrations, MigrationHarness};
+
+#[test]
+fn schema_matches_migrations() {
+    let Ok(database_url) = env::var("DATABASE_URL") else {
+        assert!(env::var_os("CI").is_none(), "DATABASE_URL must be set in CI, the schema drift test would be skipped");
+        eprintln!("DATABASE_URL not set, skipping schema drift test");
+        return;
+    };
+    if Command::new("diesel").arg("--version").output().is_err() {
+        assert!(env::var_os("CI").is_none(), "diesel CLI must be installed in CI, the schema drift test would be skipped");
+        eprintln!("diesel CLI not installed, skipping schema drift test");
+        return;
+    }
+    let (server_url, _) = database_url.rsplit_once('/').expect("DATABASE_URL must contain a database name");
+    let scratch_database = format!("swift_schema_drift_{}", std::process::id());
+
+    let mut admin = PgConnection::establish(&database_url).expect("Unable to connect to DATABASE_URL");
+    sql_query(format!("CREATE DATABASE {}", scratch_database)).execute(&mut admin).unwrap();
+
+    let printed = std::panic::catch_unwind(|| print_schema(&format!("{}/{}", server_url, scratch_database)));
+    sql_query(format!("DROP DATABASE {}", scratch_database)).execute(&mut admin).unwrap();
+
+    let declared = include_str!("../src/common/schema.rs").replace("\r\n", "\n");
+    assert_eq!(declared.trim_end(), printed.unwrap().trim_end(), "src/common/schema.rs drifted from the migrations");
+}
+
+fn print_schema(url: &str) -> String {
+    let mut conn = PgConnection::establish(url).unwrap();
+    let migrations = FileBasedMigrations::find_migrations_directory().unwrap();
+    conn.run_pending_migrations(migrations).unwrap();
+
+    let output = Command::new("diesel")
+        .args(["print-schema", "--database-url", url])
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "diesel print-schema failed: {}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap().replace("\r\n", "\n")
+}